@@ -21,4 +21,21 @@ contract ExperimentalSsssHub is ExperimentalSsssPermitter {
         if (permit.expiry <= block.timestamp) revert Unauthorized();
         emit SharesDealt();
     }
+
+    /// Like `dealShares`, but each share is given the index at the same position in `indices`,
+    /// such as one that a recipient keeps across deals, instead of its own position.
+    function dealIndexedShares(
+        IdentityId identity,
+        string calldata, /* secretName */
+        uint64, /* version */
+        bytes calldata, /* pk */
+        bytes32, /* nonce */
+        uint64[] calldata, /* indices */
+        bytes[] calldata /* shares */
+    ) external {
+        IIdentityRegistry.Permit memory permit =
+            _getIdentityRegistry().readPermit(msg.sender, identity);
+        if (permit.expiry <= block.timestamp) revert Unauthorized();
+        emit SharesDealt();
+    }
 }
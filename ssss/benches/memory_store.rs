@@ -0,0 +1,79 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use ethers::types::{Address, H256};
+use ssss::{
+    store::{memory::MemoryStore, Store as _},
+    types::*,
+};
+
+fn share_id(identity: IdentityId, version: u64) -> ShareId {
+    ShareId {
+        secret_name: "omni".into(),
+        identity: IdentityLocator {
+            chain: 31337,
+            registry: Address::repeat_byte(1),
+            id: identity,
+        },
+        version,
+    }
+}
+
+fn share() -> SecretShare {
+    SecretShare {
+        index: ShareIndex(1),
+        share: vec![0u8; 32].into(),
+        provenance: None,
+    }
+}
+
+/// Puts new shares while other threads read a share as fast as they can.
+fn put_share_under_reads(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let store = MemoryStore::default();
+    let read_id = share_id(IdentityId(H256::repeat_byte(1)), 1);
+    rt.block_on(store.put_share(read_id.clone(), share()))
+        .unwrap();
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let readers: Vec<_> = (0..16)
+        .map(|_| {
+            let (store, read_id, stop) = (store.clone(), read_id.clone(), stop.clone());
+            rt.spawn(async move {
+                while !stop.load(Ordering::Relaxed) {
+                    store.get_share(read_id.clone()).await.unwrap();
+                    tokio::task::yield_now().await;
+                }
+            })
+        })
+        .collect();
+
+    c.bench_function("put_share with 16 readers", |b| {
+        b.iter(|| {
+            let id = share_id(IdentityId(H256::random()), 1);
+            rt.block_on(store.put_share(id, share())).unwrap()
+        })
+    });
+
+    stop.store(true, Ordering::Relaxed);
+    for reader in readers {
+        rt.block_on(reader).unwrap();
+    }
+}
+
+fn get_share(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let store = MemoryStore::default();
+    let id = share_id(IdentityId(H256::repeat_byte(1)), 1);
+    rt.block_on(store.put_share(id.clone(), share())).unwrap();
+
+    c.bench_function("get_share", |b| {
+        b.iter(|| rt.block_on(store.get_share(id.clone())).unwrap())
+    });
+}
+
+criterion_group!(benches, put_share_under_reads, get_share);
+criterion_main!(benches);
@@ -162,6 +162,22 @@ resource "aws_dynamodb_table" "verifiers" {
   }
 }
 
+resource "aws_dynamodb_table" "intents" {
+  name         = "escrin-intents-${terraform.workspace}"
+  billing_mode = "PAY_PER_REQUEST"
+  hash_key     = "id"
+  tags         = local.tags
+
+  attribute {
+    name = "id"
+    type = "N"
+  }
+
+  lifecycle {
+    prevent_destroy = true
+  }
+}
+
 data "aws_iam_policy_document" "policy" {
   statement {
     effect = "Allow"
@@ -172,6 +188,7 @@ data "aws_iam_policy_document" "policy" {
       "dynamodb:PutItem",
       "dynamodb:UpdateItem",
       "dynamodb:Query",
+      "dynamodb:Scan",
     ]
     resources = [
       "${aws_dynamodb_table.secrets.arn}",
@@ -179,6 +196,7 @@ data "aws_iam_policy_document" "policy" {
       "${aws_dynamodb_table.nonces.arn}",
       "${aws_dynamodb_table.verifiers.arn}",
       "${aws_dynamodb_table.chain_state.arn}",
+      "${aws_dynamodb_table.intents.arn}",
     ]
   }
 }
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+// Runs the whole policy ingest path, as an arbitrary compressed input rarely decompresses.
+fuzz_target!(|input: (bool, &[u8])| {
+    let (compressed, config) = input;
+    match compressed {
+        true => {
+            if let Ok(config) = ssss::sync::decompress_policy(config) {
+                let _ = ssss::types::PolicyPreamble::decode(&config);
+            }
+        }
+        false => {
+            let _ = ssss::types::PolicyPreamble::decode(config);
+        }
+    }
+});
@@ -0,0 +1,7 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|args: &[u8]| {
+    let _ = ssss::eth::decode_shares_dealt(args, Default::default());
+});
@@ -0,0 +1,9 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|config_br: &[u8]| {
+    if let Ok(config) = ssss::sync::decompress_policy(config_br) {
+        assert!(config.len() <= ssss::sync::MAX_POLICY_SIZE);
+    }
+});
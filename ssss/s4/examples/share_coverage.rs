@@ -0,0 +1,94 @@
+//! Reports the deals for which fewer than `--threshold` of the given SSSSs hold a share.
+//!
+//! ```sh
+//! cargo run -p s4 --example share_coverage -- --threshold 2 http://ssss-0:1075 http://ssss-1:1075
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use clap::Parser;
+use eyre::Result;
+use futures_util::future::join_all;
+use s4::{ProxyConfig, SsssClient};
+use ssss::types::{ChainId, ShareIndex};
+
+#[derive(Parser)]
+struct Args {
+    /// The number of shares needed to reconstruct a secret.
+    #[arg(short, long)]
+    threshold: usize,
+
+    /// A SOCKS5 proxy through which to query the SSSSs.
+    #[arg(long)]
+    proxy: Option<url::Url>,
+
+    /// The SSSSs to query.
+    #[arg(required = true)]
+    sssss: Vec<url::Url>,
+}
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let proxy = args.proxy.map(ProxyConfig::socks5).transpose()?;
+    let clients = args
+        .sssss
+        .iter()
+        .map(|url| match &proxy {
+            Some(proxy) => SsssClient::with_proxy(url.clone(), proxy.clone()),
+            None => Ok(SsssClient::new(url.clone())),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let statuses = join_all(
+        args.sssss
+            .iter()
+            .zip(&clients)
+            .map(|(url, client)| async move { (url, client.get_status().await) }),
+    )
+    .await;
+
+    // Holders are counted by share index so that nodes holding the same share count once.
+    let mut coverage: BTreeMap<(ChainId, String, String, u64), BTreeSet<ShareIndex>> =
+        BTreeMap::new();
+    for (url, status) in statuses {
+        let status = match status {
+            Ok(status) => status,
+            Err(e) => {
+                eprintln!("failed to get status of {url}: {e}");
+                continue;
+            }
+        };
+        for (chain, chain_status) in status.chains {
+            for share in chain_status.shares {
+                let holders = coverage
+                    .entry((
+                        chain,
+                        format!("{:#x}", share.identity.0),
+                        share.secret_name,
+                        share.version,
+                    ))
+                    .or_default();
+                holders.extend(share.index);
+            }
+        }
+    }
+
+    let mut below_threshold = 0;
+    for ((chain, identity, secret_name, version), holders) in coverage {
+        if holders.len() < args.threshold {
+            below_threshold += 1;
+            println!(
+                "chain {chain} identity {identity} secret {secret_name} v{version}: {} of {} \
+                 shares held",
+                holders.len(),
+                args.threshold
+            );
+        }
+    }
+    if below_threshold > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
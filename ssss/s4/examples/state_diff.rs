@@ -0,0 +1,205 @@
+//! Compares what the given SSSSs report about their sync and the deals that they have seen, and
+//! prints where they disagree as JSON, such as to find the node at fault when reconstruction fails.
+//!
+//! ```sh
+//! cargo run -p s4 --example state_diff -- http://ssss-0:1075 http://ssss-1:1075
+//! ```
+//!
+//! Nodes only report the deals that they have seen since they started, so a node that restarted
+//! after a deal is reported as not having seen it.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use clap::Parser;
+use eyre::Result;
+use futures_util::future::join_all;
+use s4::{ProxyConfig, SsssClient};
+use serde::Serialize;
+use ssss::types::{api::StatusResponse, ChainId, ShareIndex};
+
+#[derive(Parser)]
+struct Args {
+    /// How many blocks apart the nodes' sync of a chain may be before it is reported.
+    #[arg(long, default_value_t = 10)]
+    block_tolerance: u64,
+
+    /// A SOCKS5 proxy through which to query the SSSSs.
+    #[arg(long)]
+    proxy: Option<url::Url>,
+
+    /// The SSSSs to query.
+    #[arg(required = true, num_args = 2..)]
+    sssss: Vec<url::Url>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+struct Secret {
+    chain: ChainId,
+    identity: String,
+    secret_name: String,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+enum Discrepancy {
+    /// The nodes' sync of the chain is further apart than the tolerance.
+    ProcessedBlock {
+        chain: ChainId,
+        processed_blocks: BTreeMap<String, u64>,
+    },
+    /// Some of the nodes have not seen a deal that others have.
+    UnseenDeal {
+        #[serde(flatten)]
+        secret: Secret,
+        version: u64,
+        unseen_by: Vec<String>,
+    },
+    /// The nodes hold shares of different latest versions of the secret.
+    LatestVersion {
+        #[serde(flatten)]
+        secret: Secret,
+        /// The latest version of which each holder holds a share, and the index of that share.
+        held: BTreeMap<String, (u64, ShareIndex)>,
+    },
+    /// More than one node holds the same share of a deal, so fewer distinct shares are held than
+    /// there are holders.
+    DuplicateIndex {
+        #[serde(flatten)]
+        secret: Secret,
+        version: u64,
+        index: ShareIndex,
+        holders: Vec<String>,
+    },
+}
+
+/// The shares that each node reports for each version of each secret, by node.
+type Deals = BTreeMap<Secret, BTreeMap<u64, BTreeMap<String, Option<ShareIndex>>>>;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let args = Args::parse();
+
+    let proxy = args.proxy.map(ProxyConfig::socks5).transpose()?;
+    let clients = args
+        .sssss
+        .iter()
+        .map(|url| match &proxy {
+            Some(proxy) => SsssClient::with_proxy(url.clone(), proxy.clone()),
+            None => Ok(SsssClient::new(url.clone())),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let statuses = join_all(
+        args.sssss
+            .iter()
+            .zip(&clients)
+            .map(|(url, client)| async move { (url.to_string(), client.get_status().await) }),
+    )
+    .await;
+    let mut snapshots = BTreeMap::new();
+    for (node, status) in statuses {
+        match status {
+            Ok(status) => {
+                snapshots.insert(node, status);
+            }
+            // Every node must be compared for the diff to mean anything.
+            Err(e) => eyre::bail!("failed to get status of {node}: {e}"),
+        }
+    }
+
+    let discrepancies = diff(&snapshots, args.block_tolerance);
+    println!("{}", serde_json::to_string_pretty(&discrepancies)?);
+    if !discrepancies.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn diff(snapshots: &BTreeMap<String, StatusResponse>, block_tolerance: u64) -> Vec<Discrepancy> {
+    let nodes: Vec<&String> = snapshots.keys().collect();
+    let mut discrepancies = Vec::new();
+
+    let mut processed_blocks: BTreeMap<ChainId, BTreeMap<String, u64>> = BTreeMap::new();
+    let mut deals = Deals::new();
+    for (node, status) in snapshots {
+        for (&chain, chain_status) in &status.chains {
+            processed_blocks
+                .entry(chain)
+                .or_default()
+                .insert(node.clone(), chain_status.processed_block);
+            for share in &chain_status.shares {
+                let secret = Secret {
+                    chain,
+                    identity: format!("{:#x}", share.identity.0),
+                    secret_name: share.secret_name.clone(),
+                };
+                deals
+                    .entry(secret)
+                    .or_default()
+                    .entry(share.version)
+                    .or_default()
+                    .insert(node.clone(), share.index);
+            }
+        }
+    }
+
+    for (chain, processed_blocks) in processed_blocks {
+        let (Some(min), Some(max)) = (
+            processed_blocks.values().min(),
+            processed_blocks.values().max(),
+        ) else {
+            continue;
+        };
+        if max - min > block_tolerance || processed_blocks.len() < nodes.len() {
+            discrepancies.push(Discrepancy::ProcessedBlock {
+                chain,
+                processed_blocks,
+            });
+        }
+    }
+
+    for (secret, versions) in deals {
+        let mut held: BTreeMap<String, (u64, ShareIndex)> = BTreeMap::new();
+        for (&version, reports) in &versions {
+            let unseen_by: Vec<String> = nodes
+                .iter()
+                .filter(|&&node| !reports.contains_key(node))
+                .map(|&node| node.clone())
+                .collect();
+            if !unseen_by.is_empty() {
+                discrepancies.push(Discrepancy::UnseenDeal {
+                    secret: secret.clone(),
+                    version,
+                    unseen_by,
+                });
+            }
+
+            let mut holders: BTreeMap<ShareIndex, Vec<String>> = BTreeMap::new();
+            for (node, index) in reports {
+                if let Some(index) = *index {
+                    holders.entry(index).or_default().push(node.clone());
+                    // Versions are visited in ascending order, so the last one seen is the latest.
+                    held.insert(node.clone(), (version, index));
+                }
+            }
+            for (index, holders) in holders {
+                if holders.len() > 1 {
+                    discrepancies.push(Discrepancy::DuplicateIndex {
+                        secret: secret.clone(),
+                        version,
+                        index,
+                        holders,
+                    });
+                }
+            }
+        }
+
+        // Nodes that were not dealt a share are not expected to hold one.
+        let latest_versions: BTreeSet<_> = held.values().map(|(version, _)| version).collect();
+        if latest_versions.len() > 1 {
+            discrepancies.push(Discrepancy::LatestVersion { secret, held });
+        }
+    }
+
+    discrepancies
+}
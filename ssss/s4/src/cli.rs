@@ -10,6 +10,10 @@ pub struct Args {
     #[arg(short, long, action = Count, default_value_t = 0)]
     pub verbosity: u8,
 
+    /// A SOCKS5 proxy, such as Tor at `socks5h://127.0.0.1:9050`, through which to reach SSSSs.
+    #[arg(long, global = true, value_hint = ValueHint::Url)]
+    pub proxy: Option<url::Url>,
+
     #[command(subcommand)]
     pub command: Command,
 }
@@ -88,12 +92,31 @@ pub enum Command {
         #[command(flatten)]
         version: ShareVersion,
 
+        /// Also requests the next version from each SSSS, as while the secret is being rotated,
+        /// and reconstructs the newest version of which the threshold of shares is returned.
+        #[arg(long)]
+        rotating: bool,
+
         #[command(flatten)]
         sssss: Sssss,
 
+        #[command(flatten)]
+        threshold: ReconstructThreshold,
+
         #[command(flatten)]
         wallet: Wallet,
     },
+    /// Counts the requested SSSSs that hold a share, as attested by their signed receipts.
+    CheckReceipts {
+        #[command(flatten)]
+        il: IdentityLocatorArgs,
+
+        #[command(flatten)]
+        version: ShareVersion,
+
+        #[command(flatten)]
+        sssss: Sssss,
+    },
 }
 
 #[derive(Clone, Debug, clap::Args)]
@@ -118,6 +141,34 @@ pub struct Threshold {
     pub threshold: f64,
 }
 
+impl Threshold {
+    /// The number of the `sssss` SSSSs that the threshold represents.
+    pub fn of(&self, sssss: usize) -> usize {
+        if self.threshold > 1.0 {
+            self.threshold as usize
+        } else {
+            (self.threshold * (sssss as f64)).ceil() as usize
+        }
+    }
+}
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct ReconstructThreshold {
+    /// The threshold of SSSSs that must return correct shares for the secret to be reconstructed,
+    /// as for dealing. Every SSSS must return its share if not specified.
+    #[arg(short, long)]
+    pub threshold: Option<f64>,
+}
+
+impl ReconstructThreshold {
+    pub fn of(&self, sssss: usize) -> usize {
+        match self.threshold {
+            Some(threshold) => Threshold { threshold }.of(sssss),
+            None => sssss,
+        }
+    }
+}
+
 #[derive(Clone, Debug, clap::Args)]
 pub struct Wallet {
     #[arg(
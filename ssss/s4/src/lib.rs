@@ -1,3 +1,5 @@
+use std::collections::BTreeMap;
+
 use aes_gcm_siv::AeadInPlace;
 use ethers::{
     core::utils::keccak256,
@@ -5,7 +7,6 @@ use ethers::{
     types::transaction::eip712::Eip712 as _,
 };
 use eyre::Result;
-use futures_util::TryFutureExt as _;
 use headers::Header as _;
 use reqwest::StatusCode;
 use ssss::types::{api::*, *};
@@ -14,6 +15,40 @@ use ssss::types::{api::*, *};
 pub struct SsssClient {
     client: reqwest::Client,
     url: url::Url,
+    proxy: Option<ProxyConfig>,
+}
+
+/// A SOCKS5 proxy, such as Tor, through which every request to an SSSS is sent so that the SSSS
+/// does not learn the client's IP address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProxyConfig {
+    url: url::Url,
+}
+
+impl ProxyConfig {
+    /// Accepts `socks5://` URLs, with which node hostnames are resolved locally, and `socks5h://`
+    /// URLs, with which the proxy resolves them, as is needed to reach onion services.
+    pub fn socks5(url: url::Url) -> Result<Self> {
+        match url.scheme() {
+            "socks5" | "socks5h" => Ok(Self { url }),
+            scheme => Err(eyre::eyre!("unsupported proxy scheme: {scheme}")),
+        }
+    }
+
+    pub fn url(&self) -> &url::Url {
+        &self.url
+    }
+}
+
+/// A request could not be sent through the proxy, so it says nothing about the health of the SSSS.
+///
+/// Client methods return it within their [`eyre::Report`], from which it can be downcast.
+#[derive(Debug, thiserror::Error)]
+#[error("failed to reach {ssss} through proxy {proxy}: {source}")]
+pub struct ProxyError {
+    pub ssss: url::Url,
+    pub proxy: url::Url,
+    pub source: reqwest::Error,
 }
 
 impl SsssClient {
@@ -21,18 +56,53 @@ impl SsssClient {
         Self {
             client: Default::default(),
             url: ssss,
+            proxy: None,
         }
     }
 
+    /// Creates a client that connects to the SSSS only through `proxy`.
+    pub fn with_proxy(ssss: url::Url, proxy: ProxyConfig) -> Result<Self> {
+        Ok(Self {
+            client: reqwest::Client::builder()
+                .proxy(reqwest::Proxy::all(proxy.url.clone())?)
+                .build()?,
+            url: ssss,
+            proxy: Some(proxy),
+        })
+    }
+
     // TODO: cache this
     pub async fn get_ssss_identity(&self) -> Result<IdentityResponse> {
-        Ok(reqwest::get(self.url.join("/v1/identity").unwrap())
+        Ok(self
+            .send(self.client.get(self.url.join("/v1/identity").unwrap()))
             .await?
             .error_for_status()?
             .json()
             .await?)
     }
 
+    pub async fn get_status(&self) -> Result<StatusResponse> {
+        Ok(self
+            .send(self.client.get(self.url.join("/v1/status").unwrap()))
+            .await?
+            .error_for_status()?
+            .json()
+            .await?)
+    }
+
+    /// Sends the request, reporting failures to connect through the proxy as [`ProxyError`]s.
+    async fn send(&self, req: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        req.send().await.map_err(|e| match &self.proxy {
+            Some(proxy) if e.is_connect() => ProxyError {
+                ssss: self.url.clone(),
+                proxy: proxy.url.clone(),
+                source: e,
+            }
+            .into(),
+            _ => e.into(),
+        })
+    }
+
     /// Returns whether the SSSS optimistically granted the permit.
     pub async fn acquire_identity(
         &self,
@@ -74,7 +144,7 @@ impl SsssClient {
         };
 
         let req = self.client.request(method.clone(), url.clone());
-        let res = match signer {
+        let req = match signer {
             Some(signer) => Self::attach_escrin1_sig(
                 req,
                 SsssRequest {
@@ -88,9 +158,8 @@ impl SsssClient {
             None => req,
         }
         .header("content-type", "application/json")
-        .body(body)
-        .send()
-        .await?;
+        .body(body);
+        let res = self.send(req).await?;
 
         if !res.status().is_success() {
             let res_text = res.text().await?;
@@ -122,22 +191,26 @@ impl SsssClient {
             None => std::borrow::Cow::Owned(p384::SecretKey::random(&mut rand::thread_rng())),
         };
 
-        let shares_req = Self::attach_escrin1_sig(
-            self.client.get(url.clone()),
-            SsssRequest {
-                method: "GET".into(),
-                host: url.authority().to_string(),
-                path_and_query: paq,
-                body: Default::default(),
-            },
-            signer,
-        )?
-        .header(
-            RequesterPublicKeyHeader::name().as_str(),
-            RequesterPublicKeyHeader(sk.public_key()).to_string(),
-        )
-        .send()
-        .map_err(eyre::Error::from);
+        let shares_req = self.send(
+            Self::attach_escrin1_sig(
+                self.client.get(url.clone()),
+                SsssRequest {
+                    method: "GET".into(),
+                    host: url.authority().to_string(),
+                    path_and_query: paq,
+                    body: Default::default(),
+                },
+                signer,
+            )?
+            .header(
+                RequesterPublicKeyHeader::name().as_str(),
+                RequesterPublicKeyHeader(sk.public_key()).to_string(),
+            )
+            .header(
+                ShareEncapsulationHeader::name().as_str(),
+                ShareEncapsulationHeader::VALUE,
+            ),
+        );
 
         let (shares_res, ssss_identity) = tokio::try_join!(shares_req, self.get_ssss_identity())?;
 
@@ -152,25 +225,42 @@ impl SsssClient {
         }
 
         let res: ShareResponse = shares_res.error_for_status()?.json().await?;
+        decrypt_share(res, &sk, &ssss_identity.ephemeral)
+    }
 
-        let share = match res.format {
-            ShareResponseFormat::Plain => res.ss.share,
-            ShareResponseFormat::EncAes256GcmSiv { nonce } => {
-                let mut share = res.ss.share;
-                let ssss_pk = p384::PublicKey::from_jwk(&ssss_identity.ephemeral)?;
-                let cipher = ssss::identity::derive_shared_cipher(
-                    &sk.to_nonzero_scalar(),
-                    &ssss_pk,
-                    ssss::identity::GET_SHARE_DOMAIN_SEP,
-                );
-                cipher
-                    .decrypt_in_place(&nonce.into(), &[], &mut share)
-                    .map_err(|_| eyre::eyre!("share decryption failed"))?;
-                share
-            }
-        };
+    /// Fetches the SSSS's receipt for the share and checks that its persistent identity signed it.
+    pub async fn get_share_receipt(
+        &self,
+        name: &str,
+        IdentityLocator {
+            chain,
+            registry,
+            id: IdentityId(identity),
+        }: IdentityLocator,
+        version: u64,
+    ) -> Result<ShareReceipt> {
+        let paq =
+            format!("/v1/receipts/{name}/{chain}/{registry:x}/{identity:x}?version={version}");
+        let receipt_req = self.send(self.client.get(self.url.join(&paq)?));
+        let (receipt_res, ssss_identity) = tokio::try_join!(receipt_req, self.get_ssss_identity())?;
 
-        Ok((res.ss.index, share))
+        if !receipt_res.status().is_success() {
+            let res_text = receipt_res.text().await?;
+            let ErrorResponse { error } =
+                serde_json::from_str(&res_text).unwrap_or(ErrorResponse { error: res_text });
+            return Err(eyre::eyre!(
+                "failed to get share receipt from {}: {error}",
+                self.url
+            ));
+        }
+
+        let ShareReceiptResponse { receipt, signature } = receipt_res.json().await?;
+        let signature = p384::ecdsa::Signature::from_slice(&signature)?;
+        let ssss_pk = p384::PublicKey::from_jwk(&ssss_identity.persistent)?;
+        if !receipt.verify(&ssss_pk, &signature) {
+            return Err(eyre::eyre!("{} sent an invalid share receipt", self.url));
+        }
+        Ok(receipt)
     }
 
     fn attach_escrin1_sig(
@@ -191,3 +281,383 @@ impl SsssClient {
             ))
     }
 }
+
+/// Which part of a secret to reconstruct.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum SecretSelector {
+    #[default]
+    Whole,
+    /// A range of bytes of the secret.
+    Bytes(std::ops::Range<usize>),
+}
+
+/// The sharing scheme stores the secret in a form that can only be reconstructed in full.
+#[derive(Debug, thiserror::Error)]
+#[error("the {scheme} sharing scheme cannot reconstruct part of a secret")]
+pub struct PartialReconstructionUnsupported {
+    pub scheme: &'static str,
+}
+
+/// Combines shares dealt with Shamir's scheme over P-384 scalars into the selected part of the
+/// secret.
+///
+/// The secret is a single scalar, so any part of it takes a threshold of whole shares and
+/// yields all of it. Selecting less than the whole returns [`PartialReconstructionUnsupported`]
+/// rather than exposing the whole secret to a caller that asked for part of it.
+/// Decrypts a served share with the requester's key `sk`. Shares that were not encapsulated were
+/// encrypted by the node's ephemeral identity, whose public key is `ssss_ephemeral`.
+fn decrypt_share(
+    res: ShareResponse,
+    sk: &p384::SecretKey,
+    ssss_ephemeral: &p384::elliptic_curve::JwkEcKey,
+) -> Result<(u64, Vec<u8>)> {
+    let (nonce, ssss_pk) = match res.format {
+        ShareResponseFormat::Plain => return Ok((*res.ss.index, res.ss.share)),
+        ShareResponseFormat::EncAes256GcmSiv { nonce } => {
+            (nonce, p384::PublicKey::from_jwk(ssss_ephemeral)?)
+        }
+        // Nodes that honor the encapsulation header send the key that they used.
+        ShareResponseFormat::EncAes256GcmSivEphemeral { nonce, pk } => {
+            (nonce, p384::PublicKey::from_sec1_bytes(&pk)?)
+        }
+    };
+    let mut share = res.ss.share;
+    let cipher = ssss::identity::derive_shared_cipher(
+        &sk.to_nonzero_scalar(),
+        &ssss_pk,
+        ssss::identity::GET_SHARE_DOMAIN_SEP,
+    );
+    cipher
+        .decrypt_in_place(&nonce.into(), &[], &mut share)
+        .map_err(|_| eyre::eyre!("share decryption failed"))?;
+    Ok((*res.ss.index, share))
+}
+
+pub fn reconstruct_shamir(shares: &[Vec<u8>], selector: &SecretSelector) -> Result<Vec<u8>> {
+    if *selector != SecretSelector::Whole {
+        return Err(PartialReconstructionUnsupported { scheme: "shamir" }.into());
+    }
+    let secret = vsss_rs::combine_shares::<p384::Scalar, u8, Vec<u8>>(shares)
+        .map_err(|_| eyre::eyre!("failed to reconstruct shares"))?;
+    Ok(secret.to_bytes().to_vec())
+}
+
+/// Enough shares were returned to reach the threshold, but not of any one version of the secret,
+/// as can happen while it is being rotated. Combining shares of different versions would yield
+/// garbage rather than either secret.
+#[derive(Debug, thiserror::Error)]
+#[error(
+    "{total} shares were returned, but no version of the secret has the threshold of {threshold} \
+     (shares per version: {shares_per_version:?})"
+)]
+pub struct SharesSplitAcrossVersions {
+    pub threshold: usize,
+    pub total: usize,
+    pub shares_per_version: BTreeMap<u64, usize>,
+}
+
+/// Reconstructs the newest version of the secret of which at least `threshold` of the given
+/// `(version, share)`s were returned, and returns that version along with the secret.
+pub fn reconstruct_shamir_versioned(
+    shares: impl IntoIterator<Item = (u64, Vec<u8>)>,
+    threshold: usize,
+    selector: &SecretSelector,
+) -> Result<(u64, Vec<u8>)> {
+    let mut versions: BTreeMap<u64, Vec<Vec<u8>>> = Default::default();
+    for (version, share) in shares {
+        versions.entry(version).or_default().push(share);
+    }
+    if let Some((&version, shares)) = versions
+        .iter()
+        .rev()
+        .find(|(_, shares)| shares.len() >= threshold)
+    {
+        return Ok((version, reconstruct_shamir(shares, selector)?));
+    }
+
+    let shares_per_version: BTreeMap<u64, usize> = versions
+        .iter()
+        .map(|(&version, shares)| (version, shares.len()))
+        .collect();
+    let total = shares_per_version.values().sum();
+    if total >= threshold {
+        return Err(SharesSplitAcrossVersions {
+            threshold,
+            total,
+            shares_per_version,
+        }
+        .into());
+    }
+    Err(eyre::eyre!(
+        "only {total} shares were returned, fewer than the threshold of {threshold}"
+    ))
+}
+
+/// Like [`reconstruct_shamir_versioned`], but takes the outcome of each request for a share.
+/// Failed requests are tolerated while the threshold is still reached, and are reported along
+/// with the shortfall otherwise.
+pub fn reconstruct_shamir_responses(
+    responses: impl IntoIterator<Item = (u64, Result<Vec<u8>>)>,
+    threshold: usize,
+    selector: &SecretSelector,
+) -> Result<(u64, Vec<u8>)> {
+    let mut failures = Vec::new();
+    let shares: Vec<_> = responses
+        .into_iter()
+        .filter_map(|(version, share)| {
+            share
+                .map_err(|e| failures.push(format!("{e:#}")))
+                .ok()
+                .map(|share| (version, share))
+        })
+        .collect();
+    reconstruct_shamir_versioned(shares, threshold, selector).map_err(|e| {
+        if failures.is_empty() {
+            return e;
+        }
+        e.wrap_err(format!(
+            "{} requests for shares failed: {}",
+            failures.len(),
+            failures.join("; ")
+        ))
+    })
+}
+
+/// Reconstructs a version of the named secret of an identity from the shares of it held by
+/// `stores`, as when
+/// a test harness runs every node of a deal in one process. The shares are read straight from
+/// the stores, with no request to any node and so no permit. The stored shares are not
+/// authenticated beyond what reconstruction itself checks, so this is not for production.
+#[cfg(feature = "local-reconstruct")]
+pub async fn reconstruct_local<S: ssss::store::Store>(
+    stores: &[S],
+    secret_name: &str,
+    identity: IdentityLocator,
+    version: u64,
+    threshold: usize,
+) -> Result<zeroize::Zeroizing<Vec<u8>>> {
+    let id = ShareId {
+        secret_name: secret_name.into(),
+        identity,
+        version,
+    };
+    let mut shares = zeroize::Zeroizing::new(Vec::with_capacity(stores.len()));
+    for store in stores {
+        let share = store
+            .get_share(id.clone())
+            .await
+            .map_err(|e| eyre::eyre!("failed to read share: {e:#}"))?;
+        if let Some(share) = share {
+            shares.push(share.share.to_vec());
+        }
+    }
+    if shares.len() < threshold {
+        eyre::bail!(
+            "only {} of the stores hold a v{version} share, fewer than the threshold of \
+             {threshold}",
+            shares.len()
+        );
+    }
+    Ok(reconstruct_shamir(&shares, &SecretSelector::Whole)?.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(secret: u64, threshold: usize, limit: usize) -> Vec<Vec<u8>> {
+        vsss_rs::shamir::split_secret::<p384::Scalar, u8, Vec<u8>>(
+            threshold,
+            limit,
+            p384::Scalar::from(secret),
+            &mut rand::thread_rng(),
+        )
+        .unwrap()
+    }
+
+    fn secret_bytes(secret: u64) -> Vec<u8> {
+        p384::Scalar::from(secret).to_bytes().to_vec()
+    }
+
+    #[test]
+    fn reconstruct_newest_version_with_threshold() {
+        let (v1, v2, v3) = (split(1, 2, 3), split(2, 2, 3), split(3, 2, 3));
+        // Version 3 is newest but short of the threshold, so version 2 is the one reconstructed.
+        let shares = [
+            (1, v1[0].clone()),
+            (2, v2[0].clone()),
+            (3, v3[0].clone()),
+            (1, v1[1].clone()),
+            (2, v2[2].clone()),
+        ];
+        assert_eq!(
+            reconstruct_shamir_versioned(shares, 2, &SecretSelector::Whole).unwrap(),
+            (2, secret_bytes(2))
+        );
+    }
+
+    #[test]
+    fn refuse_partial_shamir_reconstruction() {
+        let shares = split(1, 2, 3);
+        let err = reconstruct_shamir(&shares, &SecretSelector::Bytes(0..16))
+            .unwrap_err()
+            .downcast::<PartialReconstructionUnsupported>()
+            .unwrap();
+        assert_eq!(err.scheme, "shamir");
+    }
+
+    #[test]
+    fn report_shares_split_across_versions() {
+        let (v1, v2) = (split(1, 3, 3), split(2, 3, 3));
+        let shares = [
+            (1, v1[0].clone()),
+            (1, v1[1].clone()),
+            (2, v2[0].clone()),
+            (2, v2[1].clone()),
+        ];
+        let err = reconstruct_shamir_versioned(shares.clone(), 3, &SecretSelector::Whole)
+            .unwrap_err()
+            .downcast::<SharesSplitAcrossVersions>()
+            .unwrap();
+        assert_eq!((err.threshold, err.total), (3, 4));
+        assert_eq!(err.shares_per_version, [(1, 2), (2, 2)].into());
+
+        // Too few shares in total is not a split.
+        let err = reconstruct_shamir_versioned(shares, 5, &SecretSelector::Whole).unwrap_err();
+        assert!(
+            err.downcast_ref::<SharesSplitAcrossVersions>().is_none(),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn report_failed_requests_short_of_threshold() {
+        let shares = split(1, 2, 3);
+        let responses = || {
+            vec![
+                (1, Ok(shares[0].clone())),
+                (1, Err(eyre::eyre!("node 2 is down"))),
+                (1, Ok(shares[2].clone())),
+            ]
+        };
+        assert_eq!(
+            reconstruct_shamir_responses(responses(), 2, &SecretSelector::Whole).unwrap(),
+            (1, secret_bytes(1))
+        );
+
+        let err = reconstruct_shamir_responses(responses(), 3, &SecretSelector::Whole).unwrap_err();
+        assert!(format!("{err:#}").contains("node 2 is down"), "{err:#}");
+    }
+
+    #[test]
+    fn accept_only_socks5_proxies() {
+        for url in ["socks5://127.0.0.1:9050", "socks5h://127.0.0.1:9050"] {
+            let url: url::Url = url.parse().unwrap();
+            assert_eq!(ProxyConfig::socks5(url.clone()).unwrap().url(), &url);
+        }
+        for url in ["http://127.0.0.1:8080", "socks4://127.0.0.1:9050"] {
+            let err = ProxyConfig::socks5(url.parse().unwrap()).unwrap_err();
+            assert!(
+                err.to_string().contains("unsupported proxy scheme"),
+                "{err}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn report_refused_proxy_connections() {
+        // Nothing listens on a port once its listener is dropped, so connections to it are refused.
+        let refused = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let ssss: url::Url = "http://ssss.invalid".parse().unwrap();
+        let proxy = ProxyConfig::socks5(format!("socks5h://{refused}").parse().unwrap()).unwrap();
+
+        let client = SsssClient::with_proxy(ssss.clone(), proxy.clone()).unwrap();
+        let err = client
+            .get_status()
+            .await
+            .unwrap_err()
+            .downcast::<ProxyError>()
+            .unwrap();
+        assert_eq!((err.ssss, &err.proxy), (ssss, proxy.url()));
+
+        // Without a proxy, failing to reach the SSSS is not blamed on one.
+        let client = SsssClient::new(format!("http://{refused}").parse().unwrap());
+        let err = client.get_status().await.unwrap_err();
+        assert!(err.downcast_ref::<ProxyError>().is_none(), "{err}");
+    }
+
+    #[cfg(feature = "local-reconstruct")]
+    #[tokio::test]
+    async fn reconstruct_from_local_stores() {
+        use ssss::store::{memory::MemoryStore, Store as _};
+
+        let identity = IdentityLocator {
+            chain: 31337,
+            registry: ethers::types::Address::repeat_byte(1),
+            id: IdentityId(ethers::types::H256::random()),
+        };
+        let stores: Vec<_> = (0..3).map(|_| MemoryStore::default()).collect();
+        for (i, (store, share)) in stores.iter().zip(split(7, 2, 3)).enumerate() {
+            let id = ShareId {
+                secret_name: "backup".into(),
+                identity,
+                version: 1,
+            };
+            let share = SecretShare {
+                index: ShareIndex(i as u64 + 1),
+                share: share.into(),
+                provenance: None,
+            };
+            assert!(store.put_share(id, share).await.unwrap());
+        }
+        // One node has yet to be dealt a share.
+        let stores = [stores, vec![MemoryStore::default()]].concat();
+
+        let secret = reconstruct_local(&stores[1..], "backup", identity, 1, 2)
+            .await
+            .unwrap();
+        assert_eq!(*secret, secret_bytes(7));
+
+        let err = reconstruct_local(&stores[2..], "backup", identity, 1, 2)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("only 1 of the stores"), "{err}");
+        // No store holds a share of a secret by that name.
+        let err = reconstruct_local(&stores, "omni", identity, 1, 2)
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("only 0 of the stores"), "{err}");
+    }
+
+    #[test]
+    fn decrypt_encapsulated_share() {
+        let requester = p384::SecretKey::random(&mut rand::thread_rng());
+        let (pk, nonce, share) =
+            ssss::identity::encapsulate_share(&requester.public_key(), b"share").unwrap();
+        let res = ShareResponse {
+            format: ShareResponseFormat::EncAes256GcmSivEphemeral {
+                nonce,
+                pk: pk.to_sec1_bytes().into_vec(),
+            },
+            ss: WrappedSecretShare {
+                index: ShareIndex(3),
+                share,
+            },
+        };
+        // The node's ephemeral identity plays no part in an encapsulated share.
+        let ssss_ephemeral = ssss::identity::Identity::ephemeral()
+            .public_key()
+            .unwrap()
+            .to_jwk();
+
+        assert_eq!(
+            decrypt_share(res.clone(), &requester, &ssss_ephemeral).unwrap(),
+            (3, b"share".to_vec())
+        );
+        let wrong_key = p384::SecretKey::random(&mut rand::thread_rng());
+        assert!(decrypt_share(res, &wrong_key, &ssss_ephemeral).is_err());
+    }
+}
@@ -33,6 +33,15 @@ async fn main() -> Result<()> {
         .without_time()
         .init();
 
+    let proxy = args.proxy.map(s4::ProxyConfig::socks5).transpose()?;
+    let client = |ssss: &str| -> Result<SsssClient> {
+        let url = ssss.parse()?;
+        match &proxy {
+            Some(proxy) => SsssClient::with_proxy(url, proxy.clone()),
+            None => Ok(SsssClient::new(url)),
+        }
+    };
+
     match args.command {
         cli::Command::SetPolicy {
             policy_path,
@@ -97,11 +106,11 @@ async fn main() -> Result<()> {
             let ssss_identities =
                 futures_util::future::try_join_all(sssss.iter().map(|maybe_ssss_url| async {
                     Ok::<_, eyre::Error>(
-                        SsssClient::new(maybe_ssss_url.parse()?)
+                        client(maybe_ssss_url)?
                             .get_ssss_identity()
                             .await?
                             .persistent
-                            .to_public_key()?,
+                            .to_public_key::<p384::NistP384>()?,
                     )
                 }))
                 .await?;
@@ -135,11 +144,7 @@ async fn main() -> Result<()> {
                 };
                 vec![secret]
             } else {
-                let threshold = if *threshold > 1.0 {
-                    *threshold as usize
-                } else {
-                    (*threshold * (limit as f64)).ceil() as usize
-                };
+                let threshold = threshold.of(limit);
                 let secret = match secret {
                     Some(s) => p384::Scalar::from_slice(&s)?,
                     None => {
@@ -162,8 +167,10 @@ async fn main() -> Result<()> {
             my_identity.public_key();
 
             for (i, ssss_identity) in ssss_identities.into_iter().enumerate() {
-                let cipher = my_identity
-                    .derive_shared_cipher(ssss_identity, identity::DEAL_SHARES_DOMAIN_SEP);
+                let cipher = my_identity.try_derive_shared_cipher(
+                    &ssss_identity.to_sec1_bytes(),
+                    identity::DEAL_SHARES_DOMAIN_SEP,
+                )?;
                 cipher
                     .encrypt_in_place(&shares_nonce, &[], &mut shares[i])
                     .unwrap();
@@ -172,7 +179,7 @@ async fn main() -> Result<()> {
             ssss.deal_shares_sss(
                 (*identity).into(),
                 *version,
-                my_identity.public_key().to_sec1_bytes().into_vec(),
+                my_identity.peer_key().to_bytes(),
                 nonce,
                 shares.into_iter().map(Bytes::from).collect(),
             )
@@ -181,25 +188,44 @@ async fn main() -> Result<()> {
         cli::Command::Reconstruct {
             il,
             version,
+            rotating,
             sssss,
+            threshold,
             wallet,
         } => {
             let wallet = &*wallet;
-            let shares =
-                futures_util::future::try_join_all(sssss.iter().map(|url_str| async move {
-                    let url: url::Url = url_str.parse()?;
-                    SsssClient::new(url)
-                        .get_share("omni", il.into(), *version, wallet, None)
-                        .await
-                }))
-                .await?;
+            let versions = if rotating {
+                vec![*version, *version + 1]
+            } else {
+                vec![*version]
+            };
+            let requests = sssss.iter().flat_map(|url_str| {
+                versions.iter().map(move |&version| async move {
+                    let share = async {
+                        client(url_str)?
+                            .get_share("omni", il.into(), version, wallet, None)
+                            .await
+                    }
+                    .await
+                    .map(|(_, share)| share)
+                    .wrap_err_with(|| format!("failed to get v{version} share from {url_str}"));
+                    if let Err(e) = &share {
+                        // A node that has not been dealt the next version yet is expected.
+                        warn!("{e:#}");
+                    }
+                    (version, share)
+                })
+            });
+            let responses = futures_util::future::join_all(requests).await;
 
-            let secret = vsss_rs::combine_shares::<p384::Scalar, u8, Vec<u8>>(
-                &shares.into_iter().map(|s| s.1).collect::<Vec<_>>(),
-            )
-            .map_err(|_| eyre::eyre!("failed to reconstruct shares"))?;
+            let (version, secret) = s4::reconstruct_shamir_responses(
+                responses,
+                threshold.of(sssss.len()),
+                &s4::SecretSelector::Whole,
+            )?;
 
-            println!("{:x}", Bytes::from(secret.to_bytes().to_vec()))
+            info!("reconstructed v{version} of the secret");
+            println!("{:x}", Bytes::from(secret))
         }
         cli::Command::AcquireIdentity {
             ssss,
@@ -211,7 +237,7 @@ async fn main() -> Result<()> {
             permitter,
             recipient,
         } => {
-            let permit_created = SsssClient::new(ssss.parse()?)
+            let permit_created = client(&ssss)?
                 .acquire_identity(
                     il.into(),
                     &AcqRelIdentityRequest {
@@ -228,6 +254,28 @@ async fn main() -> Result<()> {
                 info!("SSSS optimistically created permit");
             }
         }
+        cli::Command::CheckReceipts { il, version, sssss } => {
+            let receipts = futures_util::future::join_all(sssss.iter().map(|url_str| async move {
+                client(url_str)?
+                    .get_share_receipt("omni", il.into(), *version)
+                    .await
+            }))
+            .await;
+            let mut confirmed = 0;
+            for (url_str, receipt) in sssss.iter().zip(receipts) {
+                match receipt {
+                    Ok(ShareReceipt { event, .. }) => {
+                        confirmed += 1;
+                        info!(
+                            "{url_str} stored the share dealt at block {} log {}",
+                            event.block, event.log_index
+                        );
+                    }
+                    Err(e) => warn!("{url_str} has no receipt: {e}"),
+                }
+            }
+            println!("{confirmed} of {} SSSSs stored the share", sssss.len());
+        }
     }
 
     Ok(())
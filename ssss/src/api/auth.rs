@@ -37,10 +37,14 @@ pub async fn permitted_requester<S: Store>(
         registry,
         id: identity,
     };
-    retry_times(|| store.read_permit(identity_locator, requester), 3)
-        .await
-        .map_err(anyhow::Error::from)?
-        .ok_or_else(|| Error::Unauthorized("no acceptable permit found".into()))?;
+    retry_times(
+        "read_permit",
+        || store.read_permit(identity_locator, requester),
+        3,
+    )
+    .await
+    .map_err(anyhow::Error::from)?
+    .ok_or_else(|| Error::Unauthorized("no acceptable permit found".into()))?;
     Ok(next.run(req).await)
 }
 
@@ -1,11 +1,16 @@
 mod auth;
+#[cfg(test)]
+mod tests;
 
 use std::{
     collections::HashMap,
     net::{Ipv4Addr, SocketAddrV4},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
 };
 
-use aes_gcm_siv::AeadInPlace as _;
 use axum::{
     extract::{Path, Query, Request, State},
     http::{header, uri::Authority, Method, StatusCode},
@@ -19,11 +24,13 @@ use ethers::{middleware::Middleware, types::Address};
 use futures_util::TryFutureExt as _;
 use p384::elliptic_curve::JwkEcKey;
 use ssss::identity::{self, Identity};
+use tokio::sync::{watch, Semaphore};
 use tower_http::cors;
 
 use crate::{
     eth::SsssHub,
-    store::Store,
+    metrics,
+    store::{Store, StoreStatistics},
     types::{api::*, *},
     utils::retry_times,
     verify,
@@ -34,8 +41,42 @@ struct AppState<M: Middleware, S> {
     store: S,
     sssss: HashMap<ChainId, SsssHub<M>>,
     host: Authority,
+    persistent_identity: Identity,
     persistent_identity_jwk: JwkEcKey,
     ephemeral_identity: Identity,
+    share_serving: ShareServing,
+    /// The statistics last counted by the store, which are too costly to count per request.
+    statistics: watch::Receiver<Option<StoreStatistics>>,
+}
+
+/// Bounds the total number of share requests in flight so that reconstruction load is shed with
+/// 503s instead of queuing and starving sync, and allows serving to be paused outright.
+#[derive(Clone)]
+struct ShareServing {
+    permits: Arc<Semaphore>,
+    limit: usize,
+    enabled: Arc<AtomicBool>,
+    /// Whether to refuse shares of identities on chains whose sync is stalled.
+    withhold_stalled: bool,
+}
+
+impl ShareServing {
+    fn new(limit: usize, withhold_stalled: bool) -> Self {
+        Self {
+            permits: Arc::new(Semaphore::new(limit)),
+            limit,
+            enabled: Arc::new(AtomicBool::new(true)),
+            withhold_stalled,
+        }
+    }
+
+    fn status(&self) -> ShareServingStatus {
+        ShareServingStatus {
+            enabled: self.enabled.load(Ordering::Relaxed),
+            in_flight: self.limit - self.permits.available_permits(),
+            limit: self.limit,
+        }
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -48,6 +89,8 @@ enum Error {
     Unauthorized(String),
     #[error("{0}")]
     Forbidden(String),
+    #[error("{0}")]
+    NotReady(String),
     #[error("internal server error")]
     Unhandled(#[from] anyhow::Error),
 }
@@ -62,6 +105,7 @@ impl IntoResponse for Error {
             Self::NotFound(_) => StatusCode::NOT_FOUND,
             Self::Unauthorized(_) => StatusCode::UNAUTHORIZED,
             Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::NotReady(_) => StatusCode::SERVICE_UNAVAILABLE,
             Self::Unhandled(_) => StatusCode::INTERNAL_SERVER_ERROR,
         };
         (
@@ -78,8 +122,39 @@ pub async fn serve<M: Middleware + Clone + 'static, S: Store>(
     store: S,
     sssss: impl Iterator<Item = SsssHub<M>>,
     host: Authority,
-    identity_jwk: JwkEcKey,
-) {
+    identity: Identity,
+    max_concurrent_shares: usize,
+    withhold_stalled_shares: bool,
+    statistics_interval: std::time::Duration,
+) -> anyhow::Result<()> {
+    let (statistics_tx, statistics) = watch::channel(None);
+    tokio::spawn({
+        let store = store.clone();
+        async move {
+            loop {
+                match store.statistics().await {
+                    Ok(stats) => {
+                        metrics::set_store_statistics(&stats);
+                        statistics_tx.send_replace(Some(stats));
+                    }
+                    Err(e) => tracing::warn!(error = ?e, "failed to count store statistics"),
+                }
+                tokio::time::sleep(statistics_interval).await;
+            }
+        }
+    });
+    let share_serving = ShareServing::new(max_concurrent_shares, withhold_stalled_shares);
+    #[cfg(unix)]
+    match toggle_share_serving_on_signal(share_serving.enabled.clone()) {
+        Ok(toggle) => {
+            tokio::spawn(toggle);
+        }
+        Err(e) => tracing::error!(error = %e, "failed to listen for share serving signals"),
+    }
+    let identity_jwk = identity
+        .public_key()
+        .ok_or_else(|| anyhow::anyhow!("the API needs a P-384 identity"))?
+        .to_jwk();
     assert!(identity_jwk.is_public_key());
     let bind_addr = SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), host.port_u16().unwrap_or(443));
     let listener = tokio::net::TcpListener::bind(bind_addr).await.unwrap();
@@ -89,21 +164,29 @@ pub async fn serve<M: Middleware + Clone + 'static, S: Store>(
             store,
             sssss: sssss.map(|ssss| (ssss.chain, ssss)).collect(),
             host,
+            persistent_identity: identity,
             persistent_identity_jwk: identity_jwk,
             ephemeral_identity: Identity::ephemeral(),
+            share_serving,
+            statistics,
         }),
     )
     .await
     .unwrap();
+    Ok(())
 }
 
 fn make_router<M: Middleware + Clone + 'static, S: Store>(state: AppState<M, S>) -> Router {
     Router::new()
         .route("/", any(root))
+        .route("/metrics", get(get_metrics))
         .nest(
             "/v1",
             Router::new()
                 .route("/identity", get(get_ssss_identity))
+                .route("/status", get(get_status))
+                .route("/statistics", get(get_statistics))
+                .route("/processed/:chain/:block", get(get_processed))
                 .nest(
                     "/permits/:chain/:registry/:identity",
                     Router::new()
@@ -125,7 +208,29 @@ fn make_router<M: Middleware + Clone + 'static, S: Store>(state: AppState<M, S>)
                             state.host.clone(),
                             auth::escrin1,
                         ))
-                        .layer(axum::middleware::from_fn(support_only_omni("share"))),
+                        .layer(axum::middleware::from_fn(support_only_omni("share")))
+                        .layer(axum::middleware::from_fn_with_state(
+                            state.share_serving.clone(),
+                            limit_share_serving,
+                        )),
+                )
+                .route(
+                    "/shares",
+                    post(get_shares)
+                        .layer(axum::middleware::from_fn_with_state(
+                            state.host.clone(),
+                            auth::escrin1,
+                        ))
+                        .layer(axum::middleware::from_fn_with_state(
+                            state.share_serving.clone(),
+                            limit_share_serving,
+                        )),
+                )
+                .route(
+                    "/receipts/:name/:chain/:registry/:identity",
+                    get(get_share_receipt).layer(axum::middleware::from_fn(support_only_omni(
+                        "share receipt",
+                    ))),
                 )
                 .nest(
                     "/keys/:name/:chain/:registry/:identity",
@@ -156,6 +261,7 @@ fn make_router<M: Middleware + Clone + 'static, S: Store>(state: AppState<M, S>)
                     SignatureHeader::name().clone(),
                     RequesterHeader::name().clone(),
                     RequesterPublicKeyHeader::name().clone(),
+                    ShareEncapsulationHeader::name().clone(),
                 ]),
         )
 }
@@ -178,10 +284,52 @@ fn support_only_omni(
     }
 }
 
+/// Refuses the request rather than waiting if serving is paused or already at capacity.
+async fn limit_share_serving(
+    State(serving): State<ShareServing>,
+    req: Request,
+    next: Next,
+) -> Result<Response, Error> {
+    if !serving.enabled.load(Ordering::Relaxed) {
+        return Err(Error::NotReady("share serving is paused".into()));
+    }
+    let Ok(_permit) = serving.permits.try_acquire() else {
+        return Err(Error::NotReady(
+            "too many shares are being served. try again later".into(),
+        ));
+    };
+    Ok(next.run(req).await)
+}
+
+/// Pauses share serving on SIGUSR1 and resumes it on SIGUSR2, e.g. around maintenance.
+/// The signals are handled from when this returns, and toggle serving while the future runs.
+#[cfg(unix)]
+fn toggle_share_serving_on_signal(
+    enabled: Arc<AtomicBool>,
+) -> std::io::Result<impl std::future::Future<Output = ()>> {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut pause = signal(SignalKind::user_defined1())?;
+    let mut resume = signal(SignalKind::user_defined2())?;
+    Ok(async move {
+        loop {
+            let enable = tokio::select! {
+                _ = pause.recv() => false,
+                _ = resume.recv() => true,
+            };
+            enabled.store(enable, Ordering::Relaxed);
+            tracing::info!(enabled = enable, "share serving toggled");
+        }
+    })
+}
+
 async fn root() -> StatusCode {
     StatusCode::NO_CONTENT
 }
 
+async fn get_metrics() -> String {
+    metrics::encode()
+}
+
 async fn get_ssss_identity<M: Middleware + 'static, S: Store>(
     State(AppState {
         persistent_identity_jwk,
@@ -191,10 +339,74 @@ async fn get_ssss_identity<M: Middleware + 'static, S: Store>(
 ) -> Json<IdentityResponse> {
     Json(IdentityResponse {
         persistent: persistent_identity_jwk,
-        ephemeral: ephemeral_identity.public_key().to_jwk(),
+        ephemeral: ephemeral_identity
+            .public_key()
+            .expect("ephemeral identities are P-384")
+            .to_jwk(),
+    })
+}
+
+async fn get_status<M: Middleware + 'static, S: Store>(
+    State(AppState {
+        sssss,
+        share_serving,
+        ..
+    }): State<AppState<M, S>>,
+) -> Json<StatusResponse> {
+    Json(StatusResponse {
+        chains: sssss
+            .iter()
+            .map(|(chain, ssss)| {
+                let health = if ssss.is_stalled() {
+                    ChainHealth::Stalled
+                } else if ssss.is_rate_limited() {
+                    ChainHealth::RateLimited
+                } else {
+                    ChainHealth::Healthy
+                };
+                (
+                    *chain,
+                    ChainStatus {
+                        health,
+                        processed_block: ssss.processed_block(),
+                        head_block: ssss.last_known_head(),
+                        progress: ssss.sync_progress(),
+                        sync_error: ssss.sync_error(),
+                        shares: ssss.tracked_shares(),
+                    },
+                )
+            })
+            .collect(),
+        share_serving: share_serving.status(),
     })
 }
 
+async fn get_statistics<M: Middleware + 'static, S: Store>(
+    State(AppState { statistics, .. }): State<AppState<M, S>>,
+) -> Result<Json<StoreStatistics>, Error> {
+    let stats = *statistics.borrow();
+    stats
+        .map(Json)
+        .ok_or_else(|| Error::NotReady("store statistics have not been counted yet".into()))
+}
+
+async fn get_processed<M: Middleware + 'static, S: Store>(
+    Path((chain, block)): Path<(ChainId, u64)>,
+    State(AppState { store, sssss, .. }): State<AppState<M, S>>,
+) -> Result<Json<ProcessedResponse>, Error> {
+    if !sssss.contains_key(&chain) {
+        return Err(Error::BadRequest(format!("unsupported chain: {chain}")));
+    }
+    let processed = retry_times(
+        "is_processed_through",
+        || store.is_processed_through(chain, block),
+        3,
+    )
+    .await
+    .map_err(anyhow::Error::from)?;
+    Ok(Json(ProcessedResponse { processed }))
+}
+
 async fn acqrel_identity<M: Middleware + 'static, S: Store>(
     method: Method,
     Path((chain, registry, identity)): Path<(ChainId, Address, IdentityId)>,
@@ -213,12 +425,14 @@ async fn acqrel_identity<M: Middleware + 'static, S: Store>(
         .ok_or_else(|| Error::BadRequest(format!("unsupported chain: {chain}")))?;
 
     let policy_bytes = retry_times(
+        "get_verifier",
         || store.get_verifier(PermitterLocator::new(chain, permitter), identity),
         3,
     )
     .await
     .map_err(anyhow::Error::from)?
-    .ok_or_else(|| Error::NotFound("policy".into()))?;
+    .ok_or_else(|| Error::NotFound("policy".into()))?
+    .config;
 
     let identity_locator = IdentityLocator {
         chain,
@@ -254,6 +468,7 @@ async fn acqrel_identity<M: Middleware + 'static, S: Store>(
                 .expiry
                 .ok_or_else(|| Error::Unauthorized("verification failed".into()))?;
             retry_times(
+                "create_permit",
                 || {
                     store.create_permit(
                         identity_locator,
@@ -276,9 +491,13 @@ async fn acqrel_identity<M: Middleware + 'static, S: Store>(
             Ok(StatusCode::CREATED)
         }
         Method::DELETE => {
-            retry_times(|| store.delete_permit(identity_locator, recipient), 3)
-                .await
-                .map_err(anyhow::Error::from)?;
+            retry_times(
+                "delete_permit",
+                || store.delete_permit(identity_locator, recipient),
+                3,
+            )
+            .await
+            .map_err(anyhow::Error::from)?;
             Ok(StatusCode::NO_CONTENT)
         }
         _ => unreachable!(),
@@ -287,23 +506,160 @@ async fn acqrel_identity<M: Middleware + 'static, S: Store>(
 
 async fn get_share<M: Middleware, S: Store>(
     Path((_name, chain, registry, identity)): Path<(String, ChainId, Address, IdentityId)>,
-    Query(GetShareQuery { version }): Query<GetShareQuery>,
+    Query(GetShareQuery {
+        version,
+        min_synced_block,
+    }): Query<GetShareQuery>,
     requester_pk: Option<TypedHeader<RequesterPublicKeyHeader>>,
-    State(AppState {
+    encapsulation: Option<TypedHeader<ShareEncapsulationHeader>>,
+    State(state): State<AppState<M, S>>,
+) -> Result<Json<ShareResponse>, Error> {
+    if let Some(min_synced_block) = min_synced_block {
+        let synced_block = state.sssss.get(&chain).map(|ssss| ssss.processed_block());
+        if synced_block.unwrap_or_default() < min_synced_block {
+            return Err(Error::NotReady(format!(
+                "not yet synced to block {min_synced_block} of chain {chain}"
+            )));
+        }
+    }
+    let identity = IdentityLocator {
+        chain,
+        registry,
+        id: identity,
+    };
+    let encryption = ShareEncryption::new(requester_pk, encapsulation);
+    Ok(Json(
+        find_share(&state, identity, version, encryption).await?,
+    ))
+}
+
+/// The most shares that can be requested at once.
+const MAX_BATCH_SHARES: usize = 64;
+
+/// Serves several shares to one requester in a single round trip. Each share is checked against
+/// the requester's permit for its identity on its own and gets its own outcome.
+async fn get_shares<M: Middleware, S: Store>(
+    requester_pk: Option<TypedHeader<RequesterPublicKeyHeader>>,
+    encapsulation: Option<TypedHeader<ShareEncapsulationHeader>>,
+    TypedHeader(RequesterHeader(requester)): TypedHeader<RequesterHeader>,
+    State(state): State<AppState<M, S>>,
+    Json(GetSharesRequest { shares }): Json<GetSharesRequest>,
+) -> Result<Json<GetSharesResponse>, Error> {
+    if shares.len() > MAX_BATCH_SHARES {
+        return Err(Error::BadRequest(format!(
+            "at most {MAX_BATCH_SHARES} shares may be requested at once"
+        )));
+    }
+    let encryption = ShareEncryption::new(requester_pk, encapsulation);
+    let results = futures_util::future::try_join_all(shares.into_iter().map(
+        |ShareRequestItem {
+             name,
+             identity,
+             version,
+         }| {
+            let state = &state;
+            async move {
+                if name != "omni" {
+                    return Ok(ShareResult::NotHeld);
+                }
+                let permit = retry_times(
+                    "read_permit",
+                    || state.store.read_permit(identity, requester),
+                    3,
+                )
+                .await
+                .map_err(anyhow::Error::from)?;
+                if permit.is_none() {
+                    return Ok(ShareResult::Denied {
+                        reason: "no acceptable permit found".into(),
+                    });
+                }
+                match find_share(state, identity, version, encryption).await {
+                    Ok(share) => Ok(ShareResult::Served(share)),
+                    Err(Error::NotFound(_)) => Ok(ShareResult::NotHeld),
+                    Err(e @ (Error::Forbidden(_) | Error::NotReady(_))) => {
+                        Ok(ShareResult::Denied {
+                            reason: e.to_string(),
+                        })
+                    }
+                    Err(e) => Err(e),
+                }
+            }
+        },
+    ))
+    .await?;
+    Ok(Json(GetSharesResponse { results }))
+}
+
+/// How a served share is protected in transit.
+#[derive(Clone, Copy)]
+enum ShareEncryption {
+    Plain,
+    /// Under a key agreed between the requester's key and the node's ephemeral identity.
+    ToRequester(p384::PublicKey),
+    /// Under a key agreed between the requester's key and a key pair used for one response.
+    Encapsulated(p384::PublicKey),
+}
+
+impl ShareEncryption {
+    fn new(
+        requester_pk: Option<TypedHeader<RequesterPublicKeyHeader>>,
+        encapsulation: Option<TypedHeader<ShareEncapsulationHeader>>,
+    ) -> Self {
+        match (requester_pk, encapsulation) {
+            (Some(pk), Some(_)) => Self::Encapsulated(*pk.0),
+            (Some(pk), None) => Self::ToRequester(*pk.0),
+            (None, _) => Self::Plain,
+        }
+    }
+}
+
+/// Looks up a share that the requester is permitted to get and encrypts it as asked.
+async fn find_share<M: Middleware, S: Store>(
+    AppState {
         store,
+        sssss,
         ephemeral_identity,
+        share_serving,
         ..
-    }): State<AppState<M, S>>,
-) -> Result<Json<ShareResponse>, Error> {
-    let SecretShare { index, share } = retry_times(
+    }: &AppState<M, S>,
+    identity: IdentityLocator,
+    version: u64,
+    encryption: ShareEncryption,
+) -> Result<ShareResponse, Error> {
+    if let Some(ssss) = sssss.get(&identity.chain) {
+        if share_serving.withhold_stalled && ssss.is_stalled() {
+            return Err(Error::NotReady(format!(
+                "sync of chain {} is stalled",
+                identity.chain
+            )));
+        }
+        let policy = retry_times(
+            "get_verifier",
+            || {
+                store.get_verifier(
+                    PermitterLocator::new(identity.chain, ssss.address),
+                    identity.id,
+                )
+            },
+            3,
+        )
+        .await
+        .map_err(anyhow::Error::from)?;
+        if policy.is_some_and(|record| record.config == BROKEN_POLICY) {
+            return Err(Error::Forbidden(
+                "the identity's policy is broken. no shares will be served until it is replaced"
+                    .into(),
+            ));
+        }
+    }
+
+    let SecretShare { index, share, .. } = retry_times(
+        "get_share",
         || {
             store.get_share(ShareId {
                 secret_name: "omni".into(),
-                identity: IdentityLocator {
-                    chain,
-                    registry,
-                    id: identity,
-                },
+                identity,
                 version,
             })
         },
@@ -313,29 +669,78 @@ async fn get_share<M: Middleware, S: Store>(
     .await?
     .ok_or_else(|| Error::NotFound("share".into()))?;
 
-    let (format, share) = match requester_pk {
-        Some(pk) => {
-            let cipher =
-                ephemeral_identity.derive_shared_cipher(*pk.0, identity::GET_SHARE_DOMAIN_SEP);
-            let mut nonce = aes_gcm_siv::Nonce::default();
-            rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut nonce);
-            let mut enc_share = (*share).clone();
-            cipher
-                .encrypt_in_place(&nonce, &[], &mut enc_share)
-                .map_err(|e| Error::Unhandled(anyhow::anyhow!("encryption error: {e}")))?;
+    let (format, share) = match encryption {
+        ShareEncryption::Plain => (ShareResponseFormat::Plain, (*share).clone()),
+        ShareEncryption::ToRequester(pk) => {
+            let (nonce, enc_share) =
+                identity::encrypt_share(ephemeral_identity, &pk, &share).map_err(share_error)?;
+            (ShareResponseFormat::EncAes256GcmSiv { nonce }, enc_share)
+        }
+        ShareEncryption::Encapsulated(pk) => {
+            let (sender_pk, nonce, enc_share) =
+                identity::encapsulate_share(&pk, &share).map_err(share_error)?;
             (
-                ShareResponseFormat::EncAes256GcmSiv {
-                    nonce: nonce.into(),
+                ShareResponseFormat::EncAes256GcmSivEphemeral {
+                    nonce,
+                    pk: sender_pk.to_sec1_bytes().into_vec(),
                 },
                 enc_share,
             )
         }
-        None => (ShareResponseFormat::Plain, (*share).clone()),
     };
 
-    Ok(Json(ShareResponse {
+    Ok(ShareResponse {
         format,
         ss: WrappedSecretShare { index, share },
+    })
+}
+
+fn share_error(e: identity::Error) -> Error {
+    match e {
+        identity::Error::ShareEncryption => Error::Unhandled(e.into()),
+        e => Error::BadRequest(format!("requester public key: {e}")),
+    }
+}
+
+/// Receipts hold no secret material, so anyone may ask for one.
+async fn get_share_receipt<M: Middleware, S: Store>(
+    Path((name, chain, registry, identity)): Path<(String, ChainId, Address, IdentityId)>,
+    Query(GetShareReceiptQuery { version }): Query<GetShareReceiptQuery>,
+    State(AppState {
+        store,
+        persistent_identity,
+        ..
+    }): State<AppState<M, S>>,
+) -> Result<Json<ShareReceiptResponse>, Error> {
+    let share_id = ShareId {
+        secret_name: name,
+        identity: IdentityLocator {
+            chain,
+            registry,
+            id: identity,
+        },
+        version,
+    };
+    let SecretShare { provenance, .. } =
+        retry_times("get_share", || store.get_share(share_id.clone()), 3)
+            .map_err(anyhow::Error::from)
+            .await?
+            .ok_or_else(|| Error::NotFound("share".into()))?;
+    // Shares stored before provenance was recorded cannot name the event that dealt them.
+    let Some(ShareProvenance { event, .. }) = provenance else {
+        return Err(Error::NotFound("share receipt".into()));
+    };
+
+    let receipt = ShareReceipt {
+        share: share_id,
+        event,
+    };
+    let signature = persistent_identity
+        .sign(&receipt.signing_bytes())
+        .ok_or_else(|| anyhow::anyhow!("the persistent identity cannot sign receipts"))?;
+    Ok(Json(ShareReceiptResponse {
+        receipt,
+        signature: signature.to_bytes().to_vec(),
     }))
 }
 
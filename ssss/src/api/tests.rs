@@ -0,0 +1,384 @@
+use std::time::Duration;
+
+use axum::body::Body;
+use ethers::{
+    providers::{MockProvider, Provider},
+    signers::{LocalWallet, Signer as _},
+    types::{transaction::eip712::Eip712 as _, H256},
+    utils::keccak256,
+};
+use tower::ServiceExt as _;
+
+use super::*;
+use crate::store::memory::MemoryStore;
+
+const HOST: &str = "ssss.example.com";
+
+fn app_state(store: MemoryStore) -> AppState<Provider<MockProvider>, MemoryStore> {
+    let persistent_identity = Identity::ephemeral();
+    AppState {
+        store,
+        sssss: Default::default(),
+        host: Authority::from_static(HOST),
+        persistent_identity_jwk: persistent_identity.public_key().unwrap().to_jwk(),
+        persistent_identity,
+        ephemeral_identity: Identity::ephemeral(),
+        share_serving: ShareServing::new(4, false),
+        statistics: watch::channel(None).1,
+    }
+}
+
+fn identity(byte: u8) -> IdentityLocator {
+    IdentityLocator {
+        chain: 31337,
+        registry: Address::repeat_byte(1),
+        id: IdentityId(H256::repeat_byte(byte)),
+    }
+}
+
+async fn put_share(store: &MemoryStore, identity: IdentityLocator, index: u64) {
+    let share_id = ShareId {
+        secret_name: "omni".into(),
+        identity,
+        version: 1,
+    };
+    let share = SecretShare {
+        index: ShareIndex(index),
+        share: vec![index as u8; 32].into(),
+        provenance: None,
+    };
+    assert!(store.put_share(share_id, share).await.unwrap());
+}
+
+async fn permit(store: &MemoryStore, identity: IdentityLocator, requester: Address) {
+    store
+        .create_permit(identity, requester, u64::MAX, H256::random().0.to_vec())
+        .await
+        .unwrap()
+        .unwrap();
+}
+
+/// Builds a request signed by `signer` as the escrin1 scheme expects.
+fn signed_request(
+    method: Method,
+    path_and_query: &str,
+    body: Vec<u8>,
+    signer: &LocalWallet,
+) -> Request {
+    let req721 = SsssRequest {
+        method: method.to_string(),
+        host: HOST.into(),
+        path_and_query: path_and_query.into(),
+        body: match method {
+            Method::GET | Method::DELETE => Default::default(),
+            _ => keccak256(&body).into(),
+        },
+    };
+    let sig = signer
+        .sign_hash(req721.encode_eip712().unwrap().into())
+        .unwrap();
+    Request::builder()
+        .method(method)
+        .uri(path_and_query)
+        .header(header::HOST, HOST)
+        .header(header::CONTENT_TYPE, "application/json")
+        .header(SignatureHeader::name(), SignatureHeader(sig).to_string())
+        .header(
+            RequesterHeader::name(),
+            RequesterHeader(signer.address()).to_string(),
+        )
+        .body(Body::from(body))
+        .unwrap()
+}
+
+async fn send(state: &AppState<Provider<MockProvider>, MemoryStore>, req: Request) -> Response {
+    make_router(state.clone()).oneshot(req).await.unwrap()
+}
+
+async fn json<T: serde::de::DeserializeOwned>(res: Response) -> T {
+    let body = axum::body::to_bytes(res.into_body(), usize::MAX)
+        .await
+        .unwrap();
+    serde_json::from_slice(&body).unwrap()
+}
+
+#[tokio::test]
+async fn get_shares_reports_each_outcome() {
+    let store = MemoryStore::default();
+    let requester = LocalWallet::new(&mut rand::thread_rng());
+    let (held, missing, unpermitted) = (identity(1), identity(2), identity(3));
+    put_share(&store, held, 1).await;
+    put_share(&store, unpermitted, 3).await;
+    permit(&store, held, requester.address()).await;
+    permit(&store, missing, requester.address()).await;
+
+    let body = serde_json::to_vec(&GetSharesRequest {
+        shares: [held, missing, unpermitted]
+            .into_iter()
+            .map(|identity| ShareRequestItem {
+                name: "omni".into(),
+                identity,
+                version: 1,
+            })
+            .collect(),
+    })
+    .unwrap();
+    let res = send(
+        &app_state(store),
+        signed_request(Method::POST, "/v1/shares", body, &requester),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let GetSharesResponse { results } = json(res).await;
+
+    let [served, not_held, denied] = &results[..] else {
+        panic!("unexpected results: {results:?}");
+    };
+    let ShareResult::Served(ShareResponse {
+        format: ShareResponseFormat::Plain,
+        ss,
+    }) = served
+    else {
+        panic!("unexpected result for the held share: {served:?}");
+    };
+    assert_eq!((ss.index, &ss.share), (ShareIndex(1), &vec![1; 32]));
+    assert!(matches!(not_held, ShareResult::NotHeld), "{not_held:?}");
+    assert!(matches!(denied, ShareResult::Denied { .. }), "{denied:?}");
+}
+
+#[tokio::test]
+async fn get_shares_rejects_forged_signature() {
+    let store = MemoryStore::default();
+    let requester = LocalWallet::new(&mut rand::thread_rng());
+    put_share(&store, identity(1), 1).await;
+    permit(&store, identity(1), requester.address()).await;
+
+    let body = serde_json::to_vec(&GetSharesRequest {
+        shares: vec![ShareRequestItem {
+            name: "omni".into(),
+            identity: identity(1),
+            version: 1,
+        }],
+    })
+    .unwrap();
+    let mut req = signed_request(
+        Method::POST,
+        "/v1/shares",
+        body,
+        &LocalWallet::new(&mut rand::thread_rng()),
+    );
+    // Claim to be the permitted requester without holding its key.
+    req.headers_mut().insert(
+        RequesterHeader::name(),
+        RequesterHeader(requester.address())
+            .to_string()
+            .parse()
+            .unwrap(),
+    );
+    let res = send(&app_state(store), req).await;
+    assert!(!res.status().is_success(), "{}", res.status());
+}
+
+#[cfg(unix)]
+#[tokio::test]
+async fn share_serving_pauses_and_resumes_on_signal() {
+    let store = MemoryStore::default();
+    let requester = LocalWallet::new(&mut rand::thread_rng());
+    put_share(&store, identity(1), 1).await;
+    permit(&store, identity(1), requester.address()).await;
+    let state = app_state(store);
+    tokio::spawn(toggle_share_serving_on_signal(state.share_serving.enabled.clone()).unwrap());
+
+    let path = format!(
+        "/v1/shares/omni/31337/{:x}/{:x}?version=1",
+        identity(1).registry,
+        identity(1).id.0
+    );
+    let get_share = || signed_request(Method::GET, &path, vec![], &requester);
+    let toggle = |signal: &str, enabled: bool| {
+        let status = std::process::Command::new("kill")
+            .args([signal, &std::process::id().to_string()])
+            .status()
+            .unwrap();
+        assert!(status.success());
+        let serving = state.share_serving.enabled.clone();
+        async move {
+            tokio::time::timeout(Duration::from_secs(10), async {
+                while serving.load(Ordering::Relaxed) != enabled {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await
+            .expect("the signal did not toggle share serving");
+        }
+    };
+
+    assert_eq!(send(&state, get_share()).await.status(), StatusCode::OK);
+    toggle("-USR1", false).await;
+    let res = send(&state, get_share()).await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let ErrorResponse { error } = json(res).await;
+    assert_eq!(error, "share serving is paused");
+    toggle("-USR2", true).await;
+    assert_eq!(send(&state, get_share()).await.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn get_share_waits_for_min_synced_block() {
+    let store = MemoryStore::default();
+    let requester = LocalWallet::new(&mut rand::thread_rng());
+    put_share(&store, identity(1), 1).await;
+    permit(&store, identity(1), requester.address()).await;
+    let mut state = app_state(store);
+    let (provider, _mock) = Provider::mocked();
+    let ssss = SsssHub::new(31337, Address::repeat_byte(1), provider);
+    ssss.set_processed_block(10);
+    state.sssss.insert(31337, ssss);
+
+    let get_share = |min_synced_block: u64| {
+        let path = format!(
+            "/v1/shares/omni/31337/{:x}/{:x}?version=1&min_synced_block={min_synced_block}",
+            identity(1).registry,
+            identity(1).id.0
+        );
+        signed_request(Method::GET, &path, vec![], &requester)
+    };
+    assert_eq!(send(&state, get_share(10)).await.status(), StatusCode::OK);
+    let res = send(&state, get_share(11)).await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let ErrorResponse { error } = json(res).await;
+    assert_eq!(error, "not yet synced to block 11 of chain 31337");
+}
+
+#[tokio::test]
+async fn get_share_receipt_signed_by_persistent_identity() {
+    let store = MemoryStore::default();
+    let share_id = ShareId {
+        secret_name: "omni".into(),
+        identity: identity(1),
+        version: 1,
+    };
+    let event = EventIndex {
+        block: 7,
+        log_index: 2,
+    };
+    let share = SecretShare {
+        index: ShareIndex(1),
+        share: vec![1; 32].into(),
+        provenance: Some(ShareProvenance {
+            event,
+            tx: None,
+            dealer_pk: vec![],
+            stored_at: 0,
+        }),
+    };
+    assert!(store.put_share(share_id.clone(), share).await.unwrap());
+    // Shares stored before provenance was recorded have no receipt.
+    put_share(&store, identity(2), 1).await;
+    let state = app_state(store);
+    let get_receipt = |identity: IdentityLocator| {
+        let path = format!(
+            "/v1/receipts/omni/31337/{:x}/{:x}?version=1",
+            identity.registry, identity.id.0
+        );
+        Request::builder()
+            .uri(path)
+            .header(header::HOST, HOST)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let res = send(&state, get_receipt(identity(1))).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    let ShareReceiptResponse { receipt, signature } = json(res).await;
+    assert_eq!((&receipt.share, receipt.event), (&share_id, event));
+    let identity_req = Request::builder()
+        .uri("/v1/identity")
+        .header(header::HOST, HOST)
+        .body(Body::empty())
+        .unwrap();
+    let IdentityResponse { persistent, .. } = json(send(&state, identity_req).await).await;
+    let signer = p384::PublicKey::from_jwk(&persistent).unwrap();
+    let signature = p384::ecdsa::Signature::from_slice(&signature).unwrap();
+    assert!(receipt.verify(&signer, &signature));
+
+    for (identity, missing) in [(identity(2), "share receipt"), (identity(3), "share")] {
+        let res = send(&state, get_receipt(identity)).await;
+        assert_eq!(res.status(), StatusCode::NOT_FOUND);
+        let ErrorResponse { error } = json(res).await;
+        assert_eq!(error, format!("unable to find the requested {missing}"));
+    }
+}
+
+#[tokio::test]
+async fn get_share_refuses_broken_policy() {
+    let store = MemoryStore::default();
+    let requester = LocalWallet::new(&mut rand::thread_rng());
+    put_share(&store, identity(1), 1).await;
+    permit(&store, identity(1), requester.address()).await;
+    let permitter = PermitterLocator::new(31337, Address::repeat_byte(1));
+    let version = EventIndex {
+        block: 1,
+        log_index: 0,
+    };
+    assert!(store
+        .put_verifier_if_absent(permitter, identity(1).id, BROKEN_POLICY.to_vec(), version)
+        .await
+        .unwrap());
+    let mut state = app_state(store);
+    let (provider, _mock) = Provider::mocked();
+    let ssss = SsssHub::new(31337, Address::repeat_byte(1), provider);
+    state.sssss.insert(31337, ssss);
+
+    let path = format!(
+        "/v1/shares/omni/31337/{:x}/{:x}?version=1",
+        identity(1).registry,
+        identity(1).id.0
+    );
+    let res = send(
+        &state,
+        signed_request(Method::GET, &path, vec![], &requester),
+    )
+    .await;
+    assert_eq!(res.status(), StatusCode::FORBIDDEN);
+    let ErrorResponse { error } = json(res).await;
+    assert_eq!(
+        error,
+        "the identity's policy is broken. no shares will be served until it is replaced"
+    );
+}
+
+#[tokio::test]
+async fn get_statistics_once_counted() {
+    let store = MemoryStore::default();
+    put_share(&store, identity(1), 1).await;
+    put_share(&store, identity(2), 2).await;
+    let (statistics_tx, statistics) = watch::channel(None);
+    let state = AppState {
+        statistics,
+        ..app_state(store.clone())
+    };
+    let get_statistics = || {
+        Request::builder()
+            .uri("/v1/statistics")
+            .header(header::HOST, HOST)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let res = send(&state, get_statistics()).await;
+    assert_eq!(res.status(), StatusCode::SERVICE_UNAVAILABLE);
+    let ErrorResponse { error } = json(res).await;
+    assert_eq!(error, "store statistics have not been counted yet");
+
+    statistics_tx.send_replace(Some(store.statistics().await.unwrap()));
+    let res = send(&state, get_statistics()).await;
+    assert_eq!(res.status(), StatusCode::OK);
+    assert_eq!(
+        json::<StoreStatistics>(res).await,
+        StoreStatistics {
+            total_shares: 2,
+            ..Default::default()
+        }
+    );
+}
@@ -1,11 +1,11 @@
 use clap::{
     builder::TypedValueParser,
     ArgAction::{Append, Count},
-    Parser, ValueHint,
+    Parser, ValueEnum as _, ValueHint,
 };
-use ethers::types::Address;
+use ethers::types::{Address, TxHash};
 
-use crate::types::ChainId;
+use crate::types::{ChainId, EventIndex, IdentityId, IdentityLocator, ShareId};
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
@@ -13,6 +13,10 @@ pub struct Args {
     #[arg(short, long, action = Count, default_value_t = 0)]
     pub verbosity: u8,
 
+    /// The OTLP (gRPC) collector endpoint to which trace spans are exported, if any.
+    #[arg(long, value_hint = ValueHint::Url)]
+    pub otlp_endpoint: Option<String>,
+
     /// The domain at which this SSSS's API is expected to be served.
     #[arg(long, default_value = "127.0.0.1:1075")]
     pub host: axum::http::uri::Authority,
@@ -30,8 +34,150 @@ pub struct Args {
     ])]
     pub permitter: Vec<(ChainId, Address)>,
 
+    /// The block in which the permitter of a chain was created, to sync from without asking the
+    /// permitter. It is only used when the chain has no saved sync state.
+    #[arg(long, value_parser = creation_block_parser(), action = Append, value_name = "CHAIN=BLOCK")]
+    pub creation_block: Vec<(ChainId, u64)>,
+
+    /// The identity registry per chain, for deployments whose permitter does not report the
+    /// registry that identities belong to. Chains without one ask their permitter.
+    #[arg(long, value_parser = registry_parser(), action = Append, value_name = "CHAIN=ADDRESS")]
+    pub registry: Vec<(ChainId, Address)>,
+
+    /// A chain whose events are handled only once the provider reports their blocks as finalized,
+    /// rather than after `--tail-confirmations`. The provider must support the `finalized` tag.
+    #[arg(long, action = Append, value_name = "CHAIN")]
+    pub finalized_chain: Vec<ChainId>,
+
+    /// The rule that decides which blocks are final per chain, rather than
+    /// `--tail-confirmations`: `instant`, `confirmations:<n>`, or `checkpoint:<contract_address>`.
+    #[arg(long, value_parser = finality_parser(), action = Append, value_name = "CHAIN=RULE")]
+    pub finality: Vec<(ChainId, crate::eth::FinalityRule)>,
+
+    /// The kinds of permitter log to request per chain, for nodes that need only some of them.
+    /// Chains without any request every kind.
+    #[arg(long, value_parser = log_kinds_parser(), action = Append, value_name = "CHAIN=KIND[,KIND]")]
+    pub log_kinds: Vec<(ChainId, Vec<crate::eth::LogKind>)>,
+
+    /// Log the decoded call behind each permitter event at trace level, with shares redacted.
+    #[arg(long)]
+    pub log_calls: bool,
+
     #[arg(short, long, value_enum, default_value = "dev")]
     pub env: crate::store::Environment,
+
+    /// How to treat policies that cannot be decompressed or decoded.
+    #[arg(long, value_enum, default_value = "skip-and-warn")]
+    pub policy_fail_mode: crate::sync::PolicyFailMode,
+
+    /// How to treat dealt shares that decrypt to an unexpected length. Empty shares are always
+    /// skipped.
+    #[arg(long, value_enum, default_value = "skip-and-warn")]
+    pub malformed_share_mode: crate::sync::MalformedShareMode,
+
+    /// The most versions of each secret's share to keep, at least one. Older versions are evicted
+    /// only when a new one is stored, so a secret that gets no further versions keeps the ones it
+    /// has. The local store does not support this.
+    #[arg(long)]
+    pub max_share_versions: Option<std::num::NonZeroU64>,
+
+    /// How long, in seconds, to keep a share version beyond `--max-share-versions` after the next
+    /// version was stored, so that reconstructions of it can finish.
+    #[arg(long, default_value_t = 3600, requires = "max_share_versions")]
+    pub share_eviction_grace_secs: u64,
+
+    /// How often, in seconds, to checkpoint the store's write-ahead log, if it has one.
+    #[arg(long, default_value_t = 600, value_parser = clap::value_parser!(u64).range(1..))]
+    pub checkpoint_interval_secs: u64,
+
+    /// How many blocks behind head a chain must be to backfill it with concurrent block fetches.
+    #[arg(long, default_value_t = 128)]
+    pub backfill_threshold: u64,
+
+    /// How many blocks to fetch concurrently while backfilling.
+    #[arg(long, default_value_t = 8, value_parser = clap::value_parser!(u64).range(1..))]
+    pub backfill_concurrency: u64,
+
+    /// How many blocks must follow a block before it is processed once backfilling is done.
+    #[arg(long, default_value_t = 0)]
+    pub tail_confirmations: u64,
+
+    /// Skip blocks more than this many behind head when sync starts, such as to avoid replaying
+    /// years of events after a bad resume point. Deals in skipped blocks are never stored, and
+    /// policy changes in them are never applied, leaving the policies before them in force.
+    #[arg(long)]
+    pub max_event_age_blocks: Option<u64>,
+
+    /// Hold each policy change back this many blocks and apply only the latest of those made to
+    /// an identity in that time, such as the last step of a multi-step update.
+    #[arg(long, default_value_t = 0)]
+    pub policy_debounce_blocks: u64,
+
+    /// How many seconds to wait before restarting a chain's sync after it fails. The wait doubles
+    /// with each failure that follows a run of under ten minutes.
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    pub restart_backoff_secs: u64,
+
+    /// The most seconds to wait before restarting a chain's sync.
+    #[arg(long, default_value_t = 300, value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_restart_backoff_secs: u64,
+
+    /// Mark a chain stalled when its sync goes this many seconds without processing a block while
+    /// the chain head moves past it. Stalled chains are reported by the status API.
+    #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+    pub stall_deadline_secs: Option<u64>,
+
+    /// Refuse to serve shares of identities on stalled chains. Requires `--stall-deadline-secs`.
+    #[arg(long, requires = "stall_deadline_secs")]
+    pub withhold_stalled_shares: bool,
+
+    /// How often, in seconds, to count the store's records for the statistics API and metrics.
+    #[arg(long, default_value_t = 300, value_parser = clap::value_parser!(u64).range(1..))]
+    pub statistics_interval_secs: u64,
+
+    /// How many share requests may be served at once. Requests beyond this are refused with 503.
+    #[arg(long, default_value_t = 64, value_parser = clap::value_parser!(u64).range(1..))]
+    pub max_concurrent_share_requests: u64,
+
+    /// The number of retries that may happen in a burst across all chains and requests.
+    #[arg(long, default_value_t = 100)]
+    pub retry_burst: u32,
+
+    /// The sustained number of retries per second allowed across all chains and requests.
+    #[arg(long, default_value_t = 20.0, value_parser = retry_rate_parser())]
+    pub retry_rate: f64,
+
+    /// Load the identity key, hex encoded, from `file:<path>` or `env:<var>` instead of the store.
+    #[arg(long, value_name = "SOURCE")]
+    pub identity_key: Option<ssss::secrets::SecretSource>,
+
+    /// Also try to decrypt dealt shares with a second identity, without storing what it decrypts.
+    /// Used to check that dealers target a new identity before migrating to it.
+    #[arg(long)]
+    pub shadow_identity: bool,
+
+    /// Report what handling each event emitted by this transaction would do, then exit.
+    /// Nothing is written to the store.
+    #[arg(long)]
+    pub reprocess_tx: Option<TxHash>,
+
+    /// Check that a stored share is what its deal decrypts to, fetching the deal from the chain
+    /// again, then exit.
+    #[arg(
+        long,
+        value_parser = share_id_parser(),
+        value_name = "NAME:CHAIN:REGISTRY:IDENTITY:VERSION"
+    )]
+    pub verify_share: Option<ShareId>,
+
+    /// Make the next sync of a chain start from the given event, then exit.
+    #[arg(long, value_parser = resume_point_parser(), value_name = "CHAIN:BLOCK[:LOG_INDEX]")]
+    pub resume_from: Option<(ChainId, EventIndex)>,
+
+    /// Delete a chain's verifiers and sync progress, so that it syncs from the start if it is
+    /// configured again, then exit.
+    #[arg(long, value_name = "CHAIN")]
+    pub decommission_chain: Option<ChainId>,
 }
 
 impl Args {
@@ -40,6 +186,107 @@ impl Args {
     }
 }
 
+fn resume_point_parser() -> impl TypedValueParser {
+    clap::builder::StringValueParser::default().try_map(|v| {
+        let err = "resume point must have format <chain_id>:<block>[:<log_index>]";
+        let mut parts = v.split(':').map(|p| p.parse::<u64>().map_err(|_| err));
+        let (Some(chain), Some(block), log_index, None) =
+            (parts.next(), parts.next(), parts.next(), parts.next())
+        else {
+            return Err(err);
+        };
+        Ok((
+            chain?,
+            EventIndex {
+                block: block?,
+                log_index: log_index.transpose()?.unwrap_or_default(),
+            },
+        ))
+    })
+}
+
+fn share_id_parser() -> impl TypedValueParser {
+    clap::builder::StringValueParser::default().try_map(|v| {
+        let err = "share must have format <name>:<chain_id>:<registry>:<identity>:<version>";
+        let parts: Vec<&str> = v.split(':').collect();
+        let [name, chain, registry, identity, version] = parts[..] else {
+            return Err(err);
+        };
+        Ok(ShareId {
+            secret_name: name.into(),
+            identity: IdentityLocator {
+                chain: chain.parse().map_err(|_| err)?,
+                registry: registry.parse().map_err(|_| err)?,
+                id: IdentityId(identity.parse().map_err(|_| err)?),
+            },
+            version: version.parse().map_err(|_| err)?,
+        })
+    })
+}
+
+fn creation_block_parser() -> impl TypedValueParser {
+    clap::builder::StringValueParser::default().try_map(|v| {
+        let err = "creation block argument must have format <chain_id>=<block>";
+        match v.split_once('=') {
+            Some((chain_str, block_str)) => {
+                let chain: ChainId = chain_str.parse().map_err(|_| err)?;
+                let block: u64 = block_str.parse().map_err(|_| err)?;
+                Ok((chain, block))
+            }
+            _ => Err(err),
+        }
+    })
+}
+
+fn retry_rate_parser() -> impl TypedValueParser<Value = f64> {
+    clap::builder::StringValueParser::default().try_map(|v| match v.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err("retry rate must be a finite, positive number"),
+    })
+}
+
+fn registry_parser() -> impl TypedValueParser {
+    clap::builder::StringValueParser::default().try_map(|v| {
+        let err = "registry argument must have format <chain_id>=<registry_address>";
+        let (chain, addr) = v.split_once('=').ok_or(err)?;
+        Ok::<_, &str>((
+            chain.parse::<u64>().map_err(|_| err)?,
+            addr.parse::<Address>().map_err(|_| err)?,
+        ))
+    })
+}
+
+fn log_kinds_parser() -> impl TypedValueParser {
+    clap::builder::StringValueParser::default().try_map(|v| {
+        let err = "log kinds argument must have format <chain_id>=<kind>[,<kind>]";
+        let (chain, kinds) = v.split_once('=').ok_or(err)?;
+        let kinds = kinds
+            .split(',')
+            .map(|kind| crate::eth::LogKind::from_str(kind, true).map_err(|_| err))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok::<_, &str>((chain.parse::<u64>().map_err(|_| err)?, kinds))
+    })
+}
+
+fn finality_parser() -> impl TypedValueParser {
+    clap::builder::StringValueParser::default().try_map(|v| {
+        let err = "finality argument must have format <chain_id>=instant, \
+                   <chain_id>=confirmations:<n>, or <chain_id>=checkpoint:<contract_address>";
+        let (chain, rule) = v.split_once('=').ok_or(err)?;
+        let rule = match rule.split_once(':') {
+            None if rule == "instant" => crate::eth::FinalityRule::Instant,
+            Some(("confirmations", n)) => {
+                crate::eth::FinalityRule::Confirmations(n.parse().map_err(|_| err)?)
+            }
+            Some(("checkpoint", contract)) => crate::eth::FinalityRule::Checkpoint {
+                contract: contract.parse().map_err(|_| err)?,
+            },
+            _ => return Err(err),
+        };
+        Ok::<_, &str>((chain.parse::<u64>().map_err(|_| err)?, rule))
+    })
+}
+
 fn permitters_parser() -> impl TypedValueParser {
     clap::builder::StringValueParser::default().try_map(|v| {
         let err = "permitter argument must have format <chain_id>=<permitter_address>";
@@ -1,24 +1,26 @@
 use std::{
-    collections::HashMap,
-    sync::Arc,
+    collections::{HashMap, HashSet},
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        Arc, RwLock,
+    },
     time::{Duration, Instant},
 };
 
 use ethers::{
     abi::AbiDecode,
-    contract::{ContractCall, EthLogDecode as _},
+    contract::{ContractCall, EthCall as _, EthEvent as _, EthLogDecode as _},
     providers::{self, JsonRpcClient as _},
     types::{Address, Bytes, Filter, Log, Transaction, TxHash, ValueOrArray, H256, U256, U64},
 };
-use futures_util::{future::BoxFuture, FutureExt, Stream, StreamExt as _, TryStreamExt as _};
-use smallvec::{smallvec, SmallVec};
-use tokio::sync::{Mutex, OnceCell};
+use futures_util::{
+    future::BoxFuture, FutureExt, Stream, StreamExt as _, TryFutureExt as _, TryStreamExt as _,
+};
+use smallvec::SmallVec;
+use tokio::sync::{watch, Mutex, OnceCell};
 use tracing::{trace, warn};
 
-use crate::{
-    types::*,
-    utils::{retry, retry_if},
-};
+use crate::{types::*, utils::retry_if_with_backoff};
 
 ethers::contract::abigen!(
     SsssHubContract,
@@ -33,9 +35,21 @@ ethers::contract::abigen!(
         function setPolicy(bytes32 identity, bytes calldata config)
 
         function dealShares(bytes32 identity, string secretName, uint64 version, bytes pk, bytes32 nonce, bytes[] shares)
+        function dealIndexedShares(bytes32 identity, string secretName, uint64 version, bytes pk, bytes32 nonce, uint64[] indices, bytes[] shares)
+    ]"
+);
+
+ethers::contract::abigen!(
+    CheckpointContract,
+    r"[
+        function latestCheckpointEpoch() view returns (uint256)
+        function checkpoint(uint256 epoch) view returns (uint256 start, uint256 end)
     ]"
 );
 
+/// The index of this process's share of each deal seen, keyed by identity, secret name, and version.
+type TrackedShares = HashMap<(IdentityId, String, u64), Option<ShareIndex>>;
+
 #[derive(Clone)]
 pub struct SsssHub<M> {
     pub chain: u64,
@@ -44,7 +58,172 @@ pub struct SsssHub<M> {
     provider: Arc<M>,
 
     creation_block: Arc<OnceCell<u64>>,
+    /// The creation block supplied by the operator, which is used instead of asking the contract.
+    pinned_creation_block: Option<u64>,
     upstream: Arc<Mutex<(Address, Instant)>>,
+    rate_limited: Arc<AtomicBool>,
+    stalled: Arc<AtomicBool>,
+    /// Why sync last exited, until it processes a block again.
+    sync_error: Arc<std::sync::Mutex<Option<String>>>,
+    processed_block: Arc<AtomicU64>,
+    /// The highest head reported by the provider and when the provider was last asked.
+    last_head: Arc<std::sync::Mutex<Option<(u64, Instant)>>>,
+    /// The newest final block found when events were last streamed.
+    last_final: Arc<std::sync::Mutex<Option<u64>>>,
+    tracked_shares: Arc<RwLock<TrackedShares>>,
+    paused: Arc<watch::Sender<bool>>,
+    /// Resolves the identity registry instead of asking the permitter, if set.
+    registry_resolver: Option<Arc<dyn RegistryResolver>>,
+    /// Decides which blocks are final instead of the confirmations given to [`Self::events`], if
+    /// set.
+    finality: Option<Arc<dyn FinalityProvider<M>>>,
+    /// Whether to log each decoded permitter call, without its shares, for auditing.
+    log_calls: bool,
+    /// The topics of the only logs to request, if limited by [`Self::with_log_kinds`].
+    log_topics: Option<Vec<H256>>,
+}
+
+/// A kind of log emitted by the permitter, which a hub may be limited to requesting.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, clap::ValueEnum)]
+pub enum LogKind {
+    PolicyChange,
+    SharesDealt,
+}
+
+impl LogKind {
+    fn topic(self) -> H256 {
+        match self {
+            Self::PolicyChange => PolicyChangeFilter::signature(),
+            Self::SharesDealt => SharesDealtFilter::signature(),
+        }
+    }
+}
+
+/// Finds the identity registry of a permitter for deployments where the permitter's own view of
+/// it is not the registry that identities are keyed by, such as behind a proxy.
+pub trait RegistryResolver: Send + Sync {
+    fn resolve(&self, chain: ChainId, permitter: Address)
+        -> BoxFuture<'_, anyhow::Result<Address>>;
+}
+
+/// Resolves every permitter to the same registry.
+pub struct FixedRegistry(pub Address);
+
+impl RegistryResolver for FixedRegistry {
+    fn resolve(&self, _: ChainId, _: Address) -> BoxFuture<'_, anyhow::Result<Address>> {
+        futures_util::future::ready(Ok(self.0)).boxed()
+    }
+}
+
+/// Decides which blocks of a chain are final, and so whose events may be handled without risk of
+/// them being reorganized away.
+pub trait FinalityProvider<M: providers::Middleware>: Send + Sync {
+    /// Returns the newest final block given the chain's head, or `None` if no block is final yet.
+    fn final_block<'a>(
+        &'a self,
+        provider: &'a M,
+        head: u64,
+    ) -> BoxFuture<'a, Result<Option<u64>, Error<M>>>;
+}
+
+/// Treats blocks as final once a fixed number of blocks have been built on top of them.
+pub struct FixedConfirmations(pub u64);
+
+impl FixedConfirmations {
+    fn final_under(&self, head: u64) -> Option<u64> {
+        head.checked_sub(self.0)
+    }
+}
+
+impl<M: providers::Middleware> FinalityProvider<M> for FixedConfirmations {
+    fn final_block<'a>(
+        &'a self,
+        _: &'a M,
+        head: u64,
+    ) -> BoxFuture<'a, Result<Option<u64>, Error<M>>> {
+        futures_util::future::ready(Ok(self.final_under(head))).boxed()
+    }
+}
+
+/// Asks the provider for the block that the chain itself considers final, for chains that
+/// support the `finalized` block tag.
+pub struct FinalizedTag;
+
+impl<M: providers::Middleware> FinalityProvider<M> for FinalizedTag {
+    fn final_block<'a>(
+        &'a self,
+        provider: &'a M,
+        _: u64,
+    ) -> BoxFuture<'a, Result<Option<u64>, Error<M>>> {
+        async move {
+            let block = provider
+                .get_block(ethers::types::BlockNumber::Finalized)
+                .await
+                .map_err(Error::RpcProvider)?;
+            Ok(block
+                .and_then(|block| block.number)
+                .map(|number| number.as_u64()))
+        }
+        .boxed()
+    }
+}
+
+/// A chain's rule for when a block is final, for chains whose finality is neither a fixed depth
+/// nor reported by the `finalized` tag.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FinalityRule {
+    /// Blocks are final once this many blocks have been built on top of them.
+    Confirmations(u64),
+    /// Blocks are final once they are covered by the contract's latest checkpoint, as on chains
+    /// that periodically checkpoint their blocks elsewhere. The contract is called on this chain,
+    /// and must report the blocks of each epoch's checkpoint through `checkpoint(epoch)`.
+    Checkpoint { contract: Address },
+    /// Blocks are final as soon as they are built, as on optimistic chains whose sequencer is
+    /// trusted not to reorganize them.
+    Instant,
+}
+
+impl<M: providers::Middleware> FinalityProvider<M> for FinalityRule {
+    fn final_block<'a>(
+        &'a self,
+        provider: &'a M,
+        head: u64,
+    ) -> BoxFuture<'a, Result<Option<u64>, Error<M>>> {
+        let fixed = match *self {
+            Self::Confirmations(confirmations) => FixedConfirmations(confirmations),
+            Self::Checkpoint { contract } => {
+                return async move {
+                    let LatestCheckpointEpochReturn(epoch) =
+                        call_view(provider, contract, LatestCheckpointEpochCall).await?;
+                    let CheckpointReturn { end, .. } =
+                        call_view(provider, contract, CheckpointCall { epoch }).await?;
+                    let end = u64::try_from(end).map_err(|_| Error::CheckpointOutOfRange(end))?;
+                    // Checkpoints may cover blocks that this provider has yet to see.
+                    Ok(Some(end.min(head)))
+                }
+                .boxed();
+            }
+            Self::Instant => FixedConfirmations(0),
+        };
+        futures_util::future::ready(Ok(fixed.final_under(head))).boxed()
+    }
+}
+
+/// Calls a view function of `contract` through a bare provider, which, unlike an abigen contract,
+/// need not be shared.
+async fn call_view<M: providers::Middleware, C: ethers::contract::EthCall, R: AbiDecode>(
+    provider: &M,
+    contract: Address,
+    call: C,
+) -> Result<R, Error<M>> {
+    let tx = ethers::types::TransactionRequest::new()
+        .to(contract)
+        .data(ethers::abi::AbiEncode::encode(call));
+    let output = provider
+        .call(&tx.into(), None)
+        .await
+        .map_err(Error::RpcProvider)?;
+    Ok(R::decode(output).map_err(ethers::contract::ContractError::<M>::from)?)
 }
 
 impl<M: providers::Middleware> SsssHub<M> {
@@ -56,21 +235,265 @@ impl<M: providers::Middleware> SsssHub<M> {
             contract: SsssHubContract::new(address, provider.clone()),
             provider,
             creation_block: Default::default(),
+            pinned_creation_block: None,
             upstream: Arc::new(Mutex::new((Address::zero(), Instant::now()))),
+            rate_limited: Default::default(),
+            stalled: Default::default(),
+            sync_error: Default::default(),
+            processed_block: Default::default(),
+            last_head: Default::default(),
+            last_final: Default::default(),
+            tracked_shares: Default::default(),
+            paused: Arc::new(watch::channel(false).0),
+            registry_resolver: None,
+            finality: None,
+            log_calls: false,
+            log_topics: None,
+        }
+    }
+
+    /// The latest block that this process has finished syncing, or zero if none.
+    pub fn processed_block(&self) -> u64 {
+        self.processed_block.load(Ordering::Acquire)
+    }
+
+    pub fn set_processed_block(&self, block: u64) {
+        self.processed_block.fetch_max(block, Ordering::AcqRel);
+    }
+
+    /// Stops sync of this chain before its next event until [`Self::resume`] is called.
+    pub fn pause(&self) {
+        self.paused.send_replace(true);
+    }
+
+    pub fn resume(&self) {
+        self.paused.send_replace(false);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        *self.paused.borrow()
+    }
+
+    pub async fn wait_unpaused(&self) {
+        // The sender is owned by `self`, so the channel cannot close while this waits.
+        let _ = self.paused.subscribe().wait_for(|paused| !paused).await;
+    }
+
+    /// Records that a deal was seen and, if this process holds a share of it, the share's index.
+    pub fn track_share(
+        &self,
+        identity: IdentityId,
+        secret_name: String,
+        version: u64,
+        index: Option<ShareIndex>,
+    ) {
+        self.tracked_shares
+            .write()
+            .unwrap()
+            .insert((identity, secret_name, version), index);
+    }
+
+    /// The deals seen since this process started syncing, ordered by identity, name, and version.
+    pub fn tracked_shares(&self) -> Vec<api::TrackedShare> {
+        let mut shares: Vec<_> = self
+            .tracked_shares
+            .read()
+            .unwrap()
+            .iter()
+            .map(
+                |((identity, secret_name, version), index)| api::TrackedShare {
+                    identity: *identity,
+                    secret_name: secret_name.clone(),
+                    version: *version,
+                    held: index.is_some(),
+                    index: *index,
+                },
+            )
+            .collect();
+        shares.sort_unstable_by(|a, b| {
+            (a.identity.0, &a.secret_name, a.version).cmp(&(
+                b.identity.0,
+                &b.secret_name,
+                b.version,
+            ))
+        });
+        shares
+    }
+
+    /// Whether the most recent provider request was refused due to rate limiting.
+    pub fn is_rate_limited(&self) -> bool {
+        self.rate_limited.load(Ordering::Relaxed)
+    }
+
+    /// Whether sync has stopped processing blocks even though the chain has moved past it.
+    pub fn is_stalled(&self) -> bool {
+        self.stalled.load(Ordering::Relaxed)
+    }
+
+    pub fn set_stalled(&self, stalled: bool) {
+        self.stalled.store(stalled, Ordering::Relaxed);
+    }
+
+    /// The error with which sync of this chain last exited, if it has not processed a block since.
+    pub fn sync_error(&self) -> Option<String> {
+        self.sync_error.lock().unwrap().clone()
+    }
+
+    pub fn set_sync_error(&self, error: Option<String>) {
+        *self.sync_error.lock().unwrap() = error;
+    }
+
+    /// Uses `block` as the block in which the contract was created instead of asking the contract,
+    /// which a provider without archival state may answer wrongly.
+    pub fn with_creation_block(mut self, block: u64) -> Self {
+        self.pinned_creation_block = Some(block);
+        self
+    }
+
+    /// Uses `resolver` to find the identity registry instead of asking the contract.
+    pub fn with_registry_resolver(mut self, resolver: impl RegistryResolver + 'static) -> Self {
+        self.registry_resolver = Some(Arc::new(resolver));
+        self
+    }
+
+    /// Handles events only once `finality` considers their blocks final, whether backfilling or
+    /// tailing. Without one, [`Self::events`] waits for the confirmations that it is given.
+    pub fn with_finality(mut self, finality: impl FinalityProvider<M> + 'static) -> Self {
+        self.finality = Some(Arc::new(finality));
+        self
+    }
+
+    /// Logs, at trace level, the decoded call behind each permitter event. Dealt shares are
+    /// redacted.
+    pub fn with_call_logging(mut self) -> Self {
+        self.log_calls = true;
+        self
+    }
+
+    /// Requests only logs of the given kinds, so that others are neither fetched nor decoded.
+    /// Every block still yields its `ProcessedBlock`.
+    pub fn with_log_kinds(mut self, kinds: impl IntoIterator<Item = LogKind>) -> Self {
+        self.log_topics = Some(kinds.into_iter().map(LogKind::topic).collect());
+        self
+    }
+
+    fn requests_log(&self, log: &Log) -> bool {
+        match (&self.log_topics, log.topics.first()) {
+            (None, _) => true,
+            (Some(topics), Some(topic)) => topics.contains(topic),
+            (Some(_), None) => false,
         }
     }
 
     pub async fn creation_block(&self) -> Result<u64, Error<M>> {
-        match self.creation_block.get() {
-            Some(b) => Ok(*b),
+        self.creation_block
+            .get_or_try_init(|| async {
+                let Some(block) = self.pinned_creation_block else {
+                    return Ok(self
+                        .retry_rpc(
+                            || async { Ok(self.contract.creation_block().call().await?) },
+                            Some,
+                        )
+                        .await
+                        .as_u64());
+                };
+                let head = self.head_block().await?;
+                if block > head {
+                    return Err(Error::CreationBlockAhead { block, head });
+                }
+                Ok(block)
+            })
+            .await
+            .copied()
+    }
+
+    /// Checks that the provider is reachable by asking it for the latest block number.
+    pub async fn provider_health_check(&self) -> Result<(), Error<M>> {
+        self.get_block_number().await?;
+        Ok(())
+    }
+
+    /// Checks that the provider serves the chain that this hub was configured for, so that a
+    /// misconfigured hub does not handle another network's events as this chain's.
+    pub async fn verify_chain_id(&self) -> Result<(), Error<M>> {
+        let actual = self
+            .provider
+            .get_chainid()
+            .await
+            .map_err(Error::RpcProvider)?
+            .as_u64();
+        if actual != self.chain {
+            return Err(Error::ChainIdMismatch {
+                expected: self.chain,
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    pub async fn head_block(&self) -> Result<u64, Error<M>> {
+        self.get_block_number().await
+    }
+
+    /// Returns the chain head, reusing the head last seen by any request if it was seen within
+    /// [`HEAD_MAX_AGE`].
+    pub async fn current_block(&self) -> Result<u64, Error<M>> {
+        let last_head = *self.last_head.lock().unwrap();
+        match last_head {
+            Some((head, seen_at)) if seen_at.elapsed() < HEAD_MAX_AGE => Ok(head),
+            _ => self.get_block_number().await,
+        }
+    }
+
+    /// The highest head that the provider has reported, without asking it again.
+    pub fn last_known_head(&self) -> Option<u64> {
+        self.last_head.lock().unwrap().map(|(head, _)| head)
+    }
+
+    /// The newest final block found while streaming events, without asking the provider again.
+    /// Unlike the head, this is the most that sync could have processed.
+    pub fn last_known_final_block(&self) -> Option<u64> {
+        *self.last_final.lock().unwrap()
+    }
+
+    /// Returns the newest final block, as decided by the hub's [`FinalityProvider`] or else as
+    /// `confirmations` below the head, or `None` if no block is final yet.
+    pub async fn final_block(&self, confirmations: u64) -> Result<Option<u64>, Error<M>> {
+        match &self.finality {
+            Some(finality) => self.get_final_block(&**finality).await,
             None => {
-                let b = self.contract.creation_block().call().await?.as_u64();
-                self.creation_block.set(b).ok();
-                Ok(b)
+                self.get_final_block(&FixedConfirmations(confirmations))
+                    .await
             }
         }
     }
 
+    /// The fraction of blocks from the creation block through the known final block that sync has
+    /// finished, or `None` until both are known.
+    pub fn sync_progress(&self) -> Option<f64> {
+        let creation = self
+            .pinned_creation_block
+            .or_else(|| self.creation_block.get().copied())?;
+        Some(sync_progress(
+            creation,
+            self.processed_block(),
+            self.last_known_final_block()?,
+        ))
+    }
+
+    async fn get_block_number(&self) -> Result<u64, Error<M>> {
+        let head = self
+            .provider
+            .get_block_number()
+            .await
+            .map_err(Error::RpcProvider)?
+            .as_u64();
+        let mut last_head = self.last_head.lock().unwrap();
+        let highest = last_head.map_or(head, |(last, _)| last.max(head));
+        *last_head = Some((highest, Instant::now()));
+        Ok(highest)
+    }
+
     pub async fn upstream(&self) -> Result<Address, Error<M>> {
         let mut up = self.upstream.lock().await;
         if up.1 > Instant::now() {
@@ -81,8 +504,15 @@ impl<M: providers::Middleware> SsssHub<M> {
         Ok(r)
     }
 
+    /// Returns the registry whose identities this permitter's shares are stored under.
     pub async fn registry(&self) -> Result<Address, Error<M>> {
-        Ok(self.contract.get_identity_registry().call().await?)
+        match &self.registry_resolver {
+            Some(resolver) => resolver
+                .resolve(self.chain, self.address)
+                .await
+                .map_err(Error::RegistryResolution),
+            None => Ok(self.contract.get_identity_registry().call().await?),
+        }
     }
 
     pub async fn set_policy(
@@ -113,6 +543,32 @@ impl<M: providers::Middleware> SsssHub<M> {
         .await
     }
 
+    /// Like [`Self::deal_shares_sss`], but gives each share the index paired with it rather than
+    /// its position among the shares.
+    pub async fn deal_indexed_shares_sss(
+        &self,
+        identity: IdentityId,
+        version: u64,
+        pk: impl Into<Bytes>,
+        nonce: [u8; 32],
+        shares: Vec<(ShareIndex, impl Into<Bytes>)>,
+    ) -> Result<TxHash, Error<M>> {
+        let (indices, shares) = shares
+            .into_iter()
+            .map(|(index, share)| (index.0, share.into()))
+            .unzip();
+        self.send_tx(self.contract.deal_indexed_shares(
+            identity.0.into(),
+            "omni".into(),
+            version,
+            pk.into(),
+            nonce,
+            indices,
+            shares,
+        ))
+        .await
+    }
+
     async fn send_tx(&self, call: ContractCall<M, ()>) -> Result<TxHash, Error<M>> {
         let receipt = call
             .send()
@@ -129,20 +585,20 @@ impl<M: providers::Middleware> SsssHub<M> {
         }
     }
 
+    /// Streams the events of each block from `start_block` through `stop_block`, if any.
+    /// A block is only yielded once it is final, which, unless the hub was given a
+    /// [`FinalityProvider`], is once `confirmations` more blocks have been built on top of it.
+    /// The events of a block are in log index order, followed by its `ProcessedBlock`, which
+    /// carries the block's hash.
     pub fn events(
         &self,
         start_block: u64,
         stop_block: Option<u64>,
+        confirmations: u64,
     ) -> impl Stream<Item = BoxFuture<SmallVec<[Event; 4]>>> {
         async_stream::stream!({
-            for await block in self.blocks(start_block).await {
-                yield self.get_block_events(block, self.address).boxed();
-                yield futures_util::future::ready(smallvec![Event {
-                    kind: EventKind::ProcessedBlock,
-                    index: Default::default(),
-                    tx: Default::default(),
-                }])
-                .boxed();
+            for await block in self.blocks(start_block, confirmations).await {
+                yield self.get_processed_block_events(block).boxed();
                 if Some(block) == stop_block {
                     break;
                 }
@@ -150,63 +606,184 @@ impl<M: providers::Middleware> SsssHub<M> {
         })
     }
 
-    async fn blocks(&self, start_block: u64) -> impl Stream<Item = u64> + '_ {
-        let init_block = retry(|| async {
-            Ok::<_, Error<M>>(
-                self.provider
-                    .get_block_number()
-                    .await
-                    .map_err(Error::RpcProvider)?
-                    .as_u64(),
-            )
-        })
-        .await;
+    /// Returns the events emitted by this contract in the given transaction.
+    pub async fn tx_events(&self, tx: TxHash) -> Result<Vec<Event>, Error<M>> {
+        let Some(receipt) = self
+            .provider
+            .get_transaction_receipt(tx)
+            .await
+            .map_err(Error::RpcProvider)?
+        else {
+            return Ok(Vec::new());
+        };
+        Ok(futures_util::stream::iter(
+            receipt
+                .logs
+                .into_iter()
+                .filter(|log| log.address == self.address && self.requests_log(log)),
+        )
+        .then(|log| self.decode_permitter_event(log))
+        .filter_map(futures_util::future::ready)
+        .collect()
+        .await)
+    }
+
+    async fn blocks(&self, start_block: u64, confirmations: u64) -> impl Stream<Item = u64> + '_ {
+        let finality = self
+            .finality
+            .clone()
+            .unwrap_or_else(|| Arc::new(FixedConfirmations(confirmations)));
+        let init_final = self
+            .retry_rpc(|| self.get_final_block(&*finality), Some)
+            .await;
         async_stream::stream!({
+            let mut final_block = init_final;
             let mut current_block = start_block;
             loop {
-                if current_block <= init_block {
-                    yield current_block;
-                } else {
-                    self.wait_for_block(current_block).await;
-                    yield current_block;
+                if final_block.map_or(true, |final_block| current_block > final_block) {
+                    final_block = Some(self.wait_for_final_block(current_block, &*finality).await);
                 }
+                yield current_block;
                 current_block += 1;
             }
         })
     }
 
-    async fn wait_for_block(&self, block_number: u64) {
+    async fn get_final_block(
+        &self,
+        finality: &dyn FinalityProvider<M>,
+    ) -> Result<Option<u64>, Error<M>> {
+        let head = self.get_block_number().await?;
+        let final_block = finality.final_block(&self.provider, head).await?;
+        *self.last_final.lock().unwrap() = final_block;
+        Ok(final_block)
+    }
+
+    /// Waits until `block_number` is final and returns the newest final block at that time.
+    async fn wait_for_final_block(
+        &self,
+        block_number: u64,
+        finality: &dyn FinalityProvider<M>,
+    ) -> u64 {
         trace!(block = block_number, "waiting for block");
-        retry_if(
-            || async {
-                Ok::<_, Error<M>>(
-                    self.provider
-                        .get_block_number()
-                        .await
-                        .map_err(Error::RpcProvider)?
-                        .as_u64(),
-                )
+        let final_block = self
+            .retry_rpc(
+                || self.get_final_block(finality),
+                |final_block| final_block.filter(|&final_block| final_block >= block_number),
+            )
+            .await;
+        trace!(block = block_number, "waited for block");
+        final_block
+    }
+
+    async fn get_block_hash(&self, block_number: u64) -> H256 {
+        self.retry_rpc(
+            || {
+                self.provider
+                    .get_block(block_number)
+                    .map_err(Error::RpcProvider)
             },
-            |num| (num >= block_number).then_some(num),
+            |block| block?.hash,
         )
-        .await;
-        trace!(block = block_number, "waited for block");
+        .await
     }
 
-    async fn get_block_events(&self, block_number: u64, addr: Address) -> SmallVec<[Event; 4]> {
-        retry(move || {
-            let provider = self.provider.clone();
-            let filter = Filter::new()
-                .select(block_number)
-                .address(ValueOrArray::Value(addr));
-            async move { provider.get_logs(&filter).await }
-        })
-        .map(futures_util::stream::iter)
-        .flatten_stream()
+    /// Returns the block's events followed by one that marks the block as processed.
+    async fn get_processed_block_events(&self, block_number: u64) -> SmallVec<[Event; 4]> {
+        let (mut events, logs_block_hash) = self.get_block_events(block_number, self.address).await;
+        // The hash of the block that the logs came from is recorded, so that a reorg between
+        // requests cannot pair the logs with another block. Only a block without logs needs its
+        // header fetched.
+        let block_hash = match logs_block_hash {
+            Some(block_hash) => block_hash,
+            None => self.get_block_hash(block_number).await,
+        };
+        events.push(Event {
+            kind: EventKind::ProcessedBlock { block_hash },
+            // Sorts after every log in the block.
+            index: EventIndex {
+                block: block_number,
+                log_index: u64::MAX,
+            },
+            tx: Default::default(),
+        });
+        events
+    }
+
+    /// Returns the block's events and the hash of the block that their logs came from, if any.
+    async fn get_block_events(
+        &self,
+        block_number: u64,
+        addr: Address,
+    ) -> (SmallVec<[Event; 4]>, Option<H256>) {
+        let mut filter = Filter::new()
+            .select(block_number)
+            .address(ValueOrArray::Value(addr));
+        if let Some(topics) = &self.log_topics {
+            filter = filter.topic0(topics.clone());
+        }
+        let logs = self
+            .retry_rpc(
+                || self.provider.get_logs(&filter).map_err(Error::RpcProvider),
+                // A provider that answers from two forks at once is asked again.
+                |logs: Vec<Log>| {
+                    let mut hashes = logs.iter().map(|log| log.block_hash);
+                    let first = hashes.next().flatten();
+                    hashes.all(|hash| hash == first).then_some(logs)
+                },
+            )
+            .await;
+        let block_hash = logs.first().and_then(|log| log.block_hash);
+        // Some providers repeat entries, especially near the head, which would otherwise be
+        // handled twice.
+        let mut seen_tx_log = HashSet::new();
+        let mut events = futures_util::stream::iter(logs.into_iter().filter(move |log| {
+            match (log.transaction_hash, log.log_index) {
+                (Some(tx), Some(log_index)) => {
+                    let unseen = seen_tx_log.insert((tx, log_index.as_u64()));
+                    if !unseen {
+                        trace!(block = block_number, tx = ?tx, %log_index, "skipping duplicate log");
+                    }
+                    unseen
+                }
+                _ => true,
+            }
+        }))
         .map(|log| async move { self.decode_permitter_event(log).await })
         .buffer_unordered(100)
         .filter_map(futures_util::future::ready)
         .collect::<SmallVec<[Event; 4]>>()
+        .await;
+        // Logs are decoded concurrently and providers need not return them in order, but a
+        // policy must be applied before any share dealt after it in the same block.
+        events.sort_unstable_by_key(|event| event.index);
+        (events, block_hash)
+    }
+
+    /// Retries a provider request until `map_done` accepts its result, backing off for longer
+    /// while the provider is rate limiting.
+    async fn retry_rpc<T, U, Fut>(
+        &self,
+        f: impl Fn() -> Fut,
+        map_done: impl Fn(T) -> Option<U>,
+    ) -> U
+    where
+        Fut: std::future::Future<Output = Result<T, Error<M>>>,
+    {
+        retry_if_with_backoff(
+            "rpc",
+            f,
+            |res| {
+                self.rate_limited.store(false, Ordering::Relaxed);
+                map_done(res)
+            },
+            |e: &Error<M>| {
+                let backoff = e.rate_limit_backoff()?;
+                warn!(chain = self.chain, backoff = ?backoff, "rate limited by provider");
+                self.rate_limited.store(true, Ordering::Relaxed);
+                Some(backoff)
+            },
+        )
         .await
     }
 
@@ -230,8 +807,16 @@ impl<M: providers::Middleware> SsssHub<M> {
                 return None;
             }
         };
-        let Transaction { input, .. } =
-            retry_if(|| self.provider.get_transaction(tx), |tx| tx).await;
+        let Transaction { input, from, .. } = self
+            .retry_rpc(
+                || {
+                    self.provider
+                        .get_transaction(tx)
+                        .map_err(Error::RpcProvider)
+                },
+                |tx| tx,
+            )
+            .await;
         let kind = match event {
             SsssHubContractEvents::PolicyChangeFilter(_) => {
                 let (identity, config): (H256, Bytes) = AbiDecode::decode(&input[4..]).unwrap();
@@ -241,26 +826,33 @@ impl<M: providers::Middleware> SsssHub<M> {
                 })
             }
             SsssHubContractEvents::SharesDealtFilter(_) => {
-                let (identity, secret_name, version, pk, nonce, shares): (
-                    H256,
-                    String,
-                    U256,
-                    Bytes,
-                    H256,
-                    Vec<Bytes>,
-                ) = AbiDecode::decode(&input[4..]).unwrap();
-                EventKind::SharesDealt(SharesDealt {
-                    identity: identity.into(),
-                    secret_name,
-                    version: version.low_u64(),
-                    scheme: SsScheme::Shamir {
-                        pk: p384::PublicKey::from_sec1_bytes(&pk).ok()?,
-                        nonce,
-                        shares,
-                    },
-                })
+                let (selector, args) = input.split_at(input.len().min(4));
+                let dealt = if selector == DealIndexedSharesCall::selector() {
+                    decode_indexed_shares_dealt(args, from)
+                } else {
+                    decode_shares_dealt(args, from)
+                };
+                match dealt {
+                    Ok(dealt) => EventKind::SharesDealt(dealt),
+                    Err(e) => {
+                        warn!(tx = ?tx, "failed to decode dealt shares: {e}");
+                        return None;
+                    }
+                }
             }
         };
+        if self.log_calls {
+            trace!(
+                tx = ?tx,
+                from = ?from,
+                selector = %hex::encode(input.get(..4).unwrap_or_default()),
+                call = %AuditedCall {
+                    selector: input.get(..4).unwrap_or_default(),
+                    kind: &kind,
+                },
+                "decoded permitter call"
+            );
+        }
         Some(Event {
             kind,
             tx: Some(tx),
@@ -269,6 +861,196 @@ impl<M: providers::Middleware> SsssHub<M> {
     }
 }
 
+/// Formats the call that produced an event with its arguments, except for any shares.
+struct AuditedCall<'a> {
+    selector: &'a [u8],
+    kind: &'a EventKind,
+}
+
+impl std::fmt::Display for AuditedCall<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            EventKind::PolicyChange(PolicyChange { identity, config }) => write!(
+                f,
+                "setPolicy(identity: {:#x}, config: 0x{})",
+                identity.0,
+                hex::encode(config)
+            ),
+            EventKind::SharesDealt(SharesDealt {
+                identity,
+                secret_name,
+                version,
+                scheme:
+                    SsScheme::Shamir {
+                        pk,
+                        nonce,
+                        indices,
+                        shares,
+                        ..
+                    },
+                ..
+            }) => {
+                let indexed = self.selector == DealIndexedSharesCall::selector();
+                write!(
+                    f,
+                    "{}(identity: {:#x}, secretName: {secret_name:?}, version: {version}, pk: \
+                     0x{}, nonce: {nonce:#x}, ",
+                    if indexed {
+                        "dealIndexedShares"
+                    } else {
+                        "dealShares"
+                    },
+                    identity.0,
+                    hex::encode(pk.to_bytes()),
+                )?;
+                if indexed {
+                    let indices: Vec<_> = indices.iter().map(|index| index.0).collect();
+                    write!(f, "indices: {indices:?}, ")?;
+                }
+                write!(f, "shares: [\"<share_bytes>\"; {}])", shares.len())
+            }
+            EventKind::ProcessedBlock { .. } => f.write_str("<none>"),
+        }
+    }
+}
+
+/// The most shares that one deal can hold. Shamir share identifiers are a single nonzero byte.
+pub const MAX_SHARES_PER_DEAL: usize = u8::MAX as usize;
+
+/// The length of the authentication tag that AES-GCM-SIV appends to each encrypted share.
+const TAG_SIZE: usize = 16;
+
+/// Decodes the arguments of a `dealShares` call, which follow the function selector. Each share
+/// is indexed by its position among the shares.
+pub fn decode_shares_dealt(args: &[u8], dealer: Address) -> Result<SharesDealt, EventParseError> {
+    let (identity, secret_name, version, pk, nonce, shares): (
+        H256,
+        String,
+        U256,
+        Bytes,
+        H256,
+        Vec<Bytes>,
+    ) = AbiDecode::decode(args)?;
+    check_dealt_shares(&shares)?;
+    Ok(SharesDealt {
+        identity: identity.into(),
+        secret_name,
+        version: version.low_u64(),
+        scheme: SsScheme::Shamir {
+            pk: crate::identity::PeerKey::from_bytes(&pk)
+                .ok_or(EventParseError::InvalidDealerKey)?,
+            nonce,
+            indices: (0..shares.len() as u64).map(ShareIndex).collect(),
+            indexed: false,
+            shares,
+        },
+        dealer,
+    })
+}
+
+/// Decodes the arguments of a `dealIndexedShares` call, which follow the function selector.
+pub fn decode_indexed_shares_dealt(
+    args: &[u8],
+    dealer: Address,
+) -> Result<SharesDealt, EventParseError> {
+    let (identity, secret_name, version, pk, nonce, indices, shares): (
+        H256,
+        String,
+        U256,
+        Bytes,
+        H256,
+        Vec<u64>,
+        Vec<Bytes>,
+    ) = AbiDecode::decode(args)?;
+    check_dealt_shares(&shares)?;
+    if indices.len() != shares.len() {
+        return Err(EventParseError::IndexCountMismatch {
+            indices: indices.len(),
+            shares: shares.len(),
+        });
+    }
+    let mut seen = HashSet::with_capacity(indices.len());
+    if let Some(&index) = indices.iter().find(|&&index| !seen.insert(index)) {
+        return Err(EventParseError::DuplicateShareIndex(index));
+    }
+    Ok(SharesDealt {
+        identity: identity.into(),
+        secret_name,
+        version: version.low_u64(),
+        scheme: SsScheme::Shamir {
+            pk: crate::identity::PeerKey::from_bytes(&pk)
+                .ok_or(EventParseError::InvalidDealerKey)?,
+            nonce,
+            indices: indices.into_iter().map(ShareIndex).collect(),
+            indexed: true,
+            shares,
+        },
+        dealer,
+    })
+}
+
+fn check_dealt_shares(shares: &[Bytes]) -> Result<(), EventParseError> {
+    if shares.len() > MAX_SHARES_PER_DEAL {
+        return Err(EventParseError::TooManyShares(shares.len()));
+    }
+    if let Some((index, share)) = shares
+        .iter()
+        .enumerate()
+        .find(|(_, share)| share.len() <= TAG_SIZE)
+    {
+        return Err(EventParseError::ShareTooShort {
+            index,
+            length: share.len(),
+        });
+    }
+    Ok(())
+}
+
+fn sync_progress(creation: u64, processed: u64, head: u64) -> f64 {
+    // Both the creation block and the head need syncing.
+    let total = (head + 1).saturating_sub(creation);
+    if total == 0 {
+        return 0.0;
+    }
+    let done = (processed + 1).saturating_sub(creation).min(total);
+    done as f64 / total as f64
+}
+
+/// How long a seen head is reused by [`SsssHub::current_block`].
+pub const HEAD_MAX_AGE: Duration = Duration::from_secs(5);
+
+/// How long to wait after being rate limited when the provider does not say.
+const RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(30);
+const MAX_RATE_LIMIT_BACKOFF: Duration = Duration::from_secs(10 * 60);
+
+/// Returns how long to back off for if `e` means that the provider is rate limiting requests.
+fn rate_limit_backoff(e: &impl providers::MiddlewareError) -> Option<Duration> {
+    let is_rate_limit_message = |msg: &str| {
+        let msg = msg.to_lowercase();
+        msg.contains("rate limit") || msg.contains("too many requests")
+    };
+    let Some(res) = e.as_error_response() else {
+        return is_rate_limit_message(&e.to_string()).then_some(RATE_LIMIT_BACKOFF);
+    };
+    // -32005 is the "limit exceeded" code used by Infura and others.
+    if res.code != 429 && res.code != -32005 && !is_rate_limit_message(&res.message) {
+        return None;
+    }
+    let hint = res.data.as_ref().and_then(|data| {
+        ["retry_after", "retryAfter", "backoff_seconds"]
+            .into_iter()
+            .find_map(|k| data.get(k))
+            .or_else(|| data.get("rate")?.get("backoff_seconds"))?
+            .as_f64()
+            .filter(|secs| secs.is_finite() && *secs >= 0.0)
+    });
+    Some(
+        hint.map(Duration::from_secs_f64)
+            .unwrap_or(RATE_LIMIT_BACKOFF)
+            .min(MAX_RATE_LIMIT_BACKOFF),
+    )
+}
+
 type Providers = HashMap<ChainId, Provider>;
 type Provider =
     providers::Provider<Arc<providers::QuorumProvider<providers::RetryClient<providers::Http>>>>;
@@ -330,7 +1112,17 @@ pub struct Event {
 pub enum EventKind {
     PolicyChange(PolicyChange),
     SharesDealt(SharesDealt),
-    ProcessedBlock,
+    ProcessedBlock { block_hash: H256 },
+}
+
+impl EventKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::PolicyChange(_) => "PolicyChange",
+            Self::SharesDealt(_) => "SharesDealt",
+            Self::ProcessedBlock { .. } => "ProcessedBlock",
+        }
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -345,13 +1137,23 @@ pub struct SharesDealt {
     pub secret_name: String,
     pub version: u64,
     pub scheme: SsScheme,
+    /// The account that sent the dealing transaction.
+    pub dealer: Address,
 }
 
 #[derive(Clone, Debug)]
 pub enum SsScheme {
     Shamir {
-        pk: p384::PublicKey,
+        /// The dealer's public key: SEC1-encoded P-384, or raw X25519.
+        pk: crate::identity::PeerKey,
         nonce: H256,
+        /// The index of each of `shares`, which is stored with the share that decrypts. Dealers
+        /// that index by recipient rather than by position keep each recipient's index the same
+        /// across deals.
+        indices: Vec<ShareIndex>,
+        /// Whether the dealer gave `indices`, in which case each must match the Shamir identifier
+        /// that begins its share.
+        indexed: bool,
         /// Encrypted secret shares. One of which belongs to this SSSS.
         shares: Vec<Bytes>,
     },
@@ -367,4 +1169,642 @@ pub enum Error<M: providers::Middleware> {
     Provider(#[from] ethers::providers::ProviderError),
     #[error("unsupported rpc url: {0}")]
     UnsupportedRpc(String),
+    #[error("creation block {block} is ahead of the chain head at {head}")]
+    CreationBlockAhead { block: u64, head: u64 },
+    #[error("registry resolution error: {0}")]
+    RegistryResolution(anyhow::Error),
+    #[error("expected chain {expected}, but the provider serves chain {actual}")]
+    ChainIdMismatch { expected: ChainId, actual: ChainId },
+    #[error("checkpoint ends at block {0}, which is out of range")]
+    CheckpointOutOfRange(U256),
+}
+
+/// Why the transaction that emitted an event could not be decoded into the event's contents.
+#[derive(Debug, PartialEq, Eq, thiserror::Error)]
+pub enum EventParseError {
+    #[error("invalid call data: {0}")]
+    Abi(String),
+    #[error("dealer public key is neither P-384 nor X25519")]
+    InvalidDealerKey,
+    #[error("{0} shares were dealt, but at most {MAX_SHARES_PER_DEAL} are allowed")]
+    TooManyShares(usize),
+    #[error("share {index} is {length} bytes, which is too short to be an encrypted share")]
+    ShareTooShort { index: usize, length: usize },
+    #[error("{indices} share indices were given for {shares} shares")]
+    IndexCountMismatch { indices: usize, shares: usize },
+    #[error("share index {0} was given to more than one share")]
+    DuplicateShareIndex(u64),
+}
+
+impl From<ethers::abi::AbiError> for EventParseError {
+    fn from(e: ethers::abi::AbiError) -> Self {
+        Self::Abi(e.to_string())
+    }
+}
+
+impl<M: providers::Middleware> Error<M> {
+    /// Returns how long to back off for if the provider refused the request due to rate limiting.
+    fn rate_limit_backoff(&self) -> Option<Duration> {
+        match self {
+            Self::Contract(e) => e.as_middleware_error().and_then(rate_limit_backoff),
+            Self::RpcProvider(e) => rate_limit_backoff(e),
+            Self::Provider(e) => rate_limit_backoff(e),
+            Self::UnsupportedRpc(_)
+            | Self::CreationBlockAhead { .. }
+            | Self::RegistryResolution(_)
+            | Self::ChainIdMismatch { .. }
+            | Self::CheckpointOutOfRange(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        abi::AbiEncode as _,
+        providers::{JsonRpcError, MockError, ProviderError},
+    };
+
+    use super::*;
+    use crate::testing::mocked_provider;
+
+    fn rpc_error(code: i64, message: &str, data: Option<serde_json::Value>) -> ProviderError {
+        ProviderError::JsonRpcClientError(Box::new(MockError::JsonRpcError(JsonRpcError {
+            code,
+            message: message.into(),
+            data,
+        })))
+    }
+
+    #[tokio::test]
+    async fn decode_shares_dealt_dealer() {
+        use ethers::{abi::AbiEncode as _, contract::EthEvent as _};
+
+        let (provider, mock) = mocked_provider();
+        let hub = SsssHub::new(31337, Address::repeat_byte(1), provider);
+        let dealer = Address::repeat_byte(3);
+        let identity = H256::random();
+        let tx = TxHash::random();
+
+        let mut input = vec![0u8; 4];
+        input.extend(
+            (
+                identity,
+                "omni".to_string(),
+                U256::from(2),
+                Bytes::from(
+                    crate::identity::Identity::ephemeral()
+                        .public_key()
+                        .unwrap()
+                        .to_sec1_bytes()
+                        .to_vec(),
+                ),
+                H256::random(),
+                vec![Bytes::from(vec![0; TAG_SIZE + 1])],
+            )
+                .encode(),
+        );
+        mock.push(Transaction {
+            hash: tx,
+            from: dealer,
+            input: input.into(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let event = hub
+            .decode_permitter_event(Log {
+                address: hub.address,
+                topics: vec![SharesDealtFilter::signature()],
+                block_number: Some(5.into()),
+                transaction_hash: Some(tx),
+                log_index: Some(0.into()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        let EventKind::SharesDealt(dealt) = event.kind else {
+            panic!("unexpected event: {event:?}");
+        };
+        assert_eq!(dealt.dealer, dealer);
+        assert_eq!(dealt.identity, IdentityId(identity));
+        assert_eq!(dealt.version, 2);
+    }
+
+    #[tokio::test]
+    async fn reject_provider_of_another_chain() {
+        let (provider, mock) = mocked_provider();
+        let hub = SsssHub::new(1, Address::repeat_byte(1), provider);
+        mock.push(U64::from(5)).unwrap();
+        assert!(matches!(
+            hub.verify_chain_id().await,
+            Err(Error::ChainIdMismatch {
+                expected: 1,
+                actual: 5
+            })
+        ));
+
+        mock.push(U64::from(1)).unwrap();
+        hub.verify_chain_id().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn request_only_given_log_kinds() {
+        let (provider, mock) = mocked_provider();
+        let hub = SsssHub::new(31337, Address::repeat_byte(1), provider)
+            .with_log_kinds([LogKind::SharesDealt]);
+        mock.push::<Vec<Log>, _>(Vec::new()).unwrap();
+
+        assert!(hub.get_block_events(5, hub.address).await.0.is_empty());
+        let filter = Filter::new()
+            .select(5u64)
+            .address(ValueOrArray::Value(hub.address))
+            .topic0(vec![SharesDealtFilter::signature()]);
+        mock.assert_request("eth_getLogs", [filter]).unwrap();
+
+        let policy_log = Log {
+            topics: vec![PolicyChangeFilter::signature()],
+            ..Default::default()
+        };
+        assert!(!hub.requests_log(&policy_log));
+    }
+
+    #[tokio::test]
+    async fn log_decoded_calls_without_shares() {
+        use ethers::{abi::AbiEncode as _, contract::EthEvent as _};
+
+        let (provider, mock) = mocked_provider();
+        let hub = SsssHub::new(31337, Address::repeat_byte(1), provider).with_call_logging();
+        let identity = H256::repeat_byte(0xab);
+        let share = vec![0x5e; TAG_SIZE + 1];
+
+        let mut input = DealIndexedSharesCall::selector().to_vec();
+        input.extend(
+            (
+                identity,
+                "omni".to_string(),
+                U256::from(2),
+                Bytes::from(
+                    crate::identity::Identity::ephemeral()
+                        .public_key()
+                        .unwrap()
+                        .to_sec1_bytes()
+                        .to_vec(),
+                ),
+                H256::random(),
+                vec![7u64],
+                vec![Bytes::from(share.clone())],
+            )
+                .encode(),
+        );
+        mock.push(Transaction {
+            input: input.into(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        let capture = crate::testing::LogCapture::start();
+        hub.decode_permitter_event(Log {
+            address: hub.address,
+            topics: vec![SharesDealtFilter::signature()],
+            block_number: Some(5.into()),
+            transaction_hash: Some(TxHash::random()),
+            log_index: Some(0.into()),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        // The provider's own tracing logs the raw transaction, so check only the decoded call.
+        let logs = capture.logs();
+        let call = logs
+            .lines()
+            .find(|line| line.contains("decoded permitter call"))
+            .unwrap();
+        assert!(call.contains(&hex::encode(DealIndexedSharesCall::selector())));
+        assert!(call.contains(&format!("dealIndexedShares(identity: {identity:#x}")));
+        assert!(call.contains(r#"secretName: "omni", version: 2"#));
+        assert!(call.contains("indices: [7]"));
+        assert!(call.contains(r#"shares: ["<share_bytes>"; 1]"#));
+        assert!(!call.contains(&hex::encode(&share)));
+    }
+
+    #[test]
+    fn reject_short_dealt_shares() {
+        use ethers::abi::AbiEncode as _;
+
+        let encode_deal = |shares: Vec<Bytes>| {
+            (
+                H256::random(),
+                "omni".to_string(),
+                U256::from(1),
+                Bytes::from(
+                    crate::identity::Identity::ephemeral()
+                        .public_key()
+                        .unwrap()
+                        .to_sec1_bytes()
+                        .to_vec(),
+                ),
+                H256::random(),
+                shares,
+            )
+                .encode()
+        };
+        let dealer = Address::repeat_byte(3);
+
+        assert_eq!(
+            decode_shares_dealt(&encode_deal(vec![Bytes::new()]), dealer).unwrap_err(),
+            EventParseError::ShareTooShort {
+                index: 0,
+                length: 0
+            }
+        );
+        assert_eq!(
+            decode_shares_dealt(
+                &encode_deal(vec![vec![0; TAG_SIZE + 1].into(), vec![0; TAG_SIZE].into()]),
+                dealer
+            )
+            .unwrap_err(),
+            EventParseError::ShareTooShort {
+                index: 1,
+                length: TAG_SIZE
+            }
+        );
+        assert_eq!(
+            decode_shares_dealt(
+                &encode_deal(vec![vec![0; TAG_SIZE + 1].into(); MAX_SHARES_PER_DEAL + 1]),
+                dealer
+            )
+            .unwrap_err(),
+            EventParseError::TooManyShares(MAX_SHARES_PER_DEAL + 1)
+        );
+
+        let shares: Vec<Bytes> = (0..MAX_SHARES_PER_DEAL)
+            .map(|i| vec![i as u8; TAG_SIZE + 1 + i].into())
+            .collect();
+        let dealt = decode_shares_dealt(&encode_deal(shares.clone()), dealer).unwrap();
+        let SsScheme::Shamir {
+            shares: decoded, ..
+        } = dealt.scheme;
+        assert_eq!(decoded, shares);
+    }
+
+    #[test]
+    fn decode_indexed_dealt_shares() {
+        use ethers::abi::AbiEncode as _;
+
+        let encode_deal = |indices: Vec<u64>, count: usize| {
+            (
+                H256::random(),
+                "omni".to_string(),
+                U256::from(1),
+                Bytes::from(
+                    crate::identity::Identity::ephemeral()
+                        .public_key()
+                        .unwrap()
+                        .to_sec1_bytes()
+                        .to_vec(),
+                ),
+                H256::random(),
+                indices,
+                vec![Bytes::from(vec![0; TAG_SIZE + 1]); count],
+            )
+                .encode()
+        };
+        let dealer = Address::repeat_byte(3);
+
+        let dealt = decode_indexed_shares_dealt(&encode_deal(vec![9, 4], 2), dealer).unwrap();
+        let SsScheme::Shamir {
+            indices, indexed, ..
+        } = dealt.scheme;
+        assert_eq!(indices, vec![ShareIndex(9), ShareIndex(4)]);
+        assert!(indexed);
+
+        assert_eq!(
+            decode_indexed_shares_dealt(&encode_deal(vec![9], 2), dealer).unwrap_err(),
+            EventParseError::IndexCountMismatch {
+                indices: 1,
+                shares: 2
+            }
+        );
+        assert_eq!(
+            decode_indexed_shares_dealt(&encode_deal(vec![9, 4, 9], 3), dealer).unwrap_err(),
+            EventParseError::DuplicateShareIndex(9)
+        );
+    }
+
+    #[tokio::test]
+    async fn block_events_follow_log_order_once() {
+        use ethers::{abi::AbiEncode as _, contract::EthEvent as _};
+
+        let cases: [(&[u64], &[u64]); 2] = [
+            // Out of order.
+            (&[2, 0, 1], &[0, 1, 2]),
+            // Duplicated.
+            (&[0, 1, 0], &[0, 1]),
+        ];
+        for (log_indices, expected) in cases {
+            let (provider, mock) = mocked_provider();
+            let hub = SsssHub::new(31337, Address::repeat_byte(1), provider);
+            let tx = TxHash::random();
+
+            let mut input = vec![0u8; 4];
+            input.extend((H256::random(), Bytes::from_static(b"policy")).encode());
+            for _ in log_indices {
+                mock.push(Transaction {
+                    hash: tx,
+                    input: input.clone().into(),
+                    ..Default::default()
+                })
+                .unwrap();
+            }
+            let logs: Vec<_> = log_indices
+                .iter()
+                .map(|&log_index| Log {
+                    address: hub.address,
+                    topics: vec![PolicyChangeFilter::signature()],
+                    block_number: Some(5.into()),
+                    transaction_hash: Some(tx),
+                    log_index: Some(log_index.into()),
+                    ..Default::default()
+                })
+                .collect();
+            mock.push::<Vec<Log>, _>(logs).unwrap();
+
+            let indices: Vec<_> = hub
+                .get_block_events(5, hub.address)
+                .await
+                .0
+                .into_iter()
+                .map(|event| event.index.log_index)
+                .collect();
+            assert_eq!(indices, expected, "logs {log_indices:?}");
+        }
+    }
+
+    #[tokio::test]
+    async fn processed_block_hash_comes_from_logs() {
+        use ethers::{abi::AbiEncode as _, contract::EthEvent as _};
+
+        let (provider, mock) = mocked_provider();
+        let hub = SsssHub::new(31337, Address::repeat_byte(1), provider);
+        let (tx, block_hash) = (TxHash::random(), H256::random());
+        let mut input = vec![0u8; 4];
+        input.extend((H256::random(), Bytes::from_static(b"policy")).encode());
+        mock.push(Transaction {
+            hash: tx,
+            input: input.into(),
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push::<Vec<Log>, _>(vec![Log {
+            address: hub.address,
+            topics: vec![PolicyChangeFilter::signature()],
+            block_hash: Some(block_hash),
+            block_number: Some(5.into()),
+            transaction_hash: Some(tx),
+            log_index: Some(0.into()),
+            ..Default::default()
+        }])
+        .unwrap();
+
+        // No block header is available, so fetching one would never finish.
+        let events = tokio::time::timeout(
+            std::time::Duration::from_secs(5),
+            hub.get_processed_block_events(5),
+        )
+        .await
+        .unwrap();
+        let [policy_change, processed] = &events[..] else {
+            panic!("unexpected events: {events:?}");
+        };
+        assert!(matches!(policy_change.kind, EventKind::PolicyChange(_)));
+        assert!(matches!(
+            processed.kind,
+            EventKind::ProcessedBlock { block_hash: hash } if hash == block_hash
+        ));
+    }
+
+    #[tokio::test]
+    async fn resolve_registry() {
+        use ethers::abi::AbiEncode as _;
+
+        struct PerChainRegistry;
+        impl RegistryResolver for PerChainRegistry {
+            fn resolve(
+                &self,
+                chain: ChainId,
+                permitter: Address,
+            ) -> BoxFuture<'_, anyhow::Result<Address>> {
+                async move {
+                    anyhow::ensure!(chain == 31337, "unknown chain {chain}");
+                    Ok(Address::from_low_u64_be(permitter.to_low_u64_be() + 1))
+                }
+                .boxed()
+            }
+        }
+
+        let (provider, mock) = mocked_provider();
+        mock.push::<Bytes, Bytes>(Address::repeat_byte(2).encode().into())
+            .unwrap();
+        let hub = SsssHub::new(31337, Address::from_low_u64_be(1), provider.clone());
+        assert_eq!(hub.registry().await.unwrap(), Address::repeat_byte(2));
+
+        let hub = hub.with_registry_resolver(FixedRegistry(Address::repeat_byte(3)));
+        assert_eq!(hub.registry().await.unwrap(), Address::repeat_byte(3));
+
+        let hub = hub.with_registry_resolver(PerChainRegistry);
+        assert_eq!(hub.registry().await.unwrap(), Address::from_low_u64_be(2));
+        let hub = SsssHub::new(1, Address::from_low_u64_be(1), provider)
+            .with_registry_resolver(PerChainRegistry);
+        assert!(matches!(
+            hub.registry().await,
+            Err(Error::RegistryResolution(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn pinned_creation_block() {
+        let (provider, mock) = mocked_provider();
+        let hub = SsssHub::new(31337, Address::repeat_byte(1), provider).with_creation_block(5);
+        // Only the head is requested, and only once.
+        mock.push(U64::from(10)).unwrap();
+        assert_eq!(hub.creation_block().await.unwrap(), 5);
+        assert_eq!(hub.creation_block().await.unwrap(), 5);
+
+        let (provider, mock) = mocked_provider();
+        let hub = SsssHub::new(31337, Address::repeat_byte(1), provider).with_creation_block(5);
+        mock.push(U64::from(4)).unwrap();
+        assert!(matches!(
+            hub.creation_block().await,
+            Err(Error::CreationBlockAhead { block: 5, head: 4 })
+        ));
+    }
+
+    #[test]
+    fn processed_block_never_regresses() {
+        let (provider, _mock) = mocked_provider();
+        let hub = SsssHub::new(31337, Address::repeat_byte(1), provider);
+        assert_eq!(hub.processed_block(), 0);
+        hub.set_processed_block(10);
+        hub.set_processed_block(7);
+        assert_eq!(hub.processed_block(), 10);
+    }
+
+    #[test]
+    fn sync_progress_is_clamped() {
+        assert_eq!(sync_progress(100, 0, 199), 0.0);
+        assert_eq!(sync_progress(100, 100, 199), 0.01);
+        assert_eq!(sync_progress(100, 149, 199), 0.5);
+        assert_eq!(sync_progress(100, 199, 199), 1.0);
+        // The head may be seen before the block that reached it is processed, or lag behind it.
+        assert_eq!(sync_progress(100, 250, 199), 1.0);
+        assert_eq!(sync_progress(100, 100, 100), 1.0);
+    }
+
+    #[tokio::test]
+    async fn current_block_is_cached() {
+        let (provider, mock) = mocked_provider();
+        let hub = SsssHub::new(31337, Address::repeat_byte(1), provider);
+        assert_eq!(hub.last_known_head(), None);
+
+        mock.push(U64::from(12345)).unwrap();
+        assert_eq!(hub.current_block().await.unwrap(), 12345);
+        // The provider has no more responses, so this must be the cached head.
+        assert_eq!(hub.current_block().await.unwrap(), 12345);
+        assert_eq!(hub.last_known_head(), Some(12345));
+
+        // A lagging provider does not move the known head backwards.
+        mock.push(U64::from(12000)).unwrap();
+        assert_eq!(hub.head_block().await.unwrap(), 12345);
+    }
+
+    #[tokio::test]
+    async fn blocks_wait_until_finalized() {
+        let (provider, mock) = mocked_provider();
+        let hub =
+            SsssHub::new(31337, Address::repeat_byte(1), provider).with_finality(FinalizedTag);
+        let finalized = |number: u64| ethers::types::Block::<TxHash> {
+            number: Some(number.into()),
+            ..Default::default()
+        };
+        mock.push(finalized(8)).unwrap();
+        mock.push(U64::from(11)).unwrap();
+        mock.push(finalized(7)).unwrap();
+        mock.push(U64::from(10)).unwrap();
+
+        // The confirmations given are ignored in favor of the chain's own finality.
+        let blocks: Vec<u64> = hub.blocks(5, 100).await.take(4).collect().await;
+        assert_eq!(blocks, vec![5, 6, 7, 8]);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn blocks_follow_finality_rule() {
+        let (provider, mock) = mocked_provider();
+        let hub = SsssHub::new(31337, Address::repeat_byte(1), provider)
+            .with_finality(FinalityRule::Instant);
+        mock.push(U64::from(10)).unwrap();
+        let blocks: Vec<u64> = hub.blocks(5, 100).await.take(6).collect().await;
+        assert_eq!(blocks, vec![5, 6, 7, 8, 9, 10]);
+
+        let (provider, mock) = mocked_provider();
+        let hub = SsssHub::new(31337, Address::repeat_byte(1), provider)
+            .with_finality(FinalityRule::Confirmations(12));
+        mock.push(U64::from(20)).unwrap();
+        mock.push(U64::from(16)).unwrap();
+        mock.push(U64::from(10)).unwrap();
+        // Block 5 is held back until the head is 17, ignoring the confirmations given.
+        let blocks: Vec<u64> = hub.blocks(5, 0).await.take(4).collect().await;
+        assert_eq!(blocks, vec![5, 6, 7, 8]);
+        for _ in 0..3 {
+            mock.assert_request("eth_blockNumber", ()).unwrap();
+        }
+
+        let (provider, mock) = mocked_provider();
+        let contract = Address::repeat_byte(2);
+        let hub = SsssHub::new(31337, Address::repeat_byte(1), provider)
+            .with_finality(FinalityRule::Checkpoint { contract });
+        // Epoch 3 checkpointed blocks 5 through 8.
+        mock.push::<Bytes, Bytes>((U256::from(5), U256::from(8)).encode().into())
+            .unwrap();
+        mock.push::<Bytes, Bytes>(U256::from(3).encode().into())
+            .unwrap();
+        mock.push(U64::from(12)).unwrap();
+        let blocks: Vec<u64> = hub.blocks(5, 0).await.take(4).collect().await;
+        assert_eq!(blocks, vec![5, 6, 7, 8]);
+        let call = |data: Vec<u8>| -> ethers::types::transaction::eip2718::TypedTransaction {
+            ethers::types::TransactionRequest::new()
+                .to(contract)
+                .data(data)
+                .into()
+        };
+        mock.assert_request("eth_blockNumber", ()).unwrap();
+        mock.assert_request(
+            "eth_call",
+            (call(LatestCheckpointEpochCall.encode()), "latest"),
+        )
+        .unwrap();
+        mock.assert_request(
+            "eth_call",
+            (
+                call(
+                    CheckpointCall {
+                        epoch: U256::from(3),
+                    }
+                    .encode(),
+                ),
+                "latest",
+            ),
+        )
+        .unwrap();
+
+        let (provider, mock) = mocked_provider();
+        mock.push::<Bytes, Bytes>((U256::zero(), U256::MAX).encode().into())
+            .unwrap();
+        mock.push::<Bytes, Bytes>(U256::one().encode().into())
+            .unwrap();
+        assert!(matches!(
+            FinalityRule::Checkpoint { contract }
+                .final_block(&provider, 12)
+                .await,
+            Err(Error::CheckpointOutOfRange(end)) if end == U256::MAX
+        ));
+    }
+
+    #[test]
+    fn classify_rate_limit_errors() {
+        assert_eq!(
+            rate_limit_backoff(&rpc_error(429, "slow down", None)),
+            Some(RATE_LIMIT_BACKOFF)
+        );
+        assert_eq!(
+            rate_limit_backoff(&rpc_error(
+                -32005,
+                "daily request count exceeded",
+                Some(serde_json::json!({ "rate": { "backoff_seconds": 4 } }))
+            )),
+            Some(Duration::from_secs(4))
+        );
+        assert_eq!(
+            rate_limit_backoff(&rpc_error(
+                -32000,
+                "Rate limit reached",
+                Some(serde_json::json!({ "retry_after": 1e9 }))
+            )),
+            Some(MAX_RATE_LIMIT_BACKOFF)
+        );
+        assert_eq!(
+            rate_limit_backoff(&ProviderError::CustomError(
+                "HTTP status client error (429 Too Many Requests)".into()
+            )),
+            Some(RATE_LIMIT_BACKOFF)
+        );
+        assert_eq!(
+            rate_limit_backoff(&rpc_error(-32000, "execution reverted", None)),
+            None
+        );
+        assert_eq!(
+            rate_limit_backoff(&ProviderError::CustomError("connection reset".into())),
+            None
+        );
+    }
 }
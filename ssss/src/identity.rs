@@ -1,34 +1,292 @@
-use aes_gcm_siv::{Aes256GcmSiv, KeyInit as _};
+use aes_gcm_siv::{AeadInPlace as _, Aes256GcmSiv, KeyInit as _};
 
-#[derive(Clone, Copy)]
+/// The key agreement that an identity uses to derive ciphers shared with its peers.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CryptoMode {
+    /// ECDH on P-384.
+    #[default]
+    P384,
+    /// X25519, whose public keys are 32 bytes.
+    X25519,
+}
+
+/// The secret key is zeroed when the identity is dropped, so identities are cloned rather than
+/// copied.
+#[derive(Clone, zeroize::Zeroize, zeroize::ZeroizeOnDrop)]
 pub struct Identity {
-    sk: p384::NonZeroScalar,
+    sk: SecretKey,
+}
+
+#[derive(Clone, zeroize::Zeroize)]
+enum SecretKey {
+    P384(p384::NonZeroScalar),
+    X25519([u8; 32]),
+}
+
+/// The public half of an identity of either mode, as carried by events and requests.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PeerKey {
+    P384(p384::PublicKey),
+    X25519(x25519_dalek::PublicKey),
 }
 
 pub static DEAL_SHARES_DOMAIN_SEP: &[u8] = b"deal-shares";
 pub static GET_SHARE_DOMAIN_SEP: &[u8] = b"get-share";
+pub static SHARE_RECEIPT_DOMAIN_SEP: &[u8] = b"share-receipt";
+
+static SELF_TEST_DOMAIN_SEP: &[u8] = b"self-test";
+static SELF_TEST_PLAINTEXT: &[u8] = b"ssss identity self-test";
+/// The plaintext as encrypted under zero nonces by the cipher that the test key `[1; _]` shares
+/// with the test key `[2; _]` in each mode.
+static P384_KNOWN_ANSWER: &str =
+    "b7176543c4556e1c42fdf52c479ef6e3bce4af645b81c67247632d6078c5773b7d1dbb9d367d17";
+static X25519_KNOWN_ANSWER: &str =
+    "336097434694234b0fd01723dad5671907afb51e68d351429943017e0a7854172b6e1a6fcccf5f";
 
 impl Identity {
     pub fn persistent(sk: p384::SecretKey) -> Self {
         let scalar = sk.to_nonzero_scalar();
-        Self { sk: scalar }
+        Self {
+            sk: SecretKey::P384(scalar),
+        }
+    }
+
+    /// Creates a persistent identity from a secret holding the raw scalar bytes.
+    pub fn from_secret(secret: &crate::secrets::Secret) -> Result<Self, crate::secrets::Error> {
+        let sk = p384::SecretKey::from_slice(secret.expose())
+            .map_err(|_| crate::secrets::Error::InvalidKey)?;
+        Ok(Self::persistent(sk))
     }
 
+    pub fn from_x25519(key: x25519_dalek::StaticSecret) -> Self {
+        Self {
+            sk: SecretKey::X25519(key.to_bytes()),
+        }
+    }
+
+    /// Generates a P-384 identity.
     pub fn ephemeral() -> Self {
         Self {
-            sk: p384::NonZeroScalar::random(&mut rand::thread_rng()),
+            sk: SecretKey::P384(p384::NonZeroScalar::random(&mut rand::thread_rng())),
+        }
+    }
+
+    fn random(mode: CryptoMode) -> Self {
+        match mode {
+            CryptoMode::P384 => Self::ephemeral(),
+            CryptoMode::X25519 => Self::from_x25519(x25519_dalek::StaticSecret::random_from_rng(
+                rand::thread_rng(),
+            )),
+        }
+    }
+
+    /// Checks that key agreement in this identity's mode gives known answers, and that a dealer
+    /// who knows only this identity's encoded public key derives the same ciphers as it does, so
+    /// that a misconfigured identity fails at startup rather than as shares that never decrypt.
+    pub fn self_test(&self) -> Result<(), Error> {
+        let (test, test_peer, known_answer) = match self.mode() {
+            CryptoMode::P384 => (
+                Self::persistent(p384::SecretKey::from_slice(&[1; 48]).unwrap()),
+                Self::persistent(p384::SecretKey::from_slice(&[2; 48]).unwrap()),
+                P384_KNOWN_ANSWER,
+            ),
+            CryptoMode::X25519 => (
+                Self::from_x25519([1; 32].into()),
+                Self::from_x25519([2; 32].into()),
+                X25519_KNOWN_ANSWER,
+            ),
+        };
+        let mut msg = SELF_TEST_PLAINTEXT.to_vec();
+        test.try_shared_cipher(&test_peer.peer_key(), SELF_TEST_DOMAIN_SEP)?
+            .encrypt_in_place(&Default::default(), &[], &mut msg)
+            .map_err(|_| Error::SelfTestFailed("encryption failed"))?;
+        if hex::encode(&msg) != known_answer {
+            return Err(Error::SelfTestFailed(
+                "key agreement did not give the known answer",
+            ));
+        }
+
+        let dealer = Self::random(self.mode());
+        let mut msg = SELF_TEST_PLAINTEXT.to_vec();
+        dealer
+            .try_derive_shared_cipher(&self.peer_key().to_bytes(), SELF_TEST_DOMAIN_SEP)?
+            .encrypt_in_place(&Default::default(), &[], &mut msg)
+            .map_err(|_| Error::SelfTestFailed("encryption failed"))?;
+        self.try_derive_shared_cipher(&dealer.peer_key().to_bytes(), SELF_TEST_DOMAIN_SEP)?
+            .decrypt_in_place(&Default::default(), &[], &mut msg)
+            .map_err(|_| Error::SelfTestFailed("a dealer's cipher does not match this identity's"))
+    }
+
+    pub fn mode(&self) -> CryptoMode {
+        match self.sk {
+            SecretKey::P384(_) => CryptoMode::P384,
+            SecretKey::X25519(_) => CryptoMode::X25519,
         }
     }
 
+    /// Panics if this is not a P-384 identity.
+    #[deprecated(note = "use `try_derive_shared_cipher`, which validates the peer key")]
     pub fn derive_shared_cipher(&self, opk: p384::PublicKey, hkdf_info: &[u8]) -> Aes256GcmSiv {
-        derive_shared_cipher(&self.sk, &opk, hkdf_info)
+        self.shared_cipher(&PeerKey::P384(opk), hkdf_info)
+            .expect("not a P-384 identity")
+    }
+
+    /// Panics if this is not an X25519 identity.
+    #[deprecated(note = "use `try_derive_shared_cipher`, which validates the peer key")]
+    pub fn derive_shared_cipher_x25519(
+        &self,
+        opk: &x25519_dalek::PublicKey,
+        hkdf_info: &[u8],
+    ) -> Aes256GcmSiv {
+        self.shared_cipher(&PeerKey::X25519(*opk), hkdf_info)
+            .expect("not an X25519 identity")
+    }
+
+    /// Derives the cipher shared with the peer whose encoded public key is `opk`, rejecting keys
+    /// that are not valid points or that would yield a predictable shared secret.
+    pub fn try_derive_shared_cipher(
+        &self,
+        opk: &[u8],
+        hkdf_info: &[u8],
+    ) -> Result<Aes256GcmSiv, Error> {
+        let opk = PeerKey::from_bytes(opk).ok_or(Error::InvalidPeerKey)?;
+        self.try_shared_cipher(&opk, hkdf_info)
+    }
+
+    /// Derives the cipher shared with the peer, or returns `None` if the peer's key is of
+    /// another mode or is invalid.
+    pub fn shared_cipher(&self, opk: &PeerKey, hkdf_info: &[u8]) -> Option<Aes256GcmSiv> {
+        self.try_shared_cipher(opk, hkdf_info).ok()
+    }
+
+    pub fn try_shared_cipher(
+        &self,
+        opk: &PeerKey,
+        hkdf_info: &[u8],
+    ) -> Result<Aes256GcmSiv, Error> {
+        match (&self.sk, opk) {
+            // Decoding a P-384 key already rejects the identity and off-curve points, and the
+            // curve has cofactor 1, so every remaining point is in the prime-order subgroup.
+            (SecretKey::P384(sk), PeerKey::P384(opk)) => {
+                Ok(derive_shared_cipher(sk, opk, hkdf_info))
+            }
+            (SecretKey::X25519(sk), PeerKey::X25519(opk)) => {
+                let shared = x25519_dalek::StaticSecret::from(*sk).diffie_hellman(opk);
+                // Low-order points such as all zeros force the shared secret to zero.
+                if !shared.was_contributory() {
+                    return Err(Error::InvalidPeerKey);
+                }
+                Ok(hkdf_cipher(
+                    b"ssss_x25519_aes-256-gcm-siv",
+                    shared.as_bytes(),
+                    hkdf_info,
+                ))
+            }
+            (_, opk) => Err(Error::ModeMismatch(opk.mode())),
+        }
+    }
+
+    /// Returns the P-384 public key, or `None` if this is not a P-384 identity.
+    pub fn public_key(&self) -> Option<p384::PublicKey> {
+        match self.peer_key() {
+            PeerKey::P384(pk) => Some(pk),
+            PeerKey::X25519(_) => None,
+        }
+    }
+
+    /// Signs the message with ECDSA over SHA-384, or returns `None` if this is not a P-384
+    /// identity.
+    pub fn sign(&self, msg: &[u8]) -> Option<p384::ecdsa::Signature> {
+        use p384::ecdsa::signature::Signer as _;
+        match &self.sk {
+            SecretKey::P384(sk) => Some(p384::ecdsa::SigningKey::from(*sk).sign(msg)),
+            SecretKey::X25519(_) => None,
+        }
+    }
+
+    pub fn peer_key(&self) -> PeerKey {
+        match &self.sk {
+            SecretKey::P384(sk) => PeerKey::P384(p384::PublicKey::from_secret_scalar(sk)),
+            SecretKey::X25519(sk) => {
+                PeerKey::X25519((&x25519_dalek::StaticSecret::from(*sk)).into())
+            }
+        }
+    }
+}
+
+impl PeerKey {
+    /// Decodes a raw 32-byte X25519 key or a SEC1-encoded P-384 key.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        match <[u8; 32]>::try_from(bytes) {
+            Ok(x25519) => Some(Self::X25519(x25519.into())),
+            Err(_) => p384::PublicKey::from_sec1_bytes(bytes).ok().map(Self::P384),
+        }
     }
 
-    pub fn public_key(&self) -> p384::PublicKey {
-        p384::PublicKey::from_secret_scalar(&self.sk)
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::P384(pk) => pk.to_sec1_bytes().into_vec(),
+            Self::X25519(pk) => pk.as_bytes().to_vec(),
+        }
+    }
+
+    pub fn mode(&self) -> CryptoMode {
+        match self {
+            Self::P384(_) => CryptoMode::P384,
+            Self::X25519(_) => CryptoMode::X25519,
+        }
     }
 }
 
+impl From<p384::PublicKey> for PeerKey {
+    fn from(pk: p384::PublicKey) -> Self {
+        Self::P384(pk)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("invalid peer public key")]
+    InvalidPeerKey,
+    #[error("peer key of mode {0:?} does not match this identity")]
+    ModeMismatch(CryptoMode),
+    #[error("identity self-test failed: {0}")]
+    SelfTestFailed(&'static str),
+    #[error("share encryption failed")]
+    ShareEncryption,
+}
+
+/// Encrypts a served share under the key that `sender` agrees with the requester, returning the
+/// nonce and the encrypted share.
+pub fn encrypt_share(
+    sender: &Identity,
+    requester_pk: &p384::PublicKey,
+    share: &[u8],
+) -> Result<([u8; 12], Vec<u8>), Error> {
+    let cipher = sender.try_shared_cipher(&PeerKey::P384(*requester_pk), GET_SHARE_DOMAIN_SEP)?;
+    let mut nonce = aes_gcm_siv::Nonce::default();
+    rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut nonce);
+    let mut enc_share = share.to_vec();
+    cipher
+        .encrypt_in_place(&nonce, &[], &mut enc_share)
+        .map_err(|_| Error::ShareEncryption)?;
+    Ok((nonce.into(), enc_share))
+}
+
+/// Like [`encrypt_share`], but from a key pair generated for this share alone, whose public key
+/// is returned with the nonce and the encrypted share.
+pub fn encapsulate_share(
+    requester_pk: &p384::PublicKey,
+    share: &[u8],
+) -> Result<(p384::PublicKey, [u8; 12], Vec<u8>), Error> {
+    let sk = p384::NonZeroScalar::random(&mut rand::thread_rng());
+    let sender = Identity {
+        sk: SecretKey::P384(sk),
+    };
+    let (nonce, enc_share) = encrypt_share(&sender, requester_pk, share)?;
+    Ok((p384::PublicKey::from_secret_scalar(&sk), nonce, enc_share))
+}
+
 pub fn derive_shared_cipher(
     sk: &p384::NonZeroScalar,
     opk: &p384::PublicKey,
@@ -40,3 +298,145 @@ pub fn derive_shared_cipher(
     hkdf.expand(hkdf_info, &mut aes_key).unwrap();
     Aes256GcmSiv::new_from_slice(&aes_key).unwrap()
 }
+
+fn hkdf_cipher(salt: &[u8], shared: &[u8], hkdf_info: &[u8]) -> Aes256GcmSiv {
+    let hkdf = hkdf::Hkdf::<sha2::Sha256>::new(Some(salt), shared);
+    let mut aes_key = zeroize::Zeroizing::new([0u8; 32]);
+    hkdf.expand(hkdf_info, &mut *aes_key).unwrap();
+    Aes256GcmSiv::new_from_slice(&*aes_key).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use aes_gcm_siv::AeadInPlace as _;
+
+    use super::*;
+
+    #[test]
+    fn x25519_shared_cipher() {
+        let node = Identity::from_x25519(x25519_dalek::StaticSecret::random_from_rng(
+            rand::thread_rng(),
+        ));
+        let dealer = Identity::from_x25519(x25519_dalek::StaticSecret::random_from_rng(
+            rand::thread_rng(),
+        ));
+        assert_eq!(node.mode(), CryptoMode::X25519);
+        assert_eq!(
+            PeerKey::from_bytes(&node.peer_key().to_bytes()),
+            Some(node.peer_key())
+        );
+
+        let nonce = aes_gcm_siv::Nonce::default();
+        let mut share = b"share".to_vec();
+        dealer
+            .try_derive_shared_cipher(&node.peer_key().to_bytes(), DEAL_SHARES_DOMAIN_SEP)
+            .unwrap()
+            .encrypt_in_place(&nonce, &[], &mut share)
+            .unwrap();
+        node.shared_cipher(&dealer.peer_key(), DEAL_SHARES_DOMAIN_SEP)
+            .unwrap()
+            .decrypt_in_place(&nonce, &[], &mut share)
+            .unwrap();
+        assert_eq!(share, b"share");
+
+        // Keys of different modes cannot agree on a cipher.
+        assert!(node
+            .shared_cipher(&Identity::ephemeral().peer_key(), DEAL_SHARES_DOMAIN_SEP)
+            .is_none());
+    }
+
+    #[test]
+    fn reject_invalid_peer_keys() {
+        let p384 = Identity::ephemeral();
+        let x25519 = Identity::from_x25519(x25519_dalek::StaticSecret::random_from_rng(
+            rand::thread_rng(),
+        ));
+        // The SEC1 encoding of the point at infinity.
+        assert!(matches!(
+            p384.try_derive_shared_cipher(&[0], DEAL_SHARES_DOMAIN_SEP),
+            Err(Error::InvalidPeerKey)
+        ));
+        assert!(matches!(
+            x25519.try_derive_shared_cipher(&[0; 32], DEAL_SHARES_DOMAIN_SEP),
+            Err(Error::InvalidPeerKey)
+        ));
+        assert!(matches!(
+            p384.try_derive_shared_cipher(&[0; 49], DEAL_SHARES_DOMAIN_SEP),
+            Err(Error::InvalidPeerKey)
+        ));
+        assert!(matches!(
+            p384.try_derive_shared_cipher(&x25519.peer_key().to_bytes(), DEAL_SHARES_DOMAIN_SEP),
+            Err(Error::ModeMismatch(CryptoMode::X25519))
+        ));
+    }
+
+    #[test]
+    fn signed_share_receipt() {
+        let node = Identity::ephemeral();
+        let mut receipt = crate::types::ShareReceipt {
+            share: crate::types::ShareId {
+                secret_name: "omni".into(),
+                identity: crate::types::IdentityLocator {
+                    chain: 31337,
+                    registry: ethers::types::Address::repeat_byte(1),
+                    id: crate::types::IdentityId(ethers::types::H256::repeat_byte(2)),
+                },
+                version: 1,
+            },
+            event: crate::types::EventIndex {
+                block: 5,
+                log_index: 1,
+            },
+        };
+        let signature = node.sign(&receipt.signing_bytes()).unwrap();
+        let node_pk = node.public_key().unwrap();
+        assert!(receipt.verify(&node_pk, &signature));
+        assert!(!receipt.verify(&Identity::ephemeral().public_key().unwrap(), &signature));
+
+        receipt.event.log_index += 1;
+        assert!(!receipt.verify(&node_pk, &signature));
+    }
+
+    #[test]
+    fn self_test_passes_in_each_mode() {
+        Identity::ephemeral().self_test().unwrap();
+        Identity::random(CryptoMode::X25519).self_test().unwrap();
+    }
+
+    #[test]
+    fn only_p384_identities_sign() {
+        let x25519 = Identity::random(CryptoMode::X25519);
+        assert!(x25519.public_key().is_none());
+        assert!(x25519.sign(b"receipt").is_none());
+        assert!(Identity::ephemeral().sign(b"receipt").is_some());
+    }
+
+    static_assertions::assert_impl_all!(Identity: zeroize::ZeroizeOnDrop);
+
+    #[test]
+    fn secret_key_is_zeroed_on_drop() {
+        let mut x25519 = std::mem::MaybeUninit::new(Identity::from_x25519([7; 32].into()));
+        // SAFETY: the identity is initialized until it is dropped in place, after which its
+        // memory remains allocated, so the key can still be read.
+        unsafe {
+            let SecretKey::X25519(key) = &x25519.assume_init_ref().sk else {
+                unreachable!()
+            };
+            let key: *const [u8; 32] = key;
+            x25519.assume_init_drop();
+            assert_eq!(*key, [0; 32]);
+        }
+
+        let mut p384 = std::mem::MaybeUninit::new(Identity::ephemeral());
+        // SAFETY: as above.
+        unsafe {
+            let SecretKey::P384(scalar) = &p384.assume_init_ref().sk else {
+                unreachable!()
+            };
+            let scalar: *const p384::NonZeroScalar = scalar;
+            p384.assume_init_drop();
+            // Zeroing a non-zero scalar leaves it at one.
+            assert_eq!(**scalar, p384::Scalar::ONE);
+        }
+    }
+}
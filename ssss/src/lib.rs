@@ -1,5 +1,10 @@
 pub mod eth;
 pub mod identity;
+pub mod metrics;
+pub mod secrets;
 pub mod store;
+pub mod sync;
+#[cfg(test)]
+mod testing;
 pub mod types;
 pub mod utils;
@@ -2,24 +2,44 @@
 
 mod api;
 mod cli;
-mod sync;
 mod verify;
 
 use std::collections::HashMap;
 
 use anyhow::Result;
 use ethers::middleware::MiddlewareBuilder as _;
+use opentelemetry_otlp::WithExportConfig as _;
 use ssss::{
-    eth,
-    store::{self, Store as _},
-    types, utils,
+    eth, metrics,
+    store::{self, Store},
+    sync, types, utils,
 };
-use tracing::{debug, trace};
+use tracing::{debug, info, trace};
+use tracing_subscriber::{layer::SubscriberExt as _, util::SubscriberInitExt as _};
 
 #[tokio::main]
 async fn main() -> Result<()> {
     let args = cli::Args::parse();
 
+    let otel_tracer = match &args.otlp_endpoint {
+        Some(endpoint) => Some(
+            opentelemetry_otlp::new_pipeline()
+                .tracing()
+                .with_exporter(
+                    opentelemetry_otlp::new_exporter()
+                        .tonic()
+                        .with_endpoint(endpoint),
+                )
+                .with_trace_config(opentelemetry_sdk::trace::config().with_resource(
+                    opentelemetry_sdk::Resource::new([opentelemetry::KeyValue::new(
+                        "service.name",
+                        "ssss",
+                    )]),
+                ))
+                .install_batch(opentelemetry_sdk::runtime::Tokio)?,
+        ),
+        None => None,
+    };
     let subscriber = tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .with_env_filter(match args.verbosity {
@@ -30,13 +50,25 @@ async fn main() -> Result<()> {
         })
         .with_target(true);
     if cfg!(not(debug_assertions)) {
-        subscriber.json().with_ansi(false).init();
+        subscriber
+            .json()
+            .with_ansi(false)
+            .finish()
+            .with(otel_tracer.map(|t| tracing_opentelemetry::layer().with_tracer(t)))
+            .init();
     } else {
-        subscriber.without_time().init();
+        subscriber
+            .without_time()
+            .finish()
+            .with(otel_tracer.map(|t| tracing_opentelemetry::layer().with_tracer(t)))
+            .init();
     }
 
     debug!(args = ?args, "loaded config");
 
+    utils::set_retry_budget(utils::RetryBudget::new(args.retry_burst, args.retry_rate)?)
+        .expect("retry budget already set");
+
     trace!("loading providers");
     let providers = eth::providers(args.gateway.iter()).await?;
     let permitters: HashMap<_, _> = args.permitter.into_iter().collect();
@@ -51,51 +83,221 @@ async fn main() -> Result<()> {
             missing_providers.join(", ")
         );
     }
+    let creation_blocks: HashMap<_, _> = args.creation_block.into_iter().collect();
+    if let Some(chain) = creation_blocks
+        .keys()
+        .find(|chain| !permitters.contains_key(chain))
+    {
+        anyhow::bail!("creation block given for chain {chain}, which has no permitter");
+    }
+    let registries: HashMap<_, _> = args.registry.into_iter().collect();
+    if let Some(chain) = registries
+        .keys()
+        .find(|chain| !permitters.contains_key(chain))
+    {
+        anyhow::bail!("registry given for chain {chain}, which has no permitter");
+    }
+    if let Some(chain) = args
+        .finalized_chain
+        .iter()
+        .find(|chain| !permitters.contains_key(chain))
+    {
+        anyhow::bail!("finality given for chain {chain}, which has no permitter");
+    }
+    let finality_rules: HashMap<_, _> = args.finality.into_iter().collect();
+    if let Some(chain) = finality_rules
+        .keys()
+        .find(|chain| !permitters.contains_key(chain))
+    {
+        anyhow::bail!("finality rule given for chain {chain}, which has no permitter");
+    }
+    if let Some(chain) = args
+        .finalized_chain
+        .iter()
+        .find(|chain| finality_rules.contains_key(chain))
+    {
+        anyhow::bail!("chain {chain} is given both a finality rule and --finalized-chain");
+    }
+    let log_kinds: HashMap<_, _> = args.log_kinds.into_iter().collect();
+    if let Some(chain) = log_kinds
+        .keys()
+        .find(|chain| !permitters.contains_key(chain))
+    {
+        anyhow::bail!("log kinds given for chain {chain}, which has no permitter");
+    }
     let signer = ethers::signers::LocalWallet::new(&mut rand::thread_rng());
     let sssss: Vec<_> = providers
         .into_iter()
         .filter_map(|(chain, provider)| {
             let permitter = permitters.get(&chain)?;
-            Some(eth::SsssHub::new(
-                chain,
-                *permitter,
-                provider.with_signer(signer.clone()),
-            ))
+            let ssss = eth::SsssHub::new(chain, *permitter, provider.with_signer(signer.clone()));
+            let ssss = match creation_blocks.get(&chain) {
+                Some(&block) => ssss.with_creation_block(block),
+                None => ssss,
+            };
+            let ssss = match registries.get(&chain) {
+                Some(&registry) => ssss.with_registry_resolver(eth::FixedRegistry(registry)),
+                None => ssss,
+            };
+            let ssss = match args.finalized_chain.contains(&chain) {
+                true => ssss.with_finality(eth::FinalizedTag),
+                false => ssss,
+            };
+            let ssss = match finality_rules.get(&chain) {
+                Some(&rule) => ssss.with_finality(rule),
+                None => ssss,
+            };
+            let ssss = match log_kinds.get(&chain) {
+                Some(kinds) => ssss.with_log_kinds(kinds.iter().copied()),
+                None => ssss,
+            };
+            Some(match args.log_calls {
+                true => ssss.with_call_logging(),
+                false => ssss,
+            })
         })
         .collect();
 
     trace!("creating store");
-    let store = store::create(args.store, args.env, &args.host).await?;
+    let share_retention = args
+        .max_share_versions
+        .map(|max_versions| store::ShareRetention {
+            max_versions,
+            grace: std::time::Duration::from_secs(args.share_eviction_grace_secs),
+        });
+    let store = store::create(args.store, args.env, &args.host, share_retention).await?;
+    let recovered = store::intent::recover(&store).await?;
+    if recovered > 0 {
+        info!("completed {recovered} interrupted store operations");
+    }
 
-    let identity_key_id = types::KeyId {
-        name: "ssss-identity".into(),
-        identity: types::IdentityLocator {
-            chain: 0,
-            registry: Default::default(),
-            id: types::IdentityId(Default::default()),
-        },
-        version: 1,
+    let identity = match &args.identity_key {
+        Some(source) => ssss::identity::Identity::from_secret(&source.load()?.decode_hex()?)?,
+        None => load_identity(&store, "ssss-identity").await?,
     };
-    let identity_key = match store.get_key(identity_key_id.clone()).await? {
-        Some(k) => p384::SecretKey::from_slice(&k.into_vec())?,
-        None => {
-            let identity_key = p384::SecretKey::random(&mut rand::thread_rng());
-            store
-                .put_key(identity_key_id, identity_key.to_bytes().to_vec().into())
-                .await?;
-            identity_key
+    // A misconfigured identity would otherwise only show as shares that never decrypt.
+    identity.self_test()?;
+    let shadow_identity = match args.shadow_identity {
+        true => {
+            let shadow_identity = load_identity(&store, "ssss-shadow-identity").await?;
+            shadow_identity.self_test()?;
+            info!(
+                public_key = %hex::encode(shadow_identity.peer_key().to_bytes()),
+                "loaded shadow identity"
+            );
+            Some(shadow_identity)
         }
+        false => None,
+    };
+
+    let sync_config = sync::SyncConfig {
+        policy_fail_mode: args.policy_fail_mode,
+        checkpoint_interval: std::time::Duration::from_secs(args.checkpoint_interval_secs),
+        backfill_threshold: args.backfill_threshold,
+        backfill_concurrency: args.backfill_concurrency as usize,
+        tail_confirmations: args.tail_confirmations,
+        malformed_share_mode: args.malformed_share_mode,
+        stall_deadline: args.stall_deadline_secs.map(std::time::Duration::from_secs),
+        max_event_age_blocks: args.max_event_age_blocks,
+        policy_debounce_blocks: args.policy_debounce_blocks,
+        restart_backoff: std::time::Duration::from_secs(args.restart_backoff_secs),
+        max_restart_backoff: std::time::Duration::from_secs(args.max_restart_backoff_secs),
     };
-    let identity = ssss::identity::Identity::persistent(identity_key);
-    let identity_pub_jwk = identity.public_key().to_jwk();
+
+    if let Some(chain) = args.decommission_chain {
+        sync::decommission_chain(&store, chain).await?;
+        println!("chain {chain} was decommissioned");
+        return Ok(());
+    }
+
+    if let Some((chain, index)) = args.resume_from {
+        let Some(ssss) = sssss.iter().find(|ssss| ssss.chain == chain) else {
+            anyhow::bail!("no permitter configured for chain {chain}");
+        };
+        sync::set_resume_point(&store, ssss, index).await?;
+        println!(
+            "chain {chain} will resume from block {} log {}",
+            index.block, index.log_index
+        );
+        return Ok(());
+    }
+
+    if let Some(share_id) = args.verify_share {
+        let chain = share_id.identity.chain;
+        let Some(ssss) = sssss.iter().find(|ssss| ssss.chain == chain) else {
+            anyhow::bail!("no permitter configured for chain {chain}");
+        };
+        let result = sync::verify_stored_share(&store, ssss, vec![identity], share_id).await?;
+        println!("{result:?}");
+        return Ok(());
+    }
+
+    if let Some(tx) = args.reprocess_tx {
+        for ssss in sssss.iter() {
+            let outcomes =
+                sync::reprocess_tx(&store, ssss, vec![identity.clone()], &sync_config, tx).await?;
+            for (event, outcome) in outcomes {
+                println!(
+                    "chain {} block {} log {}: {outcome:?}",
+                    ssss.chain, event.index.block, event.index.log_index
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    if let Some(memory_store) = store.as_memory().cloned() {
+        tokio::spawn(async move {
+            loop {
+                metrics::MEMORY_STORE_BYTES.set(memory_store.size_bytes() as i64);
+                tokio::time::sleep(std::time::Duration::from_secs(15)).await;
+            }
+        });
+    }
 
     trace!("running sync tasks");
-    sync::run(store.clone(), sssss.iter().cloned(), identity).await?;
+    let started = sync::run(
+        store.clone(),
+        sssss.iter().cloned().map(Ok),
+        vec![identity.clone()],
+        shadow_identity,
+        sync_config,
+        None,
+        None,
+    )
+    .await;
+    info!("started {started} of {} sync tasks", sssss.len());
 
     trace!("starting API task");
-    let api_task = api::serve(store, sssss.into_iter(), args.host, identity_pub_jwk);
+    let api_task = api::serve(
+        store,
+        sssss.into_iter(),
+        args.host,
+        identity,
+        args.max_concurrent_share_requests as usize,
+        args.withhold_stalled_shares,
+        std::time::Duration::from_secs(args.statistics_interval_secs),
+    );
 
-    tokio::join!(api_task);
+    api_task.await
+}
 
-    Ok(())
+async fn load_identity(store: &impl Store, name: &str) -> Result<ssss::identity::Identity> {
+    let key_id = types::KeyId {
+        name: name.into(),
+        identity: types::IdentityLocator {
+            chain: 0,
+            registry: Default::default(),
+            id: types::IdentityId(Default::default()),
+        },
+        version: 1,
+    };
+    if let Some(k) = store.get_key(key_id.clone()).await? {
+        return Ok(ssss::identity::Identity::from_secret(&k.into_vec().into())?);
+    }
+    let key = p384::SecretKey::random(&mut rand::thread_rng());
+    store
+        .put_key(key_id, key.to_bytes().to_vec().into())
+        .await?;
+    Ok(ssss::identity::Identity::persistent(key))
 }
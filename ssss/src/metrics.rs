@@ -0,0 +1,161 @@
+use once_cell::sync::Lazy;
+use prometheus::{
+    register_histogram_vec, register_int_counter, register_int_counter_vec, register_int_gauge,
+    register_int_gauge_vec, HistogramVec, IntCounter, IntCounterVec, IntGauge, IntGaugeVec,
+    TextEncoder,
+};
+
+/// Share deals in which the shadow identity was able to decrypt a share.
+pub static SHADOW_SHARE_MATCHES: Lazy<IntCounter> = Lazy::new(|| {
+    register_int_counter!(
+        "ssss_shadow_share_matches_total",
+        "Number of share deals containing a share for the shadow identity"
+    )
+    .unwrap()
+});
+
+/// The approximate size of the in-memory store, if it is the one in use.
+pub static MEMORY_STORE_BYTES: Lazy<IntGauge> = Lazy::new(|| {
+    register_int_gauge!(
+        "ssss_memory_store_bytes",
+        "Approximate number of bytes held by the memory store"
+    )
+    .unwrap()
+});
+
+/// Also recorded to OpenTelemetry by [`observe_event_handler_duration`].
+pub static EVENT_HANDLER_DURATION: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "ssss_event_handler_duration_seconds",
+        "Time taken to handle a permitter event",
+        &["chain_id", "event_kind"]
+    )
+    .unwrap()
+});
+
+/// Blocks whose events have been requested from the provider but not yet handled.
+/// A full buffer means that event handling, rather than the provider, limits the sync.
+pub static EVENT_BUFFER_DEPTH: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "ssss_event_buffer_depth",
+        "Number of blocks of events buffered for handling",
+        &["chain_id"]
+    )
+    .unwrap()
+});
+
+/// Events that the provider delivered at the same index as the event before them.
+pub static REPEATED_EVENT_INDICES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "ssss_repeated_event_indices_total",
+        "Number of events delivered at the same index as the previous event",
+        &["chain_id"]
+    )
+    .unwrap()
+});
+
+/// Retries by the operation retried and `kind`: `error`, or `backoff` for errors that set their
+/// own delay. Polling again for a result that was not yet what was wanted is not a retry.
+pub static RETRIES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "ssss_retries_total",
+        "Number of retried attempts",
+        &["operation", "kind"]
+    )
+    .unwrap()
+});
+
+pub static RETRY_DELAY: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "ssss_retry_delay_seconds",
+        "Time waited before each retry",
+        &["operation"]
+    )
+    .unwrap()
+});
+
+/// Operations that were given up on because their retry policy allowed no more attempts.
+pub static RETRIES_EXHAUSTED: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "ssss_retries_exhausted_total",
+        "Number of operations given up on after their last allowed attempt",
+        &["operation"]
+    )
+    .unwrap()
+});
+
+/// The number of chain states, live shares, and verifiers in the store, by `kind`.
+pub static STORE_RECORDS: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "ssss_store_records",
+        "Number of records of each kind held by the store",
+        &["kind"]
+    )
+    .unwrap()
+});
+
+/// The lowest and highest blocks from which chain syncs will resume, by `bound`.
+pub static STORE_CHAIN_BLOCK: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "ssss_store_chain_block",
+        "Oldest and newest block that a chain sync will resume from",
+        &["bound"]
+    )
+    .unwrap()
+});
+
+pub fn set_store_statistics(stats: &crate::store::StoreStatistics) {
+    for (kind, count) in [
+        ("chains", stats.chain_count),
+        ("shares", stats.total_shares),
+        ("verifiers", stats.total_verifiers),
+    ] {
+        STORE_RECORDS.with_label_values(&[kind]).set(count as i64);
+    }
+    for (bound, block) in [
+        ("oldest", stats.oldest_chain_block),
+        ("newest", stats.newest_chain_block),
+    ] {
+        match block {
+            Some(block) => STORE_CHAIN_BLOCK
+                .with_label_values(&[bound])
+                .set(block as i64),
+            None => {
+                let _ = STORE_CHAIN_BLOCK.remove_label_values(&[bound]);
+            }
+        }
+    }
+}
+
+/// Renders every registered metric in the Prometheus text exposition format.
+/// Records how long an event took to handle, both to [`EVENT_HANDLER_DURATION`] and as an
+/// OpenTelemetry histogram of the same name. The histogram is created through the global meter
+/// provider on each call, so that it is exported by whichever provider is installed when the event
+/// is handled, and is a no-op if none is.
+pub fn observe_event_handler_duration(
+    chain_id: crate::types::ChainId,
+    event_kind: &'static str,
+    duration: std::time::Duration,
+) {
+    EVENT_HANDLER_DURATION
+        .with_label_values(&[&chain_id.to_string(), event_kind])
+        .observe(duration.as_secs_f64());
+    opentelemetry::global::meter("ssss")
+        .f64_histogram("ssss_event_handler_duration_seconds")
+        .with_description("Time taken to handle a permitter event")
+        .with_unit(opentelemetry::metrics::Unit::new("s"))
+        .init()
+        .record(
+            duration.as_secs_f64(),
+            &[
+                opentelemetry::KeyValue::new("chain_id", chain_id as i64),
+                opentelemetry::KeyValue::new("event_kind", event_kind),
+            ],
+        );
+}
+
+pub fn encode() -> String {
+    TextEncoder::new()
+        .encode_to_string(&prometheus::gather())
+        .unwrap_or_default()
+}
@@ -0,0 +1,149 @@
+use std::{fmt, path::PathBuf, str::FromStr};
+
+use zeroize::Zeroizing;
+
+/// Sensitive bytes that are wiped on drop and never printed.
+#[derive(Clone)]
+pub struct Secret(Zeroizing<Vec<u8>>);
+
+impl Secret {
+    pub fn expose(&self) -> &[u8] {
+        &self.0
+    }
+
+    /// Decodes a secret stored as hex text, ignoring surrounding whitespace and any `0x` prefix.
+    pub fn decode_hex(&self) -> Result<Secret, Error> {
+        let text = std::str::from_utf8(&self.0)
+            .map_err(|_| Error::NotHex)?
+            .trim();
+        let text = text.strip_prefix("0x").unwrap_or(text);
+        let mut bytes = Zeroizing::new(vec![0u8; text.len() / 2]);
+        hex::decode_to_slice(text, &mut bytes).map_err(|_| Error::NotHex)?;
+        Ok(Secret(bytes))
+    }
+}
+
+impl From<Vec<u8>> for Secret {
+    fn from(bytes: Vec<u8>) -> Self {
+        Self(Zeroizing::new(bytes))
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret([redacted])")
+    }
+}
+
+/// Where a secret is loaded from. Parsed from `file:<path>` or `env:<var>`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SecretSource {
+    /// A file that only its owner may read or write.
+    File(PathBuf),
+    Env(String),
+}
+
+impl SecretSource {
+    pub fn load(&self) -> Result<Secret, Error> {
+        match self {
+            Self::File(path) => {
+                #[cfg(unix)]
+                {
+                    use std::os::unix::fs::PermissionsExt as _;
+                    let mode = std::fs::metadata(path)?.permissions().mode();
+                    if mode & 0o077 != 0 {
+                        return Err(Error::InsecurePermissions {
+                            path: path.clone(),
+                            mode: mode & 0o777,
+                        });
+                    }
+                }
+                Ok(std::fs::read(path)?.into())
+            }
+            Self::Env(var) => match std::env::var_os(var) {
+                Some(value) => Ok(value.into_encoded_bytes().into()),
+                None => Err(Error::MissingEnv(var.clone())),
+            },
+        }
+    }
+}
+
+impl FromStr for SecretSource {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some(("file", path)) if !path.is_empty() => Ok(Self::File(path.into())),
+            Some(("env", var)) if !var.is_empty() => Ok(Self::Env(var.into())),
+            _ => Err(Error::InvalidSource(s.into())),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("secret source must have format file:<path> or env:<var>, not {0:?}")]
+    InvalidSource(String),
+    #[error("secret file {path:?} must not be accessible by group or others (mode {mode:o})")]
+    InsecurePermissions { path: PathBuf, mode: u32 },
+    #[error("secret env var {0} is not set")]
+    MissingEnv(String),
+    #[error("secret is not valid hex")]
+    NotHex,
+    #[error("invalid secret key")]
+    InvalidKey,
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_file(contents: &[u8], mode: u32) -> PathBuf {
+        use std::os::unix::fs::PermissionsExt as _;
+        let path = std::env::temp_dir().join(format!("ssss-secret-{}", rand::random::<u64>()));
+        std::fs::write(&path, contents).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+        path
+    }
+
+    #[test]
+    fn load_file_secret() {
+        let path = temp_file(b"0xc0ffee\n", 0o600);
+        let secret = SecretSource::File(path.clone()).load().unwrap();
+        assert_eq!(secret.decode_hex().unwrap().expose(), [0xc0, 0xff, 0xee]);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reject_readable_secret_file() {
+        let path = temp_file(b"c0ffee", 0o644);
+        let res = SecretSource::File(path.clone()).load();
+        assert!(matches!(
+            res,
+            Err(Error::InsecurePermissions { mode: 0o644, .. })
+        ));
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn parse_secret_source() {
+        assert_eq!(
+            "env:SSSS_KEY".parse::<SecretSource>().unwrap(),
+            SecretSource::Env("SSSS_KEY".into())
+        );
+        assert_eq!(
+            "file:/run/secrets/key".parse::<SecretSource>().unwrap(),
+            SecretSource::File("/run/secrets/key".into())
+        );
+        assert!("/run/secrets/key".parse::<SecretSource>().is_err());
+        assert!("env:".parse::<SecretSource>().is_err());
+    }
+
+    #[test]
+    fn secret_is_redacted() {
+        let secret = Secret::from(b"hunter2".to_vec());
+        assert!(!format!("{secret:?}").contains("hunter2"));
+    }
+}
@@ -4,16 +4,21 @@ use aws_sdk_dynamodb::{
     primitives::Blob,
     types::{
         AttributeValue::{self, B, N, S},
-        Put, TransactWriteItem,
+        Delete, Put, Select, TransactWriteItem,
     },
 };
+use ethers::types::H256;
 
-use super::*;
+use super::{
+    codec::{CborCodec, RecordCodec},
+    *,
+};
 
 #[derive(Clone)]
 pub struct Client {
     db: aws_sdk_dynamodb::Client,
     env: Environment,
+    share_retention: Option<ShareRetention>,
 }
 
 macro_rules! naming_fn {
@@ -31,7 +36,16 @@ impl Client {
     pub async fn connect(env: Environment) -> Self {
         let config = aws_config::load_defaults(aws_config::BehaviorVersion::v2023_11_09()).await;
         let db_client = aws_sdk_dynamodb::Client::new(&config);
-        Self { db: db_client, env }
+        Self {
+            db: db_client,
+            env,
+            share_retention: None,
+        }
+    }
+
+    pub fn with_share_retention(mut self, retention: ShareRetention) -> Self {
+        self.share_retention = Some(retention);
+        self
     }
 
     naming_fn!(secrets_table, "escrin-secrets");
@@ -39,6 +53,7 @@ impl Client {
     naming_fn!(nonces_table, "escrin-nonces");
     naming_fn!(verifiers_table, "escrin-verifiers");
     naming_fn!(chain_state_table, "escrin-chain-state");
+    naming_fn!(intents_table, "escrin-intents");
 
     async fn current_secret_version(
         &self,
@@ -92,6 +107,91 @@ impl Client {
         }
     }
 
+    /// Puts a share version and, in the same transaction, deletes the versions that `retention`
+    /// evicts now that it has been stored.
+    async fn put_share_evicting(
+        &self,
+        id: &ShareId,
+        share: Vec<u8>,
+        mut items: HashMap<String, AttributeValue>,
+        retention: ShareRetention,
+    ) -> Result<bool, Error> {
+        let now_ms = now_millis();
+        let mut versions = self
+            .db
+            .query()
+            .table_name(self.secrets_table())
+            .key_condition_expression("id = :id")
+            .expression_attribute_values(":id", id.to_attribute_value())
+            .projection_expression("version, stored_at_ms, deleted_at")
+            .into_paginator()
+            .items()
+            .send();
+        let mut current_version = 0;
+        let (mut live, mut stored_for) = (Vec::new(), Vec::new());
+        while let Some(item) = versions.next().await {
+            let item = item.map_err(aws_sdk_dynamodb::Error::from)?;
+            let version = unpack_u64("version", &item);
+            current_version = current_version.max(version);
+            if item.contains_key("deleted_at") {
+                continue;
+            }
+            live.push(version);
+            // Versions stored before puts were timed were superseded long ago.
+            stored_for.push(match item.get("stored_at_ms") {
+                Some(N(ms)) => Duration::from_millis(now_ms.saturating_sub(ms.parse()?)),
+                Some(_) => anyhow::bail!("stored_at_ms not numeric"),
+                None => Duration::MAX,
+            });
+        }
+        if id.version != current_version + 1 {
+            return Ok(false);
+        }
+        stored_for.push(Duration::ZERO);
+
+        items.insert("id".into(), id.to_attribute_value());
+        items.insert("version".into(), N(id.version.to_string()));
+        items.insert("secret".into(), B(Blob::new(share)));
+        let mut transaction = vec![TransactWriteItem::builder()
+            .put(
+                Put::builder()
+                    .table_name(self.secrets_table())
+                    .set_item(Some(items))
+                    .condition_expression(
+                        "attribute_not_exists(id) AND attribute_not_exists(version)",
+                    )
+                    .build()?,
+            )
+            .build()];
+        // A transaction holds at most 100 items, so any further evictions wait for the next put.
+        for version in live.iter().take(retention.evictions(&stored_for)).take(99) {
+            transaction.push(
+                TransactWriteItem::builder()
+                    .delete(
+                        Delete::builder()
+                            .table_name(self.secrets_table())
+                            .key("id", id.to_attribute_value())
+                            .key("version", N(version.to_string()))
+                            .build()?,
+                    )
+                    .build(),
+            );
+        }
+        let res = self
+            .db
+            .transact_write_items()
+            .set_transact_items(Some(transaction))
+            .send()
+            .await
+            .map_err(aws_sdk_dynamodb::Error::from);
+        match res {
+            Ok(_) => Ok(true),
+            // Only the put is conditional, so it is the version that was taken.
+            Err(aws_sdk_dynamodb::Error::TransactionCanceledException(_)) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     async fn get_secret(
         &self,
         id: &impl ToAttributeValue,
@@ -139,31 +239,144 @@ impl Client {
 
 impl Store for Client {
     async fn put_share(&self, id: ShareId, ss: SecretShare) -> Result<bool, Error> {
-        self.put_secret(
-            &id,
-            id.version,
-            (*ss.share).clone(),
-            Some(HashMap::from_iter(std::iter::once((
-                "index".to_string(),
-                N(ss.index.to_string()),
-            )))),
-        )
-        .await
+        let mut items = HashMap::from([
+            ("index".to_string(), N(ss.index.to_string())),
+            ("stored_at_ms".to_string(), N(now_millis().to_string())),
+        ]);
+        if let Some(provenance) = &ss.provenance {
+            items.insert(
+                "provenance".into(),
+                B(Blob::new(CborCodec.encode(provenance)?)),
+            );
+        }
+        let incoming = ss.index;
+        let put = match self.share_retention {
+            Some(retention) => {
+                self.put_share_evicting(&id, (*ss.share).clone(), items, retention)
+                    .await?
+            }
+            None => {
+                self.put_secret(&id, id.version, (*ss.share).clone(), Some(items))
+                    .await?
+            }
+        };
+        if !put {
+            let existing = self.get_share(id.clone()).await?;
+            ShareIndexConflict::check(&id, existing.as_ref(), incoming)?;
+        }
+        Ok(put)
     }
 
     async fn get_share(&self, id: ShareId) -> Result<Option<SecretShare>, Error> {
         let Some((share, items)) = self.get_secret(&id, id.version).await? else {
             return Ok(None);
         };
-        let index = unpack_u64("index", &items);
-        Ok(Some(SecretShare {
-            index,
-            share: share.into(),
-        }))
+        if items.contains_key("deleted_at") {
+            return Ok(None);
+        }
+        decode_share(share, items)
+            .map(Some)
+            .map_err(|e| DeserializeError::new(id, e).into())
+    }
+
+    async fn get_share_age(&self, id: ShareId) -> Result<Option<Duration>, Error> {
+        let Some((_, items)) = self.get_secret(&id, id.version).await? else {
+            return Ok(None);
+        };
+        if items.contains_key("deleted_at") {
+            return Ok(None);
+        }
+        let stored_at_ms: u64 = match items.get("stored_at_ms") {
+            Some(N(n)) => n.parse()?,
+            Some(_) => anyhow::bail!("stored_at_ms not numeric"),
+            None => return Ok(None),
+        };
+        Ok(Some(Duration::from_millis(
+            now_millis().saturating_sub(stored_at_ms),
+        )))
     }
 
     async fn delete_share_version(&self, id: ShareId) -> Result<(), Error> {
-        self.delete_secret_version(&id, id.version).await
+        self.mark_share_deleted(&id, true).await?;
+        Ok(())
+    }
+
+    async fn restore_share(&self, id: ShareId) -> Result<bool, Error> {
+        self.mark_share_deleted(&id, false).await
+    }
+
+    async fn list_deleted_shares(&self) -> Result<Vec<ShareId>, Error> {
+        let mut items = self
+            .db
+            .scan()
+            .table_name(self.secrets_table())
+            .filter_expression("attribute_exists(deleted_at)")
+            .projection_expression("id, version")
+            .into_paginator()
+            .items()
+            .send();
+        let mut ids = Vec::new();
+        while let Some(item) = items.next().await {
+            let item = item.map_err(aws_sdk_dynamodb::Error::from)?;
+            let Some(S(id)) = item.get("id") else {
+                anyhow::bail!("deleted share has no id");
+            };
+            ids.push(ShareId::from_key(id, unpack_u64("version", &item))?);
+        }
+        Ok(ids)
+    }
+
+    /// Scans the table in its own order, so a page holds at most `page_size` of the items scanned
+    /// and may hold fewer shares once keys and deleted shares are skipped.
+    async fn list_shares_page(
+        &self,
+        filter: ShareFilter,
+        cursor: Option<ShareCursor>,
+        page_size: u32,
+    ) -> Result<SharePage, Error> {
+        let res = self
+            .db
+            .scan()
+            .table_name(self.secrets_table())
+            .filter_expression("begins_with(id, :prefix) AND attribute_not_exists(deleted_at)")
+            .expression_attribute_values(":prefix", S("share-".into()))
+            .set_exclusive_start_key(cursor.map(|ShareCursor(id)| {
+                HashMap::from([
+                    ("id".to_string(), id.to_attribute_value()),
+                    ("version".to_string(), N(id.version.to_string())),
+                ])
+            }))
+            .limit(page_size.try_into().unwrap_or(i32::MAX))
+            .send()
+            .await
+            .map_err(aws_sdk_dynamodb::Error::from)?;
+        let mut items = Vec::new();
+        for mut item in res.items.unwrap_or_default() {
+            let Some(S(key)) = item.get("id") else {
+                anyhow::bail!("share has no id");
+            };
+            let id = ShareId::from_key(key, unpack_u64("version", &item))?;
+            if !filter.matches(&id) {
+                continue;
+            }
+            let share = unpack_blob("secret", &mut item).into_inner();
+            let share =
+                decode_share(share, item).map_err(|e| DeserializeError::new(id.clone(), e))?;
+            items.push((id, share));
+        }
+        let next_cursor = match res.last_evaluated_key {
+            Some(key) => {
+                let Some(S(id)) = key.get("id") else {
+                    anyhow::bail!("last evaluated key has no id");
+                };
+                Some(ShareCursor(ShareId::from_key(
+                    id,
+                    unpack_u64("version", &key),
+                )?))
+            }
+            None => None,
+        };
+        Ok(SharePage { items, next_cursor })
     }
 
     async fn put_key(&self, id: KeyId, key: WrappedKey) -> Result<bool, Error> {
@@ -312,22 +525,34 @@ impl Store for Client {
             .first()
             .map(|v| ChainState {
                 block: unpack_u64("block", v),
+                log_index: v
+                    .contains_key("log_index")
+                    .then(|| unpack_u64("log_index", v))
+                    .unwrap_or_default(),
+                block_hash: v
+                    .get("block_hash")
+                    .and_then(|h| h.as_b().ok())
+                    .map(|h| H256::from_slice(h.as_ref())),
             }))
     }
 
     async fn update_chain_state(&self, chain: u64, update: ChainStateUpdate) -> Result<(), Error> {
-        let ChainStateUpdate { block } = update;
+        let ChainStateUpdate { block, block_hash } = update;
         let Some(new_block) = block else {
             return Ok(());
         };
 
         let n_block = N(new_block.to_string());
-        let res = self
+        let mut put = self
             .db
             .put_item()
             .table_name(self.chain_state_table())
             .item("chain", N(chain.to_string()))
-            .item("block", n_block.clone())
+            .item("block", n_block.clone());
+        if let Some(hash) = block_hash {
+            put = put.item("block_hash", B(Blob::new(hash.as_bytes())));
+        }
+        let res = put
             .condition_expression("attribute_not_exists(#b) OR #b < :block")
             .expression_attribute_names("#b", "block")
             .expression_attribute_values(":block", n_block)
@@ -341,7 +566,23 @@ impl Store for Client {
         }
     }
 
-    #[cfg(test)]
+    async fn set_resume_point(
+        &self,
+        chain: u64,
+        EventIndex { block, log_index }: EventIndex,
+    ) -> Result<(), Error> {
+        self.db
+            .put_item()
+            .table_name(self.chain_state_table())
+            .item("chain", N(chain.to_string()))
+            .item("block", N(block.to_string()))
+            .item("log_index", N(log_index.to_string()))
+            .send()
+            .await
+            .map_err(aws_sdk_dynamodb::Error::from)?;
+        Ok(())
+    }
+
     async fn clear_chain_state(&self, chain: u64) -> Result<(), Error> {
         self.db
             .delete_item()
@@ -357,8 +598,8 @@ impl Store for Client {
         &self,
         permitter: PermitterLocator,
         identity: IdentityId,
-    ) -> Result<Option<Vec<u8>>, Error> {
-        Ok(self
+    ) -> Result<Option<VerifierRecord>, Error> {
+        let Some(mut item) = self
             .db
             .query()
             .table_name(self.verifiers_table())
@@ -366,18 +607,29 @@ impl Store for Client {
             .expression_attribute_names("#i", "identity")
             .expression_attribute_values(":permitter", permitter.to_attribute_value())
             .expression_attribute_values(":identity", identity.to_attribute_value())
-            .projection_expression("config")
+            .projection_expression("config, #b, log_index, updated_at_ms")
+            .expression_attribute_names("#b", "block")
             .send()
             .await
             .map_err(aws_sdk_dynamodb::Error::from)?
             .items
             .and_then(|items| items.into_iter().nth(0))
-            .and_then(|mut res| {
-                res.remove("config").map(|v| match v {
-                    B(config) => config.into_inner(),
-                    _ => panic!("expected blob config"),
-                })
-            }))
+        else {
+            return Ok(None);
+        };
+        let updated_at = match item.get("updated_at_ms") {
+            Some(N(ms)) => Some(std::time::UNIX_EPOCH + Duration::from_millis(ms.parse()?)),
+            Some(_) => anyhow::bail!("updated_at_ms not numeric"),
+            None => None,
+        };
+        Ok(Some(VerifierRecord {
+            event_index: EventIndex {
+                block: unpack_u64("block", &item),
+                log_index: unpack_u64("log_index", &item),
+            },
+            config: unpack_blob("config", &mut item).into_inner(),
+            updated_at,
+        }))
     }
 
     async fn update_verifier(
@@ -398,6 +650,7 @@ impl Store for Client {
             .item("config", B(Blob::new(config)))
             .item("block", n_block.clone())
             .item("log_index", n_log_index.clone())
+            .item("updated_at_ms", N(now_millis().to_string()))
             .condition_expression(
                 "attribute_not_exists(#b) OR #b < :block OR (#b = :block AND log_index < :li)",
             )
@@ -414,6 +667,34 @@ impl Store for Client {
         }
     }
 
+    async fn put_verifier_if_absent(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+        config: Vec<u8>,
+        EventIndex { block, log_index }: EventIndex,
+    ) -> Result<bool, Error> {
+        let res = self
+            .db
+            .put_item()
+            .table_name(self.verifiers_table())
+            .item("permitter", permitter.to_attribute_value())
+            .item("identity", identity.to_attribute_value())
+            .item("config", B(Blob::new(config)))
+            .item("block", N(block.to_string()))
+            .item("log_index", N(log_index.to_string()))
+            .item("updated_at_ms", N(now_millis().to_string()))
+            .condition_expression("attribute_not_exists(permitter)")
+            .send()
+            .await
+            .map_err(aws_sdk_dynamodb::Error::from);
+        match res {
+            Ok(_) => Ok(true),
+            Err(aws_sdk_dynamodb::Error::ConditionalCheckFailedException(_)) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
     #[cfg(test)]
     async fn clear_verifier(
         &self,
@@ -430,6 +711,246 @@ impl Store for Client {
             .map_err(aws_sdk_dynamodb::Error::from)?;
         Ok(())
     }
+
+    async fn delete_verifiers_for_chain(&self, chain: ChainId) -> Result<u64, Error> {
+        self.delete_verifiers_where(|p| p.chain == chain).await
+    }
+
+    /// The permitter is the partition key of the verifiers, so they are queried rather than
+    /// found by scanning the table.
+    async fn delete_verifiers_for_permitter(
+        &self,
+        permitter: PermitterLocator,
+    ) -> Result<u64, Error> {
+        let mut items = self
+            .db
+            .query()
+            .table_name(self.verifiers_table())
+            .key_condition_expression("permitter = :permitter")
+            .expression_attribute_values(":permitter", permitter.to_attribute_value())
+            .projection_expression("#i")
+            .expression_attribute_names("#i", "identity")
+            .into_paginator()
+            .items()
+            .send();
+        let mut deleted = 0;
+        while let Some(item) = items.next().await {
+            let mut item = item.map_err(aws_sdk_dynamodb::Error::from)?;
+            let Some(identity) = item.remove("identity") else {
+                continue;
+            };
+            self.db
+                .delete_item()
+                .table_name(self.verifiers_table())
+                .key("permitter", permitter.to_attribute_value())
+                .key("identity", identity)
+                .send()
+                .await
+                .map_err(aws_sdk_dynamodb::Error::from)?;
+            deleted += 1;
+        }
+        Ok(deleted)
+    }
+
+    async fn gc(&self, retain_chain_ids: &[ChainId]) -> Result<GcReport, Error> {
+        Ok(GcReport {
+            nonce_entries_deleted: self
+                .delete_where(self.nonces_table(), "identity", "nonce", |k| {
+                    Ok(!retain_chain_ids.contains(&IdentityLocator::from_key(k)?.chain))
+                })
+                .await?,
+            verifier_entries_deleted: self
+                .delete_verifiers_where(|p| !retain_chain_ids.contains(&p.chain))
+                .await?,
+        })
+    }
+
+    async fn checkpoint(&self) -> Result<CheckpointReport, Error> {
+        Ok(CheckpointReport::default())
+    }
+
+    /// DynamoDB has no aggregate queries, so this scans every table but the permits.
+    async fn statistics(&self) -> Result<StoreStatistics, Error> {
+        let mut stats = StoreStatistics::default();
+        let mut pages = self
+            .db
+            .scan()
+            .table_name(self.secrets_table())
+            .filter_expression("begins_with(id, :prefix) AND attribute_not_exists(deleted_at)")
+            .expression_attribute_values(":prefix", S("share-".into()))
+            .select(Select::Count)
+            .into_paginator()
+            .send();
+        while let Some(page) = pages.next().await {
+            stats.total_shares += page.map_err(aws_sdk_dynamodb::Error::from)?.count as u64;
+        }
+        let mut pages = self
+            .db
+            .scan()
+            .table_name(self.verifiers_table())
+            .select(Select::Count)
+            .into_paginator()
+            .send();
+        while let Some(page) = pages.next().await {
+            stats.total_verifiers += page.map_err(aws_sdk_dynamodb::Error::from)?.count as u64;
+        }
+        let mut items = self
+            .db
+            .scan()
+            .table_name(self.chain_state_table())
+            .projection_expression("#b")
+            .expression_attribute_names("#b", "block")
+            .into_paginator()
+            .items()
+            .send();
+        while let Some(item) = items.next().await {
+            stats.add_chain(unpack_u64(
+                "block",
+                &item.map_err(aws_sdk_dynamodb::Error::from)?,
+            ));
+        }
+        Ok(stats)
+    }
+
+    async fn put_intent(&self, intent: intent::Intent) -> Result<(), Error> {
+        self.db
+            .put_item()
+            .table_name(self.intents_table())
+            .item("id", N(intent.id.to_string()))
+            .item("intent", B(Blob::new(CborCodec.encode(&intent)?)))
+            .send()
+            .await
+            .map_err(aws_sdk_dynamodb::Error::from)?;
+        Ok(())
+    }
+
+    async fn list_intents(&self) -> Result<Vec<intent::Intent>, Error> {
+        let mut items = self
+            .db
+            .scan()
+            .table_name(self.intents_table())
+            .into_paginator()
+            .items()
+            .send();
+        let mut intents = Vec::new();
+        while let Some(item) = items.next().await {
+            let mut item = item.map_err(aws_sdk_dynamodb::Error::from)?;
+            intents.push(CborCodec.decode(unpack_blob("intent", &mut item).as_ref())?);
+        }
+        Ok(intents)
+    }
+
+    async fn clear_intent(&self, id: u64) -> Result<(), Error> {
+        self.db
+            .delete_item()
+            .table_name(self.intents_table())
+            .key("id", N(id.to_string()))
+            .send()
+            .await
+            .map_err(aws_sdk_dynamodb::Error::from)?;
+        Ok(())
+    }
+}
+
+impl Client {
+    async fn delete_verifiers_where(
+        &self,
+        delete: impl Fn(PermitterLocator) -> bool,
+    ) -> Result<u64, Error> {
+        self.delete_where(self.verifiers_table(), "permitter", "identity", |k| {
+            Ok(delete(PermitterLocator::from_key(k)?))
+        })
+        .await
+    }
+
+    /// Sets or clears the share version's deletion time. Returns whether that changed anything.
+    async fn mark_share_deleted(&self, id: &ShareId, deleted: bool) -> Result<bool, Error> {
+        let update = self
+            .db
+            .update_item()
+            .table_name(self.secrets_table())
+            .key("id", id.to_attribute_value())
+            .key("version", N(id.version.to_string()));
+        let update = match deleted {
+            true => update
+                .update_expression("SET deleted_at = :now")
+                .condition_expression(
+                    "attribute_exists(secret) AND attribute_not_exists(deleted_at)",
+                )
+                .expression_attribute_values(":now", N(now().to_string())),
+            false => update
+                .update_expression("REMOVE deleted_at")
+                .condition_expression("attribute_exists(deleted_at)"),
+        };
+        match update.send().await.map_err(aws_sdk_dynamodb::Error::from) {
+            Ok(_) => Ok(true),
+            Err(aws_sdk_dynamodb::Error::ConditionalCheckFailedException(_)) => Ok(false),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Deletes every item of `table` for which `delete` accepts the partition key.
+    async fn delete_where(
+        &self,
+        table: &'static str,
+        partition_key: &'static str,
+        sort_key: &'static str,
+        delete: impl Fn(&str) -> Result<bool, Error>,
+    ) -> Result<u64, Error> {
+        let mut items = self
+            .db
+            .scan()
+            .table_name(table)
+            .projection_expression("#pk, #sk")
+            .expression_attribute_names("#pk", partition_key)
+            .expression_attribute_names("#sk", sort_key)
+            .into_paginator()
+            .items()
+            .send();
+        let mut deleted = 0;
+        while let Some(item) = items.next().await {
+            let mut item = item.map_err(aws_sdk_dynamodb::Error::from)?;
+            let (Some(pk), Some(sk)) = (item.remove(partition_key), item.remove(sort_key)) else {
+                continue;
+            };
+            let Ok(key) = pk.as_s() else {
+                continue;
+            };
+            if !delete(key)? {
+                continue;
+            }
+            self.db
+                .delete_item()
+                .table_name(table)
+                .key(partition_key, pk)
+                .key(sort_key, sk)
+                .send()
+                .await
+                .map_err(aws_sdk_dynamodb::Error::from)?;
+            deleted += 1;
+        }
+        Ok(deleted)
+    }
+}
+
+fn decode_share(
+    share: Vec<u8>,
+    mut items: HashMap<String, AttributeValue>,
+) -> Result<SecretShare, Error> {
+    let index = match items.get("index") {
+        Some(N(n)) => ShareIndex(n.parse()?),
+        _ => anyhow::bail!("missing or non-numeric index"),
+    };
+    let provenance = match items.remove("provenance") {
+        Some(B(b)) => Some(CborCodec.decode(b.as_ref())?),
+        Some(_) => anyhow::bail!("provenance not blob"),
+        None => None,
+    };
+    Ok(SecretShare {
+        index,
+        share: share.into(),
+        provenance,
+    })
 }
 
 fn unpack_u64(key: &'static str, res: &HashMap<String, AttributeValue>) -> u64 {
@@ -470,4 +991,29 @@ mod tests {
     use super::*;
 
     crate::make_store_tests!(Client::connect(Environment::Dev));
+    crate::make_store_tests!(
+        async {
+            Client::connect(Environment::Dev)
+                .await
+                .with_share_retention(ShareRetention {
+                    max_versions: 2.try_into().unwrap(),
+                    grace: Duration::ZERO,
+                })
+        },
+        evict_old_share_versions
+    );
+
+    #[test]
+    fn decode_corrupt_share() {
+        let items = |index: AttributeValue| {
+            HashMap::from_iter([
+                ("index".to_string(), index),
+                ("provenance".to_string(), B(Blob::new(vec![0xff, 0x00]))),
+            ])
+        };
+        assert!(decode_share(vec![1], items(N("one".into()))).is_err());
+        assert!(decode_share(vec![1], items(S("1".into()))).is_err());
+        assert!(decode_share(vec![1], items(N("1".into()))).is_err());
+        assert!(decode_share(vec![1], HashMap::new()).is_err());
+    }
 }
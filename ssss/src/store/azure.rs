@@ -6,7 +6,10 @@ use azure_data_tables::prelude::*;
 use futures_util::{StreamExt as _, TryStreamExt as _};
 use serde::{Deserialize, Serialize};
 
-use super::*;
+use super::{
+    codec::{CborCodec, RecordCodec},
+    *,
+};
 
 #[derive(Clone)]
 pub struct Client {
@@ -14,6 +17,7 @@ pub struct Client {
 
     secrets: Arc<azure_security_keyvault::SecretClient>,
     db: Arc<TableServiceClient>,
+    share_retention: Option<ShareRetention>,
 }
 
 static SECRET_VERSIONS_TABLE: &str = "secretversions";
@@ -21,6 +25,7 @@ static PERMITS_TABLE: &str = "permits";
 static NONCES_TABLE: &str = "nonces";
 static VERIFIERS_TABLE: &str = "verifiers";
 static CHAIN_STATE_TABLE: &str = "chainstate";
+static INTENTS_TABLE: &str = "intents";
 
 impl Client {
     pub async fn connect(host: &Authority, env: Environment) -> Result<Self, Error> {
@@ -51,7 +56,18 @@ impl Client {
         let creds: Arc<dyn azure_core::auth::TokenCredential + 'static> = creds;
         let db = Arc::new(TableServiceClient::new(sa_name, creds));
 
-        Ok(Self { env, secrets, db })
+        Ok(Self {
+            env,
+            secrets,
+            db,
+            share_retention: None,
+        })
+    }
+
+    /// Evicts old versions of each secret's share as new ones are put.
+    pub fn with_share_retention(mut self, retention: ShareRetention) -> Self {
+        self.share_retention = Some(retention);
+        self
     }
 
     async fn get_current_chain_state(
@@ -142,6 +158,8 @@ impl Client {
                 id: id.to_key(),
                 version: InvSortableInt(version),
                 guid: secret.id.rsplit_once('/').unwrap().1.to_string(),
+                stored_at_ms: Some(now_millis()),
+                deleted_at: None,
             })?
             .return_entity(false)
             .into_future()
@@ -183,36 +201,274 @@ impl Client {
             .await
             .or_else(default_if_notfound)
     }
+
+    /// Disables or re-enables the share version's secret and records when it was deleted.
+    /// Returns whether that changed anything.
+    async fn mark_share_deleted(&self, id: &ShareId, deleted: bool) -> Result<bool, Error> {
+        let Some((_, entity)) = self
+            .get_current::<SecretVersionEntity>(
+                SECRET_VERSIONS_TABLE,
+                id,
+                Some(&InvSortableInt(id.version)),
+            )
+            .await?
+        else {
+            return Ok(false);
+        };
+        if entity.deleted_at.is_some() == deleted {
+            return Ok(false);
+        }
+        self.secrets
+            .update(id.to_key())
+            .version(entity.guid.clone())
+            .enabled(!deleted)
+            .into_future()
+            .await?;
+        self.db
+            .table_client(SECRET_VERSIONS_TABLE)
+            .partition_key_client(entity.id.clone())
+            .entity_client(entity.version.to_key())
+            .insert_or_replace(SecretVersionEntity {
+                deleted_at: deleted.then(now),
+                ..entity
+            })?
+            .into_future()
+            .await?;
+        Ok(true)
+    }
+
+    /// Deletes the versions of a share that `retention` evicts now that `id` has been stored.
+    async fn evict_share_versions(
+        &self,
+        id: &ShareId,
+        retention: ShareRetention,
+    ) -> Result<(), Error> {
+        let now_ms = now_millis();
+        let mut live = Vec::new();
+        let mut pages = self
+            .db
+            .table_client(SECRET_VERSIONS_TABLE)
+            .query()
+            .filter(format!("PartitionKey eq '{}'", id.to_key()))
+            .into_stream::<SecretVersionEntity>();
+        while let Some(page) = pages.try_next().await? {
+            live.extend(
+                page.entities
+                    .into_iter()
+                    .filter(|entity| entity.deleted_at.is_none()),
+            );
+        }
+        // Rows sort newest first.
+        live.reverse();
+        // Versions stored before puts were timed were superseded long ago.
+        let stored_for: Vec<_> = live
+            .iter()
+            .map(|entity| match entity.stored_at_ms {
+                Some(ms) => Duration::from_millis(now_ms.saturating_sub(ms)),
+                None => Duration::MAX,
+            })
+            .collect();
+        for entity in &live[..retention.evictions(&stored_for)] {
+            self.secrets
+                .update(id.to_key())
+                .version(entity.guid.clone())
+                .enabled(false)
+                .into_future()
+                .await
+                .or_else(default_if_notfound)?;
+            self.db
+                .table_client(SECRET_VERSIONS_TABLE)
+                .partition_key_client(entity.id.clone())
+                .entity_client(entity.version.to_key())
+                .delete()
+                .into_future()
+                .await
+                .map(|_| ())
+                .or_else(default_if_notfound)?;
+        }
+        Ok(())
+    }
+
+    async fn delete_verifiers_where(
+        &self,
+        delete: impl Fn(PermitterLocator) -> bool,
+    ) -> Result<u64, Error> {
+        let mut deleted = 0;
+        let mut pages = self
+            .db
+            .table_client(VERIFIERS_TABLE)
+            .query()
+            .into_stream::<VerifierEntity>();
+        while let Some(page) = pages.try_next().await? {
+            for VerifierEntity {
+                permitter,
+                identity,
+                ..
+            } in page.entities
+            {
+                if !delete(permitter) {
+                    continue;
+                }
+                self.db
+                    .table_client(VERIFIERS_TABLE)
+                    .partition_key_client(permitter.to_key())
+                    .entity_client(identity.to_key())
+                    .delete()
+                    .into_future()
+                    .await
+                    .map(|_| ())
+                    .or_else(default_if_notfound)?;
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
+    }
 }
 
-fn encode_ss(SecretShare { index, share }: SecretShare) -> String {
-    format!("{index}-{}", hex::encode(&share))
+/// Encodes a share as `index-share[-provenance]`, where the provenance is hex-encoded CBOR.
+fn encode_ss(
+    SecretShare {
+        index,
+        share,
+        provenance,
+    }: SecretShare,
+) -> Result<String, Error> {
+    let mut s = format!("{index}-{}", hex::encode(&share));
+    if let Some(provenance) = provenance {
+        s.push('-');
+        s.push_str(&CborCodec.encode_text(&provenance)?);
+    }
+    Ok(s)
 }
 
 fn decode_ss(s: String) -> Result<SecretShare, Error> {
     let s = zeroize::Zeroizing::new(s);
-    let Some((index_str, share_hex)) = s.split_once('-') else {
+    let mut parts = s.splitn(3, '-');
+    let (Some(index_str), Some(share_hex)) = (parts.next(), parts.next()) else {
         return Err(anyhow::anyhow!("invalid encoded secret share"));
     };
-    let index = index_str.parse()?;
+    let index = ShareIndex(index_str.parse()?);
     let share = hex::decode(share_hex)?.into();
-    Ok(SecretShare { index, share })
+    let provenance = parts.next().map(|p| CborCodec.decode_text(p)).transpose()?;
+    Ok(SecretShare {
+        index,
+        share,
+        provenance,
+    })
 }
 
 impl Store for Client {
     async fn put_share(&self, id: ShareId, ss: SecretShare) -> Result<bool, Error> {
-        self.put_secret(&id, id.version, encode_ss(ss)).await
+        let incoming = ss.index;
+        let put = self.put_secret(&id, id.version, encode_ss(ss)?).await?;
+        if !put {
+            let existing = self.get_share(id.clone()).await?;
+            ShareIndexConflict::check(&id, existing.as_ref(), incoming)?;
+        } else if let Some(retention) = self.share_retention {
+            self.evict_share_versions(&id, retention).await?;
+        }
+        Ok(put)
     }
 
     async fn get_share(&self, id: ShareId) -> Result<Option<SecretShare>, Error> {
         let Some(s) = self.get_secret(&id, id.version).await? else {
             return Ok(None);
         };
-        decode_ss(s).map(Some)
+        decode_ss(s)
+            .map(Some)
+            .map_err(|e| DeserializeError::new(id, e).into())
+    }
+
+    async fn get_share_age(&self, id: ShareId) -> Result<Option<Duration>, Error> {
+        let Some((_, entity)) = self
+            .get_current::<SecretVersionEntity>(
+                SECRET_VERSIONS_TABLE,
+                &id,
+                Some(&InvSortableInt(id.version)),
+            )
+            .await?
+        else {
+            return Ok(None);
+        };
+        if entity.deleted_at.is_some() {
+            return Ok(None);
+        }
+        Ok(entity
+            .stored_at_ms
+            .map(|stored_at_ms| Duration::from_millis(now_millis().saturating_sub(stored_at_ms))))
     }
 
     async fn delete_share_version(&self, id: ShareId) -> Result<(), Error> {
-        self.delete_secret_version(&id, id.version).await
+        self.mark_share_deleted(&id, true).await?;
+        Ok(())
+    }
+
+    async fn restore_share(&self, id: ShareId) -> Result<bool, Error> {
+        self.mark_share_deleted(&id, false).await
+    }
+
+    async fn list_deleted_shares(&self) -> Result<Vec<ShareId>, Error> {
+        let mut ids = Vec::new();
+        let mut pages = self
+            .db
+            .table_client(SECRET_VERSIONS_TABLE)
+            .query()
+            .into_stream::<SecretVersionEntity>();
+        while let Some(page) = pages.try_next().await? {
+            for entity in page.entities {
+                if entity.deleted_at.is_some() {
+                    ids.push(ShareId::from_key(&entity.id, entity.version.0)?);
+                }
+            }
+        }
+        Ok(ids)
+    }
+
+    /// Pages through the version index in key order, so a page may hold fewer than `page_size`
+    /// shares once deleted and filtered versions are skipped.
+    async fn list_shares_page(
+        &self,
+        filter: ShareFilter,
+        cursor: Option<ShareCursor>,
+        page_size: u32,
+    ) -> Result<SharePage, Error> {
+        // `.` is the character after `-`, so this selects the partitions of every share.
+        let mut query = "PartitionKey ge 'share-' and PartitionKey lt 'share.'".to_string();
+        if let Some(ShareCursor(last)) = &cursor {
+            let (partition, row) = (last.to_key(), InvSortableInt(last.version));
+            query.push_str(&format!(
+                " and (PartitionKey gt '{partition}' or (PartitionKey eq '{partition}' and RowKey \
+                 gt '{row}'))"
+            ));
+        }
+        let entities = self
+            .db
+            .table_client(SECRET_VERSIONS_TABLE)
+            .query()
+            .filter(query)
+            .top(page_size)
+            .into_stream::<SecretVersionEntity>()
+            .try_next()
+            .await?
+            .map(|page| page.entities)
+            .unwrap_or_default();
+        let mut items = Vec::new();
+        let mut last = None;
+        for entity in entities.iter() {
+            let id = ShareId::from_key(&entity.id, entity.version.0)?;
+            last = Some(id.clone());
+            if entity.deleted_at.is_some() || !filter.matches(&id) {
+                continue;
+            }
+            if let Some(share) = self.get_share(id.clone()).await? {
+                items.push((id, share));
+            }
+        }
+        let next_cursor = match entities.len() < page_size as usize {
+            true => None,
+            false => last.map(ShareCursor),
+        };
+        Ok(SharePage { items, next_cursor })
     }
 
     async fn put_key(&self, id: KeyId, key: WrappedKey) -> Result<bool, Error> {
@@ -346,7 +602,8 @@ impl Store for Client {
         chain: ChainId,
         update: ChainStateUpdate,
     ) -> Result<(), Error> {
-        let Some(block) = update.block else {
+        let ChainStateUpdate { block, block_hash } = update;
+        let Some(block) = block else {
             return Ok(());
         };
         // TODO: use etag and conditional insert once etag is supported
@@ -363,13 +620,35 @@ impl Store for Client {
             .table_client(CHAIN_STATE_TABLE)
             .partition_key_client(chain.to_key())
             .entity_client("")
-            .insert_or_merge(update)?
+            .insert_or_replace(ChainState {
+                block,
+                log_index: 0,
+                block_hash,
+            })?
+            .into_future()
+            .await?;
+        Ok(())
+    }
+
+    async fn set_resume_point(
+        &self,
+        chain: ChainId,
+        EventIndex { block, log_index }: EventIndex,
+    ) -> Result<(), Error> {
+        self.db
+            .table_client(CHAIN_STATE_TABLE)
+            .partition_key_client(chain.to_key())
+            .entity_client("")
+            .insert_or_replace(ChainState {
+                block,
+                log_index,
+                block_hash: None,
+            })?
             .into_future()
             .await?;
         Ok(())
     }
 
-    #[cfg(test)]
     async fn clear_chain_state(&self, chain: ChainId) -> Result<(), Error> {
         self.db
             .table_client(CHAIN_STATE_TABLE)
@@ -386,11 +665,20 @@ impl Store for Client {
         &self,
         permitter: PermitterLocator,
         identity: IdentityId,
-    ) -> Result<Option<Vec<u8>>, Error> {
+    ) -> Result<Option<VerifierRecord>, Error> {
         Ok(self
             .get_current_verifier(permitter, identity)
             .await?
-            .map(|(_, v)| v.config))
+            .map(|(_, v)| VerifierRecord {
+                config: v.config,
+                event_index: EventIndex {
+                    block: v.block,
+                    log_index: v.log_index,
+                },
+                updated_at: v
+                    .updated_at_ms
+                    .map(|ms| std::time::UNIX_EPOCH + Duration::from_millis(ms)),
+            }))
     }
 
     async fn update_verifier(
@@ -418,12 +706,46 @@ impl Store for Client {
                 config,
                 block,
                 log_index,
+                updated_at_ms: Some(now_millis()),
             })?
             .into_future()
             .await?;
         Ok(())
     }
 
+    async fn put_verifier_if_absent(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+        config: Vec<u8>,
+        EventIndex { block, log_index }: EventIndex,
+    ) -> Result<bool, Error> {
+        let res = self
+            .db
+            .table_client(VERIFIERS_TABLE)
+            .insert::<_, ()>(VerifierEntity {
+                permitter,
+                identity,
+                config,
+                block,
+                log_index,
+                updated_at_ms: Some(now_millis()),
+            })?
+            .return_entity(false)
+            .into_future()
+            .await;
+        match res {
+            Ok(_) => Ok(true),
+            Err(e) => match e.kind() {
+                azure_core::error::ErrorKind::HttpResponse {
+                    status: azure_core::StatusCode::Conflict,
+                    ..
+                } => Ok(false),
+                _ => Err(e.into()),
+            },
+        }
+    }
+
     #[cfg(test)]
     async fn clear_verifier(
         &self,
@@ -440,6 +762,153 @@ impl Store for Client {
             .map(|_| ())
             .or_else(default_if_notfound)
     }
+
+    async fn delete_verifiers_for_chain(&self, chain: ChainId) -> Result<u64, Error> {
+        self.delete_verifiers_where(|p| p.chain == chain).await
+    }
+
+    async fn delete_verifiers_for_permitter(
+        &self,
+        permitter: PermitterLocator,
+    ) -> Result<u64, Error> {
+        self.delete_verifiers_where(|p| p == permitter).await
+    }
+
+    async fn gc(&self, retain_chain_ids: &[ChainId]) -> Result<GcReport, Error> {
+        let mut nonce_entries_deleted = 0;
+        let mut pages = self
+            .db
+            .table_client(NONCES_TABLE)
+            .query()
+            .into_stream::<NonceEntity>();
+        while let Some(page) = pages.try_next().await? {
+            for NonceEntity { identity, nonce } in page.entities {
+                if retain_chain_ids.contains(&identity.chain) {
+                    continue;
+                }
+                self.db
+                    .table_client(NONCES_TABLE)
+                    .partition_key_client(identity.to_key())
+                    .entity_client(nonce.as_slice().to_key())
+                    .delete()
+                    .into_future()
+                    .await
+                    .map(|_| ())
+                    .or_else(default_if_notfound)?;
+                nonce_entries_deleted += 1;
+            }
+        }
+
+        Ok(GcReport {
+            nonce_entries_deleted,
+            verifier_entries_deleted: self
+                .delete_verifiers_where(|p| !retain_chain_ids.contains(&p.chain))
+                .await?,
+        })
+    }
+
+    async fn checkpoint(&self) -> Result<CheckpointReport, Error> {
+        Ok(CheckpointReport::default())
+    }
+
+    /// Table storage has no aggregate queries, so this pages through the tables being counted.
+    async fn statistics(&self) -> Result<StoreStatistics, Error> {
+        let mut stats = StoreStatistics::default();
+        let mut pages = self
+            .db
+            .table_client(SECRET_VERSIONS_TABLE)
+            .query()
+            .filter("PartitionKey ge 'share-' and PartitionKey lt 'share.'")
+            .into_stream::<SecretVersionEntity>();
+        while let Some(page) = pages.try_next().await? {
+            stats.total_shares += page
+                .entities
+                .iter()
+                .filter(|entity| entity.deleted_at.is_none())
+                .count() as u64;
+        }
+        let mut pages = self
+            .db
+            .table_client(VERIFIERS_TABLE)
+            .query()
+            .select("PartitionKey")
+            .into_stream::<serde_json::Value>();
+        while let Some(page) = pages.try_next().await? {
+            stats.total_verifiers += page.entities.len() as u64;
+        }
+        let mut pages = self
+            .db
+            .table_client(CHAIN_STATE_TABLE)
+            .query()
+            .into_stream::<ChainState>();
+        while let Some(page) = pages.try_next().await? {
+            for state in page.entities {
+                stats.add_chain(state.block);
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Intents can carry shares, so they are kept in the vault and only indexed by the table.
+    async fn put_intent(&self, intent: intent::Intent) -> Result<(), Error> {
+        self.secrets
+            .set(
+                intent_secret_name(intent.id),
+                CborCodec.encode_text(&intent)?,
+            )
+            .into_future()
+            .await?;
+        self.db
+            .table_client(INTENTS_TABLE)
+            .partition_key_client(intent.id.to_key())
+            .entity_client("")
+            .insert_or_replace(IntentEntity { id: intent.id })?
+            .into_future()
+            .await?;
+        Ok(())
+    }
+
+    async fn list_intents(&self) -> Result<Vec<intent::Intent>, Error> {
+        let mut intents = Vec::new();
+        let mut pages = self
+            .db
+            .table_client(INTENTS_TABLE)
+            .query()
+            .into_stream::<IntentEntity>();
+        while let Some(page) = pages.try_next().await? {
+            for IntentEntity { id } in page.entities {
+                let secret = self
+                    .secrets
+                    .get(intent_secret_name(id))
+                    .into_future()
+                    .await?;
+                intents.push(CborCodec.decode_text(&secret.value)?);
+            }
+        }
+        Ok(intents)
+    }
+
+    async fn clear_intent(&self, id: u64) -> Result<(), Error> {
+        self.db
+            .table_client(INTENTS_TABLE)
+            .partition_key_client(id.to_key())
+            .entity_client("")
+            .delete()
+            .into_future()
+            .await
+            .map(|_| ())
+            .or_else(default_if_notfound)?;
+        self.secrets
+            .delete(intent_secret_name(id))
+            .into_future()
+            .await
+            .map(|_| ())
+            .or_else(default_if_notfound)
+    }
+}
+
+fn intent_secret_name(id: u64) -> String {
+    format!("intent-{id}")
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -471,6 +940,12 @@ struct PermitEntity {
     recipient: Address,
 }
 
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+struct IntentEntity {
+    #[serde(rename = "PartitionKey", with = "serde_key")]
+    id: u64,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 struct VerifierEntity {
     #[serde(rename = "PartitionKey", with = "serde_key")]
@@ -481,6 +956,9 @@ struct VerifierEntity {
     config: Vec<u8>,
     block: u64,
     log_index: u64,
+    /// When the config was written, in milliseconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    updated_at_ms: Option<u64>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -490,6 +968,12 @@ struct SecretVersionEntity {
     #[serde(rename = "RowKey")]
     version: InvSortableInt,
     guid: String,
+    /// When the version was put, in milliseconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stored_at_ms: Option<u64>,
+    /// When the share was deleted, in seconds since the Unix epoch.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deleted_at: Option<u64>,
 }
 
 /// An integer that sorts inverse numerically when stringified,
@@ -539,10 +1023,30 @@ fn default_if_notfound<T: Default>(e: azure_core::Error) -> Result<T, Error> {
 mod tests {
     use super::*;
 
-    crate::make_store_tests!(async {
+    async fn connect() -> Client {
         let ssss_host = std::env::var("SSSS_HOST").expect("SSSS_HOST must be set");
         Client::connect(&Authority::try_from(ssss_host).unwrap(), Environment::Dev)
             .await
             .unwrap()
-    });
+    }
+
+    crate::make_store_tests!(connect());
+    crate::make_store_tests!(
+        async {
+            connect().await.with_share_retention(ShareRetention {
+                max_versions: 2.try_into().unwrap(),
+                grace: Duration::ZERO,
+            })
+        },
+        evict_old_share_versions
+    );
+
+    #[test]
+    fn decode_corrupt_share() {
+        assert!(decode_ss("1-abcd".into()).is_ok());
+        assert!(decode_ss("1".into()).is_err());
+        assert!(decode_ss("one-abcd".into()).is_err());
+        assert!(decode_ss("1-xyz".into()).is_err());
+        assert!(decode_ss("1-abcd-ff00".into()).is_err());
+    }
 }
@@ -0,0 +1,230 @@
+//! Versioned encodings of store records, so that backends and backups agree on one format.
+//!
+//! Each record is wrapped in an envelope naming its kind and the format version that wrote it.
+//! Fields added in later versions must be defaulted when absent so that older records still
+//! decode, and unknown fields are ignored so that a downgraded node can read newer ones.
+
+use base64::{engine::general_purpose::STANDARD, Engine as _};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use super::Error;
+use crate::types::*;
+
+/// The version written by this build. Records of any version up to this one can be decoded.
+pub const FORMAT_VERSION: u32 = 1;
+
+pub trait Record: Serialize + DeserializeOwned {
+    const KIND: &'static str;
+}
+
+impl Record for SecretShare {
+    const KIND: &'static str = "share";
+}
+
+impl Record for ShareProvenance {
+    const KIND: &'static str = "share-provenance";
+}
+
+impl Record for super::intent::Intent {
+    const KIND: &'static str = "intent";
+}
+
+impl Record for ShareId {
+    const KIND: &'static str = "share-id";
+}
+
+/// The policy that an identity's permits are checked against, and the event that set it.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifierRecord {
+    #[serde(with = "crate::utils::bytes_serde")]
+    pub config: Vec<u8>,
+    pub version: EventIndex,
+}
+
+impl Record for VerifierRecord {
+    const KIND: &'static str = "verifier";
+}
+
+pub trait RecordCodec {
+    fn encode<R: Record>(&self, record: &R) -> Result<Vec<u8>, Error>;
+
+    fn decode<R: Record>(&self, bytes: &[u8]) -> Result<R, Error>;
+
+    /// Encodes the record as base64, for backends that can only hold strings.
+    fn encode_text<R: Record>(&self, record: &R) -> Result<String, Error> {
+        Ok(STANDARD.encode(self.encode(record)?))
+    }
+
+    fn decode_text<R: Record>(&self, text: &str) -> Result<R, Error> {
+        self.decode(&STANDARD.decode(text)?)
+    }
+}
+
+/// Compact binary encoding for storage.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct CborCodec;
+
+impl RecordCodec for CborCodec {
+    fn encode<R: Record>(&self, record: &R) -> Result<Vec<u8>, Error> {
+        let mut buf = Vec::new();
+        ciborium::ser::into_writer(&Envelope::new(record), &mut buf)?;
+        Ok(buf)
+    }
+
+    fn decode<R: Record>(&self, bytes: &[u8]) -> Result<R, Error> {
+        ciborium::de::from_reader_with_recursion_limit::<Envelope<R>, _>(bytes, 16)?.open()
+    }
+}
+
+/// Human-readable encoding for debugging dumps. Bytes are written as hex.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct JsonCodec;
+
+impl RecordCodec for JsonCodec {
+    fn encode<R: Record>(&self, record: &R) -> Result<Vec<u8>, Error> {
+        Ok(serde_json::to_vec_pretty(&Envelope::new(record))?)
+    }
+
+    fn decode<R: Record>(&self, bytes: &[u8]) -> Result<R, Error> {
+        serde_json::from_slice::<Envelope<R>>(bytes)?.open()
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope<T> {
+    v: u32,
+    kind: String,
+    record: T,
+}
+
+impl<'a, R: Record> Envelope<&'a R> {
+    fn new(record: &'a R) -> Self {
+        Self {
+            v: FORMAT_VERSION,
+            kind: R::KIND.into(),
+            record,
+        }
+    }
+}
+
+impl<R: Record> Envelope<R> {
+    fn open(self) -> Result<R, Error> {
+        if self.kind != R::KIND {
+            anyhow::bail!(
+                "expected a {} record but found a {} record",
+                R::KIND,
+                self.kind
+            );
+        }
+        if self.v > FORMAT_VERSION {
+            anyhow::bail!(
+                "record format v{} is newer than supported v{FORMAT_VERSION}",
+                self.v
+            );
+        }
+        Ok(self.record)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{Address, H256};
+
+    use super::*;
+
+    fn share() -> SecretShare {
+        SecretShare {
+            index: ShareIndex(3),
+            share: vec![0xab; 32].into(),
+            provenance: None,
+        }
+    }
+
+    fn share_id() -> ShareId {
+        ShareId {
+            secret_name: "omni".into(),
+            identity: IdentityLocator {
+                chain: 31337,
+                registry: Address::repeat_byte(1),
+                id: IdentityId(H256::repeat_byte(2)),
+            },
+            version: 7,
+        }
+    }
+
+    fn verifier() -> VerifierRecord {
+        VerifierRecord {
+            config: b"policy".to_vec(),
+            version: EventIndex {
+                block: 5,
+                log_index: 1,
+            },
+        }
+    }
+
+    fn roundtrip(codec: impl RecordCodec) {
+        assert_eq!(
+            codec
+                .decode::<SecretShare>(&codec.encode(&share()).unwrap())
+                .unwrap(),
+            share()
+        );
+        assert_eq!(
+            codec
+                .decode::<ShareId>(&codec.encode(&share_id()).unwrap())
+                .unwrap(),
+            share_id()
+        );
+        assert_eq!(
+            codec
+                .decode::<VerifierRecord>(&codec.encode(&verifier()).unwrap())
+                .unwrap(),
+            verifier()
+        );
+        codec
+            .decode::<ShareId>(&codec.encode(&share()).unwrap())
+            .unwrap_err();
+    }
+
+    #[test]
+    fn roundtrip_cbor() {
+        roundtrip(CborCodec);
+        // Bytes are written as a byte string rather than as an array of integers or hex.
+        assert!(CborCodec
+            .encode(&share())
+            .unwrap()
+            .windows(32)
+            .any(|w| w == *share().share));
+    }
+
+    #[test]
+    fn roundtrip_text() {
+        let text = CborCodec.encode_text(&share_id()).unwrap();
+        assert_eq!(CborCodec.decode_text::<ShareId>(&text).unwrap(), share_id());
+        CborCodec.decode_text::<ShareId>("not base64!").unwrap_err();
+    }
+
+    #[test]
+    fn roundtrip_json() {
+        roundtrip(JsonCodec);
+    }
+
+    #[test]
+    fn decode_other_versions() {
+        let with_future_field = format!(
+            r#"{{"v":1,"kind":"share","record":{{"index":3,"share":"{}","threshold":2}}}}"#,
+            "ab".repeat(32)
+        );
+        assert_eq!(
+            JsonCodec
+                .decode::<SecretShare>(with_future_field.as_bytes())
+                .unwrap(),
+            share()
+        );
+
+        let newer = with_future_field.replace(r#""v":1"#, r#""v":2"#);
+        JsonCodec
+            .decode::<SecretShare>(newer.as_bytes())
+            .unwrap_err();
+    }
+}
@@ -0,0 +1,286 @@
+//! A write-ahead intent log that makes multi-record updates crash consistent on any backend.
+//!
+//! An operation run through [`atomically`] is first recorded as an [`Intent`] listing its steps.
+//! The steps are then applied and the intent is cleared. If the process dies partway through, the
+//! intent outlives it, and [`recover`] rolls the operation forward on the next startup. Recovery
+//! replays every step from the first, so steps are restricted to writes that are idempotent.
+//! Replayed resume points only ever move sync forward, so an intent cannot be used to rewind it.
+
+use serde::{Deserialize, Serialize};
+use tracing::info;
+
+use super::{Error, Store};
+use crate::types::*;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq, Eq))]
+pub struct Intent {
+    /// A random id that distinguishes this intent from others outstanding at the same time.
+    pub id: u64,
+    /// When the intent was recorded, in seconds since the Unix epoch.
+    pub created_at: u64,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[cfg_attr(test, derive(Debug, PartialEq, Eq))]
+pub enum Step {
+    /// Writes the share version. A version that already exists is left as is.
+    PutShare {
+        id: ShareId,
+        share: SecretShare,
+    },
+    DeleteShareVersion(ShareId),
+    RestoreShare(ShareId),
+    /// Writes the key version. A version that already exists is left as is.
+    PutKey {
+        id: KeyId,
+        key: WrappedKey,
+    },
+    DeleteKeyVersion(KeyId),
+    /// Sets the resume point, or when replayed by [`recover`], advances it to `index` if it is
+    /// behind.
+    SetResumePoint {
+        chain: ChainId,
+        index: EventIndex,
+    },
+    ClearChainState(ChainId),
+    UpdateChainState {
+        chain: ChainId,
+        update: ChainStateUpdate,
+    },
+    UpdateVerifier {
+        permitter: PermitterLocator,
+        identity: IdentityId,
+        #[serde(with = "crate::utils::bytes_serde")]
+        config: Vec<u8>,
+        version: EventIndex,
+    },
+    DeleteVerifiersForChain(ChainId),
+    DeleteVerifiersForPermitter(PermitterLocator),
+}
+
+impl Step {
+    pub async fn apply(self, store: &impl Store) -> Result<(), Error> {
+        match self {
+            // A replayed put finds the version written by the interrupted attempt.
+            Self::PutShare { id, share } => {
+                store.put_share(id, share).await?;
+            }
+            Self::DeleteShareVersion(id) => store.delete_share_version(id).await?,
+            Self::RestoreShare(id) => {
+                store.restore_share(id).await?;
+            }
+            Self::PutKey { id, key } => {
+                store.put_key(id, key).await?;
+            }
+            Self::DeleteKeyVersion(id) => store.delete_key_version(id).await?,
+            Self::SetResumePoint { chain, index } => store.set_resume_point(chain, index).await?,
+            Self::ClearChainState(chain) => store.clear_chain_state(chain).await?,
+            Self::UpdateChainState { chain, update } => {
+                store.update_chain_state(chain, update).await?
+            }
+            Self::UpdateVerifier {
+                permitter,
+                identity,
+                config,
+                version,
+            } => {
+                store
+                    .update_verifier(permitter, identity, config, version)
+                    .await?
+            }
+            Self::DeleteVerifiersForChain(chain) => {
+                store.delete_verifiers_for_chain(chain).await?;
+            }
+            Self::DeleteVerifiersForPermitter(permitter) => {
+                store.delete_verifiers_for_permitter(permitter).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the step as if for the first time, except that a resume point is not moved back.
+    /// Sync may have progressed past it since the intent was recorded.
+    async fn replay(self, store: &impl Store) -> Result<(), Error> {
+        if let Self::SetResumePoint { chain, index } = &self {
+            let current = store.get_chain_state(*chain).await?;
+            if current.is_some_and(|state| state.resume_point() >= *index) {
+                return Ok(());
+            }
+        }
+        self.apply(store).await
+    }
+}
+
+/// Applies the steps in order such that, even across a crash, either all or none of them take
+/// effect once [`recover`] has run. If a step fails, the intent is kept for recovery to retry.
+pub async fn atomically(store: &impl Store, steps: Vec<Step>) -> Result<(), Error> {
+    let intent = Intent {
+        id: rand::random(),
+        created_at: super::now(),
+        steps,
+    };
+    store.put_intent(intent.clone()).await?;
+    for step in intent.steps {
+        step.apply(store).await?;
+    }
+    store.clear_intent(intent.id).await
+}
+
+/// Finishes every operation that was interrupted after its intent was recorded, oldest first, and
+/// returns how many there were.
+///
+/// This should run before the store is otherwise written to, so that operations are finished
+/// before anything observes them half done.
+pub async fn recover(store: &impl Store) -> Result<usize, Error> {
+    let mut intents = store.list_intents().await?;
+    intents.sort_by_key(|intent| (intent.created_at, intent.id));
+    let count = intents.len();
+    for intent in intents {
+        info!(
+            id = intent.id,
+            created_at = intent.created_at,
+            "completing interrupted store operation"
+        );
+        for step in intent.steps {
+            step.replay(store).await?;
+        }
+        store.clear_intent(intent.id).await?;
+    }
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::{Address, H256};
+
+    use super::*;
+    use crate::store::memory::MemoryStore;
+
+    fn share_id(version: u64) -> ShareId {
+        ShareId {
+            secret_name: "omni".into(),
+            identity: IdentityLocator {
+                chain: 31337,
+                registry: Address::repeat_byte(1),
+                id: IdentityId(H256::repeat_byte(2)),
+            },
+            version,
+        }
+    }
+
+    fn put_share(version: u64) -> Step {
+        Step::PutShare {
+            id: share_id(version),
+            share: SecretShare {
+                index: ShareIndex(1),
+                share: vec![version as u8; 32].into(),
+                provenance: None,
+            },
+        }
+    }
+
+    #[tokio::test]
+    async fn atomically_applies_and_clears() {
+        let store = MemoryStore::default();
+        let resume_point = EventIndex {
+            block: 5,
+            log_index: 1,
+        };
+        atomically(
+            &store,
+            vec![
+                put_share(1),
+                Step::SetResumePoint {
+                    chain: 31337,
+                    index: resume_point,
+                },
+            ],
+        )
+        .await
+        .unwrap();
+
+        assert!(store.get_share(share_id(1)).await.unwrap().is_some());
+        assert_eq!(
+            store
+                .get_chain_state(31337)
+                .await
+                .unwrap()
+                .unwrap()
+                .resume_point(),
+            resume_point
+        );
+        assert!(store.list_intents().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn recover_completes_interrupted_intents() {
+        let store = MemoryStore::default();
+        // The first step landed before the crash but the second did not.
+        let Step::PutShare { id, share } = put_share(1) else {
+            unreachable!()
+        };
+        store.put_share(id, share).await.unwrap();
+        store
+            .put_intent(Intent {
+                id: 1,
+                created_at: 0,
+                steps: vec![put_share(1), put_share(2)],
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(recover(&store).await.unwrap(), 1);
+        assert!(store.get_share(share_id(1)).await.unwrap().is_some());
+        assert!(store.get_share(share_id(2)).await.unwrap().is_some());
+        assert!(store.list_intents().await.unwrap().is_empty());
+        assert_eq!(recover(&store).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn recover_never_rewinds_resume_point() {
+        let store = MemoryStore::default();
+        let index = |block| EventIndex {
+            block,
+            log_index: 0,
+        };
+        store.set_resume_point(31337, index(10)).await.unwrap();
+        for (id, block) in [(1, 5), (2, 20)] {
+            store
+                .put_intent(Intent {
+                    id,
+                    created_at: id,
+                    steps: vec![Step::SetResumePoint {
+                        chain: 31337,
+                        index: index(block),
+                    }],
+                })
+                .await
+                .unwrap();
+        }
+        let resume_point = || async {
+            store
+                .get_chain_state(31337)
+                .await
+                .unwrap()
+                .unwrap()
+                .resume_point()
+        };
+
+        assert_eq!(recover(&store).await.unwrap(), 2);
+        assert_eq!(resume_point().await, index(20));
+
+        // Outside of recovery, the resume point is set as asked.
+        atomically(
+            &store,
+            vec![Step::SetResumePoint {
+                chain: 31337,
+                index: index(5),
+            }],
+        )
+        .await
+        .unwrap();
+        assert_eq!(resume_point().await, index(5));
+    }
+}
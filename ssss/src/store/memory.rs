@@ -1,38 +1,225 @@
 use std::{
     collections::{
         btree_map::{self, BTreeMap},
-        HashMap, HashSet,
+        BTreeSet,
     },
+    ops::Bound,
     sync::{Arc, RwLock},
+    time::Instant,
 };
 
+use dashmap::{mapref::entry::Entry, DashMap, DashSet};
+
 use super::*;
 
 #[derive(Clone, Default)]
 pub struct MemoryStore {
     state: Arc<State>,
+    share_retention: Option<ShareRetention>,
 }
 
 type Grantee = (IdentityLocator, Address);
 type PermitterIdentityLocator = (PermitterLocator, IdentityId);
-type VerionedVerifierConfig = (Vec<u8>, EventIndex);
 type IdentityNamedItem = (IdentityLocator, String);
 type IdentityNonce = (IdentityLocator, Nonce);
+/// Orders shares by chain, registry, identity, and then name.
+type ShareOrderKey = (ChainId, Address, ethers::types::H256, String);
+
+fn share_order_key((identity, name): &IdentityNamedItem) -> ShareOrderKey {
+    (
+        identity.chain,
+        identity.registry,
+        identity.id.0,
+        name.clone(),
+    )
+}
 
+/// Each map is sharded so that writes do not wait for reads of unrelated entries.
 #[derive(Default)]
 struct State {
-    shares: RwLock<HashMap<IdentityLocator, BTreeMap<u64, Option<SecretShare>>>>,
-    keys: RwLock<HashMap<IdentityNamedItem, BTreeMap<u64, Option<WrappedKey>>>>,
-    permits: RwLock<HashMap<Grantee, Permit>>,
-    verifiers: RwLock<HashMap<PermitterIdentityLocator, VerionedVerifierConfig>>,
-    chain: RwLock<HashMap<u64, ChainState>>,
-    nonces: RwLock<HashSet<IdentityNonce>>,
+    shares: DashMap<IdentityNamedItem, BTreeMap<u64, StoredShare>>,
+    /// The keys of `shares` in listing order, so that listing a page does not sort every share.
+    /// It is only written once no entry of `shares` is held, so that readers may hold it while
+    /// reading `shares`.
+    share_order: RwLock<BTreeSet<ShareOrderKey>>,
+    keys: DashMap<IdentityNamedItem, BTreeMap<u64, Option<WrappedKey>>>,
+    permits: DashMap<Grantee, Permit>,
+    verifiers: DashMap<PermitterIdentityLocator, VerifierRecord>,
+    chain: DashMap<u64, ChainState>,
+    nonces: DashSet<IdentityNonce>,
+    intents: DashMap<u64, intent::Intent>,
+}
+
+struct StoredShare {
+    share: SecretShare,
+    /// When the share was put.
+    stored_at: Instant,
+    /// When the share was deleted, in seconds since the Unix epoch.
+    deleted_at: Option<u64>,
+}
+
+/// A rough per-entry cost of map keys and bookkeeping, on top of the entry's variable-size data.
+const ENTRY_OVERHEAD: usize = 96;
+
+impl MemoryStore {
+    /// Evicts old share versions as `retention` says whenever a share is put.
+    pub fn with_share_retention(mut self, retention: ShareRetention) -> Self {
+        self.share_retention = Some(retention);
+        self
+    }
+
+    /// Approximates the number of bytes held by this store.
+    pub fn size_bytes(&self) -> usize {
+        let State {
+            shares,
+            share_order,
+            keys,
+            permits,
+            verifiers,
+            chain,
+            nonces,
+            intents,
+        } = &*self.state;
+        let shares: usize = shares
+            .iter()
+            .map(|versions| {
+                let (_, name) = versions.key();
+                name.len()
+                    + versions
+                        .values()
+                        .map(|stored| ENTRY_OVERHEAD + share_size(&stored.share))
+                        .sum::<usize>()
+            })
+            .sum();
+        let keys: usize = keys
+            .iter()
+            .map(|versions| {
+                let (_, name) = versions.key();
+                name.len()
+                    + versions
+                        .values()
+                        .map(|key| ENTRY_OVERHEAD + key.as_ref().map_or(0, |k| k.as_ref().len()))
+                        .sum::<usize>()
+            })
+            .sum();
+        let verifiers: usize = verifiers
+            .iter()
+            .map(|verifier| ENTRY_OVERHEAD + verifier.config.len())
+            .sum();
+        let nonces: usize = nonces
+            .iter()
+            .map(|nonce| ENTRY_OVERHEAD + nonce.1.len())
+            .sum();
+        let intents: usize = intents
+            .iter()
+            .map(|intent| ENTRY_OVERHEAD * (1 + intent.steps.len()))
+            .sum();
+        let share_order: usize = share_order
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(_, _, _, name)| ENTRY_OVERHEAD + name.len())
+            .sum();
+        let fixed_size_entries = permits.len() + chain.len();
+        shares
+            + share_order
+            + keys
+            + verifiers
+            + nonces
+            + intents
+            + fixed_size_entries * ENTRY_OVERHEAD
+    }
+
+    /// The number of share versions held, including deleted ones.
+    fn share_count(&self) -> usize {
+        self.state
+            .shares
+            .iter()
+            .map(|versions| versions.len())
+            .sum()
+    }
+
+    fn delete_verifiers_where(&self, delete: impl Fn(&PermitterLocator) -> bool) -> u64 {
+        let mut deleted = 0;
+        self.state.verifiers.retain(|(permitter, _), _| {
+            let retain = !delete(permitter);
+            deleted += u64::from(!retain);
+            retain
+        });
+        deleted
+    }
+}
+
+/// Drops the oldest versions beyond the retention limit whose grace period has passed. Shares
+/// zeroize their bytes when dropped.
+fn evict_share_versions(
+    versions: &mut BTreeMap<u64, StoredShare>,
+    retention: ShareRetention,
+    now: Instant,
+) {
+    let (live, stored_for): (Vec<u64>, Vec<Duration>) = versions
+        .iter()
+        .filter(|(_, stored)| stored.deleted_at.is_none())
+        .map(|(&version, stored)| (version, now.duration_since(stored.stored_at)))
+        .unzip();
+    for version in &live[..retention.evictions(&stored_for)] {
+        versions.remove(version);
+    }
+}
+
+/// Summarizes the store without any of its secret material, so that it is safe to log.
+impl std::fmt::Debug for MemoryStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        struct Redacted;
+        impl std::fmt::Debug for Redacted {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                f.write_str("<REDACTED>")
+            }
+        }
+
+        f.debug_struct("MemoryStore")
+            .field("share_count", &self.share_count())
+            .field("shares", &Redacted)
+            .field("keys", &Redacted)
+            .field("verifiers", &self.state.verifiers.len())
+            .field("chains", &self.state.chain.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl std::fmt::Display for MemoryStore {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "MemoryStore(shares={}, verifiers={}, chains={})",
+            self.share_count(),
+            self.state.verifiers.len(),
+            self.state.chain.len()
+        )
+    }
+}
+
+fn share_size(
+    SecretShare {
+        share, provenance, ..
+    }: &SecretShare,
+) -> usize {
+    share.len()
+        + std::mem::size_of::<u64>()
+        + provenance.as_ref().map_or(0, |p| {
+            std::mem::size_of::<ShareProvenance>() + p.dealer_pk.len()
+        })
 }
 
 impl Store for MemoryStore {
     async fn put_share(&self, id: ShareId, share: SecretShare) -> Result<bool, Error> {
-        let mut shares = self.state.shares.write().unwrap();
-        let versions = shares.entry(id.identity).or_default();
+        let key = (id.identity, id.secret_name.clone());
+        let order_key = share_order_key(&key);
+        let mut versions = self.state.shares.entry(key).or_default();
+        let existing = versions
+            .get(&id.version)
+            .filter(|stored| stored.deleted_at.is_none());
+        ShareIndexConflict::check(&id, existing.map(|stored| &stored.share), share.index)?;
         let current_version = versions
             .last_key_value()
             .map(|(k, _)| *k)
@@ -40,7 +227,20 @@ impl Store for MemoryStore {
         if id.version != current_version + 1 {
             return Ok(false);
         }
-        versions.insert(id.version, Some(share));
+        let stored_at = Instant::now();
+        versions.insert(
+            id.version,
+            StoredShare {
+                share,
+                stored_at,
+                deleted_at: None,
+            },
+        );
+        if let Some(retention) = self.share_retention {
+            evict_share_versions(&mut versions, retention, stored_at);
+        }
+        drop(versions);
+        self.state.share_order.write().unwrap().insert(order_key);
         Ok(true)
     }
 
@@ -48,25 +248,126 @@ impl Store for MemoryStore {
         Ok(self
             .state
             .shares
-            .read()
-            .unwrap()
-            .get(&id.identity)
-            .and_then(|versions| versions.get(&id.version).cloned())
-            .flatten())
+            .get(&(id.identity, id.secret_name))
+            .and_then(|versions| {
+                versions
+                    .get(&id.version)
+                    .filter(|stored| stored.deleted_at.is_none())
+                    .map(|stored| stored.share.clone())
+            }))
+    }
+
+    async fn get_share_age(&self, id: ShareId) -> Result<Option<Duration>, Error> {
+        Ok(self
+            .state
+            .shares
+            .get(&(id.identity, id.secret_name))
+            .and_then(|versions| {
+                versions
+                    .get(&id.version)
+                    .filter(|stored| stored.deleted_at.is_none())
+                    .map(|stored| stored.stored_at.elapsed())
+            }))
     }
 
     async fn delete_share_version(&self, id: ShareId) -> Result<(), Error> {
-        if let Some(versions) = self.state.shares.write().unwrap().get_mut(&id.identity) {
-            if let btree_map::Entry::Occupied(mut oe) = versions.entry(id.version) {
-                oe.insert(None);
+        if let Some(mut versions) = self.state.shares.get_mut(&(id.identity, id.secret_name)) {
+            if let Some(stored) = versions.get_mut(&id.version) {
+                stored.deleted_at.get_or_insert_with(now);
             }
         }
         Ok(())
     }
 
+    async fn restore_share(&self, id: ShareId) -> Result<bool, Error> {
+        let Some(mut versions) = self.state.shares.get_mut(&(id.identity, id.secret_name)) else {
+            return Ok(false);
+        };
+        Ok(versions
+            .get_mut(&id.version)
+            .and_then(|stored| stored.deleted_at.take())
+            .is_some())
+    }
+
+    async fn list_deleted_shares(&self) -> Result<Vec<ShareId>, Error> {
+        Ok(self
+            .state
+            .shares
+            .iter()
+            .flat_map(|versions| {
+                let (identity, secret_name) = versions.key().clone();
+                versions
+                    .iter()
+                    .filter(|(_, stored)| stored.deleted_at.is_some())
+                    .map(|(version, _)| ShareId {
+                        secret_name: secret_name.clone(),
+                        identity,
+                        version: *version,
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect())
+    }
+
+    async fn list_shares_page(
+        &self,
+        filter: ShareFilter,
+        cursor: Option<ShareCursor>,
+        page_size: u32,
+    ) -> Result<SharePage, Error> {
+        let after = cursor.map(|ShareCursor(id)| {
+            let version = id.version;
+            (share_order_key(&(id.identity, id.secret_name)), version)
+        });
+        let order = self.state.share_order.read().unwrap();
+        let keys = match &after {
+            Some((key, _)) => order.range(key.clone()..),
+            None => order.range::<ShareOrderKey, _>(..),
+        };
+        let mut items = Vec::new();
+        let mut more = false;
+        'keys: for key in keys {
+            let (chain, registry, identity, secret_name) = key.clone();
+            let identity = IdentityLocator {
+                chain,
+                registry,
+                id: IdentityId(identity),
+            };
+            let share_id = |version| ShareId {
+                secret_name: secret_name.clone(),
+                identity,
+                version,
+            };
+            if !filter.matches(&share_id(0)) {
+                continue;
+            }
+            let Some(versions) = self.state.shares.get(&(identity, secret_name.clone())) else {
+                continue;
+            };
+            let after_version = match &after {
+                Some((after_key, version)) if after_key == key => Bound::Excluded(*version),
+                _ => Bound::Unbounded,
+            };
+            for (&version, stored) in versions.range((after_version, Bound::Unbounded)) {
+                if stored.deleted_at.is_some() {
+                    continue;
+                }
+                if items.len() == page_size as usize {
+                    more = true;
+                    break 'keys;
+                }
+                items.push((share_id(version), stored.share.clone()));
+            }
+        }
+        let next_cursor = match more {
+            true => items.last().map(|(id, _)| ShareCursor(id.clone())),
+            false => None,
+        };
+        Ok(SharePage { items, next_cursor })
+    }
+
     async fn put_key(&self, id: KeyId, key: WrappedKey) -> Result<bool, Error> {
-        let mut keys = self.state.keys.write().unwrap();
-        let versions = keys.entry((id.identity, id.name)).or_default();
+        let mut versions = self.state.keys.entry((id.identity, id.name)).or_default();
         let current_version = versions
             .last_key_value()
             .map(|(k, _)| *k)
@@ -82,21 +383,13 @@ impl Store for MemoryStore {
         Ok(self
             .state
             .keys
-            .read()
-            .unwrap()
             .get(&(id.identity, id.name))
             .and_then(|versions| versions.get(&id.version).cloned())
             .flatten())
     }
 
     async fn delete_key_version(&self, id: KeyId) -> Result<(), Error> {
-        if let Some(versions) = self
-            .state
-            .keys
-            .write()
-            .unwrap()
-            .get_mut(&(id.identity, id.name))
-        {
+        if let Some(mut versions) = self.state.keys.get_mut(&(id.identity, id.name)) {
             if let btree_map::Entry::Occupied(mut oe) = versions.entry(id.version) {
                 oe.insert(None);
             }
@@ -111,31 +404,21 @@ impl Store for MemoryStore {
         expiry: u64,
         nonce: Nonce,
     ) -> Result<Option<Permit>, Error> {
-        if !self.state.nonces.write().unwrap().insert((identity, nonce)) {
+        if !self.state.nonces.insert((identity, nonce)) {
             return Ok(None);
         }
-        Ok(
-            match self
-                .state
-                .permits
-                .write()
-                .unwrap()
-                .entry((identity, recipient))
-            {
-                std::collections::hash_map::Entry::Occupied(mut oe) => {
-                    let permit = oe.get_mut();
-                    if permit.expiry < expiry {
-                        permit.expiry = expiry;
-                        Some(permit.to_owned())
-                    } else {
-                        None
-                    }
-                }
-                std::collections::hash_map::Entry::Vacant(ve) => {
-                    Some(ve.insert(Permit { expiry }).to_owned())
+        Ok(match self.state.permits.entry((identity, recipient)) {
+            Entry::Occupied(mut oe) => {
+                let permit = oe.get_mut();
+                if permit.expiry < expiry {
+                    permit.expiry = expiry;
+                    Some(permit.to_owned())
+                } else {
+                    None
                 }
-            },
-        )
+            }
+            Entry::Vacant(ve) => Some(ve.insert(Permit { expiry }).to_owned()),
+        })
     }
 
     async fn read_permit(
@@ -143,18 +426,10 @@ impl Store for MemoryStore {
         identity: IdentityLocator,
         recipient: Address,
     ) -> Result<Option<Permit>, Error> {
-        Ok(
-            match self
-                .state
-                .permits
-                .read()
-                .unwrap()
-                .get(&(identity, recipient))
-            {
-                Some(permit) if permit.expiry > now() => Some(permit.clone()),
-                _ => None,
-            },
-        )
+        Ok(match self.state.permits.get(&(identity, recipient)) {
+            Some(permit) if permit.expiry > now() => Some(permit.clone()),
+            _ => None,
+        })
     }
 
     async fn delete_permit(
@@ -162,35 +437,45 @@ impl Store for MemoryStore {
         identity: IdentityLocator,
         recipient: Address,
     ) -> Result<(), Error> {
-        self.state
-            .permits
-            .write()
-            .unwrap()
-            .remove(&(identity, recipient));
+        self.state.permits.remove(&(identity, recipient));
         Ok(())
     }
 
     async fn get_chain_state(&self, chain: u64) -> Result<Option<ChainState>, Error> {
-        Ok(self.state.chain.read().unwrap().get(&chain).cloned())
+        Ok(self.state.chain.get(&chain).map(|state| state.clone()))
     }
 
     async fn update_chain_state(&self, chain: u64, update: ChainStateUpdate) -> Result<(), Error> {
-        let ChainStateUpdate { block } = update;
+        let ChainStateUpdate { block, block_hash } = update;
         let new_block = match block {
             Some(block) => block,
             None => return Ok(()),
         };
-        let mut chain_state = self.state.chain.write().unwrap();
-        let current_state = chain_state.entry(chain).or_default();
+        let mut current_state = self.state.chain.entry(chain).or_default();
         if current_state.block < new_block {
-            current_state.block = new_block;
+            *current_state = ChainState {
+                block: new_block,
+                log_index: 0,
+                block_hash,
+            };
         }
         Ok(())
     }
 
-    #[cfg(test)]
+    async fn set_resume_point(&self, chain: u64, index: EventIndex) -> Result<(), Error> {
+        self.state.chain.insert(
+            chain,
+            ChainState {
+                block: index.block,
+                log_index: index.log_index,
+                block_hash: None,
+            },
+        );
+        Ok(())
+    }
+
     async fn clear_chain_state(&self, chain: u64) -> Result<(), Error> {
-        self.state.chain.write().unwrap().remove(&chain);
+        self.state.chain.remove(&chain);
         Ok(())
     }
 
@@ -198,14 +483,12 @@ impl Store for MemoryStore {
         &self,
         permitter: PermitterLocator,
         identity: IdentityId,
-    ) -> Result<Option<Vec<u8>>, Error> {
+    ) -> Result<Option<VerifierRecord>, Error> {
         Ok(self
             .state
             .verifiers
-            .read()
-            .unwrap()
             .get(&(permitter, identity))
-            .map(|(config, _)| config.clone()))
+            .map(|verifier| verifier.clone()))
     }
 
     async fn update_verifier(
@@ -215,34 +498,120 @@ impl Store for MemoryStore {
         config: Vec<u8>,
         version: EventIndex,
     ) -> Result<(), Error> {
-        let mut config = Some(config);
+        let mut record = Some(VerifierRecord {
+            config,
+            event_index: version,
+            updated_at: Some(SystemTime::now()),
+        });
         self.state
             .verifiers
-            .write()
-            .unwrap()
             .entry((permitter, identity))
-            .and_modify(|(current_config, current_version)| {
-                if version <= *current_version {
+            .and_modify(|current| {
+                if version <= current.event_index {
                     return;
                 }
-                *current_config = config.take().unwrap();
-                *current_version = version;
+                *current = record.take().unwrap();
             })
-            .or_insert_with(|| (config.take().unwrap(), version));
+            .or_insert_with(|| record.take().unwrap());
         Ok(())
     }
 
+    async fn put_verifier_if_absent(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+        config: Vec<u8>,
+        version: EventIndex,
+    ) -> Result<bool, Error> {
+        Ok(match self.state.verifiers.entry((permitter, identity)) {
+            Entry::Occupied(_) => false,
+            Entry::Vacant(ve) => {
+                ve.insert(VerifierRecord {
+                    config,
+                    event_index: version,
+                    updated_at: Some(SystemTime::now()),
+                });
+                true
+            }
+        })
+    }
+
     #[cfg(test)]
     async fn clear_verifier(
         &self,
         permitter: PermitterLocator,
         identity: IdentityId,
     ) -> Result<(), Error> {
-        self.state
-            .verifiers
-            .write()
-            .unwrap()
-            .remove(&(permitter, identity));
+        self.state.verifiers.remove(&(permitter, identity));
+        Ok(())
+    }
+
+    async fn delete_verifiers_for_chain(&self, chain: ChainId) -> Result<u64, Error> {
+        Ok(self.delete_verifiers_where(|permitter| permitter.chain == chain))
+    }
+
+    async fn delete_verifiers_for_permitter(
+        &self,
+        permitter: PermitterLocator,
+    ) -> Result<u64, Error> {
+        Ok(self.delete_verifiers_where(|p| *p == permitter))
+    }
+
+    async fn gc(&self, retain_chain_ids: &[ChainId]) -> Result<GcReport, Error> {
+        // Entries may be added while retaining, so count the removals instead of diffing lengths.
+        let mut report = GcReport::default();
+        self.state.nonces.retain(|(identity, _)| {
+            let retain = retain_chain_ids.contains(&identity.chain);
+            report.nonce_entries_deleted += u64::from(!retain);
+            retain
+        });
+        report.verifier_entries_deleted =
+            self.delete_verifiers_where(|permitter| !retain_chain_ids.contains(&permitter.chain));
+        Ok(report)
+    }
+
+    async fn checkpoint(&self) -> Result<CheckpointReport, Error> {
+        Ok(CheckpointReport::default())
+    }
+
+    async fn statistics(&self) -> Result<StoreStatistics, Error> {
+        let mut stats = StoreStatistics {
+            total_shares: self
+                .state
+                .shares
+                .iter()
+                .map(|versions| {
+                    versions
+                        .values()
+                        .filter(|stored| stored.deleted_at.is_none())
+                        .count() as u64
+                })
+                .sum(),
+            total_verifiers: self.state.verifiers.len() as u64,
+            ..Default::default()
+        };
+        for state in self.state.chain.iter() {
+            stats.add_chain(state.block);
+        }
+        Ok(stats)
+    }
+
+    async fn put_intent(&self, intent: intent::Intent) -> Result<(), Error> {
+        self.state.intents.insert(intent.id, intent);
+        Ok(())
+    }
+
+    async fn list_intents(&self) -> Result<Vec<intent::Intent>, Error> {
+        Ok(self
+            .state
+            .intents
+            .iter()
+            .map(|intent| intent.value().clone())
+            .collect())
+    }
+
+    async fn clear_intent(&self, id: u64) -> Result<(), Error> {
+        self.state.intents.remove(&id);
         Ok(())
     }
 }
@@ -252,4 +621,311 @@ mod tests {
     use super::*;
 
     crate::make_store_tests!(async { MemoryStore::default() });
+    crate::make_store_tests!(async { MemoryStore::default() }, gc_untracked_chains);
+    crate::make_store_tests!(
+        async {
+            MemoryStore::default().with_share_retention(ShareRetention {
+                max_versions: 2.try_into().unwrap(),
+                grace: Duration::ZERO,
+            })
+        },
+        evict_old_share_versions
+    );
+
+    #[tokio::test]
+    async fn keep_share_versions_through_grace() {
+        let put_versions = |store: MemoryStore| async move {
+            let identity = IdentityLocator {
+                chain: 31337,
+                registry: Address::repeat_byte(1),
+                id: IdentityId::random(),
+            };
+            let id = |version| ShareId {
+                secret_name: "omni".into(),
+                identity,
+                version,
+            };
+            for version in 1..=4 {
+                let share = SecretShare {
+                    index: ShareIndex(1),
+                    share: vec![version as u8; 32].into(),
+                    provenance: None,
+                };
+                assert!(store.put_share(id(version), share).await.unwrap());
+            }
+            let mut kept = Vec::new();
+            for version in 1..=4 {
+                if store.get_share(id(version)).await.unwrap().is_some() {
+                    kept.push(version);
+                }
+            }
+            kept
+        };
+
+        // Versions superseded within the grace period are kept until it has passed.
+        let graceful = MemoryStore::default().with_share_retention(ShareRetention {
+            max_versions: 2.try_into().unwrap(),
+            grace: std::time::Duration::from_secs(3600),
+        });
+        assert_eq!(put_versions(graceful).await, vec![1, 2, 3, 4]);
+    }
+
+    #[tokio::test]
+    async fn share_pages_are_full() {
+        let store = MemoryStore::default();
+        for i in 0..300 {
+            let id = ShareId {
+                secret_name: "test".into(),
+                identity: IdentityLocator {
+                    chain: 31337,
+                    registry: Address::repeat_byte(1),
+                    id: IdentityId::random(),
+                },
+                version: 1,
+            };
+            let share = SecretShare {
+                index: ShareIndex(1),
+                share: vec![1u8; 32].into(),
+                provenance: None,
+            };
+            assert!(store.put_share(id.clone(), share).await.unwrap());
+            // Deleted shares are skipped without leaving pages short.
+            if i % 6 == 0 {
+                store.delete_share_version(id).await.unwrap();
+            }
+        }
+
+        let mut pages = 0;
+        let mut cursor = None;
+        loop {
+            let page = store
+                .list_shares_page(Default::default(), cursor, 10)
+                .await
+                .unwrap();
+            assert_eq!(page.items.len(), 10);
+            pages += 1;
+            cursor = page.next_cursor;
+            if cursor.is_none() {
+                break;
+            }
+        }
+        assert_eq!(pages, 25);
+    }
+
+    #[tokio::test]
+    async fn count_statistics() {
+        let store = MemoryStore::default();
+        assert_eq!(
+            store.statistics().await.unwrap(),
+            StoreStatistics {
+                chain_count: 0,
+                total_shares: 0,
+                total_verifiers: 0,
+                oldest_chain_block: None,
+                newest_chain_block: None,
+            }
+        );
+
+        let share_id = |id: u64, version| ShareId {
+            secret_name: "test".into(),
+            identity: IdentityLocator {
+                chain: 31337,
+                registry: Address::repeat_byte(1),
+                id: IdentityId(ethers::types::H256::from_low_u64_be(id)),
+            },
+            version,
+        };
+        for (id, version) in [(1, 1), (1, 2), (2, 1)] {
+            let share = SecretShare {
+                index: ShareIndex(1),
+                share: vec![1u8; 32].into(),
+                provenance: None,
+            };
+            assert!(store.put_share(share_id(id, version), share).await.unwrap());
+        }
+        store.delete_share_version(share_id(1, 2)).await.unwrap();
+        for identity in 1..=2 {
+            store
+                .update_verifier(
+                    PermitterLocator::new(31337, Address::repeat_byte(3)),
+                    IdentityId(ethers::types::H256::from_low_u64_be(identity)),
+                    b"policy".to_vec(),
+                    EventIndex::default(),
+                )
+                .await
+                .unwrap();
+        }
+        for (chain, block) in [(1, 500), (5, 20), (31337, 7000)] {
+            store
+                .update_chain_state(
+                    chain,
+                    ChainStateUpdate {
+                        block: Some(block),
+                        ..Default::default()
+                    },
+                )
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(
+            store.statistics().await.unwrap(),
+            StoreStatistics {
+                chain_count: 3,
+                total_shares: 2,
+                total_verifiers: 2,
+                oldest_chain_block: Some(20),
+                newest_chain_block: Some(7000),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn debug_redacts_shares() {
+        let store = MemoryStore::default();
+        let secret = b"\xde\xad\xbe\xef secret share";
+        for version in 1..=2 {
+            let id = ShareId {
+                secret_name: "test".into(),
+                identity: IdentityLocator {
+                    chain: 31337,
+                    registry: Address::repeat_byte(1),
+                    id: IdentityId(ethers::types::H256::repeat_byte(2)),
+                },
+                version,
+            };
+            let share = SecretShare {
+                index: ShareIndex(1),
+                share: secret.to_vec().into(),
+                provenance: None,
+            };
+            assert!(store.put_share(id, share).await.unwrap());
+        }
+        store
+            .update_verifier(
+                PermitterLocator::new(31337, Address::repeat_byte(3)),
+                IdentityId(ethers::types::H256::repeat_byte(2)),
+                b"policy".to_vec(),
+                EventIndex::default(),
+            )
+            .await
+            .unwrap();
+
+        let debug = format!("{store:?}");
+        assert!(debug.contains("<REDACTED>"));
+        assert!(debug.contains("share_count: 2"));
+        for needle in [
+            "secret share".to_string(),
+            "deadbeef".to_string(),
+            format!("{:?}", &secret[..4]),
+        ] {
+            assert!(!debug.contains(&needle), "{debug} contains {needle}");
+        }
+        assert_eq!(
+            store.to_string(),
+            "MemoryStore(shares=2, verifiers=1, chains=0)"
+        );
+    }
+
+    #[tokio::test]
+    async fn size_bytes_tracks_shares() {
+        let store = MemoryStore::default();
+        assert_eq!(store.size_bytes(), 0);
+
+        let share_ids: Vec<_> = (0..100)
+            .map(|_| ShareId {
+                secret_name: "test".into(),
+                identity: IdentityLocator {
+                    chain: 31337,
+                    registry: Address::repeat_byte(1),
+                    id: IdentityId::random(),
+                },
+                version: 1,
+            })
+            .collect();
+        for id in share_ids.iter() {
+            let share = SecretShare {
+                index: ShareIndex(1),
+                share: vec![0u8; 4096].into(),
+                provenance: None,
+            };
+            assert!(store.put_share(id.clone(), share).await.unwrap());
+        }
+
+        let expected = 100 * (4096 + 8);
+        let size = store.size_bytes();
+        assert!(
+            size.abs_diff(expected) <= expected / 10,
+            "{size} is not within 10% of {expected}"
+        );
+
+        // Deleted shares are kept for review.
+        store
+            .delete_share_version(share_ids[0].clone())
+            .await
+            .unwrap();
+        assert_eq!(store.size_bytes(), size);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 17)]
+    async fn concurrent_readers_and_writer() {
+        let store = MemoryStore::default();
+        let identity = IdentityLocator {
+            chain: 31337,
+            registry: Address::repeat_byte(1),
+            id: IdentityId::random(),
+        };
+        let share_id = move |version| ShareId {
+            secret_name: "test".into(),
+            identity,
+            version,
+        };
+        // Every byte of a share and its index are its version, so partial writes are detectable.
+        let share = |version: u64| SecretShare {
+            index: ShareIndex(version),
+            share: vec![version as u8; 1024].into(),
+            provenance: None,
+        };
+
+        assert!(store.put_share(share_id(1), share(1)).await.unwrap());
+        let written = Arc::new(std::sync::atomic::AtomicU64::new(1));
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from_secs(1);
+        let readers: Vec<_> = (0..16)
+            .map(|_| {
+                let (store, written) = (store.clone(), written.clone());
+                tokio::spawn(async move {
+                    let mut reads = 0;
+                    while reads == 0 || tokio::time::Instant::now() < deadline {
+                        let version = written.load(std::sync::atomic::Ordering::Acquire);
+                        let got = store.get_share(share_id(version)).await.unwrap();
+                        assert_eq!(got, Some(share(version)));
+                        reads += 1;
+                    }
+                })
+            })
+            .collect();
+
+        let mut slowest_put = std::time::Duration::ZERO;
+        let mut version = 1;
+        while tokio::time::Instant::now() < deadline {
+            version += 1;
+            let start = std::time::Instant::now();
+            assert!(store
+                .put_share(share_id(version), share(version))
+                .await
+                .unwrap());
+            slowest_put = slowest_put.max(start.elapsed());
+            written.store(version, std::sync::atomic::Ordering::Release);
+            tokio::task::yield_now().await;
+        }
+
+        for reader in readers {
+            reader.await.unwrap();
+        }
+        // A starved writer would take most of the second instead.
+        assert!(
+            slowest_put < std::time::Duration::from_millis(500),
+            "{slowest_put:?}"
+        );
+    }
 }
@@ -2,22 +2,35 @@
 pub mod aws;
 #[cfg(feature = "azure")]
 pub mod azure;
+pub mod codec;
+pub mod intent;
 #[cfg(feature = "local")]
 pub mod local;
 pub mod memory;
+pub mod recording;
+pub mod replicated;
 #[cfg(test)]
-mod tests;
+pub(crate) mod tests;
 
-use std::future::Future;
+use std::{
+    future::Future,
+    time::{Duration, SystemTime},
+};
 
 use axum::http::uri::Authority;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine as _};
 use ethers::types::Address;
+use futures_util::{Stream, TryStreamExt as _};
+use serde::{Deserialize, Serialize};
 
 use crate::types::*;
 
 type Nonce = Vec<u8>;
 
 pub trait Store: Clone + Send + Sync + 'static {
+    /// Returns whether the share was stored, which it is only if `id` is the next version.
+    /// Putting a version that is already stored with another index fails with
+    /// [`ShareIndexConflict`].
     fn put_share(
         &self,
         id: ShareId,
@@ -29,8 +42,58 @@ pub trait Store: Clone + Send + Sync + 'static {
         id: ShareId,
     ) -> impl Future<Output = Result<Option<SecretShare>, Error>> + Send;
 
+    /// Returns how long ago the share version was put, or `None` if it is missing, deleted, or
+    /// was put before the store recorded when shares were put.
+    fn get_share_age(
+        &self,
+        id: ShareId,
+    ) -> impl Future<Output = Result<Option<Duration>, Error>> + Send;
+
+    /// Marks the share version deleted. It is no longer returned by `get_share`, but it is kept
+    /// so that it can be reviewed and restored.
     fn delete_share_version(&self, id: ShareId) -> impl Future<Output = Result<(), Error>> + Send;
 
+    /// Undoes `delete_share_version`. Returns whether the share version was deleted.
+    fn restore_share(&self, id: ShareId) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Returns the id of every deleted share version, in no particular order.
+    fn list_deleted_shares(&self) -> impl Future<Output = Result<Vec<ShareId>, Error>> + Send;
+
+    /// Returns up to `page_size` live shares that match the filter, starting after `cursor`.
+    /// The listing is exhausted once a page has no `next_cursor`. A page may hold fewer than
+    /// `page_size` shares, or none, even when more remain.
+    fn list_shares_page(
+        &self,
+        filter: ShareFilter,
+        cursor: Option<ShareCursor>,
+        page_size: u32,
+    ) -> impl Future<Output = Result<SharePage, Error>> + Send;
+
+    /// Streams every live share of identities in `registry`, on any chain, a page at a time.
+    fn get_shares_by_registry(
+        &self,
+        registry: Address,
+    ) -> impl Stream<Item = Result<(ShareId, SecretShare), Error>> + Send + '_ {
+        let filter = ShareFilter {
+            registry: Some(registry),
+            ..Default::default()
+        };
+        futures_util::stream::try_unfold(Some(None), move |cursor| {
+            let filter = filter.clone();
+            async move {
+                let Some(cursor) = cursor else {
+                    return Ok::<_, Error>(None);
+                };
+                let page = self
+                    .list_shares_page(filter, cursor, SHARES_BY_REGISTRY_PAGE_SIZE)
+                    .await?;
+                let items = futures_util::stream::iter(page.items.into_iter().map(Ok::<_, Error>));
+                Ok(Some((items, page.next_cursor.map(Some))))
+            }
+        })
+        .try_flatten()
+    }
+
     fn put_key(
         &self,
         id: KeyId,
@@ -72,14 +135,40 @@ pub trait Store: Clone + Send + Sync + 'static {
         update: ChainStateUpdate,
     ) -> impl Future<Output = Result<(), Error>> + Send;
 
-    #[cfg(test)]
+    /// Returns whether every event of `chain` through `block` has been processed, as of the last
+    /// saved chain state. A saved block counts only once its hash has been saved along with it,
+    /// as a block saved without one, such as a resume point, is processed again on resume.
+    fn is_processed_through(
+        &self,
+        chain: u64,
+        block: u64,
+    ) -> impl Future<Output = Result<bool, Error>> + Send {
+        async move {
+            Ok(match self.get_chain_state(chain).await? {
+                Some(state) => {
+                    state.block > block || (state.block == block && state.block_hash.is_some())
+                }
+                None => false,
+            })
+        }
+    }
+
+    /// Overwrites the chain state so that sync resumes from `index`, even if it is behind the
+    /// current state.
+    fn set_resume_point(
+        &self,
+        chain: u64,
+        index: EventIndex,
+    ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Forgets the chain's sync progress, so that its next sync starts from the hub's creation.
     fn clear_chain_state(&self, chain: u64) -> impl Future<Output = Result<(), Error>> + Send;
 
     fn get_verifier(
         &self,
         permitter: PermitterLocator,
         identity: IdentityId,
-    ) -> impl Future<Output = Result<Option<Vec<u8>>, Error>> + Send;
+    ) -> impl Future<Output = Result<Option<VerifierRecord>, Error>> + Send;
 
     fn update_verifier(
         &self,
@@ -89,12 +178,170 @@ pub trait Store: Clone + Send + Sync + 'static {
         version: EventIndex,
     ) -> impl Future<Output = Result<(), Error>> + Send;
 
+    /// Stores the verifier only if the identity has none yet. Returns whether it was stored.
+    fn put_verifier_if_absent(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+        config: Vec<u8>,
+        version: EventIndex,
+    ) -> impl Future<Output = Result<bool, Error>> + Send;
+
+    /// Applies the patch to the identity's current verifier config and stores the result as of
+    /// `version`. Fails if the identity has no verifier, or if its config is not CBOR.
+    ///
+    /// The read and the write are separate, so patches must not race other changes to the same
+    /// verifier, as those from one chain's sync do not.
+    fn patch_verifier(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+        patch: PolicyPatch,
+        version: EventIndex,
+    ) -> impl Future<Output = Result<(), Error>> + Send {
+        async move {
+            let config = self
+                .get_verifier(permitter, identity)
+                .await?
+                .ok_or_else(|| anyhow::anyhow!("{identity:?} has no verifier to patch"))?
+                .config;
+            let patched = patch.apply(&config)?;
+            self.update_verifier(permitter, identity, patched, version)
+                .await
+        }
+    }
+
     #[cfg(test)]
     fn clear_verifier(
         &self,
         permitter: PermitterLocator,
         identity: IdentityId,
     ) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Deletes the verifiers of every identity on `chain`. Returns how many were deleted.
+    fn delete_verifiers_for_chain(
+        &self,
+        chain: ChainId,
+    ) -> impl Future<Output = Result<u64, Error>> + Send;
+
+    /// Deletes the verifiers of every identity under `permitter`. Returns how many were deleted.
+    fn delete_verifiers_for_permitter(
+        &self,
+        permitter: PermitterLocator,
+    ) -> impl Future<Output = Result<u64, Error>> + Send;
+
+    /// Deletes the nonce and verifier entries of any chain not in `retain_chain_ids`.
+    fn gc(
+        &self,
+        retain_chain_ids: &[ChainId],
+    ) -> impl Future<Output = Result<GcReport, Error>> + Send;
+
+    /// Moves any write-ahead log contents into the main database without blocking writers.
+    /// Stores that keep no such log report that no work was done.
+    fn checkpoint(&self) -> impl Future<Output = Result<CheckpointReport, Error>> + Send;
+
+    /// Counts what the store holds, for monitoring.
+    fn statistics(&self) -> impl Future<Output = Result<StoreStatistics, Error>> + Send;
+
+    /// Records a multi-step operation before it is applied. See [`intent`].
+    fn put_intent(&self, intent: intent::Intent) -> impl Future<Output = Result<(), Error>> + Send;
+
+    /// Returns every recorded intent that has not been cleared, in no particular order.
+    fn list_intents(&self) -> impl Future<Output = Result<Vec<intent::Intent>, Error>> + Send;
+
+    fn clear_intent(&self, id: u64) -> impl Future<Output = Result<(), Error>> + Send;
+}
+
+const SHARES_BY_REGISTRY_PAGE_SIZE: u32 = 100;
+
+/// Narrows a listing of shares. Unset fields match every share.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ShareFilter {
+    pub secret_name: Option<String>,
+    pub chain: Option<ChainId>,
+    pub registry: Option<Address>,
+}
+
+impl ShareFilter {
+    pub fn matches(&self, id: &ShareId) -> bool {
+        self.secret_name
+            .as_ref()
+            .map_or(true, |name| *name == id.secret_name)
+            && self.chain.map_or(true, |chain| chain == id.identity.chain)
+            && self
+                .registry
+                .map_or(true, |registry| registry == id.identity.registry)
+    }
+}
+
+/// Where a listing of shares left off. Each backend orders shares its own way, so a cursor is
+/// only meaningful to the store that returned it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShareCursor(ShareId);
+
+impl ShareCursor {
+    /// Encodes the cursor as an opaque token that can be handed to clients.
+    pub fn to_token(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}:{}", self.0.to_key(), self.0.version))
+    }
+
+    pub fn from_token(token: &str) -> anyhow::Result<Self> {
+        let token = String::from_utf8(URL_SAFE_NO_PAD.decode(token)?)?;
+        let Some((key, version)) = token.rsplit_once(':') else {
+            anyhow::bail!("malformed share cursor");
+        };
+        Ok(Self(ShareId::from_key(key, version.parse()?)?))
+    }
+}
+
+pub struct SharePage {
+    pub items: Vec<(ShareId, SecretShare)>,
+    pub next_cursor: Option<ShareCursor>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VerifierRecord {
+    pub config: Vec<u8>,
+    /// The event that set the config.
+    pub event_index: EventIndex,
+    /// When the store last wrote the config. Verifiers stored before this was recorded have none.
+    pub updated_at: Option<SystemTime>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GcReport {
+    pub nonce_entries_deleted: u64,
+    pub verifier_entries_deleted: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StoreStatistics {
+    /// The number of chains with saved sync state.
+    pub chain_count: u64,
+    /// The number of share versions that have not been deleted.
+    pub total_shares: u64,
+    pub total_verifiers: u64,
+    /// The lowest block that any chain's sync will resume from.
+    pub oldest_chain_block: Option<u64>,
+    /// The highest block that any chain's sync will resume from.
+    pub newest_chain_block: Option<u64>,
+}
+
+impl StoreStatistics {
+    /// Counts a chain whose sync resumes from `block`.
+    fn add_chain(&mut self, block: u64) {
+        self.chain_count += 1;
+        self.oldest_chain_block = Some(self.oldest_chain_block.map_or(block, |b| b.min(block)));
+        self.newest_chain_block = Some(self.newest_chain_block.map_or(block, |b| b.max(block)));
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CheckpointReport {
+    /// The number of pages in the write-ahead log when the checkpoint ran.
+    pub pages_written: u64,
+    /// The number of those pages that were moved into the database.
+    pub pages_moved: u64,
 }
 
 #[derive(Clone)]
@@ -102,6 +349,16 @@ pub struct DynStore {
     inner: DynStoreKind,
 }
 
+impl DynStore {
+    pub fn as_memory(&self) -> Option<&memory::MemoryStore> {
+        match &self.inner {
+            DynStoreKind::Memory(s) => Some(s),
+            #[allow(unreachable_patterns)]
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone)]
 pub enum DynStoreKind {
     Memory(memory::MemoryStore),
@@ -138,6 +395,18 @@ impl Store for DynStore {
         }
     }
 
+    async fn get_share_age(&self, id: ShareId) -> Result<Option<Duration>, Error> {
+        match &self.inner {
+            DynStoreKind::Memory(s) => s.get_share_age(id).await,
+            #[cfg(feature = "aws")]
+            DynStoreKind::Aws(s) => s.get_share_age(id).await,
+            #[cfg(feature = "azure")]
+            DynStoreKind::Azure(s) => s.get_share_age(id).await,
+            #[cfg(feature = "local")]
+            DynStoreKind::Local(s) => s.get_share_age(id).await,
+        }
+    }
+
     async fn delete_share_version(&self, id: ShareId) -> Result<(), Error> {
         match &self.inner {
             DynStoreKind::Memory(s) => s.delete_share_version(id).await,
@@ -150,6 +419,47 @@ impl Store for DynStore {
         }
     }
 
+    async fn restore_share(&self, id: ShareId) -> Result<bool, Error> {
+        match &self.inner {
+            DynStoreKind::Memory(s) => s.restore_share(id).await,
+            #[cfg(feature = "aws")]
+            DynStoreKind::Aws(s) => s.restore_share(id).await,
+            #[cfg(feature = "azure")]
+            DynStoreKind::Azure(s) => s.restore_share(id).await,
+            #[cfg(feature = "local")]
+            DynStoreKind::Local(s) => s.restore_share(id).await,
+        }
+    }
+
+    async fn list_deleted_shares(&self) -> Result<Vec<ShareId>, Error> {
+        match &self.inner {
+            DynStoreKind::Memory(s) => s.list_deleted_shares().await,
+            #[cfg(feature = "aws")]
+            DynStoreKind::Aws(s) => s.list_deleted_shares().await,
+            #[cfg(feature = "azure")]
+            DynStoreKind::Azure(s) => s.list_deleted_shares().await,
+            #[cfg(feature = "local")]
+            DynStoreKind::Local(s) => s.list_deleted_shares().await,
+        }
+    }
+
+    async fn list_shares_page(
+        &self,
+        filter: ShareFilter,
+        cursor: Option<ShareCursor>,
+        page_size: u32,
+    ) -> Result<SharePage, Error> {
+        match &self.inner {
+            DynStoreKind::Memory(s) => s.list_shares_page(filter, cursor, page_size).await,
+            #[cfg(feature = "aws")]
+            DynStoreKind::Aws(s) => s.list_shares_page(filter, cursor, page_size).await,
+            #[cfg(feature = "azure")]
+            DynStoreKind::Azure(s) => s.list_shares_page(filter, cursor, page_size).await,
+            #[cfg(feature = "local")]
+            DynStoreKind::Local(s) => s.list_shares_page(filter, cursor, page_size).await,
+        }
+    }
+
     async fn put_key(&self, id: KeyId, key: WrappedKey) -> Result<bool, Error> {
         match &self.inner {
             DynStoreKind::Memory(s) => s.put_key(id, key).await,
@@ -260,7 +570,18 @@ impl Store for DynStore {
         }
     }
 
-    #[cfg(test)]
+    async fn set_resume_point(&self, chain: u64, index: EventIndex) -> Result<(), Error> {
+        match &self.inner {
+            DynStoreKind::Memory(s) => s.set_resume_point(chain, index).await,
+            #[cfg(feature = "aws")]
+            DynStoreKind::Aws(s) => s.set_resume_point(chain, index).await,
+            #[cfg(feature = "azure")]
+            DynStoreKind::Azure(s) => s.set_resume_point(chain, index).await,
+            #[cfg(feature = "local")]
+            DynStoreKind::Local(s) => s.set_resume_point(chain, index).await,
+        }
+    }
+
     async fn clear_chain_state(&self, chain: u64) -> Result<(), Error> {
         match &self.inner {
             DynStoreKind::Memory(s) => s.clear_chain_state(chain).await,
@@ -277,7 +598,7 @@ impl Store for DynStore {
         &self,
         permitter: PermitterLocator,
         identity: IdentityId,
-    ) -> Result<Option<Vec<u8>>, Error> {
+    ) -> Result<Option<VerifierRecord>, Error> {
         match &self.inner {
             DynStoreKind::Memory(s) => s.get_verifier(permitter, identity).await,
             #[cfg(feature = "aws")]
@@ -319,6 +640,36 @@ impl Store for DynStore {
         }
     }
 
+    async fn put_verifier_if_absent(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+        config: Vec<u8>,
+        version: EventIndex,
+    ) -> Result<bool, Error> {
+        match &self.inner {
+            DynStoreKind::Memory(s) => {
+                s.put_verifier_if_absent(permitter, identity, config, version)
+                    .await
+            }
+            #[cfg(feature = "aws")]
+            DynStoreKind::Aws(s) => {
+                s.put_verifier_if_absent(permitter, identity, config, version)
+                    .await
+            }
+            #[cfg(feature = "azure")]
+            DynStoreKind::Azure(s) => {
+                s.put_verifier_if_absent(permitter, identity, config, version)
+                    .await
+            }
+            #[cfg(feature = "local")]
+            DynStoreKind::Local(s) => {
+                s.put_verifier_if_absent(permitter, identity, config, version)
+                    .await
+            }
+        }
+    }
+
     #[cfg(test)]
     async fn clear_verifier(
         &self,
@@ -333,6 +684,105 @@ impl Store for DynStore {
             DynStoreKind::Local(s) => s.clear_verifier(permitter, identity).await,
         }
     }
+
+    async fn delete_verifiers_for_chain(&self, chain: ChainId) -> Result<u64, Error> {
+        match &self.inner {
+            DynStoreKind::Memory(s) => s.delete_verifiers_for_chain(chain).await,
+            #[cfg(feature = "aws")]
+            DynStoreKind::Aws(s) => s.delete_verifiers_for_chain(chain).await,
+            #[cfg(feature = "azure")]
+            DynStoreKind::Azure(s) => s.delete_verifiers_for_chain(chain).await,
+            #[cfg(feature = "local")]
+            DynStoreKind::Local(s) => s.delete_verifiers_for_chain(chain).await,
+        }
+    }
+
+    async fn delete_verifiers_for_permitter(
+        &self,
+        permitter: PermitterLocator,
+    ) -> Result<u64, Error> {
+        match &self.inner {
+            DynStoreKind::Memory(s) => s.delete_verifiers_for_permitter(permitter).await,
+            #[cfg(feature = "aws")]
+            DynStoreKind::Aws(s) => s.delete_verifiers_for_permitter(permitter).await,
+            #[cfg(feature = "azure")]
+            DynStoreKind::Azure(s) => s.delete_verifiers_for_permitter(permitter).await,
+            #[cfg(feature = "local")]
+            DynStoreKind::Local(s) => s.delete_verifiers_for_permitter(permitter).await,
+        }
+    }
+
+    async fn gc(&self, retain_chain_ids: &[ChainId]) -> Result<GcReport, Error> {
+        match &self.inner {
+            DynStoreKind::Memory(s) => s.gc(retain_chain_ids).await,
+            #[cfg(feature = "aws")]
+            DynStoreKind::Aws(s) => s.gc(retain_chain_ids).await,
+            #[cfg(feature = "azure")]
+            DynStoreKind::Azure(s) => s.gc(retain_chain_ids).await,
+            #[cfg(feature = "local")]
+            DynStoreKind::Local(s) => s.gc(retain_chain_ids).await,
+        }
+    }
+
+    async fn checkpoint(&self) -> Result<CheckpointReport, Error> {
+        match &self.inner {
+            DynStoreKind::Memory(s) => s.checkpoint().await,
+            #[cfg(feature = "aws")]
+            DynStoreKind::Aws(s) => s.checkpoint().await,
+            #[cfg(feature = "azure")]
+            DynStoreKind::Azure(s) => s.checkpoint().await,
+            #[cfg(feature = "local")]
+            DynStoreKind::Local(s) => s.checkpoint().await,
+        }
+    }
+
+    async fn statistics(&self) -> Result<StoreStatistics, Error> {
+        match &self.inner {
+            DynStoreKind::Memory(s) => s.statistics().await,
+            #[cfg(feature = "aws")]
+            DynStoreKind::Aws(s) => s.statistics().await,
+            #[cfg(feature = "azure")]
+            DynStoreKind::Azure(s) => s.statistics().await,
+            #[cfg(feature = "local")]
+            DynStoreKind::Local(s) => s.statistics().await,
+        }
+    }
+
+    async fn put_intent(&self, intent: intent::Intent) -> Result<(), Error> {
+        match &self.inner {
+            DynStoreKind::Memory(s) => s.put_intent(intent).await,
+            #[cfg(feature = "aws")]
+            DynStoreKind::Aws(s) => s.put_intent(intent).await,
+            #[cfg(feature = "azure")]
+            DynStoreKind::Azure(s) => s.put_intent(intent).await,
+            #[cfg(feature = "local")]
+            DynStoreKind::Local(s) => s.put_intent(intent).await,
+        }
+    }
+
+    async fn list_intents(&self) -> Result<Vec<intent::Intent>, Error> {
+        match &self.inner {
+            DynStoreKind::Memory(s) => s.list_intents().await,
+            #[cfg(feature = "aws")]
+            DynStoreKind::Aws(s) => s.list_intents().await,
+            #[cfg(feature = "azure")]
+            DynStoreKind::Azure(s) => s.list_intents().await,
+            #[cfg(feature = "local")]
+            DynStoreKind::Local(s) => s.list_intents().await,
+        }
+    }
+
+    async fn clear_intent(&self, id: u64) -> Result<(), Error> {
+        match &self.inner {
+            DynStoreKind::Memory(s) => s.clear_intent(id).await,
+            #[cfg(feature = "aws")]
+            DynStoreKind::Aws(s) => s.clear_intent(id).await,
+            #[cfg(feature = "azure")]
+            DynStoreKind::Azure(s) => s.clear_intent(id).await,
+            #[cfg(feature = "local")]
+            DynStoreKind::Local(s) => s.clear_intent(id).await,
+        }
+    }
 }
 
 // #[derive(Debug, thiserror::Error)]
@@ -340,6 +790,82 @@ impl Store for DynStore {
 // pub struct Error(#[from] anyhow::Error);
 pub type Error = anyhow::Error;
 
+/// A share that exists in the store but whose stored encoding could not be decoded.
+///
+/// Backends return it inside [`Error`]; callers can `downcast_ref` to tell a corrupt share
+/// apart from an absent one, which is `Ok(None)`.
+#[derive(Debug, thiserror::Error)]
+#[error("stored share {id:?} could not be decoded: {source}")]
+pub struct DeserializeError {
+    pub id: ShareId,
+    pub source: Box<dyn std::error::Error + Send + Sync>,
+}
+
+impl DeserializeError {
+    pub fn new(id: ShareId, source: impl Into<Box<dyn std::error::Error + Send + Sync>>) -> Self {
+        Self {
+            id,
+            source: source.into(),
+        }
+    }
+}
+
+/// A share was put for a version that already holds a share of another index. Shares of a
+/// version are dealt once, so its index never changes.
+#[derive(Debug, thiserror::Error)]
+#[error("share {id:?} is stored with index {existing:?}, not {incoming:?}")]
+pub struct ShareIndexConflict {
+    pub id: ShareId,
+    pub existing: ShareIndex,
+    pub incoming: ShareIndex,
+}
+
+impl ShareIndexConflict {
+    /// Returns the conflict if `existing` is a share of another index than `incoming`.
+    pub fn check(
+        id: &ShareId,
+        existing: Option<&SecretShare>,
+        incoming: ShareIndex,
+    ) -> Result<(), Self> {
+        match existing {
+            Some(existing) if existing.index != incoming => Err(Self {
+                id: id.clone(),
+                existing: existing.index,
+                incoming,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Bounds how many versions of each secret's share are kept. Once a version is put, the oldest
+/// versions beyond `max_versions` are evicted along with it, except any whose successor was
+/// stored less than `grace` ago, as a reconstruction of them may still be under way.
+///
+/// Eviction happens only when a version is put, so a secret that stops getting new versions
+/// keeps its superseded ones, including any spared by the grace period, until it gets another.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ShareRetention {
+    pub max_versions: std::num::NonZeroU64,
+    pub grace: Duration,
+}
+
+impl ShareRetention {
+    /// How many of the oldest versions to evict, given how long ago each version that is not
+    /// deleted was stored, oldest first. Deleted versions do not count toward the limit.
+    pub(crate) fn evictions(&self, stored_for: &[Duration]) -> usize {
+        let excess = stored_for
+            .len()
+            .saturating_sub(self.max_versions.get() as usize);
+        stored_for
+            .iter()
+            .skip(1)
+            .take(excess)
+            .take_while(|&&successor_stored_for| successor_stored_for >= self.grace)
+            .count()
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
 #[value(rename_all = "lowercase")]
 pub enum StoreKind {
@@ -375,30 +901,57 @@ pub async fn create(
     backend: StoreKind,
     env: Environment,
     host: &Authority,
-) -> Result<impl Store, Error> {
+    share_retention: Option<ShareRetention>,
+) -> Result<DynStore, Error> {
     Ok(DynStore {
         inner: match backend {
-            StoreKind::Memory => DynStoreKind::Memory(Default::default()),
+            StoreKind::Memory => DynStoreKind::Memory(match share_retention {
+                Some(retention) => memory::MemoryStore::default().with_share_retention(retention),
+                None => Default::default(),
+            }),
             #[cfg(feature = "aws")]
-            StoreKind::Aws => DynStoreKind::Aws(aws::Client::connect(env).await),
+            StoreKind::Aws => DynStoreKind::Aws(match share_retention {
+                Some(retention) => aws::Client::connect(env)
+                    .await
+                    .with_share_retention(retention),
+                None => aws::Client::connect(env).await,
+            }),
             #[cfg(feature = "azure")]
             StoreKind::Aws => {
                 todo!("account");
-                DynStoreKind::Azure(azure::Client::connect(host, env).await?)
+                let client = azure::Client::connect(host, env).await?;
+                DynStoreKind::Azure(match share_retention {
+                    Some(retention) => client.with_share_retention(retention),
+                    None => client,
+                })
             }
             #[cfg(feature = "local")]
-            StoreKind::Local => todo!(),
+            StoreKind::Local => {
+                if share_retention.is_some() {
+                    anyhow::bail!("share version limits are not supported by the local store");
+                }
+                todo!()
+            }
         },
     })
 }
 
-fn now() -> u64 {
+pub(crate) fn now() -> u64 {
     std::time::SystemTime::now()
         .duration_since(std::time::UNIX_EPOCH)
         .unwrap()
         .as_secs()
 }
 
+/// Milliseconds since the Unix epoch, for timestamps that must resolve more finely than [`now`].
+#[cfg(any(feature = "aws", feature = "azure"))]
+pub(crate) fn now_millis() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
 pub trait ToKey {
     fn to_key(&self) -> String;
 }
@@ -459,6 +1012,24 @@ impl ToKey for ShareId {
     }
 }
 
+impl ShareId {
+    /// Parses the key written by [`ToKey`], which does not include the version.
+    pub fn from_key(key: &str, version: u64) -> anyhow::Result<Self> {
+        let Some(key) = key.strip_prefix("share-") else {
+            anyhow::bail!("not a share key");
+        };
+        // The identity locator is the last three parts, and the secret name may contain dashes.
+        let Some((split, _)) = key.rmatch_indices('-').nth(2) else {
+            anyhow::bail!("missing identity locator");
+        };
+        Ok(Self {
+            secret_name: key[..split].into(),
+            identity: IdentityLocator::from_key(&key[split + 1..])?,
+            version,
+        })
+    }
+}
+
 impl ToKey for KeyId {
     fn to_key(&self) -> String {
         let Self {
@@ -503,6 +1074,12 @@ impl ToKey for ChainId {
     }
 }
 
+impl FromKey for ChainId {
+    fn from_key(key: &str) -> anyhow::Result<Self> {
+        Ok(key.parse()?)
+    }
+}
+
 impl ToKey for () {
     fn to_key(&self) -> String {
         Default::default()
@@ -515,6 +1092,7 @@ impl ToKey for &[u8] {
     }
 }
 
+#[cfg(feature = "azure")]
 mod serde_key {
     use serde::{
         de::{self, Deserialize, Deserializer},
@@ -0,0 +1,446 @@
+//! A store wrapper that journals every lasting mutation so that it can be replayed elsewhere, such
+//! as to rebuild a lost store or to reproduce a store's state in a test.
+//!
+//! Mutations are journaled as [`Step`]s, one JSON object per line, once the wrapped store has
+//! applied them. Shares and keys are journaled in full, so the journal must be protected like the
+//! store itself. Permits, nonces, and intents are not journaled, as they are short-lived, nor is
+//! [`Store::gc`], whose effect depends on what the store holds when it runs.
+
+use std::{
+    io::{BufRead, Write},
+    sync::Arc,
+};
+
+use ethers::types::Address;
+use tokio::sync::{MappedMutexGuard, Mutex, MutexGuard};
+
+use super::{intent::Step, *};
+
+pub struct RecordingStore<S, W> {
+    inner: S,
+    journal: Arc<Mutex<Journal<W>>>,
+}
+
+struct Journal<W> {
+    writer: W,
+    /// Steps that were applied but could not be written, in the order that they were applied.
+    unwritten: Vec<Step>,
+}
+
+impl<W: Write> Journal<W> {
+    fn write_unwritten(&mut self) -> Result<(), Error> {
+        let mut written = 0;
+        let res = self.unwritten.iter().try_for_each(|step| {
+            let mut line = serde_json::to_vec(step)?;
+            line.push(b'\n');
+            self.writer.write_all(&line)?;
+            written += 1;
+            Ok(())
+        });
+        self.unwritten.drain(..written);
+        res
+    }
+}
+
+// Derived, this would require `W: Clone`, though clones share the one journal.
+impl<S: Clone, W> Clone for RecordingStore<S, W> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+            journal: self.journal.clone(),
+        }
+    }
+}
+
+impl<S: Store, W: Write + Send + 'static> RecordingStore<S, W> {
+    pub fn new(inner: S, journal: W) -> Self {
+        Self {
+            inner,
+            journal: Arc::new(Mutex::new(Journal {
+                writer: journal,
+                unwritten: Vec::new(),
+            })),
+        }
+    }
+
+    pub async fn journal(&self) -> MappedMutexGuard<'_, W> {
+        MutexGuard::map(self.journal.lock().await, |journal| &mut journal.writer)
+    }
+
+    /// Applies a mutation to the wrapped store and journals the step, if any, that `step` makes of
+    /// its outcome. The journal stays locked throughout, so steps are journaled in the order that
+    /// they were applied.
+    ///
+    /// A mutation whose step cannot be journaled is reported as failed even though it took effect,
+    /// so that the gap is not silent. The step is kept and written before any later mutation is
+    /// applied, so that a retry which finds the mutation already applied leaves no gap either.
+    async fn apply<T>(
+        &self,
+        mutation: impl Future<Output = Result<T, Error>>,
+        step: impl FnOnce(&T) -> Option<Step>,
+    ) -> Result<T, Error> {
+        let mut journal = self.journal.lock().await;
+        journal.write_unwritten()?;
+        let outcome = mutation.await?;
+        if let Some(step) = step(&outcome) {
+            journal.unwritten.push(step);
+            journal.write_unwritten()?;
+        }
+        Ok(outcome)
+    }
+}
+
+/// Applies each step journaled by a [`RecordingStore`] to `store`, in order, and returns how many
+/// there were.
+pub async fn replay(store: &impl Store, journal: impl BufRead) -> Result<u64, Error> {
+    let mut count = 0;
+    for line in journal.lines() {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let step: Step = serde_json::from_str(&line)?;
+        step.apply(store).await?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+impl<S: Store, W: Write + Send + 'static> Store for RecordingStore<S, W> {
+    async fn put_share(&self, id: ShareId, share: SecretShare) -> Result<bool, Error> {
+        self.apply(self.inner.put_share(id.clone(), share.clone()), |&put| {
+            put.then_some(Step::PutShare { id, share })
+        })
+        .await
+    }
+
+    async fn get_share(&self, id: ShareId) -> Result<Option<SecretShare>, Error> {
+        self.inner.get_share(id).await
+    }
+
+    async fn get_share_age(&self, id: ShareId) -> Result<Option<Duration>, Error> {
+        self.inner.get_share_age(id).await
+    }
+
+    async fn delete_share_version(&self, id: ShareId) -> Result<(), Error> {
+        self.apply(self.inner.delete_share_version(id.clone()), |_| {
+            Some(Step::DeleteShareVersion(id))
+        })
+        .await
+    }
+
+    async fn restore_share(&self, id: ShareId) -> Result<bool, Error> {
+        self.apply(self.inner.restore_share(id.clone()), |&restored| {
+            restored.then_some(Step::RestoreShare(id))
+        })
+        .await
+    }
+
+    async fn list_deleted_shares(&self) -> Result<Vec<ShareId>, Error> {
+        self.inner.list_deleted_shares().await
+    }
+
+    async fn list_shares_page(
+        &self,
+        filter: ShareFilter,
+        cursor: Option<ShareCursor>,
+        page_size: u32,
+    ) -> Result<SharePage, Error> {
+        self.inner.list_shares_page(filter, cursor, page_size).await
+    }
+
+    async fn put_key(&self, id: KeyId, key: WrappedKey) -> Result<bool, Error> {
+        self.apply(self.inner.put_key(id.clone(), key.clone()), |&put| {
+            put.then_some(Step::PutKey { id, key })
+        })
+        .await
+    }
+
+    async fn get_key(&self, id: KeyId) -> Result<Option<WrappedKey>, Error> {
+        self.inner.get_key(id).await
+    }
+
+    async fn delete_key_version(&self, id: KeyId) -> Result<(), Error> {
+        self.apply(self.inner.delete_key_version(id.clone()), |_| {
+            Some(Step::DeleteKeyVersion(id))
+        })
+        .await
+    }
+
+    async fn create_permit(
+        &self,
+        identity: IdentityLocator,
+        recipient: Address,
+        expiry: u64,
+        nonce: Vec<u8>,
+    ) -> Result<Option<Permit>, Error> {
+        self.inner
+            .create_permit(identity, recipient, expiry, nonce)
+            .await
+    }
+
+    async fn read_permit(
+        &self,
+        identity: IdentityLocator,
+        recipient: Address,
+    ) -> Result<Option<Permit>, Error> {
+        self.inner.read_permit(identity, recipient).await
+    }
+
+    async fn delete_permit(
+        &self,
+        identity: IdentityLocator,
+        recipient: Address,
+    ) -> Result<(), Error> {
+        self.inner.delete_permit(identity, recipient).await
+    }
+
+    async fn get_chain_state(&self, chain: u64) -> Result<Option<ChainState>, Error> {
+        self.inner.get_chain_state(chain).await
+    }
+
+    async fn update_chain_state(&self, chain: u64, update: ChainStateUpdate) -> Result<(), Error> {
+        self.apply(self.inner.update_chain_state(chain, update.clone()), |_| {
+            Some(Step::UpdateChainState { chain, update })
+        })
+        .await
+    }
+
+    async fn set_resume_point(&self, chain: u64, index: EventIndex) -> Result<(), Error> {
+        self.apply(self.inner.set_resume_point(chain, index), |_| {
+            Some(Step::SetResumePoint { chain, index })
+        })
+        .await
+    }
+
+    async fn clear_chain_state(&self, chain: u64) -> Result<(), Error> {
+        self.apply(self.inner.clear_chain_state(chain), |_| {
+            Some(Step::ClearChainState(chain))
+        })
+        .await
+    }
+
+    async fn get_verifier(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+    ) -> Result<Option<VerifierRecord>, Error> {
+        self.inner.get_verifier(permitter, identity).await
+    }
+
+    async fn update_verifier(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+        config: Vec<u8>,
+        version: EventIndex,
+    ) -> Result<(), Error> {
+        self.apply(
+            self.inner
+                .update_verifier(permitter, identity, config.clone(), version),
+            |_| {
+                Some(Step::UpdateVerifier {
+                    permitter,
+                    identity,
+                    config,
+                    version,
+                })
+            },
+        )
+        .await
+    }
+
+    async fn put_verifier_if_absent(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+        config: Vec<u8>,
+        version: EventIndex,
+    ) -> Result<bool, Error> {
+        self.apply(
+            self.inner
+                .put_verifier_if_absent(permitter, identity, config.clone(), version),
+            // Replayed in order, the identity has no verifier at this point either.
+            |&put| {
+                put.then_some(Step::UpdateVerifier {
+                    permitter,
+                    identity,
+                    config,
+                    version,
+                })
+            },
+        )
+        .await
+    }
+
+    #[cfg(test)]
+    async fn clear_verifier(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+    ) -> Result<(), Error> {
+        self.inner.clear_verifier(permitter, identity).await
+    }
+
+    async fn delete_verifiers_for_chain(&self, chain: ChainId) -> Result<u64, Error> {
+        self.apply(self.inner.delete_verifiers_for_chain(chain), |_| {
+            Some(Step::DeleteVerifiersForChain(chain))
+        })
+        .await
+    }
+
+    async fn delete_verifiers_for_permitter(
+        &self,
+        permitter: PermitterLocator,
+    ) -> Result<u64, Error> {
+        self.apply(self.inner.delete_verifiers_for_permitter(permitter), |_| {
+            Some(Step::DeleteVerifiersForPermitter(permitter))
+        })
+        .await
+    }
+
+    async fn gc(&self, retain_chain_ids: &[ChainId]) -> Result<GcReport, Error> {
+        self.inner.gc(retain_chain_ids).await
+    }
+
+    async fn checkpoint(&self) -> Result<CheckpointReport, Error> {
+        self.inner.checkpoint().await
+    }
+
+    async fn statistics(&self) -> Result<StoreStatistics, Error> {
+        self.inner.statistics().await
+    }
+
+    async fn put_intent(&self, intent: intent::Intent) -> Result<(), Error> {
+        self.inner.put_intent(intent).await
+    }
+
+    async fn list_intents(&self) -> Result<Vec<intent::Intent>, Error> {
+        self.inner.list_intents().await
+    }
+
+    async fn clear_intent(&self, id: u64) -> Result<(), Error> {
+        self.inner.clear_intent(id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::H256;
+
+    use super::*;
+    use crate::store::{memory::MemoryStore, tests::make_share};
+
+    #[tokio::test]
+    async fn journal_steps_that_failed_to_write() {
+        /// Fails the first write, as a full disk might.
+        #[derive(Default)]
+        struct FlakyJournal {
+            failed: bool,
+            lines: Vec<u8>,
+        }
+
+        impl Write for FlakyJournal {
+            fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+                if !std::mem::replace(&mut self.failed, true) {
+                    return Err(std::io::Error::other("disk full"));
+                }
+                self.lines.write(buf)
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let store = RecordingStore::new(MemoryStore::default(), FlakyJournal::default());
+        let (id, share) = make_share(IdentityId(H256::random()), 1);
+        assert!(store.put_share(id.clone(), share.clone()).await.is_err());
+        // The share was stored, so a retry is refused, but the step is journaled all the same.
+        assert!(!store.put_share(id.clone(), share.clone()).await.unwrap());
+
+        let journal = store.journal().await.lines.clone();
+        let replayed = MemoryStore::default();
+        assert_eq!(replay(&replayed, journal.as_slice()).await.unwrap(), 1);
+        assert_eq!(replayed.get_share(id).await.unwrap(), Some(share));
+    }
+
+    #[tokio::test]
+    async fn replay_reproduces_recorded_state() {
+        let store = RecordingStore::new(MemoryStore::default(), Vec::new());
+        let identity = IdentityLocator {
+            chain: 31337,
+            registry: Address::repeat_byte(1),
+            id: IdentityId(H256::random()),
+        };
+        let share_id = |version| make_share(identity.id, version).0;
+        let key_id = KeyId {
+            name: "omni".into(),
+            identity,
+            version: 1,
+        };
+        let permitter = PermitterLocator::new(31337, Address::repeat_byte(2));
+        let policy_index = EventIndex {
+            block: 3,
+            log_index: 1,
+        };
+
+        // Version 4 is refused, so not journaled.
+        for version in [1, 2, 4] {
+            let (id, share) = make_share(identity.id, version);
+            store.put_share(id, share).await.unwrap();
+        }
+        store.delete_share_version(share_id(1)).await.unwrap();
+        store.delete_share_version(share_id(2)).await.unwrap();
+        store.restore_share(share_id(2)).await.unwrap();
+        store
+            .put_key(key_id.clone(), vec![7; 32].into())
+            .await
+            .unwrap();
+        store
+            .update_verifier(permitter, identity.id, b"policy".to_vec(), policy_index)
+            .await
+            .unwrap();
+        store
+            .update_chain_state(
+                31337,
+                ChainStateUpdate {
+                    block: Some(9),
+                    block_hash: Some(H256::repeat_byte(9)),
+                },
+            )
+            .await
+            .unwrap();
+
+        let journal = store.journal().await.clone();
+        let replayed = MemoryStore::default();
+        assert_eq!(replay(&replayed, journal.as_slice()).await.unwrap(), 8);
+
+        for version in 1..=4 {
+            assert_eq!(
+                replayed.get_share(share_id(version)).await.unwrap(),
+                store.get_share(share_id(version)).await.unwrap(),
+            );
+        }
+        assert_eq!(
+            replayed.list_deleted_shares().await.unwrap(),
+            vec![share_id(1)]
+        );
+        assert_eq!(
+            replayed.get_key(key_id.clone()).await.unwrap(),
+            store.get_key(key_id).await.unwrap(),
+        );
+        assert_eq!(
+            replayed
+                .get_verifier(permitter, identity.id)
+                .await
+                .unwrap()
+                .map(|record| record.config),
+            Some(b"policy".to_vec())
+        );
+        assert_eq!(
+            replayed.get_chain_state(31337).await.unwrap(),
+            store.get_chain_state(31337).await.unwrap(),
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! A store wrapper that serves reads from a replica so that share serving does not load the store
+//! that takes writes.
+//!
+//! Writes go to the primary, and the replica is expected to follow it, as a database read replica
+//! does, so reads through the wrapper may briefly miss recent writes. Callers that must see their
+//! own writes, such as one that reads a share right after storing it, read through
+//! [`ReplicatedStore::primary`] instead.
+//!
+//! Only shares and statistics are read from the replica. Keys, permits, intents, and chain state
+//! are read back soon after they are written, and often to decide what to write next, so they are
+//! always read from the primary. A lagging chain state would otherwise let sync or intent recovery
+//! rewind the resume point. Verifiers are read from the primary too, since a lagging replica
+//! would let a just-revoked policy keep authorizing permits.
+//!
+//! The node itself never builds one, as [`create`] makes a single store. This is for embedders
+//! that construct their own stores, such as to run [`crate::sync`] against them.
+
+use ethers::types::Address;
+
+use super::*;
+
+#[derive(Clone)]
+pub struct ReplicatedStore<P, R> {
+    primary: P,
+    replica: R,
+}
+
+impl<P: Store, R: Store> ReplicatedStore<P, R> {
+    pub fn new(primary: P, replica: R) -> Self {
+        Self { primary, replica }
+    }
+
+    /// The store that takes writes, for reads that must observe them.
+    pub fn primary(&self) -> &P {
+        &self.primary
+    }
+
+    pub fn replica(&self) -> &R {
+        &self.replica
+    }
+}
+
+impl<P: Store, R: Store> Store for ReplicatedStore<P, R> {
+    async fn put_share(&self, id: ShareId, share: SecretShare) -> Result<bool, Error> {
+        self.primary.put_share(id, share).await
+    }
+
+    async fn get_share(&self, id: ShareId) -> Result<Option<SecretShare>, Error> {
+        self.replica.get_share(id).await
+    }
+
+    async fn get_share_age(&self, id: ShareId) -> Result<Option<Duration>, Error> {
+        self.replica.get_share_age(id).await
+    }
+
+    async fn delete_share_version(&self, id: ShareId) -> Result<(), Error> {
+        self.primary.delete_share_version(id).await
+    }
+
+    async fn restore_share(&self, id: ShareId) -> Result<bool, Error> {
+        self.primary.restore_share(id).await
+    }
+
+    async fn list_deleted_shares(&self) -> Result<Vec<ShareId>, Error> {
+        self.replica.list_deleted_shares().await
+    }
+
+    async fn list_shares_page(
+        &self,
+        filter: ShareFilter,
+        cursor: Option<ShareCursor>,
+        page_size: u32,
+    ) -> Result<SharePage, Error> {
+        self.replica
+            .list_shares_page(filter, cursor, page_size)
+            .await
+    }
+
+    async fn put_key(&self, id: KeyId, key: WrappedKey) -> Result<bool, Error> {
+        self.primary.put_key(id, key).await
+    }
+
+    async fn get_key(&self, id: KeyId) -> Result<Option<WrappedKey>, Error> {
+        self.primary.get_key(id).await
+    }
+
+    async fn delete_key_version(&self, id: KeyId) -> Result<(), Error> {
+        self.primary.delete_key_version(id).await
+    }
+
+    async fn create_permit(
+        &self,
+        identity: IdentityLocator,
+        recipient: Address,
+        expiry: u64,
+        nonce: Vec<u8>,
+    ) -> Result<Option<Permit>, Error> {
+        self.primary
+            .create_permit(identity, recipient, expiry, nonce)
+            .await
+    }
+
+    async fn read_permit(
+        &self,
+        identity: IdentityLocator,
+        recipient: Address,
+    ) -> Result<Option<Permit>, Error> {
+        self.primary.read_permit(identity, recipient).await
+    }
+
+    async fn delete_permit(
+        &self,
+        identity: IdentityLocator,
+        recipient: Address,
+    ) -> Result<(), Error> {
+        self.primary.delete_permit(identity, recipient).await
+    }
+
+    async fn get_chain_state(&self, chain: u64) -> Result<Option<ChainState>, Error> {
+        self.primary.get_chain_state(chain).await
+    }
+
+    async fn update_chain_state(&self, chain: u64, update: ChainStateUpdate) -> Result<(), Error> {
+        self.primary.update_chain_state(chain, update).await
+    }
+
+    async fn set_resume_point(&self, chain: u64, index: EventIndex) -> Result<(), Error> {
+        self.primary.set_resume_point(chain, index).await
+    }
+
+    async fn clear_chain_state(&self, chain: u64) -> Result<(), Error> {
+        self.primary.clear_chain_state(chain).await
+    }
+
+    async fn get_verifier(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+    ) -> Result<Option<VerifierRecord>, Error> {
+        self.primary.get_verifier(permitter, identity).await
+    }
+
+    async fn update_verifier(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+        config: Vec<u8>,
+        version: EventIndex,
+    ) -> Result<(), Error> {
+        self.primary
+            .update_verifier(permitter, identity, config, version)
+            .await
+    }
+
+    async fn put_verifier_if_absent(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+        config: Vec<u8>,
+        version: EventIndex,
+    ) -> Result<bool, Error> {
+        self.primary
+            .put_verifier_if_absent(permitter, identity, config, version)
+            .await
+    }
+
+    async fn patch_verifier(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+        patch: PolicyPatch,
+        version: EventIndex,
+    ) -> Result<(), Error> {
+        // Patching a lagging copy would discard the changes that it has yet to see.
+        self.primary
+            .patch_verifier(permitter, identity, patch, version)
+            .await
+    }
+
+    #[cfg(test)]
+    async fn clear_verifier(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+    ) -> Result<(), Error> {
+        self.primary.clear_verifier(permitter, identity).await
+    }
+
+    async fn delete_verifiers_for_chain(&self, chain: ChainId) -> Result<u64, Error> {
+        self.primary.delete_verifiers_for_chain(chain).await
+    }
+
+    async fn delete_verifiers_for_permitter(
+        &self,
+        permitter: PermitterLocator,
+    ) -> Result<u64, Error> {
+        self.primary.delete_verifiers_for_permitter(permitter).await
+    }
+
+    async fn gc(&self, retain_chain_ids: &[ChainId]) -> Result<GcReport, Error> {
+        self.primary.gc(retain_chain_ids).await
+    }
+
+    async fn checkpoint(&self) -> Result<CheckpointReport, Error> {
+        self.primary.checkpoint().await
+    }
+
+    async fn statistics(&self) -> Result<StoreStatistics, Error> {
+        self.replica.statistics().await
+    }
+
+    async fn put_intent(&self, intent: intent::Intent) -> Result<(), Error> {
+        self.primary.put_intent(intent).await
+    }
+
+    async fn list_intents(&self) -> Result<Vec<intent::Intent>, Error> {
+        self.primary.list_intents().await
+    }
+
+    async fn clear_intent(&self, id: u64) -> Result<(), Error> {
+        self.primary.clear_intent(id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::memory::MemoryStore;
+
+    // A replica that is never behind, so the shared tests check that every call reaches a store.
+    crate::make_store_tests!(async {
+        let store = MemoryStore::default();
+        ReplicatedStore::new(store.clone(), store)
+    });
+
+    #[tokio::test]
+    async fn read_from_replica_unless_primary_is_asked() {
+        let store = ReplicatedStore::new(MemoryStore::default(), MemoryStore::default());
+        let (share_id, share) = crate::store::tests::make_share(IdentityId::random(), 1);
+        let permitter = PermitterLocator::new(31337, Address::repeat_byte(2));
+        let index = EventIndex {
+            block: 1,
+            log_index: 0,
+        };
+
+        assert!(store
+            .put_share(share_id.clone(), share.clone())
+            .await
+            .unwrap());
+        store
+            .update_verifier(permitter, share_id.identity.id, b"policy".to_vec(), index)
+            .await
+            .unwrap();
+        // The replica has yet to catch up.
+        assert_eq!(store.get_share(share_id.clone()).await.unwrap(), None);
+        assert_eq!(
+            store.primary().get_share(share_id.clone()).await.unwrap(),
+            Some(share.clone())
+        );
+        // Policies are enforced as soon as they are written.
+        assert_eq!(
+            store
+                .get_verifier(permitter, share_id.identity.id)
+                .await
+                .unwrap()
+                .map(|verifier| verifier.config),
+            Some(b"policy".to_vec())
+        );
+
+        store
+            .replica()
+            .put_share(share_id.clone(), share.clone())
+            .await
+            .unwrap();
+        assert_eq!(store.get_share(share_id).await.unwrap(), Some(share));
+    }
+
+    #[tokio::test]
+    async fn recovery_does_not_rewind_to_lagging_replica() {
+        let store = ReplicatedStore::new(MemoryStore::default(), MemoryStore::default());
+        let at = |block| EventIndex {
+            block,
+            log_index: 0,
+        };
+        store.set_resume_point(31337, at(20)).await.unwrap();
+        store
+            .replica()
+            .set_resume_point(31337, at(10))
+            .await
+            .unwrap();
+        store
+            .put_intent(intent::Intent {
+                id: 1,
+                created_at: 0,
+                steps: vec![intent::Step::SetResumePoint {
+                    chain: 31337,
+                    index: at(15),
+                }],
+            })
+            .await
+            .unwrap();
+
+        assert_eq!(intent::recover(&store).await.unwrap(), 1);
+        let state = store.primary().get_chain_state(31337).await.unwrap();
+        assert_eq!(state.map(|state| state.resume_point()), Some(at(20)));
+    }
+}
@@ -1,4 +1,5 @@
 use anyhow::ensure;
+use ethers::types::H256;
 
 use super::*;
 
@@ -8,9 +9,15 @@ macro_rules! make_store_tests {
         $crate::make_store_tests!(
             $store_factory,
             roundtrip_share,
+            get_absent_share,
+            soft_delete_share,
+            share_age,
+            paginate_shares,
+            list_shares_by_registry,
             create_second_share_version,
             create_duplicate_share_version,
             create_discontinuous_share_version,
+            create_conflicting_share_index,
             create_delete_create_share_version,
             create_second_share,
             roundtrip_key,
@@ -26,7 +33,15 @@ macro_rules! make_store_tests {
             defresh_permit_fail,
             delete_defresh_permit,
             roundtrip_chain_state,
+            set_chain_resume_point,
+            check_processed_through,
             roundtrip_verifier,
+            put_verifier_only_if_absent,
+            verifier_update_time,
+            patch_verifier_field,
+            delete_chain_verifiers,
+            delete_permitter_verifiers,
+            roundtrip_intent,
         );
     };
     ($store_factory:expr, $($test:ident),+ $(,)?) => {
@@ -40,7 +55,203 @@ macro_rules! make_store_tests {
     }
 }
 
-fn make_share(identity: IdentityId, version: u64) -> (ShareId, SecretShare) {
+/// Forwards each call to `inner` once `hook` has run with the name of the method, so that a test
+/// can slow down or fail the calls that it picks.
+#[derive(Clone)]
+pub(crate) struct HookedStore<S, H> {
+    pub(crate) inner: S,
+    hook: H,
+}
+
+impl<S, H> HookedStore<S, H> {
+    pub(crate) fn new(inner: S, hook: H) -> Self {
+        Self { inner, hook }
+    }
+}
+
+macro_rules! hooked {
+    ($self:ident.$method:ident($($arg:expr),*)) => {{
+        ($self.hook)(stringify!($method)).await?;
+        $self.inner.$method($($arg),*).await
+    }};
+}
+
+impl<S, H, F> Store for HookedStore<S, H>
+where
+    S: Store,
+    H: Fn(&'static str) -> F + Clone + Send + Sync + 'static,
+    F: std::future::Future<Output = Result<(), Error>> + Send,
+{
+    async fn put_share(&self, id: ShareId, share: SecretShare) -> Result<bool, Error> {
+        hooked!(self.put_share(id, share))
+    }
+
+    async fn get_share(&self, id: ShareId) -> Result<Option<SecretShare>, Error> {
+        hooked!(self.get_share(id))
+    }
+
+    async fn get_share_age(&self, id: ShareId) -> Result<Option<Duration>, Error> {
+        hooked!(self.get_share_age(id))
+    }
+
+    async fn delete_share_version(&self, id: ShareId) -> Result<(), Error> {
+        hooked!(self.delete_share_version(id))
+    }
+
+    async fn restore_share(&self, id: ShareId) -> Result<bool, Error> {
+        hooked!(self.restore_share(id))
+    }
+
+    async fn list_deleted_shares(&self) -> Result<Vec<ShareId>, Error> {
+        hooked!(self.list_deleted_shares())
+    }
+
+    async fn list_shares_page(
+        &self,
+        filter: ShareFilter,
+        cursor: Option<ShareCursor>,
+        page_size: u32,
+    ) -> Result<SharePage, Error> {
+        hooked!(self.list_shares_page(filter, cursor, page_size))
+    }
+
+    async fn put_key(&self, id: KeyId, key: WrappedKey) -> Result<bool, Error> {
+        hooked!(self.put_key(id, key))
+    }
+
+    async fn get_key(&self, id: KeyId) -> Result<Option<WrappedKey>, Error> {
+        hooked!(self.get_key(id))
+    }
+
+    async fn delete_key_version(&self, id: KeyId) -> Result<(), Error> {
+        hooked!(self.delete_key_version(id))
+    }
+
+    async fn create_permit(
+        &self,
+        identity: IdentityLocator,
+        recipient: Address,
+        expiry: u64,
+        nonce: Vec<u8>,
+    ) -> Result<Option<Permit>, Error> {
+        hooked!(self.create_permit(identity, recipient, expiry, nonce))
+    }
+
+    async fn read_permit(
+        &self,
+        identity: IdentityLocator,
+        recipient: Address,
+    ) -> Result<Option<Permit>, Error> {
+        hooked!(self.read_permit(identity, recipient))
+    }
+
+    async fn delete_permit(
+        &self,
+        identity: IdentityLocator,
+        recipient: Address,
+    ) -> Result<(), Error> {
+        hooked!(self.delete_permit(identity, recipient))
+    }
+
+    async fn get_chain_state(&self, chain: u64) -> Result<Option<ChainState>, Error> {
+        hooked!(self.get_chain_state(chain))
+    }
+
+    async fn update_chain_state(&self, chain: u64, update: ChainStateUpdate) -> Result<(), Error> {
+        hooked!(self.update_chain_state(chain, update))
+    }
+
+    async fn set_resume_point(&self, chain: u64, index: EventIndex) -> Result<(), Error> {
+        hooked!(self.set_resume_point(chain, index))
+    }
+
+    async fn clear_chain_state(&self, chain: u64) -> Result<(), Error> {
+        hooked!(self.clear_chain_state(chain))
+    }
+
+    async fn get_verifier(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+    ) -> Result<Option<VerifierRecord>, Error> {
+        hooked!(self.get_verifier(permitter, identity))
+    }
+
+    async fn update_verifier(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+        config: Vec<u8>,
+        version: EventIndex,
+    ) -> Result<(), Error> {
+        hooked!(self.update_verifier(permitter, identity, config, version))
+    }
+
+    async fn put_verifier_if_absent(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+        config: Vec<u8>,
+        version: EventIndex,
+    ) -> Result<bool, Error> {
+        hooked!(self.put_verifier_if_absent(permitter, identity, config, version))
+    }
+
+    async fn patch_verifier(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+        patch: PolicyPatch,
+        version: EventIndex,
+    ) -> Result<(), Error> {
+        hooked!(self.patch_verifier(permitter, identity, patch, version))
+    }
+
+    async fn clear_verifier(
+        &self,
+        permitter: PermitterLocator,
+        identity: IdentityId,
+    ) -> Result<(), Error> {
+        hooked!(self.clear_verifier(permitter, identity))
+    }
+
+    async fn delete_verifiers_for_chain(&self, chain: ChainId) -> Result<u64, Error> {
+        hooked!(self.delete_verifiers_for_chain(chain))
+    }
+
+    async fn delete_verifiers_for_permitter(
+        &self,
+        permitter: PermitterLocator,
+    ) -> Result<u64, Error> {
+        hooked!(self.delete_verifiers_for_permitter(permitter))
+    }
+
+    async fn gc(&self, retain_chain_ids: &[ChainId]) -> Result<GcReport, Error> {
+        hooked!(self.gc(retain_chain_ids))
+    }
+
+    async fn checkpoint(&self) -> Result<CheckpointReport, Error> {
+        hooked!(self.checkpoint())
+    }
+
+    async fn statistics(&self) -> Result<StoreStatistics, Error> {
+        hooked!(self.statistics())
+    }
+
+    async fn put_intent(&self, intent: intent::Intent) -> Result<(), Error> {
+        hooked!(self.put_intent(intent))
+    }
+
+    async fn list_intents(&self) -> Result<Vec<intent::Intent>, Error> {
+        hooked!(self.list_intents())
+    }
+
+    async fn clear_intent(&self, id: u64) -> Result<(), Error> {
+        hooked!(self.clear_intent(id))
+    }
+}
+
+pub(crate) fn make_share(identity: IdentityId, version: u64) -> (ShareId, SecretShare) {
     let share_id = ShareId {
         secret_name: "test".into(),
         identity: IdentityLocator {
@@ -55,8 +266,17 @@ fn make_share(identity: IdentityId, version: u64) -> (ShareId, SecretShare) {
     (
         share_id,
         SecretShare {
-            index: 1,
+            index: ShareIndex(1),
             share: share.into(),
+            provenance: Some(ShareProvenance {
+                event: EventIndex {
+                    block: 7,
+                    log_index: 2,
+                },
+                tx: Some(H256::repeat_byte(3)),
+                dealer_pk: vec![4; 49],
+                stored_at: 1_700_000_000,
+            }),
         },
     )
 }
@@ -93,29 +313,168 @@ where
     Ok(res)
 }
 
+pub async fn soft_delete_share(store: impl Store) {
+    let (share_id, share) = make_share(IdentityId::random(), 1);
+    assert!(store
+        .put_share(share_id.clone(), share.clone())
+        .await
+        .unwrap());
+    store.delete_share_version(share_id.clone()).await.unwrap();
+    assert_eq!(store.get_share(share_id.clone()).await.unwrap(), None);
+    let deleted = store.list_deleted_shares().await.unwrap();
+    assert!(deleted.contains(&share_id));
+
+    assert!(store.restore_share(share_id.clone()).await.unwrap());
+    assert_eq!(
+        store.get_share(share_id.clone()).await.unwrap(),
+        Some(share)
+    );
+    let deleted = store.list_deleted_shares().await.unwrap();
+    assert!(!deleted.contains(&share_id));
+    assert!(!store.restore_share(share_id.clone()).await.unwrap());
+
+    store.delete_share_version(share_id).await.unwrap();
+}
+
+pub async fn share_age(store: impl Store) {
+    let (share_id, share) = make_share(IdentityId::random(), 1);
+    assert_eq!(store.get_share_age(share_id.clone()).await.unwrap(), None);
+    assert!(store.put_share(share_id.clone(), share).await.unwrap());
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let age = store
+        .get_share_age(share_id.clone())
+        .await
+        .unwrap()
+        .unwrap();
+    assert!(age >= std::time::Duration::from_millis(100), "{age:?}");
+
+    store.delete_share_version(share_id.clone()).await.unwrap();
+    assert_eq!(store.get_share_age(share_id).await.unwrap(), None);
+}
+
+pub async fn paginate_shares(store: impl Store) {
+    // The chain is unique to this run so that only its shares are listed from shared backends.
+    let chain = rand::random::<u32>() as u64;
+    let mut share_ids = std::collections::HashSet::new();
+    for i in 0..125 {
+        let identity = IdentityId::random();
+        for version in 1..=2 {
+            let (mut share_id, share) = make_share(identity, version);
+            share_id.identity.chain = chain;
+            share_id.secret_name = format!("test-{}", i % 3);
+            assert!(store.put_share(share_id.clone(), share).await.unwrap());
+            share_ids.insert(share_id);
+        }
+    }
+    let deleted = share_ids.iter().next().unwrap().clone();
+    store.delete_share_version(deleted.clone()).await.unwrap();
+
+    let filter = ShareFilter {
+        chain: Some(chain),
+        ..Default::default()
+    };
+    let mut listed = Vec::new();
+    let mut cursor = None;
+    loop {
+        let page = store
+            .list_shares_page(filter.clone(), cursor, 10)
+            .await
+            .unwrap();
+        assert!(page.items.len() <= 10);
+        listed.extend(page.items.into_iter().map(|(id, _)| id));
+        let Some(next) = page.next_cursor else {
+            break;
+        };
+        cursor = Some(ShareCursor::from_token(&next.to_token()).unwrap());
+    }
+    let listed_set: std::collections::HashSet<_> = listed.iter().cloned().collect();
+    assert_eq!(listed.len(), listed_set.len(), "a share was listed twice");
+    share_ids.remove(&deleted);
+    assert_eq!(listed_set, share_ids);
+
+    for share_id in share_ids {
+        store.delete_share_version(share_id).await.unwrap();
+    }
+}
+
+pub async fn list_shares_by_registry(store: impl Store) {
+    use futures_util::TryStreamExt as _;
+
+    let registries = [Address::random(), Address::random()];
+    let mut share_ids = Vec::new();
+    for (i, registry) in registries.into_iter().enumerate() {
+        for chain in [31337, 31338] {
+            let share_id = ShareId {
+                secret_name: "omni".into(),
+                identity: IdentityLocator {
+                    chain,
+                    registry,
+                    id: IdentityId::random(),
+                },
+                version: 1,
+            };
+            let share = SecretShare {
+                index: ShareIndex(i as u64),
+                share: vec![i as u8; 32].into(),
+                provenance: None,
+            };
+            assert!(store.put_share(share_id.clone(), share).await.unwrap());
+            share_ids.push(share_id);
+        }
+    }
+
+    let mut listed: Vec<_> = store
+        .get_shares_by_registry(registries[0])
+        .map_ok(|(id, _)| id)
+        .try_collect()
+        .await
+        .unwrap();
+    listed.sort_by_key(|id| id.identity.chain);
+    assert_eq!(listed, share_ids[..2]);
+
+    for share_id in share_ids {
+        store.delete_share_version(share_id).await.unwrap();
+    }
+}
+
 pub async fn roundtrip_share(store: impl Store) {
     let identity = IdentityId::random();
-    with_new_share(&store, identity, 1, |store, share_id| async move {
-        ensure!(share_id.identity.chain == 31337, "unexpected share chain");
-        ensure!(
-            share_id.identity.registry == Address::repeat_byte(1),
-            "unexpected share registry"
-        );
-        ensure!(
-            share_id.identity.id == identity,
-            "unexpected share identity"
-        );
-        ensure!(share_id.version == 1, "unexpected share version");
-        let share = store.get_share(share_id.clone()).await?;
-        let share2 = store.get_share(share_id.clone()).await?;
-        ensure!(share == share2, "retrieved shares mismatched");
-        Ok(())
-    })
+    let (share_id, original) = make_share(identity, 1);
+    with_share(
+        &store,
+        share_id,
+        original.clone(),
+        |store, share_id| async move {
+            ensure!(share_id.identity.chain == 31337, "unexpected share chain");
+            ensure!(
+                share_id.identity.registry == Address::repeat_byte(1),
+                "unexpected share registry"
+            );
+            ensure!(
+                share_id.identity.id == identity,
+                "unexpected share identity"
+            );
+            ensure!(share_id.version == 1, "unexpected share version");
+            let share = store.get_share(share_id.clone()).await?;
+            let share2 = store.get_share(share_id.clone()).await?;
+            ensure!(share == share2, "retrieved shares mismatched");
+            ensure!(
+                share == Some(original),
+                "retrieved share differs from stored"
+            );
+            Ok(())
+        },
+    )
     .await
     .expect("test failed")
     .expect("share creation failed");
 }
 
+pub async fn get_absent_share(store: impl Store) {
+    let (share_id, _) = make_share(IdentityId::random(), 1);
+    assert!(store.get_share(share_id).await.unwrap().is_none());
+}
+
 pub async fn create_second_share_version(store: impl Store) {
     let identity = IdentityId::random();
     with_new_share(&store, identity, 1, |store, share_id1| async move {
@@ -194,6 +553,40 @@ pub async fn create_discontinuous_share_version(store: impl Store) {
     .expect("first share creation failed");
 }
 
+pub async fn create_conflicting_share_index(store: impl Store) {
+    let (share_id, mut share) = make_share(IdentityId::random(), 1);
+    share.index = ShareIndex(3);
+    with_share(
+        &store,
+        share_id,
+        share.clone(),
+        |store, share_id| async move {
+            assert!(!store
+                .put_share(share_id.clone(), share.clone())
+                .await
+                .unwrap());
+            let err = store
+                .put_share(
+                    share_id.clone(),
+                    SecretShare {
+                        index: ShareIndex(2),
+                        ..share.clone()
+                    },
+                )
+                .await
+                .unwrap_err();
+            let conflict = err.downcast_ref::<ShareIndexConflict>().unwrap();
+            assert_eq!(
+                (conflict.existing, conflict.incoming),
+                (ShareIndex(3), ShareIndex(2))
+            );
+            assert_eq!(store.get_share(share_id).await.unwrap(), Some(share));
+        },
+    )
+    .await
+    .unwrap();
+}
+
 pub async fn create_second_share(store: impl Store) {
     let identity1 = IdentityId::random();
     let identity2 = IdentityId::random();
@@ -597,32 +990,171 @@ pub async fn roundtrip_chain_state(store: impl Store) {
     assert!(start_state.is_none());
 
     store
-        .update_chain_state(chain_id, ChainStateUpdate { block: Some(42) })
+        .update_chain_state(
+            chain_id,
+            ChainStateUpdate {
+                block: Some(42),
+                block_hash: Some(H256::repeat_byte(42)),
+            },
+        )
         .await
         .unwrap();
     let updated_state = store.get_chain_state(chain_id).await.unwrap();
-    assert_eq!(updated_state, Some(ChainState { block: 42 }));
+    assert_eq!(
+        updated_state,
+        Some(ChainState {
+            block: 42,
+            log_index: 0,
+            block_hash: Some(H256::repeat_byte(42)),
+        })
+    );
 
     store
-        .update_chain_state(chain_id, ChainStateUpdate { block: Some(41) })
+        .update_chain_state(
+            chain_id,
+            ChainStateUpdate {
+                block: Some(41),
+                ..Default::default()
+            },
+        )
         .await
         .unwrap();
     let not_updated_state = store.get_chain_state(chain_id).await.unwrap();
-    assert_eq!(not_updated_state, Some(ChainState { block: 42 }));
+    assert_eq!(
+        not_updated_state,
+        Some(ChainState {
+            block: 42,
+            log_index: 0,
+            block_hash: Some(H256::repeat_byte(42)),
+        })
+    );
 
     store
-        .update_chain_state(chain_id, ChainStateUpdate { block: None })
+        .update_chain_state(
+            chain_id,
+            ChainStateUpdate {
+                block: None,
+                ..Default::default()
+            },
+        )
         .await
         .unwrap();
     let not_updated_state = store.get_chain_state(chain_id).await.unwrap();
-    assert_eq!(not_updated_state, Some(ChainState { block: 42 }));
+    assert_eq!(
+        not_updated_state,
+        Some(ChainState {
+            block: 42,
+            log_index: 0,
+            block_hash: Some(H256::repeat_byte(42)),
+        })
+    );
 
     store
-        .update_chain_state(chain_id, ChainStateUpdate { block: Some(43) })
+        .update_chain_state(
+            chain_id,
+            ChainStateUpdate {
+                block: Some(43),
+                ..Default::default()
+            },
+        )
         .await
         .unwrap();
     let re_updated_state = store.get_chain_state(chain_id).await.unwrap();
-    assert_eq!(re_updated_state, Some(ChainState { block: 43 }));
+    assert_eq!(
+        re_updated_state,
+        Some(ChainState {
+            block: 43,
+            log_index: 0,
+            block_hash: None,
+        })
+    );
+
+    store.clear_chain_state(chain_id).await.unwrap();
+}
+
+pub async fn set_chain_resume_point(store: impl Store) {
+    let chain_id = (u32::max_value() as u64)
+        .checked_add(rand::random())
+        .unwrap();
+    store
+        .update_chain_state(
+            chain_id,
+            ChainStateUpdate {
+                block: Some(42),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+    // Resume points may move backwards.
+    let resume_point = EventIndex {
+        block: 10,
+        log_index: 3,
+    };
+    store
+        .set_resume_point(chain_id, resume_point)
+        .await
+        .unwrap();
+    let state = store.get_chain_state(chain_id).await.unwrap().unwrap();
+    assert_eq!(state.resume_point(), resume_point);
+
+    // Later progress starts from the beginning of the new block.
+    store
+        .update_chain_state(
+            chain_id,
+            ChainStateUpdate {
+                block: Some(11),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+    let state = store.get_chain_state(chain_id).await.unwrap().unwrap();
+    assert_eq!(
+        state.resume_point(),
+        EventIndex {
+            block: 11,
+            log_index: 0
+        }
+    );
+
+    store.clear_chain_state(chain_id).await.unwrap();
+}
+
+pub async fn check_processed_through(store: impl Store) {
+    let chain_id = (u32::max_value() as u64)
+        .checked_add(rand::random())
+        .unwrap();
+    assert!(!store.is_processed_through(chain_id, 0).await.unwrap());
+
+    store
+        .update_chain_state(
+            chain_id,
+            ChainStateUpdate {
+                block: Some(42),
+                block_hash: Some(H256::random()),
+            },
+        )
+        .await
+        .unwrap();
+    assert!(store.is_processed_through(chain_id, 41).await.unwrap());
+    assert!(store.is_processed_through(chain_id, 42).await.unwrap());
+    assert!(!store.is_processed_through(chain_id, 43).await.unwrap());
+
+    // The resume block itself is yet to be processed.
+    store
+        .set_resume_point(
+            chain_id,
+            EventIndex {
+                block: 10,
+                log_index: 0,
+            },
+        )
+        .await
+        .unwrap();
+    assert!(store.is_processed_through(chain_id, 9).await.unwrap());
+    assert!(!store.is_processed_through(chain_id, 10).await.unwrap());
 
     store.clear_chain_state(chain_id).await.unwrap();
 }
@@ -671,7 +1203,8 @@ pub async fn roundtrip_verifier(store: impl Store) {
     let updated_state = store
         .get_verifier(chain2_permitter1, identity1)
         .await
-        .unwrap();
+        .unwrap()
+        .map(|record| record.config);
     assert_eq!(updated_state.as_deref(), Some(config4));
 
     // Assert that identities on the same permitter do not interfere.
@@ -704,7 +1237,8 @@ pub async fn roundtrip_verifier(store: impl Store) {
     let updated_state = store
         .get_verifier(chain1_permitter1, identity1)
         .await
-        .unwrap();
+        .unwrap()
+        .map(|record| record.config);
     assert_eq!(updated_state.as_deref(), Some(config1));
     store
         .update_verifier(
@@ -721,7 +1255,8 @@ pub async fn roundtrip_verifier(store: impl Store) {
     let updated_state = store
         .get_verifier(chain1_permitter1, identity1)
         .await
-        .unwrap();
+        .unwrap()
+        .map(|record| record.config);
     assert_eq!(updated_state.as_deref(), Some(config1));
 
     store
@@ -739,7 +1274,8 @@ pub async fn roundtrip_verifier(store: impl Store) {
     let updated_state = store
         .get_verifier(chain1_permitter1, identity1)
         .await
-        .unwrap();
+        .unwrap()
+        .map(|record| record.config);
     assert_eq!(updated_state.as_deref(), Some(config2));
 
     store
@@ -757,7 +1293,8 @@ pub async fn roundtrip_verifier(store: impl Store) {
     let updated_state = store
         .get_verifier(chain1_permitter1, identity1)
         .await
-        .unwrap();
+        .unwrap()
+        .map(|record| record.config);
     assert_eq!(updated_state.as_deref(), Some(config3));
 
     for permitter in [chain1_permitter1, chain1_permitter2, chain2_permitter1] {
@@ -766,3 +1303,333 @@ pub async fn roundtrip_verifier(store: impl Store) {
         }
     }
 }
+
+/// Expects a store that keeps two versions of each share, with no grace period.
+pub async fn evict_old_share_versions(store: impl Store) {
+    let identity = IdentityId::random();
+    let id = |version| make_share(identity, version).0;
+    let kept = |versions: std::ops::RangeInclusive<u64>| {
+        let store = &store;
+        async move {
+            let mut kept = Vec::new();
+            for version in versions {
+                if store.get_share(id(version)).await.unwrap().is_some() {
+                    kept.push(version);
+                }
+            }
+            kept
+        }
+    };
+
+    for version in 1..=3 {
+        let (share_id, share) = make_share(identity, version);
+        assert!(store.put_share(share_id, share).await.unwrap());
+    }
+    assert_eq!(kept(1..=3).await, vec![2, 3]);
+
+    // Deleted versions do not count toward the limit, so they do not hasten eviction.
+    store.delete_share_version(id(3)).await.unwrap();
+    let (share_id, share) = make_share(identity, 4);
+    assert!(store.put_share(share_id, share).await.unwrap());
+    assert_eq!(kept(1..=4).await, vec![2, 4]);
+
+    let (share_id, share) = make_share(identity, 5);
+    assert!(store.put_share(share_id, share).await.unwrap());
+    assert_eq!(kept(1..=5).await, vec![4, 5]);
+}
+
+pub async fn gc_untracked_chains(store: impl Store) {
+    let identity: IdentityId = rand::random();
+    let registry = Address::random();
+    let permitter = Address::random();
+    let recipient = Address::random();
+    let expiry = now() + 30;
+    let nonce = b"nonce".to_vec();
+
+    for chain in [1, 2, 3] {
+        let locator = IdentityLocator {
+            chain,
+            registry,
+            id: identity,
+        };
+        store
+            .create_permit(locator, recipient, expiry, nonce.clone())
+            .await
+            .unwrap()
+            .unwrap();
+        store
+            .update_verifier(
+                PermitterLocator { chain, permitter },
+                identity,
+                b"config".to_vec(),
+                EventIndex {
+                    block: 1,
+                    log_index: 0,
+                },
+            )
+            .await
+            .unwrap();
+    }
+
+    let report = store.gc(&[1]).await.unwrap();
+    assert_eq!(
+        report,
+        GcReport {
+            nonce_entries_deleted: 2,
+            verifier_entries_deleted: 2,
+        }
+    );
+
+    for chain in [1, 2, 3] {
+        let locator = IdentityLocator {
+            chain,
+            registry,
+            id: identity,
+        };
+        // A collected nonce is usable again, but a retained one is not.
+        let reused = store
+            .create_permit(locator, recipient, expiry + 1, nonce.clone())
+            .await
+            .unwrap();
+        assert_eq!(reused.is_some(), chain != 1, "chain {chain}");
+        let verifier = store
+            .get_verifier(PermitterLocator { chain, permitter }, identity)
+            .await
+            .unwrap();
+        assert_eq!(verifier.is_some(), chain == 1, "chain {chain}");
+    }
+
+    assert_eq!(store.gc(&[1, 2, 3]).await.unwrap(), GcReport::default());
+}
+
+pub async fn put_verifier_only_if_absent(store: impl Store) {
+    let permitter = PermitterLocator {
+        chain: 31337,
+        permitter: rand::random(),
+    };
+    let identity = rand::random();
+    let index = EventIndex {
+        block: 1,
+        log_index: 0,
+    };
+
+    let inserted = store
+        .put_verifier_if_absent(permitter, identity, b"config1".to_vec(), index)
+        .await
+        .unwrap();
+    assert!(inserted);
+    let inserted = store
+        .put_verifier_if_absent(permitter, identity, b"config2".to_vec(), index)
+        .await
+        .unwrap();
+    assert!(!inserted);
+    let config = store
+        .get_verifier(permitter, identity)
+        .await
+        .unwrap()
+        .map(|record| record.config);
+    assert_eq!(config.as_deref(), Some(b"config1".as_slice()));
+
+    store.clear_verifier(permitter, identity).await.unwrap();
+}
+
+pub async fn verifier_update_time(store: impl Store) {
+    let permitter = PermitterLocator {
+        chain: 31337,
+        permitter: rand::random(),
+    };
+    let identity = rand::random();
+    let index = |block| EventIndex {
+        block,
+        log_index: 0,
+    };
+    let updated_at = || async {
+        store
+            .get_verifier(permitter, identity)
+            .await
+            .unwrap()
+            .unwrap()
+            .updated_at
+            .unwrap()
+    };
+
+    store
+        .update_verifier(permitter, identity, b"config1".to_vec(), index(1))
+        .await
+        .unwrap();
+    let first_update = updated_at().await;
+    let skew = match std::time::SystemTime::now().duration_since(first_update) {
+        Ok(behind) => behind,
+        Err(e) => e.duration(),
+    };
+    assert!(skew < std::time::Duration::from_secs(1), "{skew:?}");
+
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    store
+        .update_verifier(permitter, identity, b"config2".to_vec(), index(2))
+        .await
+        .unwrap();
+    assert!(updated_at().await > first_update);
+
+    store.clear_verifier(permitter, identity).await.unwrap();
+}
+
+pub async fn patch_verifier_field(store: impl Store) {
+    use ciborium::Value;
+
+    let permitter = PermitterLocator {
+        chain: 31337,
+        permitter: rand::random(),
+    };
+    let identity = rand::random();
+    let config = |threshold: u64| {
+        Value::Map(vec![
+            (Value::Text("verifier".into()), Value::Text("nitro".into())),
+            (
+                Value::Text("policy".into()),
+                Value::Bytes(b"policy".to_vec()),
+            ),
+            (
+                Value::Text("threshold".into()),
+                Value::Integer(threshold.into()),
+            ),
+        ])
+    };
+    let encode = |config: &Value| {
+        let mut bytes = Vec::new();
+        ciborium::ser::into_writer(config, &mut bytes).unwrap();
+        bytes
+    };
+
+    let patch = PolicyPatch(serde_json::json!({ "threshold": 3 }));
+    let index = |block| EventIndex {
+        block,
+        log_index: 0,
+    };
+    assert!(store
+        .patch_verifier(permitter, identity, patch.clone(), index(1))
+        .await
+        .is_err());
+
+    store
+        .update_verifier(permitter, identity, encode(&config(2)), index(1))
+        .await
+        .unwrap();
+    store
+        .patch_verifier(permitter, identity, patch, index(2))
+        .await
+        .unwrap();
+    let patched = store
+        .get_verifier(permitter, identity)
+        .await
+        .unwrap()
+        .unwrap();
+    let patched: Value = ciborium::de::from_reader(patched.config.as_slice()).unwrap();
+    assert_eq!(patched, config(3));
+
+    store.clear_verifier(permitter, identity).await.unwrap();
+}
+
+/// Stores a verifier for `identity` under each of `permitters`.
+async fn put_verifiers(store: &impl Store, permitters: &[PermitterLocator], identity: IdentityId) {
+    for permitter in permitters {
+        store
+            .update_verifier(
+                *permitter,
+                identity,
+                b"config".to_vec(),
+                EventIndex {
+                    block: 1,
+                    log_index: 0,
+                },
+            )
+            .await
+            .unwrap();
+    }
+}
+
+pub async fn delete_chain_verifiers(store: impl Store) {
+    let chain1 = (u32::max_value() as u64)
+        .checked_add(rand::random())
+        .unwrap();
+    let chain2 = chain1 + 1;
+    let permitters = [
+        PermitterLocator {
+            chain: chain1,
+            permitter: rand::random(),
+        },
+        PermitterLocator {
+            chain: chain1,
+            permitter: rand::random(),
+        },
+        PermitterLocator {
+            chain: chain2,
+            permitter: rand::random(),
+        },
+    ];
+    let identity = rand::random();
+    put_verifiers(&store, &permitters, identity).await;
+
+    assert_eq!(store.delete_verifiers_for_chain(chain1).await.unwrap(), 2);
+    for permitter in permitters {
+        let verifier = store.get_verifier(permitter, identity).await.unwrap();
+        assert_eq!(verifier.is_some(), permitter.chain == chain2);
+    }
+    assert_eq!(store.delete_verifiers_for_chain(chain1).await.unwrap(), 0);
+
+    store.delete_verifiers_for_chain(chain2).await.unwrap();
+}
+
+pub async fn delete_permitter_verifiers(store: impl Store) {
+    let chain = (u32::max_value() as u64)
+        .checked_add(rand::random())
+        .unwrap();
+    let permitter1 = PermitterLocator {
+        chain,
+        permitter: rand::random(),
+    };
+    let permitter2 = PermitterLocator {
+        chain,
+        permitter: rand::random(),
+    };
+    let (identity1, identity2) = (rand::random(), rand::random());
+    put_verifiers(&store, &[permitter1, permitter2], identity1).await;
+    put_verifiers(&store, &[permitter1], identity2).await;
+
+    assert_eq!(
+        store
+            .delete_verifiers_for_permitter(permitter1)
+            .await
+            .unwrap(),
+        2
+    );
+    for identity in [identity1, identity2] {
+        let verifier = store.get_verifier(permitter1, identity).await.unwrap();
+        assert!(verifier.is_none());
+    }
+    let verifier = store.get_verifier(permitter2, identity1).await.unwrap();
+    assert!(verifier.is_some());
+
+    store.delete_verifiers_for_chain(chain).await.unwrap();
+}
+
+pub async fn roundtrip_intent(store: impl Store) {
+    let (share_id, share) = make_share(IdentityId::random(), 1);
+    let intent = intent::Intent {
+        id: rand::random(),
+        created_at: now(),
+        steps: vec![intent::Step::PutShare {
+            id: share_id,
+            share,
+        }],
+    };
+    store.put_intent(intent.clone()).await.unwrap();
+    let listed = store.list_intents().await.unwrap();
+    assert_eq!(listed.iter().find(|i| i.id == intent.id), Some(&intent));
+
+    store.clear_intent(intent.id).await.unwrap();
+    let listed = store.list_intents().await.unwrap();
+    assert!(listed.iter().all(|i| i.id != intent.id));
+    // Clearing is idempotent.
+    store.clear_intent(intent.id).await.unwrap();
+}
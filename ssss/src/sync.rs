@@ -1,42 +1,279 @@
-use std::sync::{
-    atomic::{AtomicU64, Ordering},
-    Arc,
-};
+pub mod blocking;
+
+use std::{collections::HashMap, sync::Arc};
 
 use aes_gcm_siv::AeadInPlace as _;
-use ethers::middleware::Middleware;
-use futures_util::stream::StreamExt as _;
-use ssss::identity::{self, Identity};
-use tokio::time::{sleep, Duration};
-use tracing::{error, trace, warn};
+use ethers::{
+    middleware::Middleware,
+    types::{Bytes, TxHash, H256},
+};
+use futures_util::stream::{StreamExt as _, TryStreamExt as _};
+use tokio::{
+    sync::{mpsc, Semaphore},
+    time::{interval, interval_at, sleep, Duration, Instant},
+};
+use tracing::{error, info, trace, warn};
+
+use crate::{
+    eth,
+    identity::{self, Identity},
+    metrics,
+    store::{self, Store},
+    types::*,
+    utils::{retry_while, CountingStream},
+};
+
+/// How often the latest processed block of each chain is saved to the store.
+const STATE_UPDATE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// How long to wait after the first failed provider health check. The wait doubles after each
+/// further failure, up to [`MAX_HEALTH_CHECK_BACKOFF`].
+const HEALTH_CHECK_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_HEALTH_CHECK_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How long a sync must run before it exits for its failure to no longer count as consecutive with
+/// the one before, which resets the restart backoff.
+const RESTART_BACKOFF_RESET: Duration = Duration::from_secs(10 * 60);
+
+#[derive(Clone, Copy, Debug)]
+pub struct SyncConfig {
+    pub policy_fail_mode: PolicyFailMode,
+    /// How often the store's write-ahead log, if any, is checkpointed.
+    pub checkpoint_interval: Duration,
+    /// How many blocks behind head the sync must be to backfill rather than tail the chain.
+    pub backfill_threshold: u64,
+    /// How many blocks' events are fetched concurrently while backfilling.
+    pub backfill_concurrency: usize,
+    /// How many blocks must be built on a block before it is processed while tailing.
+    pub tail_confirmations: u64,
+    /// How to treat dealt shares that decrypt to an unexpected length.
+    pub malformed_share_mode: MalformedShareMode,
+    /// How long sync may go without processing a block while the chain moves past it before the
+    /// chain is marked stalled, or `None` to never mark it.
+    pub stall_deadline: Option<Duration>,
+    /// How many blocks behind head the oldest block that sync starts from may be, or `None` for no
+    /// limit. Older blocks are skipped, in case sync would otherwise start far back in the chain.
+    /// Policy changes in skipped blocks are never applied, so the policies that they would have
+    /// replaced stay in force.
+    pub max_event_age_blocks: Option<u64>,
+    /// How many blocks a `PolicyChange` is held back in case a later one for the same identity
+    /// supersedes it, or 0 to apply each as soon as its block is processed. Shares dealt to an
+    /// identity whose policy change is held are held with it and stored just after it, so that a
+    /// share is never stored before the policy set ahead of it.
+    pub policy_debounce_blocks: u64,
+    /// How long to wait before restarting a sync that exited. The wait doubles with each
+    /// consecutive failure, up to `max_restart_backoff`.
+    pub restart_backoff: Duration,
+    pub max_restart_backoff: Duration,
+}
+
+impl SyncConfig {
+    /// Returns the last block to backfill given the chain head, or `None` if the sync at `next_block`
+    /// is close enough to head to tail it instead.
+    fn backfill_end(&self, next_block: u64, head: u64) -> Option<u64> {
+        // Backfilled blocks are not confirmation-gated, so never backfill into the tailed range.
+        let end = head.checked_sub(self.backfill_threshold.max(self.tail_confirmations))?;
+        (end >= next_block).then_some(end)
+    }
+
+    /// Returns the first block that sync may start from, if it is limited. The head is only
+    /// asked for when it is.
+    async fn oldest_block<M: Middleware + 'static>(
+        &self,
+        permitter: &eth::SsssHub<M>,
+    ) -> Result<Option<u64>, eth::Error<M>> {
+        Ok(match self.max_event_age_blocks {
+            Some(max_age) => Some(permitter.current_block().await?.saturating_sub(max_age)),
+            None => None,
+        })
+    }
 
-use crate::{eth, store::Store, types::*, utils::retry};
+    /// Returns how long to wait before restarting a sync that has failed `consecutive_failures`
+    /// times in a row.
+    fn restart_backoff(&self, consecutive_failures: u32) -> Duration {
+        let doublings = consecutive_failures.saturating_sub(1).min(31);
+        self.restart_backoff
+            .saturating_mul(1 << doublings)
+            .min(self.max_restart_backoff)
+    }
+}
+
+impl Default for SyncConfig {
+    fn default() -> Self {
+        Self {
+            policy_fail_mode: Default::default(),
+            checkpoint_interval: Duration::from_secs(10 * 60),
+            backfill_threshold: 128,
+            backfill_concurrency: 8,
+            tail_confirmations: 0,
+            malformed_share_mode: Default::default(),
+            stall_deadline: None,
+            max_event_age_blocks: None,
+            policy_debounce_blocks: 0,
+            restart_backoff: Duration::from_secs(1),
+            max_restart_backoff: Duration::from_secs(5 * 60),
+        }
+    }
+}
+
+/// What to do with a `PolicyChange` whose config cannot be decompressed or decoded.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum PolicyFailMode {
+    /// Ignore the change and keep enforcing the previous policy, if any.
+    #[default]
+    SkipAndWarn,
+    /// Mark the identity's policy as broken so that permits and shares are refused until a valid
+    /// policy arrives.
+    FailClosed,
+}
+
+/// The largest that a decompressed policy config may be. Real policies are a few hundred bytes,
+/// so this only stops a small compressed config from expanding to exhaust memory.
+pub const MAX_POLICY_SIZE: usize = 1 << 20;
+
+/// Decompresses a brotli-compressed policy config, failing if it exceeds [`MAX_POLICY_SIZE`].
+pub fn decompress_policy(config_br: &[u8]) -> std::io::Result<Vec<u8>> {
+    struct CappedWriter(Vec<u8>);
+
+    impl std::io::Write for CappedWriter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            if self.0.len() + buf.len() > MAX_POLICY_SIZE {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("policy config exceeds {MAX_POLICY_SIZE} bytes"),
+                ));
+            }
+            self.0.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
 
+    let mut config = CappedWriter(Vec::new());
+    brotli_decompressor::BrotliDecompress(&mut &*config_br, &mut config)?;
+    Ok(config.0)
+}
+
+/// The length of each share of a secret split with Shamir's scheme over P-384: a one-byte share
+/// identifier followed by the scalar.
+pub const SHAMIR_SHARE_LEN: usize = 1 + 48;
+
+/// What to do with a dealt share that decrypts to other than the length its deal implies.
+/// Empty shares are always skipped, as they can never contribute to a reconstruction.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum MalformedShareMode {
+    /// Leave the share unstored, as though it had not been dealt to this SSSS.
+    #[default]
+    SkipAndWarn,
+    /// Store the share anyway, for dealers that encode shares differently.
+    StoreAndWarn,
+}
+
+/// Returns the length that a share must have given how many shares were dealt, or `None` if it
+/// may have any nonzero length. A secret dealt to a single SSSS is sent whole rather than split.
+fn expected_share_len(dealt_shares: usize) -> Option<usize> {
+    (dealt_shares > 1).then_some(SHAMIR_SHARE_LEN)
+}
+
+/// Spawns a task to sync each chain and returns how many were spawned.
+/// Permitters that could not be constructed are logged and skipped.
+/// The [`SyncStatus`] of each chain is sent to `status`, if given, as it changes, and each event
+/// handled is sent to `observer`, if given. Progress is dropped while `status` is full, but a
+/// failed chain waits for room to report its failure before it restarts.
+///
+/// Each event is handled in a `handle_event` span. To export these to OpenTelemetry, install a
+/// subscriber with a `tracing-opentelemetry` layer before calling this, as `--otlp-endpoint`
+/// does.
 #[tracing::instrument(skip_all)]
 pub async fn run<M: Middleware + 'static>(
     store: impl Store + 'static,
-    sssss: impl Iterator<Item = eth::SsssHub<M>>,
-    ssss_identity: Identity,
-) -> Result<(), eth::Error<M>> {
+    sssss: impl Iterator<Item = Result<eth::SsssHub<M>, eth::Error<M>>>,
+    ssss_identities: Vec<Identity>,
+    shadow_identity: Option<Identity>,
+    config: SyncConfig,
+    status: Option<mpsc::Sender<SyncStatus>>,
+    observer: Option<EventObserver>,
+) -> usize {
     trace!("collating providers");
 
+    let decryptor = MultiIdentityDecryptor::new(
+        ssss_identities,
+        std::thread::available_parallelism().map_or(1, |n| n.get()),
+    );
+    // The shadow identity is only used to observe which deals it could decrypt.
+    let shadow = shadow_identity.map(|identity| MultiIdentityDecryptor::new(vec![identity], 1));
+
+    let mut started = 0;
     for ssss in sssss {
+        let ssss = match ssss {
+            Ok(ssss) => ssss,
+            Err(e) => {
+                error!("failed to construct permitter: {e}");
+                continue;
+            }
+        };
         let store = store.clone();
+        let decryptor = decryptor.clone();
+        let shadow = shadow.clone();
+        let status = status.clone();
+        let observer = observer.clone();
         let chain = ssss.chain;
         trace!("launching task for chain {chain}");
         tokio::spawn(async move {
             let ssss = &ssss;
-            loop {
-                match sync_chain(chain, ssss, &store, &ssss_identity).await {
-                    Ok(_) => warn!("sync task for chain {chain} unexpectedly exited"),
-                    Err(e) => error!("sync task for chain {chain} exited with error: {e}"),
+            let sync = restart_on_failure(ssss, &config, status.as_ref(), || {
+                sync_chain(
+                    chain,
+                    ssss,
+                    &store,
+                    &decryptor,
+                    shadow.as_ref(),
+                    &config,
+                    Reporters {
+                        status: status.as_ref(),
+                        observer: observer.as_ref(),
+                    },
+                )
+            });
+            match config.stall_deadline {
+                Some(deadline) => {
+                    tokio::select! {
+                        _ = sync => {}
+                        _ = watch_for_stall(ssss, deadline, config.tail_confirmations) => {}
+                    }
                 }
-                sleep(Duration::from_millis(1000)).await;
+                None => sync.await,
             }
         });
+        started += 1;
+    }
+    // Every chain shares the store, so its log is checkpointed once for all of them.
+    if started > 0 {
+        tokio::spawn(checkpoint_periodically(store, config.checkpoint_interval));
     }
 
-    Ok(())
+    started
+}
+
+async fn checkpoint_periodically(store: impl Store, interval: Duration) {
+    let mut ticker = interval_at(Instant::now() + interval, interval);
+    loop {
+        ticker.tick().await;
+        match store.checkpoint().await {
+            Ok(report) => trace!(?report, "checkpointed store"),
+            Err(e) => warn!("failed to checkpoint store: {e}"),
+        }
+    }
+}
+
+/// Where a chain's sync reports its progress and the events that it handles.
+#[derive(Clone, Copy, Default)]
+struct Reporters<'a> {
+    status: Option<&'a mpsc::Sender<SyncStatus>>,
+    observer: Option<&'a EventObserver>,
 }
 
 #[tracing::instrument(skip_all)]
@@ -44,143 +281,3080 @@ async fn sync_chain<M: Middleware + 'static, S: Store + 'static>(
     chain_id: ChainId,
     permitter: &eth::SsssHub<M>,
     store: &S,
-    ssss_identity: &Identity,
-) -> Result<(), Error<M>> {
-    let start_block = match store.get_chain_state(chain_id).await? {
-        Some(ChainState { block }) => block,
-        None => permitter.creation_block().await?,
+    decryptor: &MultiIdentityDecryptor,
+    shadow: Option<&MultiIdentityDecryptor>,
+    sync_config: &SyncConfig,
+    reporters: Reporters<'_>,
+) -> Result<std::convert::Infallible, Error<M>> {
+    wait_for_provider(permitter).await;
+    permitter.verify_chain_id().await?;
+
+    let resume_point = match store.get_chain_state(chain_id).await? {
+        Some(state) => state.resume_point(),
+        None => EventIndex {
+            block: permitter.creation_block().await?,
+            log_index: 0,
+        },
+    };
+    let resume_point = match sync_config.oldest_block(permitter).await? {
+        Some(oldest) if oldest > resume_point.block => {
+            warn!(
+                from = resume_point.block,
+                to = oldest - 1,
+                "skipping blocks of chain {chain_id} older than the maximum event age"
+            );
+            EventIndex {
+                block: oldest,
+                log_index: 0,
+            }
+        }
+        _ => resume_point,
     };
+    let start_block = resume_point.block;
+    // Everything before the resume point was processed before a restart, so report it as such
+    // rather than as nothing until the first block completes.
+    permitter.set_processed_block(start_block.saturating_sub(1));
 
-    let processed_block = Arc::new(AtomicU64::new(start_block));
-    let state_updater_task = tokio::spawn({
+    let processed = Arc::new(std::sync::Mutex::new(ChainStateUpdate {
+        block: Some(start_block),
+        block_hash: None,
+    }));
+    let mut state_updater_task = tokio::spawn({
         let store = store.clone();
-        let processed_block = processed_block.clone();
+        let processed = processed.clone();
         async move {
+            let mut state_ticker = interval_at(
+                Instant::now() + STATE_UPDATE_INTERVAL,
+                STATE_UPDATE_INTERVAL,
+            );
             loop {
-                sleep(Duration::from_secs(5 * 60)).await;
+                state_ticker.tick().await;
                 trace!("updating sync state for chain {chain_id}");
-                if let Err(e) = store
-                    .update_chain_state(
-                        chain_id,
-                        ChainStateUpdate {
-                            block: Some(processed_block.load(Ordering::Acquire)),
-                        },
-                    )
-                    .await
-                {
+                let update = processed.lock().unwrap().clone();
+                if let Err(e) = store.update_chain_state(chain_id, update).await {
                     warn!("failed to update sync state for chain {chain_id}: {e}");
                 }
             }
         }
     });
 
-    let processed_block = &processed_block;
-    permitter
-        .events(start_block, None)
-        .buffered(1)
-        .map(futures_util::stream::iter)
-        .flatten()
-        .for_each(|event| async move {
-            trace!(event = ?event, "event");
-            match event.kind {
-                eth::EventKind::PolicyChange(eth::PolicyChange {
-                    identity,
-                    config: config_br,
-                }) => {
-                    let mut config = Vec::new();
-                    if brotli_decompressor::BrotliDecompress(&mut config_br.as_slice(), &mut config)
-                        .is_err()
-                    {
-                        warn!("failed to decompress config");
-                        return;
+    let handler = EventHandler {
+        chain_id,
+        permitter,
+        store,
+        decryptor,
+        shadow,
+        config: sync_config,
+    };
+    let buffer_depth = metrics::EVENT_BUFFER_DEPTH.with_label_values(&[&chain_id.to_string()]);
+    let debounce = std::sync::Mutex::new(PolicyDebounce::new(sync_config.policy_debounce_blocks));
+    let order = std::sync::Mutex::new(EventOrder::new(chain_id));
+    let (handler, processed, debounce, order) = (&handler, &processed, &debounce, &order);
+    let handle = |event: eth::Event| async move {
+        let observed = reporters.observer.map(|_| event.clone());
+        let outcome = handler.handle(event, false).await?;
+        if let (Some(observer), Some(event)) = (reporters.observer, observed) {
+            observer
+                .deliver(ObservedEvent {
+                    chain_id,
+                    event,
+                    outcome: outcome.clone(),
+                })
+                .await?;
+        }
+        Ok::<_, Error<M>>(outcome)
+    };
+    // Events are handled one at a time in the order that `events` yields them, which is log index
+    // order, so a policy set earlier in a block is stored before a share dealt later in it.
+    let process = |events| {
+        futures_util::stream::StreamExt::map(events, futures_util::stream::iter)
+            .flatten()
+            .filter(|event: &eth::Event| futures_util::future::ready(event.index >= resume_point))
+            .map(Ok::<_, Error<M>>)
+            .try_for_each(|event| async move {
+                permitter.wait_unpaused().await;
+                trace!(event = ?event, "event");
+                order.lock().unwrap().observe(&event).map_err(|previous| {
+                    Error::EventOutOfOrder {
+                        previous,
+                        next: event.index,
                     }
-                    if ciborium::de::from_reader_with_recursion_limit::<PolicyPreamble, _>(
-                        config.as_slice(),
-                        10,
-                    )
-                    .is_err()
-                    {
-                        return;
+                })?;
+                let Some(event) = debounce.lock().unwrap().hold(event) else {
+                    return Ok(());
+                };
+                if let EventOutcome::BlockProcessed { block, hash } = handle(event).await? {
+                    let due = debounce.lock().unwrap().take_due(block);
+                    for event in due {
+                        handle(event).await?;
                     }
-                    retry(|| {
-                        store.update_verifier(
-                            PermitterLocator::new(chain_id, permitter.address),
-                            identity,
-                            config.clone(),
-                            event.index,
-                        )
-                    })
-                    .await;
-                    trace!("set updated policy");
+                    let held_since = debounce.lock().unwrap().held_since();
+                    *processed.lock().unwrap() = match held_since {
+                        // Resume from the oldest held policy so that a restart does not lose it.
+                        Some(held_since) => ChainStateUpdate {
+                            block: Some(held_since),
+                            block_hash: None,
+                        },
+                        None => ChainStateUpdate {
+                            block: Some(block),
+                            block_hash: Some(hash),
+                        },
+                    };
+                    permitter.set_processed_block(block);
+                    permitter.set_sync_error(None);
+                    send_status(
+                        reporters.status,
+                        SyncStatus::after_block(
+                            chain_id,
+                            block,
+                            permitter.last_known_final_block(),
+                        ),
+                    );
+                }
+                Ok(())
+            })
+    };
+
+    let synced = async {
+        // Blocks far behind head are fetched concurrently. The head moves while they are
+        // processed, so keep backfilling until the sync is within the threshold.
+        let mut next_block = start_block;
+        while let Some(end_block) =
+            sync_config.backfill_end(next_block, permitter.head_block().await?)
+        {
+            info!(
+                from = next_block,
+                to = end_block,
+                "backfilling chain {chain_id}"
+            );
+            process(CountingStream::new(
+                permitter.events(next_block, Some(end_block), 0),
+                sync_config.backfill_concurrency,
+                buffer_depth.clone(),
+            ))
+            .await?;
+            next_block = end_block + 1;
+        }
+        info!(from = next_block, "tailing chain {chain_id}");
+        process(CountingStream::new(
+            permitter.events(next_block, None, sync_config.tail_confirmations),
+            1,
+            buffer_depth,
+        ))
+        .await
+    };
+
+    // The state updater loops until it is aborted, so it has only stopped early if it panicked.
+    // Sync would otherwise carry on without ever saving its progress.
+    let synced = tokio::select! {
+        synced = synced => {
+            state_updater_task.abort();
+            match state_updater_task.await {
+                Err(e) if e.is_panic() => Err(state_updater_panicked(chain_id, e)),
+                _ => synced,
+            }
+        }
+        updated = &mut state_updater_task => match updated {
+            Err(e) if e.is_panic() => Err(state_updater_panicked(chain_id, e)),
+            _ => unreachable!("the state updater runs until it is aborted"),
+        },
+    };
+    synced?;
+    // Tailing has no last block, so its stream ending means that blocks were no longer coming.
+    Err(Error::EventStreamEnded {
+        processed: permitter.processed_block(),
+    })
+}
+
+/// Checks that a chain's events arrive in index order, as providers must deliver them.
+struct EventOrder {
+    chain_id: ChainId,
+    last: Option<(EventIndex, &'static str, Option<TxHash>)>,
+}
+
+impl EventOrder {
+    fn new(chain_id: ChainId) -> Self {
+        Self {
+            chain_id,
+            last: None,
+        }
+    }
+
+    /// Fails with the last event's index if the event precedes it, in which case events may have
+    /// been missed. An event at the same index as the last is only reported, as the provider
+    /// repeated an index but nothing was skipped.
+    fn observe(&mut self, event: &eth::Event) -> Result<(), EventIndex> {
+        let next = (event.index, event.kind.name(), event.tx);
+        let Some(last) = self.last.replace(next) else {
+            return Ok(());
+        };
+        match event.index.cmp(&last.0) {
+            std::cmp::Ordering::Greater => Ok(()),
+            std::cmp::Ordering::Equal => {
+                error!(
+                    chain = self.chain_id,
+                    block = event.index.block,
+                    log_index = event.index.log_index,
+                    previous.kind = last.1,
+                    previous.tx = ?last.2,
+                    kind = next.1,
+                    tx = ?next.2,
+                    "provider delivered two events at the same index"
+                );
+                metrics::REPEATED_EVENT_INDICES
+                    .with_label_values(&[&self.chain_id.to_string()])
+                    .inc();
+                Ok(())
+            }
+            std::cmp::Ordering::Less => Err(last.0),
+        }
+    }
+}
+
+/// Coalesces `PolicyChange`s for the same identity, such as the steps of a multi-step update, so
+/// that only the latest is written and intermediate policies are never enforced.
+struct PolicyDebounce {
+    window: u64,
+    held: HashMap<IdentityId, HeldPolicy>,
+}
+
+struct HeldPolicy {
+    /// The block of the first change that the held one superseded.
+    since: u64,
+    /// The latest change to the identity's policy.
+    policy_change: eth::Event,
+    /// The shares dealt to the identity since the change was first held, in index order.
+    deals: Vec<eth::Event>,
+}
+
+impl PolicyDebounce {
+    fn new(window: u64) -> Self {
+        Self {
+            window,
+            held: HashMap::new(),
+        }
+    }
+
+    /// Holds back the event if it is a `PolicyChange` that may yet be superseded or a share dealt
+    /// to an identity whose policy change is held, or else returns it to be handled now.
+    fn hold(&mut self, event: eth::Event) -> Option<eth::Event> {
+        let identity = match &event.kind {
+            eth::EventKind::PolicyChange(policy_change) if self.window > 0 => {
+                policy_change.identity
+            }
+            eth::EventKind::SharesDealt(deal) => {
+                return match self.held.get_mut(&deal.identity) {
+                    Some(held) => {
+                        held.deals.push(event);
+                        None
+                    }
+                    None => Some(event),
+                };
+            }
+            _ => return Some(event),
+        };
+        let block = event.index.block;
+        match self.held.get_mut(&identity) {
+            Some(held) => {
+                trace!(identity = ?identity, superseded = ?held.policy_change.index, by = ?event.index, "policy change superseded");
+                held.policy_change = event;
+            }
+            None => {
+                self.held.insert(
+                    identity,
+                    HeldPolicy {
+                        since: block,
+                        policy_change: event,
+                        deals: Vec::new(),
+                    },
+                );
+            }
+        }
+        None
+    }
+
+    /// Removes the held events that have waited the whole window as of `block`. Each policy
+    /// change is followed by the shares held with it, and the changes are in index order. The
+    /// window counts from the first change that each superseded, so that a policy changed every
+    /// block is still applied.
+    fn take_due(&mut self, block: u64) -> Vec<eth::Event> {
+        let due_ids: Vec<IdentityId> = self
+            .held
+            .iter()
+            .filter(|(_, held)| held.since + self.window <= block)
+            .map(|(identity, _)| *identity)
+            .collect();
+        let mut due: Vec<HeldPolicy> = due_ids
+            .iter()
+            .filter_map(|identity| self.held.remove(identity))
+            .collect();
+        due.sort_unstable_by_key(|held| held.policy_change.index);
+        due.into_iter()
+            .flat_map(|held| std::iter::once(held.policy_change).chain(held.deals))
+            .collect()
+    }
+
+    /// The block of the oldest change that has not been applied, if any.
+    fn held_since(&self) -> Option<u64> {
+        self.held.values().map(|held| held.since).min()
+    }
+}
+
+fn state_updater_panicked<M: Middleware>(chain_id: ChainId, e: tokio::task::JoinError) -> Error<M> {
+    let panic = e.into_panic();
+    let message = panic
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic");
+    error!("sync state updater for chain {chain_id} panicked: {message}");
+    Error::StateUpdaterPanic
+}
+
+/// Runs `sync` again each time it fails, after the configured backoff. Failures are consecutive
+/// unless the run before lasted [`RESTART_BACKOFF_RESET`], and each task keeps its own count.
+async fn restart_on_failure<M: Middleware + 'static, Fut>(
+    ssss: &eth::SsssHub<M>,
+    config: &SyncConfig,
+    status: Option<&mpsc::Sender<SyncStatus>>,
+    mut sync: impl FnMut() -> Fut,
+) where
+    Fut: std::future::Future<Output = Result<std::convert::Infallible, Error<M>>>,
+{
+    let chain = ssss.chain;
+    let mut failures = 0;
+    loop {
+        let started = Instant::now();
+        let Err(e) = sync().await else {
+            unreachable!("sync_chain only returns on error");
+        };
+        failures = match started.elapsed() >= RESTART_BACKOFF_RESET {
+            true => 1,
+            false => failures + 1,
+        };
+        let backoff = config.restart_backoff(failures);
+        error!(
+            backoff = ?backoff,
+            failures,
+            "sync task for chain {chain} exited with error: {e}"
+        );
+        ssss.set_sync_error(Some(e.to_string()));
+        if let Some(status) = status {
+            // Unlike progress, a failure is not superseded by the next status, so it waits for
+            // room rather than being dropped.
+            let failed = SyncStatus::Failed {
+                chain_id: chain,
+                error: SyncError(Arc::new(e)),
+                consecutive_failures: failures,
+                retry_in: backoff,
+            };
+            if let Err(e) = status.send(failed).await {
+                trace!("dropped sync status: {e}");
+            }
+        }
+        sleep(backoff).await;
+    }
+}
+
+/// How far a chain's sync has got, as reported to the embedder of [`run`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SyncStatus {
+    /// A block was processed this many blocks behind the newest final block.
+    InProgress {
+        chain_id: ChainId,
+        blocks_behind: u64,
+    },
+    /// The newest final block was processed, so sync is only waiting on finality.
+    CaughtUp { chain_id: ChainId },
+    /// Sync exited with an error and will be restarted after `retry_in`. This is sent for every
+    /// failure, so that embedders can apply their own alerting to runs of them.
+    Failed {
+        chain_id: ChainId,
+        error: SyncError,
+        /// How many times sync has failed in a row, including this. A run lasting ten minutes
+        /// before it fails starts a new run of failures.
+        consecutive_failures: u32,
+        retry_in: Duration,
+    },
+}
+
+/// The error that a chain's sync exited with. Errors are not otherwise comparable, so one is only
+/// equal to itself.
+#[derive(Clone, Debug)]
+pub struct SyncError(Arc<dyn std::error::Error + Send + Sync>);
+
+impl SyncError {
+    /// Returns the error, which is an [`Error`] that can be downcast by the middleware it was
+    /// synced with.
+    pub fn get(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+        &*self.0
+    }
+}
+
+impl std::fmt::Display for SyncError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl PartialEq for SyncError {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for SyncError {}
+
+impl SyncStatus {
+    fn after_block(chain_id: ChainId, block: u64, final_block: Option<u64>) -> Self {
+        match final_block.map_or(0, |final_block| final_block.saturating_sub(block)) {
+            0 => Self::CaughtUp { chain_id },
+            blocks_behind => Self::InProgress {
+                chain_id,
+                blocks_behind,
+            },
+        }
+    }
+}
+
+/// Receives a copy of each event that sync handles, along with what handling it did, such as to
+/// mirror events elsewhere.
+#[derive(Clone, Debug)]
+pub struct EventObserver {
+    sender: mpsc::Sender<ObservedEvent>,
+    delivery: Delivery,
+}
+
+/// How an [`EventObserver`] that falls behind is treated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Delivery {
+    /// Events are dropped while the observer's channel is full, so sync never waits for it.
+    BestEffort,
+    /// Sync waits for room in the observer's channel, so the observer sees every event but a
+    /// slow one slows sync. Sync fails if the observer's receiver is dropped.
+    ///
+    /// An event is delivered once handling it has taken effect, and sync only saves its progress
+    /// through a block once the block's events are delivered. An event handled just before a
+    /// crash is therefore not delivered with the outcome that it had. It is delivered again after
+    /// the restart, with the outcome of handling it a second time, such as a rejected share for
+    /// one that is already stored.
+    Guaranteed,
+}
+
+#[derive(Clone, Debug)]
+pub struct ObservedEvent {
+    pub chain_id: ChainId,
+    pub event: eth::Event,
+    pub outcome: EventOutcome,
+}
+
+impl EventObserver {
+    pub fn new(sender: mpsc::Sender<ObservedEvent>, delivery: Delivery) -> Self {
+        Self { sender, delivery }
+    }
+
+    async fn deliver<M: Middleware>(&self, event: ObservedEvent) -> Result<(), Error<M>> {
+        match self.delivery {
+            Delivery::BestEffort => {
+                if let Err(e) = self.sender.try_send(event) {
+                    trace!("dropped observed event: {e}");
                 }
-                eth::EventKind::ProcessedBlock => {
-                    processed_block.store(event.index.block, Ordering::Release);
+                Ok(())
+            }
+            Delivery::Guaranteed => {
+                let index = event.event.index;
+                self.sender
+                    .send(event)
+                    .await
+                    .map_err(|_| Error::ObserverClosed { index })
+            }
+        }
+    }
+}
+
+/// Statuses are dropped rather than holding up sync when the receiver falls behind. One is sent
+/// per processed block, so a dropped status is soon superseded.
+fn send_status(sender: Option<&mpsc::Sender<SyncStatus>>, status: SyncStatus) {
+    if let Some(Err(e)) = sender.map(|sender| sender.try_send(status)) {
+        trace!("dropped sync status: {e}");
+    }
+}
+
+/// Returns once the permitter's provider passes a health check, backing off exponentially between
+/// failures.
+async fn wait_for_provider<M: Middleware + 'static>(permitter: &eth::SsssHub<M>) {
+    let mut backoff = HEALTH_CHECK_BACKOFF;
+    while let Err(e) = permitter.provider_health_check().await {
+        warn!(
+            chain = permitter.chain,
+            backoff = ?backoff,
+            "provider health check failed: {e}"
+        );
+        sleep(backoff).await;
+        backoff = (backoff * 2).min(MAX_HEALTH_CHECK_BACKOFF);
+    }
+}
+
+/// Marks the chain stalled while its sync is stuck without erroring, which the restart loop in
+/// [`run`] cannot notice, and unmarks it once blocks are processed again.
+async fn watch_for_stall<M: Middleware + 'static>(
+    permitter: &eth::SsssHub<M>,
+    deadline: Duration,
+    confirmations: u64,
+) {
+    let chain = permitter.chain;
+    let mut watch = StallWatch::new(permitter.processed_block(), Instant::now());
+    let mut ticker = interval((deadline / 4).max(Duration::from_secs(1)));
+    loop {
+        ticker.tick().await;
+        let processed = permitter.processed_block();
+        // A paused sync is not expected to make progress.
+        let processable = match permitter.is_paused() {
+            true => processed,
+            // Blocks short of finality are held back, so sync lagging behind them is expected.
+            false => match permitter.final_block(confirmations).await {
+                Ok(final_block) => final_block.unwrap_or(processed),
+                Err(e) => {
+                    trace!("not checking chain {chain} for a stall: {e}");
+                    continue;
                 }
-                eth::EventKind::SharesDealt(eth::SharesDealt {
+            },
+        };
+        let stalled = watch.observe(processed, processable, Instant::now(), deadline);
+        if stalled == permitter.is_stalled() {
+            continue;
+        }
+        match stalled {
+            true => error!(
+                processed,
+                processable, "sync of chain {chain} has not processed a block in {deadline:?}"
+            ),
+            false => info!(processed, "sync of chain {chain} is no longer stalled"),
+        }
+        permitter.set_stalled(stalled);
+    }
+}
+
+/// Tracks when sync last processed a block or had no block to process.
+struct StallWatch {
+    processed: u64,
+    since: Instant,
+}
+
+impl StallWatch {
+    fn new(processed: u64, now: Instant) -> Self {
+        Self {
+            processed,
+            since: now,
+        }
+    }
+
+    /// Returns whether sync is stalled given the block it has processed and the latest block that
+    /// it could have processed by `now`.
+    fn observe(
+        &mut self,
+        processed: u64,
+        processable: u64,
+        now: Instant,
+        deadline: Duration,
+    ) -> bool {
+        if processed != self.processed || processable <= processed {
+            self.processed = processed;
+            self.since = now;
+            return false;
+        }
+        now.duration_since(self.since) >= deadline
+    }
+}
+
+/// Makes the next sync of the permitter's chain start from `index` instead of the last checkpoint.
+pub async fn set_resume_point<M: Middleware + 'static>(
+    store: &impl Store,
+    permitter: &eth::SsssHub<M>,
+    index: EventIndex,
+) -> Result<(), Error<M>> {
+    let head = permitter.head_block().await?;
+    if index.block > head {
+        return Err(Error::ResumePointAhead { index, head });
+    }
+    store.set_resume_point(permitter.chain, index).await?;
+    Ok(())
+}
+
+/// Deletes the chain's verifiers along with its sync progress, so that if the chain is configured
+/// again its sync starts over and restores every policy rather than resuming without them.
+pub async fn decommission_chain(store: &impl Store, chain: ChainId) -> Result<(), store::Error> {
+    store::intent::atomically(
+        store,
+        vec![
+            store::intent::Step::DeleteVerifiersForChain(chain),
+            store::intent::Step::ClearChainState(chain),
+        ],
+    )
+    .await
+}
+
+/// Decodes the permitter events emitted by a transaction and reports what handling each of them
+/// would do, without modifying the store.
+pub async fn reprocess_tx<M: Middleware + 'static>(
+    store: &impl Store,
+    permitter: &eth::SsssHub<M>,
+    ssss_identities: Vec<Identity>,
+    config: &SyncConfig,
+    tx: TxHash,
+) -> Result<Vec<(eth::Event, EventOutcome)>, Error<M>> {
+    let decryptor = MultiIdentityDecryptor::new(ssss_identities, 1);
+    let handler = EventHandler {
+        chain_id: permitter.chain,
+        permitter,
+        store,
+        decryptor: &decryptor,
+        shadow: None,
+        config,
+    };
+    let mut outcomes = Vec::new();
+    for event in permitter.tx_events(tx).await? {
+        let outcome = handler.handle(event.clone(), true).await?;
+        outcomes.push((event, outcome));
+    }
+    Ok(outcomes)
+}
+
+/// Checks that the stored share is the one that this SSSS decrypts from the deal recorded in its
+/// provenance, which is fetched from the chain again.
+pub async fn verify_stored_share<M: Middleware + 'static>(
+    store: &impl Store,
+    permitter: &eth::SsssHub<M>,
+    ssss_identities: Vec<Identity>,
+    id: ShareId,
+) -> Result<VerifyResult, Error<M>> {
+    let Some(stored) = store.get_share(id.clone()).await? else {
+        return Ok(VerifyResult::NotFound);
+    };
+    let Some(provenance) = stored
+        .provenance
+        .filter(|_| id.identity.chain == permitter.chain)
+    else {
+        return Ok(VerifyResult::Unverifiable);
+    };
+    let Some(tx) = provenance.tx else {
+        return Ok(VerifyResult::Unverifiable);
+    };
+    let deal = permitter
+        .tx_events(tx)
+        .await?
+        .into_iter()
+        .filter(|event| event.index == provenance.event)
+        .find_map(|event| match event.kind {
+            eth::EventKind::SharesDealt(deal)
+                if deal.identity == id.identity.id
+                    && deal.secret_name == id.secret_name
+                    && deal.version == id.version =>
+            {
+                Some(deal)
+            }
+            _ => None,
+        });
+    let Some(eth::SharesDealt {
+        scheme:
+            eth::SsScheme::Shamir {
+                pk,
+                nonce,
+                indices,
+                shares,
+                ..
+            },
+        ..
+    }) = deal
+    else {
+        return Ok(VerifyResult::Unverifiable);
+    };
+    let decrypted = MultiIdentityDecryptor::new(ssss_identities, 1)
+        .decrypt(pk, shares_nonce(&nonce), &shares)
+        .await;
+    let matches = decrypted.iter().any(|(_, share_idx, share)| {
+        indices[*share_idx] == stored.index && **share == *stored.share
+    });
+    Ok(match matches {
+        true => VerifyResult::Valid,
+        false => VerifyResult::Mismatch,
+    })
+}
+
+/// Returns whether a failed store operation might succeed if retried. Records that cannot be
+/// decoded and shares that conflict with stored ones stay that way.
+fn is_transient(e: &crate::store::Error) -> bool {
+    !(e.is::<crate::store::DeserializeError>() || e.is::<crate::store::ShareIndexConflict>())
+}
+
+/// Dealers encrypt shares under the first 12 bytes of the deal's nonce.
+fn shares_nonce(nonce: &H256) -> aes_gcm_siv::Nonce {
+    *aes_gcm_siv::Nonce::from_slice(&nonce[..12])
+}
+
+/// The outcome of [`verify_stored_share`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum VerifyResult {
+    /// The stored share is what its deal decrypts to.
+    Valid,
+    /// The deal has no share for this SSSS at the stored index, or it decrypts to another share.
+    Mismatch,
+    /// No such share is stored.
+    NotFound,
+    /// The share has no record of the transaction that dealt it, or the deal is no longer in it.
+    Unverifiable,
+}
+
+/// What handling an event did, or would do if it were not a dry run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EventOutcome {
+    /// The identity's policy was updated.
+    PolicySet(IdentityId),
+    /// The policy could not be decoded, so the identity's policy was marked as broken.
+    PolicyBroken(IdentityId),
+    /// The policy could not be decoded and was ignored.
+    PolicySkipped(IdentityId),
+    /// One of the dealt shares belonged to this SSSS and was stored.
+    ShareStored {
+        identity: IdentityId,
+        version: ShareVersion,
+        index: ShareIndex,
+    },
+    /// One of the dealt shares belonged to this SSSS, but the store did not accept it.
+    ShareRejected {
+        identity: IdentityId,
+        version: ShareVersion,
+        index: ShareIndex,
+    },
+    /// One of the dealt shares belonged to this SSSS, but it had the wrong length or identifier
+    /// and was skipped.
+    ShareMalformed {
+        identity: IdentityId,
+        version: ShareVersion,
+        index: ShareIndex,
+        len: usize,
+    },
+    /// None of the dealt shares belonged to this SSSS.
+    ShareNotMine(IdentityId),
+    BlockProcessed {
+        block: u64,
+        hash: H256,
+    },
+}
+
+pub(crate) struct EventHandler<'a, M, S> {
+    pub(crate) chain_id: ChainId,
+    pub(crate) permitter: &'a eth::SsssHub<M>,
+    pub(crate) store: &'a S,
+    pub(crate) decryptor: &'a MultiIdentityDecryptor,
+    pub(crate) shadow: Option<&'a MultiIdentityDecryptor>,
+    pub(crate) config: &'a SyncConfig,
+}
+
+impl<M: Middleware + 'static, S: Store> EventHandler<'_, M, S> {
+    #[tracing::instrument(
+        name = "handle_event",
+        skip_all,
+        fields(
+            chain.id = self.chain_id,
+            permitter.address = ?self.permitter.address,
+            event.kind = event.kind.name(),
+            event.block = event.index.block,
+        )
+    )]
+    pub(crate) async fn handle(
+        &self,
+        event: eth::Event,
+        dry_run: bool,
+    ) -> Result<EventOutcome, store::Error> {
+        let kind = event.kind.name();
+        let start = Instant::now();
+        let outcome = match event.kind {
+            eth::EventKind::PolicyChange(policy_change) => {
+                self.handle_policy_change(policy_change, event.index, dry_run)
+                    .await?
+            }
+            eth::EventKind::ProcessedBlock { block_hash } => EventOutcome::BlockProcessed {
+                block: event.index.block,
+                hash: block_hash,
+            },
+            eth::EventKind::SharesDealt(shares_dealt) => {
+                self.handle_shares_dealt(shares_dealt, event.index, event.tx, dry_run)
+                    .await
+            }
+        };
+        let duration = start.elapsed();
+        trace!(duration_ms = duration.as_millis() as u64, "handled event");
+        metrics::observe_event_handler_duration(self.chain_id, kind, duration);
+        Ok(outcome)
+    }
+
+    async fn handle_policy_change(
+        &self,
+        eth::PolicyChange {
+            identity,
+            config: config_br,
+        }: eth::PolicyChange,
+        index: EventIndex,
+        dry_run: bool,
+    ) -> Result<EventOutcome, store::Error> {
+        let (mut config, decoded) = match decompress_policy(&config_br) {
+            Ok(config) => {
+                let decoded = PolicyPreamble::decode(&config)
+                    .map_err(|e| warn!(identity = ?identity, "failed to decode config: {e}"))
+                    .is_ok();
+                (config, decoded)
+            }
+            Err(e) => {
+                warn!(identity = ?identity, "failed to decompress config: {e}");
+                (Vec::new(), false)
+            }
+        };
+        let outcome = if decoded {
+            EventOutcome::PolicySet(identity)
+        } else {
+            match self.config.policy_fail_mode {
+                PolicyFailMode::SkipAndWarn => return Ok(EventOutcome::PolicySkipped(identity)),
+                PolicyFailMode::FailClosed => {
+                    warn!(identity = ?identity, "marking policy as broken");
+                    config = BROKEN_POLICY.to_vec();
+                    EventOutcome::PolicyBroken(identity)
+                }
+            }
+        };
+        if dry_run {
+            return Ok(outcome);
+        }
+        // Skipping the change would leave the previous policy in force, so the event is retried
+        // when sync restarts instead.
+        retry_while(
+            "update_verifier",
+            || {
+                self.store.update_verifier(
+                    PermitterLocator::new(self.chain_id, self.permitter.address),
+                    identity,
+                    config.clone(),
+                    index,
+                )
+            },
+            is_transient,
+        )
+        .await
+        .map_err(|e| e.context(format!("failed to set the policy of {identity:?}")))?;
+        trace!("set updated policy");
+        Ok(outcome)
+    }
+
+    async fn handle_shares_dealt(
+        &self,
+        eth::SharesDealt {
+            identity: identity_id,
+            secret_name,
+            version,
+            scheme:
+                eth::SsScheme::Shamir {
+                    pk,
+                    nonce,
+                    indices,
+                    indexed,
+                    shares,
+                },
+            dealer,
+        }: eth::SharesDealt,
+        event_index: EventIndex,
+        tx: Option<TxHash>,
+        dry_run: bool,
+    ) -> EventOutcome {
+        let shares_nonce = shares_nonce(&nonce);
+        let (mut decrypted, shadow_decrypted) =
+            futures_util::join!(self.decryptor.decrypt(pk, shares_nonce, &shares), async {
+                match self.shadow {
+                    Some(shadow) => shadow.decrypt(pk, shares_nonce, &shares).await,
+                    None => Vec::new(),
+                }
+            });
+        if !shadow_decrypted.is_empty() {
+            metrics::SHADOW_SHARE_MATCHES.inc();
+            info!(
+                identity = ?identity_id,
+                version = version,
+                active_matched = !decrypted.is_empty(),
+                "shadow identity can decrypt a dealt share"
+            );
+        }
+        if decrypted.is_empty() {
+            // TODO: track all secret versions (not just own) to prevent rollbacks on new shareholder set
+            if !dry_run {
+                self.permitter
+                    .track_share(identity_id, secret_name, version, None);
+            }
+            return EventOutcome::ShareNotMine(identity_id);
+        }
+        // The store holds one share per version, so keep the one decrypted by the highest
+        // priority identity.
+        let (identity_idx, share_idx, share) = decrypted.swap_remove(0);
+        if !decrypted.is_empty() {
+            warn!(
+                identity = ?identity_id,
+                version = version,
+                "{} additional shares were decryptable and will not be stored",
+                decrypted.len()
+            );
+        }
+        trace!(
+            identity_idx = identity_idx,
+            share_idx = share_idx,
+            dealer = ?dealer,
+            "decrypted share"
+        );
+        let index = indices[share_idx];
+        let expected_len = expected_share_len(shares.len());
+        if share.is_empty() || expected_len.is_some_and(|len| share.len() != len) {
+            warn!(
+                identity = ?identity_id,
+                version = version,
+                len = share.len(),
+                expected_len = expected_len,
+                "decrypted share has the wrong length"
+            );
+            if share.is_empty()
+                || self.config.malformed_share_mode == MalformedShareMode::SkipAndWarn
+            {
+                if !dry_run {
+                    self.permitter
+                        .track_share(identity_id, secret_name, version, None);
+                }
+                return EventOutcome::ShareMalformed {
                     identity: identity_id,
-                    secret_name,
                     version,
-                    scheme: eth::SsScheme::Shamir { pk, nonce, shares },
-                }) => {
-                    let cipher =
-                        ssss_identity.derive_shared_cipher(pk, identity::DEAL_SHARES_DOMAIN_SEP);
-                    let shares_nonce = {
-                        let mut n = [0u8; 12];
-                        n.copy_from_slice(&nonce[0..12]);
-                        n.into()
+                    index,
+                    len: share.len(),
+                };
+            }
+        }
+        // A whole secret has no identifier, but a split one is recombined by the identifier that
+        // begins each share, so storing it under another index would corrupt reconstruction.
+        if indexed && expected_len.is_some() && u64::from(share[0]) != index.0 {
+            warn!(
+                identity = ?identity_id,
+                version = version,
+                index = index.0,
+                identifier = share[0],
+                "decrypted share has a different identifier than its index"
+            );
+            if !dry_run {
+                self.permitter
+                    .track_share(identity_id, secret_name, version, None);
+            }
+            return EventOutcome::ShareMalformed {
+                identity: identity_id,
+                version,
+                index,
+                len: share.len(),
+            };
+        }
+        if dry_run {
+            return EventOutcome::ShareStored {
+                identity: identity_id,
+                version,
+                index,
+            };
+        }
+        let provenance = ShareProvenance {
+            event: event_index,
+            tx,
+            dealer_pk: pk.to_bytes(),
+            stored_at: crate::store::now(),
+        };
+        let put_share = retry_while(
+            "put_share",
+            || {
+                let share = share.clone();
+                let secret_name = secret_name.clone();
+                let provenance = provenance.clone();
+                async move {
+                    let identity = IdentityLocator {
+                        chain: self.chain_id,
+                        registry: self.permitter.registry().await?,
+                        id: identity_id,
                     };
-                    let Some((index, share)) =
-                        shares.into_iter().enumerate().find_map(|(i, enc_share)| {
-                            let mut share = enc_share.to_vec();
-                            cipher
-                                .decrypt_in_place(&shares_nonce, &[], &mut share)
-                                .ok()?;
-                            Some((i as u64, zeroize::Zeroizing::new(share)))
-                        })
-                    else {
-                        return; // TODO: track all secret versions (not just own) to prevent rollbacks on new shareholder set
+                    let share_id = ShareId {
+                        secret_name,
+                        identity,
+                        version,
                     };
-                    retry(|| {
-                        let share = share.clone();
-                        let secret_name = secret_name.clone();
-                        async move {
-                            let identity = IdentityLocator {
-                                chain: chain_id,
-                                registry: permitter.registry().await?,
-                                id: identity_id,
-                            };
-                            let put_share = store
-                                .put_share(
-                                    ShareId {
-                                        secret_name,
-                                        identity,
-                                        version,
-                                    },
-                                    SecretShare { index, share },
-                                )
-                                .await?;
-                            if put_share {
-                                trace!(identity=?identity, version=version, "put share");
-                            } else {
-                                warn!(identity=?identity, version=version, "share not put");
-                            }
-                            Ok::<_, anyhow::Error>(())
-                        }
-                    })
-                    .await;
+                    let put_share = self
+                        .store
+                        .put_share(
+                            share_id.clone(),
+                            SecretShare {
+                                index,
+                                share,
+                                provenance: Some(provenance),
+                            },
+                        )
+                        .await?;
+                    if put_share {
+                        trace!(identity=?identity, version=version, "put share");
+                        return Ok::<_, anyhow::Error>((true, Some(index)));
+                    }
+                    warn!(identity=?identity, version=version, "share not put");
+                    // A reprocessed deal is refused because its share is already stored, which
+                    // still counts as holding it.
+                    let held = self.store.get_share(share_id).await?;
+                    Ok((false, held.map(|share| share.index)))
                 }
+            },
+            is_transient,
+        )
+        .await
+        .unwrap_or_else(|e| {
+            error!(identity = ?identity_id, version, "failed to put share: {e}");
+            (false, None)
+        });
+        let (put_share, held_index) = put_share;
+        self.permitter
+            .track_share(identity_id, secret_name, version, held_index);
+        if put_share {
+            EventOutcome::ShareStored {
+                identity: identity_id,
+                version,
+                index,
             }
-        })
-        .await;
+        } else {
+            EventOutcome::ShareRejected {
+                identity: identity_id,
+                version,
+                index,
+            }
+        }
+    }
+}
 
-    state_updater_task.abort();
-    Ok(())
+pub type IdentityIdx = usize;
+pub type ShareIdx = usize;
+
+/// Attempts to decrypt dealt shares using each of the identities held by this SSSS.
+#[derive(Clone)]
+pub struct MultiIdentityDecryptor {
+    identities: Arc<[Identity]>,
+    permits: Arc<Semaphore>,
+}
+
+impl MultiIdentityDecryptor {
+    /// Creates a decryptor that runs at most `max_parallelism` decryption attempts at once.
+    pub fn new(identities: Vec<Identity>, max_parallelism: usize) -> Self {
+        Self {
+            identities: identities.into(),
+            permits: Arc::new(Semaphore::new(max_parallelism.max(1))),
+        }
+    }
+
+    /// Returns every share decryptable by any identity, ordered by identity then share index.
+    pub async fn decrypt(
+        &self,
+        pk: identity::PeerKey,
+        nonce: aes_gcm_siv::Nonce,
+        shares: &[Bytes],
+    ) -> Vec<(IdentityIdx, ShareIdx, zeroize::Zeroizing<Vec<u8>>)> {
+        let shares: Arc<[Bytes]> = shares.into();
+        let attempts = self.identities.iter().enumerate().map(|(identity_idx, _)| {
+            // Shared rather than copied into the task, so that the key is not duplicated.
+            let identities = self.identities.clone();
+            let shares = shares.clone();
+            let permits = self.permits.clone();
+            async move {
+                let _permit = permits.acquire_owned().await.ok()?;
+                tokio::task::spawn_blocking(move || {
+                    let cipher = match identities[identity_idx]
+                        .try_shared_cipher(&pk, identity::DEAL_SHARES_DOMAIN_SEP)
+                    {
+                        Ok(cipher) => cipher,
+                        // Identities of another crypto mode cannot be the recipient.
+                        Err(identity::Error::ModeMismatch(_)) => return None,
+                        Err(e) => {
+                            warn!("not decrypting shares dealt to {pk:?}: {e}");
+                            return None;
+                        }
+                    };
+                    let decrypted = shares
+                        .iter()
+                        .enumerate()
+                        .filter_map(|(share_idx, enc_share)| {
+                            let mut share = zeroize::Zeroizing::new(enc_share.to_vec());
+                            cipher.decrypt_in_place(&nonce, &[], &mut *share).ok()?;
+                            Some((identity_idx, share_idx, share))
+                        })
+                        .collect::<Vec<_>>();
+                    Some(decrypted)
+                })
+                .await
+                .ok()
+                .flatten()
+            }
+        });
+        futures_util::future::join_all(attempts)
+            .await
+            .into_iter()
+            .flatten()
+            .flatten()
+            .collect()
+    }
 }
 
 #[derive(Debug, thiserror::Error)]
-enum Error<M: Middleware> {
+pub enum Error<M: Middleware> {
     #[error(transparent)]
     Store(#[from] crate::store::Error),
     #[error(transparent)]
     Eth(#[from] eth::Error<M>),
+    #[error("event stream ended while tailing after block {processed}")]
+    EventStreamEnded { processed: u64 },
+    #[error("resume point {index:?} is ahead of the chain head at block {head}")]
+    ResumePointAhead { index: EventIndex, head: u64 },
+    #[error("event at {next:?} was delivered after one at {previous:?}")]
+    EventOutOfOrder {
+        previous: EventIndex,
+        next: EventIndex,
+    },
+    #[error("sync state updater panicked")]
+    StateUpdaterPanic,
+    #[error("the observer of guaranteed delivery closed before the event at {index:?}")]
+    ObserverClosed { index: EventIndex },
+    #[error("failed to start runtime: {0}")]
+    Runtime(#[from] std::io::Error),
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::Address;
+    use opentelemetry::{trace::TracerProvider as _, Key};
+    use tracing_subscriber::layer::SubscriberExt as _;
+
+    use super::*;
+    use crate::{
+        store::{self, memory::MemoryStore},
+        testing::{deal, mocked_provider, test_handler, TestHandler},
+    };
+
+    #[test]
+    fn decompress_policy_is_capped() {
+        let compress = |config: &[u8]| {
+            let mut config_br = Vec::new();
+            brotli::BrotliCompress(&mut &*config, &mut config_br, &Default::default()).unwrap();
+            config_br
+        };
+        let config = vec![7u8; MAX_POLICY_SIZE];
+        assert_eq!(decompress_policy(&compress(&config)).unwrap(), config);
+
+        let bomb = compress(&vec![7u8; MAX_POLICY_SIZE + 1]);
+        assert!(bomb.len() < 100);
+        assert!(decompress_policy(&bomb).is_err());
+        assert!(decompress_policy(b"not brotli").is_err());
+    }
+
+    #[tokio::test]
+    async fn decrypt_shares_for_multiple_identities() {
+        let identity_a = Identity::ephemeral();
+        let identity_b = Identity::ephemeral();
+        let dealer = Identity::ephemeral();
+        let nonce = aes_gcm_siv::Nonce::default();
+        let shares = vec![
+            deal(&dealer, &identity_a, &nonce, b"share0"),
+            deal(&dealer, &identity_b, &nonce, b"share1"),
+            deal(&dealer, &identity_a, &nonce, b"share2"),
+        ];
+
+        let decryptor = MultiIdentityDecryptor::new(vec![identity_a, identity_b], 1);
+        let decrypted: Vec<_> = decryptor
+            .decrypt(dealer.peer_key(), nonce, &shares)
+            .await
+            .into_iter()
+            .map(|(identity_idx, share_idx, share)| (identity_idx, share_idx, share.to_vec()))
+            .collect();
+
+        assert_eq!(
+            decrypted,
+            vec![
+                (0, 0, b"share0".to_vec()),
+                (0, 2, b"share2".to_vec()),
+                (1, 1, b"share1".to_vec()),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn decrypt_x25519_shares() {
+        let x25519_secret = || x25519_dalek::StaticSecret::random_from_rng(rand::thread_rng());
+        let p384_identity = Identity::ephemeral();
+        let x25519_identity = Identity::from_x25519(x25519_secret());
+        let dealer = Identity::from_x25519(x25519_secret());
+        let nonce = aes_gcm_siv::Nonce::default();
+        let shares = vec![
+            Bytes::from_static(b"not for us"),
+            deal(&dealer, &x25519_identity, &nonce, b"share1"),
+        ];
+
+        let decryptor = MultiIdentityDecryptor::new(vec![p384_identity, x25519_identity], 1);
+        let decrypted: Vec<_> = decryptor
+            .decrypt(dealer.peer_key(), nonce, &shares)
+            .await
+            .into_iter()
+            .map(|(identity_idx, share_idx, share)| (identity_idx, share_idx, share.to_vec()))
+            .collect();
+
+        assert_eq!(decrypted, vec![(1, 1, b"share1".to_vec())]);
+    }
+
+    #[tokio::test]
+    async fn shadow_identity_only_observes() {
+        let active = Identity::ephemeral();
+        let shadow = Identity::ephemeral();
+        let fixture = test_handler(SyncConfig::default()).with_identities(vec![active.clone()]);
+        let fixture = TestHandler {
+            shadow: Some(MultiIdentityDecryptor::new(vec![shadow.clone()], 1)),
+            ..fixture
+        };
+        let handler = fixture.handler();
+        let identity = IdentityId(ethers::types::H256::random());
+        let deal_event = |shares| fixture.deal_event(identity, 1, shares);
+
+        let matches_before = metrics::SHADOW_SHARE_MATCHES.get();
+        let outcome = handler
+            .handle(
+                deal_event(vec![
+                    fixture.deal(&shadow, &[0; SHAMIR_SHARE_LEN]),
+                    fixture.deal(&active, &[1; SHAMIR_SHARE_LEN]),
+                ]),
+                true,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome,
+            EventOutcome::ShareStored {
+                identity,
+                version: 1,
+                index: ShareIndex(1)
+            }
+        );
+        let outcome = handler
+            .handle(deal_event(vec![fixture.deal(&shadow, b"share0")]), true)
+            .await
+            .unwrap();
+        assert_eq!(outcome, EventOutcome::ShareNotMine(identity));
+        assert_eq!(metrics::SHADOW_SHARE_MATCHES.get() - matches_before, 2);
+
+        // Outside a dry run, a share only the shadow identity can decrypt is still not stored.
+        let outcome = handler
+            .handle(
+                deal_event(vec![fixture.deal(&shadow, &[0; SHAMIR_SHARE_LEN])]),
+                false,
+            )
+            .await
+            .unwrap();
+        assert_eq!(outcome, EventOutcome::ShareNotMine(identity));
+        assert_eq!(metrics::SHADOW_SHARE_MATCHES.get() - matches_before, 3);
+        assert_eq!(fixture.store.statistics().await.unwrap().total_shares, 0);
+    }
+
+    #[tokio::test]
+    async fn share_index_comes_from_deal() {
+        let me = Identity::ephemeral();
+        let other = Identity::ephemeral();
+        let fixture = test_handler(SyncConfig::default()).with_identities(vec![me.clone()]);
+        let handler = fixture.handler();
+        let identity = IdentityId(ethers::types::H256::random());
+        let deal_event = |my_share: &[u8]| eth::Event {
+            kind: eth::EventKind::SharesDealt(eth::SharesDealt {
+                identity,
+                secret_name: "omni".into(),
+                version: 1,
+                scheme: eth::SsScheme::Shamir {
+                    pk: fixture.dealer.peer_key(),
+                    nonce: fixture.nonce,
+                    indices: vec![ShareIndex(9), ShareIndex(4)],
+                    indexed: true,
+                    shares: vec![
+                        fixture.deal(&other, &[9; SHAMIR_SHARE_LEN]),
+                        fixture.deal(&me, my_share),
+                    ],
+                },
+                dealer: Address::repeat_byte(3),
+            }),
+            index: EventIndex {
+                block: 1,
+                log_index: 0,
+            },
+            tx: None,
+        };
+
+        let outcome = handler
+            .handle(deal_event(&[4; SHAMIR_SHARE_LEN]), true)
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome,
+            EventOutcome::ShareStored {
+                identity,
+                version: 1,
+                index: ShareIndex(4)
+            }
+        );
+        // The share's identifier must agree with the index that the dealer gave it.
+        let outcome = handler
+            .handle(deal_event(&[5; SHAMIR_SHARE_LEN]), true)
+            .await
+            .unwrap();
+        assert_eq!(
+            outcome,
+            EventOutcome::ShareMalformed {
+                identity,
+                version: 1,
+                index: ShareIndex(4),
+                len: SHAMIR_SHARE_LEN,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn store_share_of_first_identity_that_decrypts() {
+        let (me_a, me_b, other) = (
+            Identity::ephemeral(),
+            Identity::ephemeral(),
+            Identity::ephemeral(),
+        );
+        let fixture =
+            test_handler(SyncConfig::default()).with_identities(vec![me_a.clone(), me_b.clone()]);
+        let handler = fixture.handler();
+        let identity = IdentityId(ethers::types::H256::random());
+        let deal_event = |shares| fixture.deal_event(identity, 1, shares);
+        let stored = |index| EventOutcome::ShareStored {
+            identity,
+            version: 1,
+            index: ShareIndex(index),
+        };
+
+        // A share for any held identity is stored, not only for the first.
+        let outcome = handler
+            .handle(
+                deal_event(vec![
+                    fixture.deal(&other, &[0; SHAMIR_SHARE_LEN]),
+                    fixture.deal(&me_b, &[1; SHAMIR_SHARE_LEN]),
+                ]),
+                true,
+            )
+            .await
+            .unwrap();
+        assert_eq!(outcome, stored(1));
+
+        // The store holds one share per version, so that of the earlier identity wins.
+        let outcome = handler
+            .handle(
+                deal_event(vec![
+                    fixture.deal(&me_b, &[0; SHAMIR_SHARE_LEN]),
+                    fixture.deal(&other, &[1; SHAMIR_SHARE_LEN]),
+                    fixture.deal(&me_a, &[2; SHAMIR_SHARE_LEN]),
+                ]),
+                true,
+            )
+            .await
+            .unwrap();
+        assert_eq!(outcome, stored(2));
+    }
+
+    #[tokio::test]
+    async fn track_seen_deals() {
+        use ethers::abi::AbiEncode as _;
+
+        let me = Identity::ephemeral();
+        let other = Identity::ephemeral();
+        let fixture = test_handler(SyncConfig::default()).with_identities(vec![me.clone()]);
+        // The registry is asked for each time that a share of mine is handled.
+        for _ in 0..2 {
+            fixture
+                .mock
+                .push::<Bytes, Bytes>(Address::repeat_byte(2).encode().into())
+                .unwrap();
+        }
+        let handler = fixture.handler();
+        let identity = IdentityId(ethers::types::H256::random());
+
+        handler
+            .handle(
+                fixture.deal_event(identity, 2, vec![fixture.deal(&other, b"share0")]),
+                false,
+            )
+            .await
+            .unwrap();
+        // Reprocessing the deal is refused by the store, but its share is still held.
+        for _ in 0..2 {
+            handler
+                .handle(
+                    fixture.deal_event(
+                        identity,
+                        1,
+                        vec![
+                            fixture.deal(&other, &[0; SHAMIR_SHARE_LEN]),
+                            fixture.deal(&me, &[1; SHAMIR_SHARE_LEN]),
+                        ],
+                    ),
+                    false,
+                )
+                .await
+                .unwrap();
+        }
+
+        let tracked: Vec<_> = fixture
+            .permitter
+            .tracked_shares()
+            .into_iter()
+            .map(|s| (s.identity, s.version, s.held, s.index))
+            .collect();
+        assert_eq!(
+            tracked,
+            vec![
+                (identity, 1, true, Some(ShareIndex(1))),
+                (identity, 2, false, None)
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn skip_malformed_shares() {
+        let me = Identity::ephemeral();
+        let other = Identity::ephemeral();
+        let fixture = test_handler(SyncConfig::default()).with_identities(vec![me.clone()]);
+        let store_anyway = SyncConfig {
+            malformed_share_mode: MalformedShareMode::StoreAndWarn,
+            ..Default::default()
+        };
+        let identity = IdentityId(ethers::types::H256::random());
+        let deal_event = |shares| fixture.deal_event(identity, 1, shares);
+        let split_deal = |share: &[u8]| {
+            deal_event(vec![
+                fixture.deal(&other, &[0; SHAMIR_SHARE_LEN]),
+                fixture.deal(&me, share),
+            ])
+        };
+        let malformed = |len| EventOutcome::ShareMalformed {
+            identity,
+            version: 1,
+            index: ShareIndex(1),
+            len,
+        };
+        let stored = |index| EventOutcome::ShareStored {
+            identity,
+            version: 1,
+            index: ShareIndex(index),
+        };
+
+        let handler_default = fixture.handler();
+        assert_eq!(
+            handler_default
+                .handle(split_deal(b"share"), true)
+                .await
+                .unwrap(),
+            malformed(5)
+        );
+        assert_eq!(
+            handler_default.handle(split_deal(b""), true).await.unwrap(),
+            malformed(0)
+        );
+        assert_eq!(
+            handler_default
+                .handle(split_deal(&[1; SHAMIR_SHARE_LEN]), true)
+                .await
+                .unwrap(),
+            stored(1)
+        );
+        // A secret dealt to one SSSS is whole, so it may have any nonzero length.
+        assert_eq!(
+            handler_default
+                .handle(deal_event(vec![fixture.deal(&me, b"secret")]), true)
+                .await
+                .unwrap(),
+            stored(0)
+        );
+        assert_eq!(
+            handler_default
+                .handle(deal_event(vec![fixture.deal(&me, b"")]), true)
+                .await
+                .unwrap(),
+            EventOutcome::ShareMalformed {
+                identity,
+                version: 1,
+                index: ShareIndex(0),
+                len: 0,
+            }
+        );
+        // Outside a dry run, a skipped share is kept out of the store.
+        assert_eq!(
+            handler_default
+                .handle(split_deal(b"share"), false)
+                .await
+                .unwrap(),
+            malformed(5)
+        );
+        assert_eq!(fixture.store.statistics().await.unwrap().total_shares, 0);
+
+        let handler_lenient = EventHandler {
+            config: &store_anyway,
+            ..fixture.handler()
+        };
+        assert_eq!(
+            handler_lenient
+                .handle(split_deal(b"share"), true)
+                .await
+                .unwrap(),
+            stored(1)
+        );
+        assert_eq!(
+            handler_lenient.handle(split_deal(b""), true).await.unwrap(),
+            malformed(0)
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sync_survives_creation_block_errors() {
+        use ethers::{
+            abi::AbiEncode as _,
+            contract::EthEvent as _,
+            providers::{JsonRpcError, MockResponse},
+            types::{Block, Log, Transaction, U256, U64},
+        };
+
+        let (provider, mock) = mocked_provider();
+        let hub_address = Address::repeat_byte(1);
+        let identity = IdentityId(ethers::types::H256::random());
+        let tx = TxHash::random();
+
+        mock.push(Block::<TxHash> {
+            hash: Some(H256::random()),
+            ..Default::default()
+        })
+        .unwrap();
+        let mut input = vec![0u8; 4];
+        input.extend((identity.0, Bytes::new()).encode());
+        mock.push(Transaction {
+            hash: tx,
+            input: input.into(),
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push::<Vec<Log>, _>(vec![Log {
+            address: hub_address,
+            topics: vec![eth::PolicyChangeFilter::signature()],
+            block_number: Some(5.into()),
+            transaction_hash: Some(tx),
+            log_index: Some(0.into()),
+            ..Default::default()
+        }])
+        .unwrap();
+        // The head as seen by the event stream, then when choosing whether to backfill.
+        mock.push(U64::from(5)).unwrap();
+        mock.push(U64::from(5)).unwrap();
+        mock.push::<Bytes, Bytes>(U256::from(5).encode().into())
+            .unwrap();
+        for _ in 0..2 {
+            mock.push_response(MockResponse::Error(JsonRpcError {
+                code: -32000,
+                message: "temporarily unavailable".into(),
+                data: None,
+            }));
+        }
+        // The provider health check.
+        mock.push(U64::from(31337)).unwrap();
+        mock.push(U64::from(5)).unwrap();
+
+        let store = MemoryStore::default();
+        let sync_task = tokio::spawn({
+            let store = store.clone();
+            async move {
+                let permitter = eth::SsssHub::new(31337, hub_address, provider);
+                sync_chain(
+                    31337,
+                    &permitter,
+                    &store,
+                    &MultiIdentityDecryptor::new(vec![], 1),
+                    None,
+                    &SyncConfig {
+                        policy_fail_mode: PolicyFailMode::FailClosed,
+                        ..Default::default()
+                    },
+                    Reporters::default(),
+                )
+                .await
+            }
+        });
+
+        let permitter = PermitterLocator::new(31337, hub_address);
+        for _ in 0..10 {
+            if store
+                .get_verifier(permitter, identity)
+                .await
+                .unwrap()
+                .is_some()
+            {
+                break;
+            }
+            sleep(Duration::from_secs(1)).await;
+        }
+        assert!(!sync_task.is_finished(), "sync exited");
+        assert_eq!(
+            store
+                .get_verifier(permitter, identity)
+                .await
+                .unwrap()
+                .map(|record| record.config),
+            Some(BROKEN_POLICY.to_vec())
+        );
+        sync_task.abort();
+    }
+
+    #[tokio::test]
+    async fn unstorable_policy_fails_handling() {
+        let store = store::tests::HookedStore::new(MemoryStore::default(), |method| async move {
+            match method {
+                // An error that retrying cannot fix.
+                "update_verifier" => Err(store::DeserializeError::new(
+                    store::tests::make_share(IdentityId::random(), 1).0,
+                    "corrupt record",
+                )
+                .into()),
+                _ => Ok(()),
+            }
+        });
+        let fixture = test_handler(SyncConfig {
+            policy_fail_mode: PolicyFailMode::FailClosed,
+            ..Default::default()
+        })
+        .with_store(store);
+        let handler = fixture.handler();
+        let identity = IdentityId::random();
+        let event = eth::Event {
+            kind: eth::EventKind::PolicyChange(eth::PolicyChange {
+                identity,
+                config: vec![],
+            }),
+            index: EventIndex {
+                block: 1,
+                log_index: 0,
+            },
+            tx: None,
+        };
+
+        // Neither the broken marker nor the previous policy may silently stay in force.
+        let err = handler.handle(event, false).await.unwrap_err();
+        assert!(err.is::<store::DeserializeError>(), "{err:#}");
+        assert_eq!(
+            fixture
+                .store
+                .inner
+                .get_verifier(
+                    PermitterLocator::new(31337, Address::repeat_byte(1)),
+                    identity
+                )
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn sync_waits_for_healthy_provider() {
+        use ethers::{
+            abi::AbiEncode as _,
+            providers::{JsonRpcError, MockResponse},
+            types::{Block, Log, U256, U64},
+        };
+
+        let (provider, mock) = mocked_provider();
+        mock.push(Block::<TxHash> {
+            hash: Some(H256::random()),
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        mock.push(U64::from(5)).unwrap();
+        mock.push(U64::from(5)).unwrap();
+        mock.push::<Bytes, Bytes>(U256::from(5).encode().into())
+            .unwrap();
+        mock.push(U64::from(31337)).unwrap();
+        mock.push(U64::from(5)).unwrap();
+        for _ in 0..3 {
+            mock.push_response(MockResponse::Error(JsonRpcError {
+                code: -32000,
+                message: "connection refused".into(),
+                data: None,
+            }));
+        }
+
+        let permitter = eth::SsssHub::new(31337, Address::repeat_byte(1), provider);
+        let (store, decryptor, config) = (
+            MemoryStore::default(),
+            MultiIdentityDecryptor::new(vec![], 1),
+            SyncConfig::default(),
+        );
+        let start = Instant::now();
+        let sync = sync_chain(
+            31337,
+            &permitter,
+            &store,
+            &decryptor,
+            None,
+            &config,
+            Reporters::default(),
+        );
+        let synced = async {
+            while permitter.processed_block() < 5 {
+                sleep(Duration::from_millis(100)).await;
+            }
+        };
+        tokio::select! {
+            res = sync => panic!("sync exited: {res:?}"),
+            _ = synced => {}
+        }
+        // Three failed checks are followed by waits of 1, 2, and 4 seconds.
+        assert!(start.elapsed() >= Duration::from_secs(7));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn resume_as_processed_up_to_resume_point() {
+        use ethers::types::U64;
+
+        let (provider, mock) = mocked_provider();
+        mock.push(U64::from(31337)).unwrap();
+        mock.push(U64::from(100)).unwrap();
+
+        let permitter = eth::SsssHub::new(31337, Address::repeat_byte(1), provider);
+        let store = MemoryStore::default();
+        store
+            .set_resume_point(
+                31337,
+                EventIndex {
+                    block: 50,
+                    log_index: 0,
+                },
+            )
+            .await
+            .unwrap();
+        let (decryptor, config) = (
+            MultiIdentityDecryptor::new(vec![], 1),
+            SyncConfig::default(),
+        );
+        let sync = sync_chain(
+            31337,
+            &permitter,
+            &store,
+            &decryptor,
+            None,
+            &config,
+            Reporters::default(),
+        );
+        // The provider has nothing more to give, so sync gets no further than starting.
+        let _ = tokio::time::timeout(Duration::from_secs(60), sync).await;
+        assert_eq!(permitter.processed_block(), 49);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn save_processed_block_hash() {
+        use ethers::{
+            abi::AbiEncode as _,
+            types::{Block, Log, U256, U64},
+        };
+
+        let (provider, mock) = mocked_provider();
+        let block_hash = H256::random();
+        mock.push(Block::<TxHash> {
+            hash: Some(block_hash),
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        mock.push(U64::from(5)).unwrap();
+        mock.push(U64::from(5)).unwrap();
+        mock.push::<Bytes, Bytes>(U256::from(5).encode().into())
+            .unwrap();
+        mock.push(U64::from(31337)).unwrap();
+        mock.push(U64::from(5)).unwrap();
+
+        let permitter = eth::SsssHub::new(31337, Address::repeat_byte(1), provider);
+        let (store, decryptor, config) = (
+            MemoryStore::default(),
+            MultiIdentityDecryptor::new(vec![], 1),
+            SyncConfig::default(),
+        );
+        let sync = sync_chain(
+            31337,
+            &permitter,
+            &store,
+            &decryptor,
+            None,
+            &config,
+            Reporters::default(),
+        );
+        let saved = async {
+            while permitter.processed_block() < 5 {
+                sleep(Duration::from_millis(100)).await;
+            }
+            // The first state update is due one interval after sync starts.
+            sleep(STATE_UPDATE_INTERVAL + Duration::from_secs(1)).await;
+        };
+        tokio::select! {
+            res = sync => panic!("sync exited: {res:?}"),
+            _ = saved => {}
+        }
+        assert_eq!(
+            store.get_chain_state(31337).await.unwrap(),
+            Some(ChainState {
+                block: 5,
+                log_index: 0,
+                block_hash: Some(block_hash),
+            })
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn state_updater_panic_ends_sync() {
+        use ethers::{
+            abi::AbiEncode as _,
+            types::{Block, Log, U256, U64},
+        };
+
+        /// Panics when the state updater first saves the chain state through it.
+        struct PanickingJournal;
+
+        impl std::io::Write for PanickingJournal {
+            fn write(&mut self, _buf: &[u8]) -> std::io::Result<usize> {
+                panic!("journal is full");
+            }
+
+            fn flush(&mut self) -> std::io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let (provider, mock) = mocked_provider();
+        mock.push(Block::<TxHash> {
+            hash: Some(H256::random()),
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        mock.push(U64::from(5)).unwrap();
+        mock.push(U64::from(5)).unwrap();
+        mock.push::<Bytes, Bytes>(U256::from(5).encode().into())
+            .unwrap();
+        mock.push(U64::from(31337)).unwrap();
+        mock.push(U64::from(5)).unwrap();
+
+        let permitter = eth::SsssHub::new(31337, Address::repeat_byte(1), provider);
+        let (store, decryptor, config) = (
+            store::recording::RecordingStore::new(MemoryStore::default(), PanickingJournal),
+            MultiIdentityDecryptor::new(vec![], 1),
+            SyncConfig::default(),
+        );
+        let capture = crate::testing::LogCapture::start();
+        let res = tokio::time::timeout(
+            STATE_UPDATE_INTERVAL * 2,
+            sync_chain(
+                31337,
+                &permitter,
+                &store,
+                &decryptor,
+                None,
+                &config,
+                Reporters::default(),
+            ),
+        )
+        .await
+        .expect("sync outlived its state updater");
+        assert!(matches!(res, Err(Error::StateUpdaterPanic)), "{res:?}");
+        assert!(capture.logs().contains("panicked: journal is full"));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn report_sync_status() {
+        use ethers::{
+            abi::AbiEncode as _,
+            types::{Block, Log, U256, U64},
+        };
+
+        let (provider, mock) = mocked_provider();
+        for _ in 1..=100 {
+            mock.push(Block::<TxHash> {
+                hash: Some(H256::random()),
+                ..Default::default()
+            })
+            .unwrap();
+            mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        }
+        mock.push(U64::from(100)).unwrap();
+        mock.push(U64::from(100)).unwrap();
+        mock.push::<Bytes, Bytes>(U256::from(1).encode().into())
+            .unwrap();
+        mock.push(U64::from(31337)).unwrap();
+        mock.push(U64::from(100)).unwrap();
+
+        let permitter = eth::SsssHub::new(31337, Address::repeat_byte(1), provider);
+        let (store, decryptor, config) = (
+            MemoryStore::default(),
+            MultiIdentityDecryptor::new(vec![], 1),
+            SyncConfig {
+                tail_confirmations: 2,
+                ..Default::default()
+            },
+        );
+        let (tx, mut rx) = mpsc::channel(128);
+        let sync = sync_chain(
+            31337,
+            &permitter,
+            &store,
+            &decryptor,
+            None,
+            &config,
+            Reporters {
+                status: Some(&tx),
+                ..Default::default()
+            },
+        );
+        let caught_up = async {
+            let mut statuses = vec![];
+            while let Some(status) = rx.recv().await {
+                let done = matches!(status, SyncStatus::CaughtUp { .. });
+                statuses.push(status);
+                if done {
+                    break;
+                }
+            }
+            statuses
+        };
+        let statuses = tokio::select! {
+            res = sync => panic!("sync exited: {res:?}"),
+            statuses = caught_up => statuses,
+        };
+        // Blocks 1 through 97 are behind, and block 98 is the first within the confirmations.
+        let expected: Vec<_> = (1..98)
+            .map(|block| SyncStatus::InProgress {
+                chain_id: 31337,
+                blocks_behind: 98 - block,
+            })
+            .chain([SyncStatus::CaughtUp { chain_id: 31337 }])
+            .collect();
+        assert_eq!(statuses, expected);
+    }
+
+    /// A provider whose head advances one block a second from 100 and whose logs take a while to
+    /// fetch, so that it can tell how many were in flight at once and how far behind head each was.
+    #[derive(Debug, Default)]
+    struct MovingChain {
+        start: std::sync::OnceLock<tokio::time::Instant>,
+        in_flight: std::sync::atomic::AtomicUsize,
+        /// The block, the head, and the number of logs requests in flight, for each logs request.
+        logs_requests: std::sync::Mutex<Vec<(u64, u64, usize)>>,
+    }
+
+    impl MovingChain {
+        fn head(&self) -> u64 {
+            100 + self
+                .start
+                .get_or_init(tokio::time::Instant::now)
+                .elapsed()
+                .as_secs()
+        }
+    }
+
+    #[async_trait::async_trait]
+    impl ethers::providers::JsonRpcClient for &'static MovingChain {
+        type Error = ethers::providers::MockError;
+
+        async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+        where
+            T: std::fmt::Debug + serde::Serialize + Send + Sync,
+            R: serde::de::DeserializeOwned + Send,
+        {
+            use std::sync::atomic::Ordering;
+
+            use ethers::{
+                abi::AbiEncode as _,
+                types::{Block, U256, U64},
+            };
+
+            let response = match method {
+                "eth_blockNumber" => serde_json::to_value(U64::from(self.head())),
+                "eth_chainId" => serde_json::to_value(U64::from(31337)),
+                "eth_call" => serde_json::to_value(Bytes::from(U256::from(1).encode())),
+                "eth_getBlockByNumber" => serde_json::to_value(Block::<TxHash> {
+                    hash: Some(H256::random()),
+                    ..Default::default()
+                }),
+                "eth_getLogs" => {
+                    let filter = &serde_json::to_value(params)?[0];
+                    let block: U64 = serde_json::from_value(filter["fromBlock"].clone())?;
+                    let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    let request = (block.as_u64(), self.head(), in_flight);
+                    self.logs_requests.lock().unwrap().push(request);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                    Ok(serde_json::Value::Array(vec![]))
+                }
+                _ => panic!("unexpected request: {method}"),
+            };
+            Ok(serde_json::from_value(response?)?)
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn backfill_concurrently_then_tail_confirmed_blocks() {
+        let chain: &'static MovingChain = Box::leak(Default::default());
+        let permitter = eth::SsssHub::new(
+            31337,
+            Address::repeat_byte(1),
+            ethers::providers::Provider::new(chain),
+        );
+        let (store, decryptor, config) = (
+            MemoryStore::default(),
+            MultiIdentityDecryptor::new(vec![], 1),
+            SyncConfig {
+                backfill_threshold: 10,
+                backfill_concurrency: 4,
+                tail_confirmations: 3,
+                ..Default::default()
+            },
+        );
+        let sync = sync_chain(
+            31337,
+            &permitter,
+            &store,
+            &decryptor,
+            None,
+            &config,
+            Default::default(),
+        );
+        let tailed = async {
+            while permitter.processed_block() < 110 {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+        };
+        tokio::select! {
+            res = sync => panic!("sync exited: {res:?}"),
+            _ = tailed => {}
+        }
+
+        let mut requests = chain.logs_requests.lock().unwrap().clone();
+        requests.sort();
+        let blocks: Vec<_> = requests.iter().map(|&(block, ..)| block).collect();
+        assert_eq!(blocks, (1..=blocks.len() as u64).collect::<Vec<_>>());
+        // Blocks at least the threshold behind the starting head of 100 are backfilled together.
+        let backfill_concurrency = requests[..90].iter().map(|&(.., in_flight)| in_flight);
+        assert_eq!(backfill_concurrency.max(), Some(4));
+        // Blocks that were ahead of the starting head are tailed one at a time, each only once it
+        // has the confirmations and well before it would have been backfilled.
+        for &(block, head, in_flight) in requests.iter().filter(|&&(block, ..)| block > 100) {
+            assert_eq!(in_flight, 1, "block {block}");
+            assert!(head >= block + 3, "block {block} requested at head {head}");
+            assert!(head < block + 10, "block {block} requested at head {head}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn guaranteed_observer_sees_every_event() {
+        use ethers::{
+            abi::AbiEncode as _,
+            types::{Block, Log, U256, U64},
+        };
+
+        let (provider, mock) = mocked_provider();
+        for _ in 1..=10 {
+            mock.push(Block::<TxHash> {
+                hash: Some(H256::random()),
+                ..Default::default()
+            })
+            .unwrap();
+            mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        }
+        mock.push(U64::from(10)).unwrap();
+        mock.push(U64::from(10)).unwrap();
+        mock.push::<Bytes, Bytes>(U256::from(1).encode().into())
+            .unwrap();
+        mock.push(U64::from(31337)).unwrap();
+        mock.push(U64::from(10)).unwrap();
+
+        let permitter = eth::SsssHub::new(31337, Address::repeat_byte(1), provider);
+        let (store, decryptor, config) = (
+            MemoryStore::default(),
+            MultiIdentityDecryptor::new(vec![], 1),
+            SyncConfig::default(),
+        );
+        // The observer holds one event at a time and is much slower than sync.
+        let (tx, mut rx) = mpsc::channel(1);
+        let observer = EventObserver::new(tx, Delivery::Guaranteed);
+        let sync = sync_chain(
+            31337,
+            &permitter,
+            &store,
+            &decryptor,
+            None,
+            &config,
+            Reporters {
+                observer: Some(&observer),
+                ..Default::default()
+            },
+        );
+        let observed = async {
+            let mut blocks = vec![];
+            while let Some(observed) = rx.recv().await {
+                sleep(Duration::from_secs(1)).await;
+                let EventOutcome::BlockProcessed { block, .. } = observed.outcome else {
+                    panic!("unexpected outcome: {:?}", observed.outcome);
+                };
+                blocks.push(block);
+                if block == 10 {
+                    break;
+                }
+            }
+            blocks
+        };
+        let blocks = tokio::time::timeout(Duration::from_secs(60), async {
+            tokio::select! {
+                res = sync => panic!("sync exited: {res:?}"),
+                blocks = observed => blocks,
+            }
+        })
+        .await
+        .expect("an observed event was lost");
+        assert_eq!(blocks, (1..=10).collect::<Vec<_>>());
+    }
+
+    #[tokio::test]
+    async fn best_effort_observer_drops_when_full() {
+        let (tx, mut rx) = mpsc::channel(1);
+        let observer = EventObserver::new(tx, Delivery::BestEffort);
+        let observed = |block| ObservedEvent {
+            chain_id: 31337,
+            event: eth::Event {
+                kind: eth::EventKind::ProcessedBlock {
+                    block_hash: H256::zero(),
+                },
+                index: EventIndex {
+                    block,
+                    log_index: u64::MAX,
+                },
+                tx: None,
+            },
+            outcome: EventOutcome::BlockProcessed {
+                block,
+                hash: H256::zero(),
+            },
+        };
+        type M = ethers::providers::Provider<ethers::providers::MockProvider>;
+        observer.deliver::<M>(observed(1)).await.unwrap();
+        observer.deliver::<M>(observed(2)).await.unwrap();
+        assert_eq!(rx.recv().await.unwrap().event.index.block, 1);
+        assert!(rx.try_recv().is_err());
+
+        // A best-effort observer that has gone away does not stop sync.
+        drop(rx);
+        observer.deliver::<M>(observed(3)).await.unwrap();
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn guaranteed_observer_closing_fails_sync() {
+        use ethers::{
+            abi::AbiEncode as _,
+            types::{Block, Log, U256, U64},
+        };
+
+        let (provider, mock) = mocked_provider();
+        mock.push(Block::<TxHash> {
+            hash: Some(H256::random()),
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        mock.push(U64::from(1)).unwrap();
+        mock.push(U64::from(1)).unwrap();
+        mock.push::<Bytes, Bytes>(U256::from(1).encode().into())
+            .unwrap();
+        mock.push(U64::from(31337)).unwrap();
+        mock.push(U64::from(1)).unwrap();
+
+        let permitter = eth::SsssHub::new(31337, Address::repeat_byte(1), provider);
+        let (store, decryptor, config) = (
+            MemoryStore::default(),
+            MultiIdentityDecryptor::new(vec![], 1),
+            SyncConfig::default(),
+        );
+        let (tx, rx) = mpsc::channel(1);
+        drop(rx);
+        let observer = EventObserver::new(tx, Delivery::Guaranteed);
+        let res = tokio::time::timeout(
+            Duration::from_secs(60),
+            sync_chain(
+                31337,
+                &permitter,
+                &store,
+                &decryptor,
+                None,
+                &config,
+                Reporters {
+                    observer: Some(&observer),
+                    ..Default::default()
+                },
+            ),
+        )
+        .await
+        .expect("sync kept going without its observer");
+        assert!(
+            matches!(res, Err(Error::ObserverClosed { index }) if index.block == 1),
+            "unexpected result: {res:?}"
+        );
+    }
+
+    #[tokio::test]
+    async fn oldest_block_asks_for_head_only_if_limited() {
+        let (provider, mock) = mocked_provider();
+        let permitter = eth::SsssHub::new(31337, Address::repeat_byte(1), provider);
+        // The provider has no responses, so asking it anything fails.
+        let unlimited = SyncConfig::default();
+        assert_eq!(unlimited.oldest_block(&permitter).await.unwrap(), None);
+
+        mock.push(ethers::types::U64::from(100)).unwrap();
+        let limited = SyncConfig {
+            max_event_age_blocks: Some(5),
+            ..Default::default()
+        };
+        assert_eq!(limited.oldest_block(&permitter).await.unwrap(), Some(95));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn skip_events_older_than_max_age() {
+        use ethers::{
+            abi::AbiEncode as _,
+            types::{Block, Log, U256, U64},
+        };
+
+        let (provider, mock) = mocked_provider();
+        let hub_address = Address::repeat_byte(1);
+        mock.push(Block::<TxHash> {
+            hash: Some(H256::random()),
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        mock.push(U64::from(100)).unwrap();
+        mock.push(U64::from(100)).unwrap();
+        mock.push::<Bytes, Bytes>(U256::from(1).encode().into())
+            .unwrap();
+        // The provider health check, whose head is reused to find the oldest block.
+        mock.push(U64::from(31337)).unwrap();
+        mock.push(U64::from(100)).unwrap();
+
+        let permitter = eth::SsssHub::new(31337, hub_address, provider);
+        let (store, decryptor, config) = (
+            MemoryStore::default(),
+            MultiIdentityDecryptor::new(vec![], 1),
+            SyncConfig {
+                max_event_age_blocks: Some(5),
+                ..Default::default()
+            },
+        );
+        let sync = sync_chain(
+            31337,
+            &permitter,
+            &store,
+            &decryptor,
+            None,
+            &config,
+            Reporters::default(),
+        );
+        let processed = async {
+            while permitter.processed_block() < 95 {
+                sleep(Duration::from_millis(100)).await;
+            }
+        };
+        tokio::select! {
+            res = sync => panic!("sync exited: {res:?}"),
+            _ = processed => {}
+        }
+        // No events of blocks 1 through 94 were asked for, so none were handled.
+        assert_eq!(permitter.processed_block(), 95);
+    }
+
+    #[test]
+    fn debounce_coalesces_policy_changes() {
+        let (a, b) = (
+            IdentityId(H256::repeat_byte(1)),
+            IdentityId(H256::repeat_byte(2)),
+        );
+        let policy_change = |identity, block, log_index| eth::Event {
+            kind: eth::EventKind::PolicyChange(eth::PolicyChange {
+                identity,
+                config: vec![log_index as u8],
+            }),
+            index: EventIndex { block, log_index },
+            tx: None,
+        };
+        let applied = |events: Vec<eth::Event>| -> Vec<EventIndex> {
+            events.into_iter().map(|event| event.index).collect()
+        };
+
+        let mut debounce = PolicyDebounce::new(2);
+        assert!(debounce.hold(policy_change(a, 5, 0)).is_none());
+        assert!(debounce.hold(policy_change(a, 6, 0)).is_none());
+        assert!(debounce.hold(policy_change(b, 6, 1)).is_none());
+        let block_processed = eth::Event {
+            kind: eth::EventKind::ProcessedBlock {
+                block_hash: H256::zero(),
+            },
+            index: EventIndex {
+                block: 6,
+                log_index: u64::MAX,
+            },
+            tx: None,
+        };
+        assert!(debounce.hold(block_processed).is_some());
+
+        assert!(debounce.take_due(6).is_empty());
+        assert_eq!(debounce.held_since(), Some(5));
+        // Only the latest of `a`'s changes is applied, once the window from its first has passed.
+        assert_eq!(
+            applied(debounce.take_due(7)),
+            vec![EventIndex {
+                block: 6,
+                log_index: 0
+            }]
+        );
+        assert_eq!(debounce.held_since(), Some(6));
+        assert_eq!(
+            applied(debounce.take_due(8)),
+            vec![EventIndex {
+                block: 6,
+                log_index: 1
+            }]
+        );
+        assert_eq!(debounce.held_since(), None);
+
+        let mut disabled = PolicyDebounce::new(0);
+        assert!(disabled.hold(policy_change(a, 5, 0)).is_some());
+    }
+
+    #[test]
+    fn debounce_holds_shares_behind_their_policy() {
+        let (a, b) = (
+            IdentityId(H256::repeat_byte(1)),
+            IdentityId(H256::repeat_byte(2)),
+        );
+        let index = |block, log_index| EventIndex { block, log_index };
+        let policy_change = |identity, index| eth::Event {
+            kind: eth::EventKind::PolicyChange(eth::PolicyChange {
+                identity,
+                config: vec![],
+            }),
+            index,
+            tx: None,
+        };
+        let deal = |identity, index| eth::Event {
+            kind: eth::EventKind::SharesDealt(eth::SharesDealt {
+                identity,
+                secret_name: "omni".into(),
+                version: 1,
+                scheme: eth::SsScheme::Shamir {
+                    pk: Identity::ephemeral().peer_key(),
+                    nonce: H256::zero(),
+                    indices: vec![],
+                    indexed: false,
+                    shares: vec![],
+                },
+                dealer: Address::zero(),
+            }),
+            index,
+            tx: None,
+        };
+
+        let mut debounce = PolicyDebounce::new(2);
+        assert!(debounce.hold(deal(a, index(4, 0))).is_some());
+        assert!(debounce.hold(policy_change(a, index(5, 0))).is_none());
+        assert!(debounce.hold(deal(a, index(5, 1))).is_none());
+        assert!(debounce.hold(deal(b, index(5, 2))).is_some());
+        // The deal follows the policy that superseded the one it was dealt after.
+        assert!(debounce.hold(policy_change(a, index(6, 0))).is_none());
+        let applied: Vec<_> = debounce
+            .take_due(7)
+            .into_iter()
+            .map(|event| event.index)
+            .collect();
+        assert_eq!(applied, vec![index(6, 0), index(5, 1)]);
+    }
+
+    #[test]
+    fn event_indices_must_increase() {
+        let event = |log_index| eth::Event {
+            kind: eth::EventKind::PolicyChange(eth::PolicyChange {
+                identity: IdentityId(H256::zero()),
+                config: vec![],
+            }),
+            index: EventIndex {
+                block: 5,
+                log_index,
+            },
+            tx: Some(TxHash::random()),
+        };
+        let chain_id: ChainId = rand::random();
+        let repeated = || {
+            metrics::REPEATED_EVENT_INDICES
+                .with_label_values(&[&chain_id.to_string()])
+                .get()
+        };
+
+        let logs = crate::testing::LogCapture::start();
+        let mut order = EventOrder::new(chain_id);
+        order.observe(&event(0)).unwrap();
+        // A repeated index is reported, but the events after it are still handled.
+        order.observe(&event(0)).unwrap();
+        assert!(logs
+            .logs()
+            .contains("provider delivered two events at the same index"));
+        assert_eq!(repeated(), 1);
+        order.observe(&event(1)).unwrap();
+
+        assert_eq!(order.observe(&event(0)), Err(event(1).index));
+    }
+
+    #[test]
+    fn restart_backoff_doubles_per_failure() {
+        let config = SyncConfig::default();
+        assert_eq!(config.restart_backoff(1), config.restart_backoff);
+        assert_eq!(config.restart_backoff(3), config.restart_backoff * 4);
+        assert_eq!(config.restart_backoff(20), config.max_restart_backoff);
+        assert_eq!(config.restart_backoff(u32::MAX), config.max_restart_backoff);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn restart_failing_sync_with_backoff() {
+        let (provider, _mock) = mocked_provider();
+        let permitter = eth::SsssHub::new(31337, Address::repeat_byte(1), provider);
+        let config = SyncConfig::default();
+        let retries = |run_for: Duration| {
+            let (tx, mut rx) = mpsc::channel(16);
+            let (permitter, config) = (permitter.clone(), config);
+            tokio::spawn(async move {
+                restart_on_failure(&permitter, &config, Some(&tx), || async move {
+                    sleep(run_for).await;
+                    Err(Error::StateUpdaterPanic)
+                })
+                .await
+            });
+            async move {
+                let mut retries = Vec::new();
+                while retries.len() < 11 {
+                    if let Some(SyncStatus::Failed { retry_in, .. }) = rx.recv().await {
+                        retries.push(retry_in.as_secs());
+                    }
+                }
+                retries
+            }
+        };
+
+        assert_eq!(
+            retries(Duration::ZERO).await,
+            [1, 2, 4, 8, 16, 32, 64, 128, 256, 300, 300]
+        );
+        // Runs that last long enough reset the backoff.
+        assert_eq!(retries(RESTART_BACKOFF_RESET).await, [1; 11]);
+    }
+
+    #[test]
+    fn stall_needs_an_advancing_head() {
+        let deadline = Duration::from_secs(60);
+        let start = Instant::now();
+        let mut watch = StallWatch::new(10, start);
+
+        // An idle chain never stalls.
+        assert!(!watch.observe(10, 10, start + deadline * 2, deadline));
+
+        let start = start + deadline * 2;
+        assert!(!watch.observe(10, 12, start + deadline / 2, deadline));
+        assert!(watch.observe(10, 12, start + deadline, deadline));
+
+        // Processing any block clears the stall and restarts the clock.
+        assert!(!watch.observe(11, 13, start + deadline * 2, deadline));
+        assert!(!watch.observe(11, 13, start + deadline * 2 + deadline / 2, deadline));
+        assert!(watch.observe(11, 13, start + deadline * 3, deadline));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stall_is_judged_by_finality() {
+        use ethers::types::{Block, U64};
+
+        let deadline = Duration::from_secs(60);
+        let watch = |permitter| async move {
+            let _ =
+                tokio::time::timeout(deadline * 3, watch_for_stall(&permitter, deadline, 2)).await;
+            permitter.is_stalled()
+        };
+
+        // The finalized block lags the head, and sync has processed it.
+        let (provider, mock) = mocked_provider();
+        let permitter = eth::SsssHub::new(31337, Address::repeat_byte(1), provider)
+            .with_finality(eth::FinalizedTag);
+        permitter.set_processed_block(50);
+        for _ in 0..20 {
+            mock.push(Block::<TxHash> {
+                number: Some(50.into()),
+                ..Default::default()
+            })
+            .unwrap();
+            mock.push(U64::from(100)).unwrap();
+        }
+        assert!(!watch(permitter).await);
+
+        // Without a finality provider, the same head leaves blocks to process.
+        let (provider, mock) = mocked_provider();
+        let permitter = eth::SsssHub::new(31337, Address::repeat_byte(1), provider);
+        permitter.set_processed_block(50);
+        for _ in 0..20 {
+            mock.push(U64::from(100)).unwrap();
+        }
+        assert!(watch(permitter).await);
+    }
+
+    #[test]
+    fn backfill_until_near_head() {
+        let config = SyncConfig {
+            backfill_threshold: 100,
+            tail_confirmations: 3,
+            ..Default::default()
+        };
+        assert_eq!(config.backfill_end(0, 1000), Some(900));
+        assert_eq!(config.backfill_end(900, 1000), Some(900));
+        assert_eq!(config.backfill_end(901, 1000), None);
+        assert_eq!(config.backfill_end(0, 50), None);
+
+        // Backfilling never reaches blocks that are still awaiting confirmations.
+        let config = SyncConfig {
+            backfill_threshold: 0,
+            tail_confirmations: 3,
+            ..Default::default()
+        };
+        assert_eq!(config.backfill_end(0, 10), Some(7));
+        assert_eq!(config.backfill_end(8, 10), None);
+    }
+
+    #[tokio::test]
+    async fn resume_point_must_not_be_ahead_of_head() {
+        let (provider, mock) = mocked_provider();
+        let permitter = eth::SsssHub::new(31337, Address::repeat_byte(1), provider);
+        let store = MemoryStore::default();
+        let at = |block| EventIndex {
+            block,
+            log_index: 2,
+        };
+
+        mock.push(ethers::types::U64::from(10)).unwrap();
+        let res = set_resume_point(&store, &permitter, at(11)).await;
+        assert!(matches!(res, Err(Error::ResumePointAhead { head: 10, .. })));
+        assert!(store.get_chain_state(31337).await.unwrap().is_none());
+
+        mock.push(ethers::types::U64::from(10)).unwrap();
+        set_resume_point(&store, &permitter, at(10)).await.unwrap();
+        let state = store.get_chain_state(31337).await.unwrap().unwrap();
+        assert_eq!(state.resume_point(), at(10));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn report_every_failure_through_run() {
+        use ethers::types::U64;
+
+        let (provider, mock) = mocked_provider();
+        for _ in 0..2 {
+            mock.push(U64::from(1)).unwrap();
+            mock.push(U64::from(10)).unwrap();
+        }
+        let permitter = eth::SsssHub::new(31337, Address::repeat_byte(1), provider);
+        let config = SyncConfig::default();
+        // Room for one status, so the second failure is only seen if it waits for the first to
+        // be read.
+        let (tx, mut rx) = mpsc::channel(1);
+        let started = run(
+            MemoryStore::default(),
+            std::iter::once(Ok(permitter)),
+            vec![],
+            None,
+            config,
+            Some(tx),
+            None,
+        )
+        .await;
+        assert_eq!(started, 1);
+        sleep(config.restart_backoff(1) + Duration::from_secs(60)).await;
+
+        for failures in 1..=2 {
+            let status = rx.recv().await.unwrap();
+            let SyncStatus::Failed {
+                chain_id: 31337,
+                consecutive_failures,
+                retry_in,
+                error,
+            } = status
+            else {
+                panic!("unexpected status: {status:?}");
+            };
+            assert_eq!(consecutive_failures, failures);
+            assert_eq!(retry_in, config.restart_backoff(failures));
+            // The error is carried whole, so embedders can tell its kind without parsing it.
+            let error = error
+                .get()
+                .downcast_ref::<Error<ethers::providers::Provider<ethers::providers::MockProvider>>>()
+                .unwrap();
+            assert!(
+                matches!(
+                    error,
+                    Error::Eth(eth::Error::ChainIdMismatch {
+                        expected: 31337,
+                        actual: 1
+                    })
+                ),
+                "{error}"
+            );
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn checkpoint_store_once_for_all_chains() {
+        let checkpoints = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let store = store::tests::HookedStore::new(MemoryStore::default(), {
+            let checkpoints = checkpoints.clone();
+            move |method| {
+                if method == "checkpoint" {
+                    checkpoints.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                }
+                async { Ok(()) }
+            }
+        });
+        let config = SyncConfig {
+            checkpoint_interval: Duration::from_secs(60),
+            ..Default::default()
+        };
+        let sssss = [31337, 31338].map(|chain| {
+            let (provider, _mock) = mocked_provider();
+            Ok(eth::SsssHub::new(chain, Address::repeat_byte(1), provider))
+        });
+        let started = run(store, sssss.into_iter(), vec![], None, config, None, None).await;
+        assert_eq!(started, 2);
+
+        sleep(config.checkpoint_interval * 2 + Duration::from_secs(1)).await;
+        assert_eq!(checkpoints.load(std::sync::atomic::Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn decommission_forgets_only_that_chain() {
+        let store = MemoryStore::default();
+        let identity = IdentityId(H256::random());
+        let index = EventIndex {
+            block: 10,
+            log_index: 0,
+        };
+        for chain in [1, 2] {
+            let permitter = PermitterLocator::new(chain, Address::repeat_byte(1));
+            store
+                .update_verifier(permitter, identity, b"policy".to_vec(), index)
+                .await
+                .unwrap();
+            store.set_resume_point(chain, index).await.unwrap();
+        }
+
+        decommission_chain(&store, 1).await.unwrap();
+
+        let verifier = |chain| {
+            store.get_verifier(
+                PermitterLocator::new(chain, Address::repeat_byte(1)),
+                identity,
+            )
+        };
+        assert_eq!(verifier(1).await.unwrap(), None);
+        assert_eq!(store.get_chain_state(1).await.unwrap(), None);
+        assert!(verifier(2).await.unwrap().is_some());
+        assert!(store.get_chain_state(2).await.unwrap().is_some());
+        assert!(store.list_intents().await.unwrap().is_empty());
+    }
+
+    /// A store whose share writes take at least 50ms.
+    fn slow_share_store() -> impl Store {
+        store::tests::HookedStore::new(MemoryStore::default(), |method| async move {
+            if method == "put_share" {
+                sleep(Duration::from_millis(50)).await;
+            }
+            Ok(())
+        })
+    }
+
+    #[tokio::test]
+    async fn stored_share_records_provenance() {
+        use ethers::abi::AbiEncode as _;
+
+        let active = Identity::ephemeral();
+        let fixture = test_handler(SyncConfig::default()).with_identities(vec![active.clone()]);
+        fixture
+            .mock
+            .push::<Bytes, Bytes>(Address::repeat_byte(2).encode().into())
+            .unwrap();
+        let identity = IdentityId(ethers::types::H256::random());
+        let index = EventIndex {
+            block: 5,
+            log_index: 3,
+        };
+        let tx = TxHash::random();
+        let event = eth::Event {
+            index,
+            tx: Some(tx),
+            ..fixture.deal_event(identity, 1, vec![fixture.deal(&active, b"share")])
+        };
+        let outcome = fixture.handler().handle(event, false).await.unwrap();
+        assert!(matches!(outcome, EventOutcome::ShareStored { .. }));
+
+        let share = fixture
+            .store
+            .get_share(ShareId {
+                secret_name: "omni".into(),
+                identity: IdentityLocator {
+                    chain: 31337,
+                    registry: Address::repeat_byte(2),
+                    id: identity,
+                },
+                version: 1,
+            })
+            .await
+            .unwrap()
+            .unwrap();
+        let provenance = share.provenance.unwrap();
+        assert_eq!(provenance.event, index);
+        assert_eq!(provenance.tx, Some(tx));
+        assert_eq!(provenance.dealer_pk, fixture.dealer.peer_key().to_bytes());
+        assert!(provenance.stored_at > 0);
+    }
+
+    #[tokio::test]
+    async fn handling_deals_logs_no_secrets() {
+        use ethers::abi::AbiEncode as _;
+
+        let logs = crate::testing::LogCapture::start();
+
+        let sk = p384::SecretKey::random(&mut rand::thread_rng());
+        let active = Identity::persistent(sk.clone());
+        let mut share = [0u8; 32];
+        rand::RngCore::fill_bytes(&mut rand::thread_rng(), &mut share);
+
+        let fixture = test_handler(SyncConfig::default()).with_identities(vec![active.clone()]);
+        // The registry, for each time the deal is handled.
+        for _ in 0..2 {
+            fixture
+                .mock
+                .push::<Bytes, Bytes>(Address::repeat_byte(2).encode().into())
+                .unwrap();
+        }
+        let handler = fixture.handler();
+        let event = eth::Event {
+            index: EventIndex {
+                block: 5,
+                log_index: 3,
+            },
+            tx: Some(TxHash::random()),
+            ..fixture.deal_event(
+                IdentityId(ethers::types::H256::random()),
+                1,
+                vec![fixture.deal(&active, &share)],
+            )
+        };
+        // As sync_chain logs every event before handling it.
+        trace!(event = ?event, "event");
+        let outcome = handler.handle(event.clone(), false).await.unwrap();
+        assert!(matches!(outcome, EventOutcome::ShareStored { .. }));
+        // Handling the deal again logs that the share was not put.
+        handler.handle(event, false).await.unwrap();
+
+        assert!(!logs.logs().is_empty(), "nothing was captured");
+        logs.assert_no_leak("decrypted share", &share);
+        logs.assert_no_leak("identity secret key", &sk.to_bytes());
+    }
+
+    #[tokio::test]
+    async fn verify_share_against_its_deal() {
+        use ethers::{
+            abi::AbiEncode as _,
+            contract::EthEvent as _,
+            types::{Log, Transaction, TransactionReceipt, U256},
+        };
+
+        let active = Identity::ephemeral();
+        let dealer = Identity::ephemeral();
+        let nonce = ethers::types::H256::random();
+        let hub_address = Address::repeat_byte(1);
+        let tx = TxHash::random();
+        let event = EventIndex {
+            block: 5,
+            log_index: 3,
+        };
+        let share_id = ShareId {
+            secret_name: "omni".into(),
+            identity: IdentityLocator {
+                chain: 31337,
+                registry: Address::repeat_byte(2),
+                id: IdentityId(ethers::types::H256::random()),
+            },
+            version: 1,
+        };
+
+        let (provider, mock) = mocked_provider();
+        let permitter = eth::SsssHub::new(31337, hub_address, provider);
+        // Each verification fetches the receipt, then the transaction of its log.
+        let push_deal = || {
+            let mut input = vec![0u8; 4];
+            input.extend(
+                (
+                    share_id.identity.id.0,
+                    share_id.secret_name.clone(),
+                    U256::from(share_id.version),
+                    Bytes::from(dealer.peer_key().to_bytes()),
+                    nonce,
+                    vec![deal(&dealer, &active, &shares_nonce(&nonce), b"share")],
+                )
+                    .encode(),
+            );
+            mock.push(Transaction {
+                hash: tx,
+                input: input.into(),
+                ..Default::default()
+            })
+            .unwrap();
+            mock.push(TransactionReceipt {
+                transaction_hash: tx,
+                logs: vec![Log {
+                    address: hub_address,
+                    topics: vec![eth::SharesDealtFilter::signature()],
+                    block_number: Some(event.block.into()),
+                    transaction_hash: Some(tx),
+                    log_index: Some(event.log_index.into()),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })
+            .unwrap();
+        };
+        let stored_share = |share: &[u8]| SecretShare {
+            index: ShareIndex(0),
+            share: share.to_vec().into(),
+            provenance: Some(ShareProvenance {
+                event,
+                tx: Some(tx),
+                dealer_pk: dealer.peer_key().to_bytes(),
+                stored_at: 0,
+            }),
+        };
+        let verify = |store: MemoryStore, identity: Identity| {
+            let (permitter, share_id) = (&permitter, share_id.clone());
+            async move {
+                verify_stored_share(&store, permitter, vec![identity], share_id)
+                    .await
+                    .unwrap()
+            }
+        };
+
+        let store = MemoryStore::default();
+        assert_eq!(
+            verify(store.clone(), active.clone()).await,
+            VerifyResult::NotFound
+        );
+
+        let forged = MemoryStore::default();
+        forged
+            .put_share(share_id.clone(), stored_share(b"forged"))
+            .await
+            .unwrap();
+        push_deal();
+        assert_eq!(verify(forged, active.clone()).await, VerifyResult::Mismatch);
+
+        store
+            .put_share(share_id.clone(), stored_share(b"share"))
+            .await
+            .unwrap();
+        push_deal();
+        assert_eq!(
+            verify(store.clone(), active.clone()).await,
+            VerifyResult::Valid
+        );
+
+        // Another identity cannot decrypt the deal, so cannot vouch for the share.
+        push_deal();
+        assert_eq!(
+            verify(store, Identity::ephemeral()).await,
+            VerifyResult::Mismatch
+        );
+    }
+
+    // Flushing the periodic reader blocks on its worker task, which must run on another thread.
+    #[tokio::test(flavor = "multi_thread")]
+    async fn event_handler_duration_is_recorded() {
+        use ethers::abi::AbiEncode as _;
+        use opentelemetry_sdk::metrics::data::Histogram;
+
+        let exporter = opentelemetry_sdk::testing::metrics::InMemoryMetricsExporter::default();
+        let meter_provider = opentelemetry_sdk::metrics::MeterProvider::builder()
+            .with_reader(
+                opentelemetry_sdk::metrics::PeriodicReader::builder(
+                    exporter.clone(),
+                    opentelemetry_sdk::runtime::Tokio,
+                )
+                .build(),
+            )
+            .build();
+        opentelemetry::global::set_meter_provider(meter_provider.clone());
+
+        let active = Identity::ephemeral();
+        // Another chain, so that no other test's handling is recorded under its label.
+        let chain_id = 31338;
+        let (provider, mock) = mocked_provider();
+        let fixture = TestHandler {
+            permitter: eth::SsssHub::new(chain_id, Address::repeat_byte(1), provider),
+            mock,
+            ..test_handler(SyncConfig::default())
+        }
+        .with_store(slow_share_store())
+        .with_identities(vec![active.clone()]);
+        // The identity registry looked up when storing the share.
+        fixture
+            .mock
+            .push::<Bytes, Bytes>(Address::repeat_byte(2).encode().into())
+            .unwrap();
+        let event = fixture.deal_event(
+            IdentityId(ethers::types::H256::random()),
+            1,
+            vec![fixture.deal(&active, b"share")],
+        );
+        let outcome = fixture.handler().handle(event, false).await.unwrap();
+        assert!(matches!(outcome, EventOutcome::ShareStored { .. }));
+
+        let histogram = metrics::EVENT_HANDLER_DURATION
+            .with_label_values(&[&chain_id.to_string(), "SharesDealt"]);
+        assert_eq!(histogram.get_sample_count(), 1);
+        assert!(histogram.get_sample_sum() >= 0.05);
+
+        meter_provider.force_flush().unwrap();
+        let metrics = exporter.get_finished_metrics().unwrap();
+        let point = metrics
+            .iter()
+            .flat_map(|resource| &resource.scope_metrics)
+            .flat_map(|scope| &scope.metrics)
+            .filter(|metric| metric.name == "ssss_event_handler_duration_seconds")
+            .filter_map(|metric| metric.data.as_any().downcast_ref::<Histogram<f64>>())
+            .flat_map(|histogram| &histogram.data_points)
+            .find(|point| {
+                point.attributes.iter().any(|(key, value)| {
+                    key.as_str() == "chain_id" && *value == opentelemetry::Value::I64(31338)
+                })
+            })
+            .expect("no duration was recorded for the chain");
+        assert_eq!(point.count, 1);
+        assert!(point.sum >= 0.05);
+    }
+
+    #[tokio::test]
+    async fn run_skips_failed_permitters() {
+        let exporter = opentelemetry_sdk::testing::trace::InMemorySpanExporter::default();
+        let tracer_provider = opentelemetry_sdk::trace::TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("test")));
+        let guard = tracing::subscriber::set_default(subscriber);
+
+        let permitter = |chain| {
+            let (provider, _mock) = mocked_provider();
+            Ok(eth::SsssHub::new(chain, Address::repeat_byte(1), provider))
+        };
+        let started = run(
+            MemoryStore::default(),
+            [
+                permitter(1),
+                Err(eth::Error::UnsupportedRpc("ftp://gateway".into())),
+                permitter(2),
+            ]
+            .into_iter(),
+            vec![],
+            None,
+            SyncConfig::default(),
+            None,
+            None,
+        )
+        .await;
+        assert_eq!(started, 2);
+
+        drop(guard);
+        tracer_provider.force_flush();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let run_span = spans.iter().find(|span| span.name == "run").unwrap();
+        let errors: Vec<_> = run_span
+            .events
+            .iter()
+            .filter(|event| {
+                event
+                    .attributes
+                    .iter()
+                    .any(|kv| kv.key.as_str() == "level" && kv.value.as_str() == "ERROR")
+            })
+            .collect();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].name.contains("ftp://gateway"));
+    }
+
+    #[tokio::test]
+    async fn event_handler_span_attributes() {
+        let exporter = opentelemetry_sdk::testing::trace::InMemorySpanExporter::default();
+        let tracer_provider = opentelemetry_sdk::trace::TracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber = tracing_subscriber::registry()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("test")));
+        let guard = tracing::subscriber::set_default(subscriber);
+
+        let fixture = test_handler(SyncConfig::default());
+        fixture
+            .handler()
+            .handle(
+                eth::Event {
+                    kind: eth::EventKind::PolicyChange(eth::PolicyChange {
+                        identity: IdentityId(ethers::types::H256::random()),
+                        config: vec![],
+                    }),
+                    index: EventIndex {
+                        block: 42,
+                        log_index: 0,
+                    },
+                    tx: None,
+                },
+                true,
+            )
+            .await
+            .unwrap();
+        drop(guard);
+        tracer_provider.force_flush();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let span = spans.iter().find(|s| s.name == "handle_event").unwrap();
+        let attr = |key: &'static str| {
+            span.attributes
+                .iter()
+                .find(|kv| kv.key == Key::from_static_str(key))
+                .map(|kv| kv.value.to_string())
+        };
+        assert_eq!(attr("chain.id").as_deref(), Some("31337"));
+        assert_eq!(
+            attr("permitter.address"),
+            Some(format!("{:?}", Address::repeat_byte(1)))
+        );
+        assert_eq!(attr("event.kind").as_deref(), Some("PolicyChange"));
+        assert_eq!(attr("event.block").as_deref(), Some("42"));
+    }
 }
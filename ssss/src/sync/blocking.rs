@@ -0,0 +1,153 @@
+//! A synchronous façade over [`super::run`] for hosts that do not run a tokio runtime.
+
+use std::collections::BTreeMap;
+
+use ethers::providers::Middleware;
+use tokio::time::Duration;
+
+use super::{Error, SyncConfig};
+use crate::{eth, identity::Identity, store::Store, types::ChainId};
+
+/// How long [`BlockingSyncer::shutdown`] waits for in-flight events to finish.
+const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Syncs chains on a runtime that it owns, so that it can be controlled from blocking code.
+pub struct BlockingSyncer<M> {
+    runtime: tokio::runtime::Runtime,
+    sssss: Vec<eth::SsssHub<M>>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ChainSyncStatus {
+    pub processed_block: u64,
+    /// See [`eth::SsssHub::sync_progress`].
+    pub progress: Option<f64>,
+    pub paused: bool,
+    pub rate_limited: bool,
+}
+
+impl<M: Middleware + Clone + 'static> BlockingSyncer<M> {
+    /// Starts syncing each chain in the background.
+    ///
+    /// Panics if called from within an async context.
+    pub fn start(
+        store: impl Store,
+        sssss: Vec<eth::SsssHub<M>>,
+        ssss_identities: Vec<Identity>,
+        shadow_identity: Option<Identity>,
+        config: SyncConfig,
+    ) -> Result<Self, Error<M>> {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .enable_all()
+            .thread_name("ssss-sync")
+            .build()?;
+        runtime.block_on(super::run(
+            store,
+            sssss.clone().into_iter().map(Ok),
+            ssss_identities,
+            shadow_identity,
+            config,
+            None,
+            None,
+        ));
+        Ok(Self { runtime, sssss })
+    }
+
+    /// Stops handling events on every chain until [`Self::resume`] is called.
+    pub fn pause(&self) {
+        self.sssss.iter().for_each(eth::SsssHub::pause);
+    }
+
+    pub fn resume(&self) {
+        self.sssss.iter().for_each(eth::SsssHub::resume);
+    }
+
+    pub fn status(&self) -> BTreeMap<ChainId, ChainSyncStatus> {
+        self.sssss
+            .iter()
+            .map(|ssss| {
+                let status = ChainSyncStatus {
+                    processed_block: ssss.processed_block(),
+                    progress: ssss.sync_progress(),
+                    paused: ssss.is_paused(),
+                    rate_limited: ssss.is_rate_limited(),
+                };
+                (ssss.chain, status)
+            })
+            .collect()
+    }
+
+    /// Stops syncing and waits briefly for the sync tasks to wind down.
+    pub fn shutdown(self) {
+        self.runtime.shutdown_timeout(SHUTDOWN_TIMEOUT);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::{
+        abi::AbiEncode as _,
+        types::{Address, Block, Bytes, Log, TxHash, H256, U256, U64},
+    };
+
+    use super::*;
+    use crate::{store::memory::MemoryStore, testing::mocked_provider};
+
+    #[test]
+    fn pause_and_resume() {
+        let (provider, mock) = mocked_provider();
+        mock.push(Block::<TxHash> {
+            hash: Some(H256::random()),
+            ..Default::default()
+        })
+        .unwrap();
+        mock.push::<Vec<Log>, _>(vec![]).unwrap();
+        // The head as seen by the event stream, then when choosing whether to backfill.
+        mock.push(U64::from(5)).unwrap();
+        mock.push(U64::from(5)).unwrap();
+        mock.push::<Bytes, Bytes>(U256::from(5).encode().into())
+            .unwrap();
+        mock.push(U64::from(31337)).unwrap();
+        // The provider health check.
+        mock.push(U64::from(5)).unwrap();
+        let hub = eth::SsssHub::new(31337, Address::repeat_byte(1), provider);
+        hub.pause();
+
+        let syncer = BlockingSyncer::start(
+            MemoryStore::default(),
+            vec![hub],
+            vec![Identity::ephemeral()],
+            None,
+            SyncConfig::default(),
+        )
+        .unwrap();
+        let status = || syncer.status()[&31337];
+
+        std::thread::sleep(std::time::Duration::from_millis(200));
+        // Sync starts at the creation block, so everything before it counts as processed.
+        assert_eq!(
+            status(),
+            ChainSyncStatus {
+                processed_block: 4,
+                progress: Some(0.0),
+                paused: true,
+                rate_limited: false,
+            }
+        );
+
+        syncer.resume();
+        for _ in 0..100 {
+            if status().processed_block == 5 {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(50));
+        }
+        assert_eq!(status().processed_block, 5);
+        assert_eq!(status().progress, Some(1.0));
+        assert!(!status().paused);
+
+        syncer.pause();
+        assert!(status().paused);
+        syncer.shutdown();
+    }
+}
@@ -0,0 +1,207 @@
+//! Helpers shared by tests across modules.
+
+use std::sync::{Arc, Mutex};
+
+use aes_gcm_siv::AeadInPlace as _;
+use ethers::{
+    providers::{MockProvider, Provider},
+    types::{Address, Bytes, H256},
+};
+use tracing_subscriber::fmt::MakeWriter;
+
+use crate::{
+    eth,
+    identity::{self, Identity},
+    store::{memory::MemoryStore, Store},
+    sync::{EventHandler, MultiIdentityDecryptor, SyncConfig},
+    types::*,
+};
+
+/// Captures everything logged on the current thread, at every level, while it is alive.
+pub struct LogCapture {
+    logs: Arc<Mutex<Vec<u8>>>,
+    _guard: tracing::subscriber::DefaultGuard,
+}
+
+impl LogCapture {
+    pub fn start() -> Self {
+        let logs = Arc::new(Mutex::new(Vec::new()));
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::TRACE)
+            .with_ansi(false)
+            .with_writer(CaptureWriter(logs.clone()))
+            .finish();
+        Self {
+            logs,
+            _guard: tracing::subscriber::set_default(subscriber),
+        }
+    }
+
+    pub fn logs(&self) -> String {
+        String::from_utf8_lossy(&self.logs.lock().unwrap()).into_owned()
+    }
+
+    /// Panics if the logs contain `secret` in any encoding that a log statement might use.
+    pub fn assert_no_leak(&self, what: &str, secret: &[u8]) {
+        let logs = self.logs();
+        let debug_list = format!("{secret:?}");
+        let encodings = [
+            hex::encode(secret),
+            hex::encode_upper(secret),
+            // Inner elements only, so that a secret within a longer list is also found.
+            debug_list[1..debug_list.len() - 1].to_string(),
+            String::from_utf8_lossy(secret).into_owned(),
+        ];
+        for encoding in encodings {
+            assert!(
+                !logs.contains(&encoding),
+                "{what} was logged as {encoding}:\n{logs}"
+            );
+        }
+    }
+}
+
+#[derive(Clone)]
+struct CaptureWriter(Arc<Mutex<Vec<u8>>>);
+
+impl std::io::Write for CaptureWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+impl<'a> MakeWriter<'a> for CaptureWriter {
+    type Writer = Self;
+
+    fn make_writer(&'a self) -> Self::Writer {
+        self.clone()
+    }
+}
+
+/// Returns a provider that answers requests with the responses pushed to its mock. Responses are
+/// popped from the back, so push them in reverse order of use.
+pub fn mocked_provider() -> (Provider<MockProvider>, MockProvider) {
+    Provider::mocked()
+}
+
+/// Encrypts `share` from `dealer` to `recipient`, as a deal carries it.
+pub fn deal(
+    dealer: &Identity,
+    recipient: &Identity,
+    nonce: &aes_gcm_siv::Nonce,
+    share: &[u8],
+) -> Bytes {
+    let cipher = dealer
+        .shared_cipher(&recipient.peer_key(), identity::DEAL_SHARES_DOMAIN_SEP)
+        .unwrap();
+    let mut enc_share = share.to_vec();
+    cipher.encrypt_in_place(nonce, &[], &mut enc_share).unwrap();
+    enc_share.into()
+}
+
+/// Indexes `count` shares by their positions, as deals without explicit indices do.
+fn positions(count: usize) -> Vec<ShareIndex> {
+    (0..count as u64).map(ShareIndex).collect()
+}
+
+/// What a chain's sync needs to handle events, for the hub at `0x0101…` on chain 31337 with a
+/// mocked provider. Shares are dealt by `dealer` under `nonce`.
+pub struct TestHandler<S = MemoryStore> {
+    pub permitter: eth::SsssHub<Provider<MockProvider>>,
+    pub mock: MockProvider,
+    pub store: S,
+    pub decryptor: MultiIdentityDecryptor,
+    pub shadow: Option<MultiIdentityDecryptor>,
+    pub config: SyncConfig,
+    pub dealer: Identity,
+    pub nonce: H256,
+}
+
+/// Returns a handler with an empty memory store that holds no identities.
+pub fn test_handler(config: SyncConfig) -> TestHandler {
+    let (provider, mock) = mocked_provider();
+    TestHandler {
+        permitter: eth::SsssHub::new(31337, Address::repeat_byte(1), provider),
+        mock,
+        store: MemoryStore::default(),
+        decryptor: MultiIdentityDecryptor::new(vec![], 1),
+        shadow: None,
+        config,
+        dealer: Identity::ephemeral(),
+        nonce: H256::random(),
+    }
+}
+
+impl<S: Store> TestHandler<S> {
+    pub fn with_identities(self, identities: Vec<Identity>) -> Self {
+        let max_parallelism = identities.len();
+        Self {
+            decryptor: MultiIdentityDecryptor::new(identities, max_parallelism),
+            ..self
+        }
+    }
+
+    pub fn with_store<S2: Store>(self, store: S2) -> TestHandler<S2> {
+        TestHandler {
+            permitter: self.permitter,
+            mock: self.mock,
+            store,
+            decryptor: self.decryptor,
+            shadow: self.shadow,
+            config: self.config,
+            dealer: self.dealer,
+            nonce: self.nonce,
+        }
+    }
+
+    pub fn handler(&self) -> EventHandler<'_, Provider<MockProvider>, S> {
+        EventHandler {
+            chain_id: self.permitter.chain,
+            permitter: &self.permitter,
+            store: &self.store,
+            decryptor: &self.decryptor,
+            shadow: self.shadow.as_ref(),
+            config: &self.config,
+        }
+    }
+
+    /// Encrypts `share` from the dealer to `recipient`.
+    pub fn deal(&self, recipient: &Identity, share: &[u8]) -> Bytes {
+        deal(
+            &self.dealer,
+            recipient,
+            aes_gcm_siv::Nonce::from_slice(&self.nonce[0..12]),
+            share,
+        )
+    }
+
+    /// Returns the dealing of `shares`, indexed by position, as version `version` of the omni
+    /// secret of `identity`. The event is the first in block `version`.
+    pub fn deal_event(&self, identity: IdentityId, version: u64, shares: Vec<Bytes>) -> eth::Event {
+        eth::Event {
+            kind: eth::EventKind::SharesDealt(eth::SharesDealt {
+                identity,
+                secret_name: "omni".into(),
+                version,
+                scheme: eth::SsScheme::Shamir {
+                    pk: self.dealer.peer_key(),
+                    nonce: self.nonce,
+                    indices: positions(shares.len()),
+                    indexed: false,
+                    shares,
+                },
+                dealer: Address::repeat_byte(3),
+            }),
+            index: EventIndex {
+                block: version,
+                log_index: 0,
+            },
+            tx: None,
+        }
+    }
+}
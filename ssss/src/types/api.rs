@@ -1,10 +1,12 @@
+use std::collections::BTreeMap;
+
 use axum::http::header;
 use axum_extra::headers;
 use ethers::types::{Address, Bytes, Signature};
 use p384::elliptic_curve::JwkEcKey;
 use serde::{Deserialize, Serialize};
 
-use super::{Permit, WrappedKey};
+use super::{ChainId, IdentityId, IdentityLocator, Permit, ShareIndex, ShareReceipt, WrappedKey};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct IdentityResponse {
@@ -31,6 +33,9 @@ pub struct AcqRelIdentityResponse {
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct GetShareQuery {
     pub version: u64,
+    /// Refuse to serve the share unless the node has synced at least this block of the chain.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_synced_block: Option<u64>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -39,15 +44,46 @@ pub struct ShareResponse {
     pub ss: WrappedSecretShare,
 }
 
+/// A request for several shares at once, each of which must be permitted to the requester.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GetSharesRequest {
+    pub shares: Vec<ShareRequestItem>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShareRequestItem {
+    pub name: String,
+    pub identity: IdentityLocator,
+    pub version: u64,
+}
+
+/// The outcome of each requested share, in request order.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GetSharesResponse {
+    pub results: Vec<ShareResult>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "status", rename_all = "kebab-case")]
+pub enum ShareResult {
+    Served(ShareResponse),
+    /// The requester may not have the share, such as for lack of a permit.
+    Denied {
+        reason: String,
+    },
+    /// This node does not hold the share.
+    NotHeld,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 #[cfg_attr(test, derive(PartialEq, Eq))]
 pub struct WrappedSecretShare {
-    pub index: u64,
+    pub index: ShareIndex,
     #[serde(with = "hex::serde")]
     pub share: Vec<u8>,
 }
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[derive(Clone, Debug, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub enum ShareResponseFormat {
     Plain,
@@ -55,6 +91,27 @@ pub enum ShareResponseFormat {
         #[serde(with = "hex::serde")]
         nonce: [u8; 12],
     },
+    /// Encrypted under a key agreed with a P-384 key pair generated for this response alone, whose
+    /// SEC1-encoded public key is `pk`. Nothing that the node keeps can decrypt the share later.
+    EncAes256GcmSivEphemeral {
+        #[serde(with = "hex::serde")]
+        nonce: [u8; 12],
+        #[serde(with = "hex::serde")]
+        pk: Vec<u8>,
+    },
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct GetShareReceiptQuery {
+    pub version: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ShareReceiptResponse {
+    pub receipt: ShareReceipt,
+    /// The persistent identity's ECDSA signature of the receipt, as the concatenation of r and s.
+    #[serde(with = "hex::serde")]
+    pub signature: Vec<u8>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -72,6 +129,72 @@ pub struct KeyResponse {
     pub key: Bytes,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct StatusResponse {
+    pub chains: BTreeMap<ChainId, ChainStatus>,
+    #[serde(default)]
+    pub share_serving: ShareServingStatus,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ShareServingStatus {
+    /// Whether share requests are being served at all. Sync continues while serving is paused.
+    pub enabled: bool,
+    /// How many share requests are being served right now.
+    pub in_flight: usize,
+    /// How many share requests may be served at once before more are refused.
+    pub limit: usize,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChainStatus {
+    pub health: ChainHealth,
+    /// The latest block that sync has finished.
+    #[serde(default)]
+    pub processed_block: u64,
+    /// The highest head that the chain's provider has reported, if it has been asked yet.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub head_block: Option<u64>,
+    /// How much of the chain from the permitter's creation through `head_block` has been synced,
+    /// from 0 to 1.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub progress: Option<f64>,
+    /// Why sync last exited, if it has not processed a block since restarting.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sync_error: Option<String>,
+    /// Every deal seen since the node started, so that holders of each deal can be counted
+    /// across nodes.
+    #[serde(default)]
+    pub shares: Vec<TrackedShare>,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct TrackedShare {
+    pub identity: IdentityId,
+    pub secret_name: String,
+    pub version: u64,
+    /// Whether one of the dealt shares was for this node.
+    pub held: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub index: Option<ShareIndex>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ChainHealth {
+    Healthy,
+    /// The chain's provider is refusing requests, so sync is backing off.
+    RateLimited,
+    /// Sync has not processed a block in too long, although the chain has moved past it.
+    Stalled,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ProcessedResponse {
+    /// Whether every event through the block has been processed, as of the saved sync state,
+    /// which trails the `processed_block` of the chain's status.
+    pub processed: bool,
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct ErrorResponse {
     pub error: String,
@@ -217,3 +340,38 @@ impl std::ops::Deref for RequesterPublicKeyHeader {
         &self.0
     }
 }
+
+/// Asks for shares to be encrypted as [`ShareResponseFormat::EncAes256GcmSivEphemeral`] rather
+/// than under the node's ephemeral identity, which lives as long as the node's process.
+/// Nodes that predate it ignore it, so clients must handle either format.
+pub struct ShareEncapsulationHeader;
+
+static SHARE_ENCAPSULATION_HEADER_NAME: header::HeaderName =
+    header::HeaderName::from_static("share-encapsulation");
+
+impl ShareEncapsulationHeader {
+    pub const VALUE: &'static str = "ephemeral";
+}
+
+impl headers::Header for ShareEncapsulationHeader {
+    fn name() -> &'static header::HeaderName {
+        &SHARE_ENCAPSULATION_HEADER_NAME
+    }
+
+    fn decode<'i, I>(values: &mut I) -> Result<Self, headers::Error>
+    where
+        Self: Sized,
+        I: Iterator<Item = &'i header::HeaderValue>,
+    {
+        match values.next() {
+            Some(v) if v == Self::VALUE => Ok(Self),
+            _ => Err(headers::Error::invalid()),
+        }
+    }
+
+    fn encode<E: Extend<header::HeaderValue>>(&self, values: &mut E) {
+        values.extend(std::iter::once(header::HeaderValue::from_static(
+            Self::VALUE,
+        )));
+    }
+}
@@ -11,6 +11,29 @@ pub type ChainId = u64;
 pub type ShareVersion = u64;
 pub type KeyVersion = u64;
 
+/// The position, starting at 0, of a share in the list of shares that were dealt.
+///
+/// This is not the Shamir x-coordinate, which the share's encoding carries itself.
+#[derive(
+    Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct ShareIndex(pub u64);
+
+impl std::ops::Deref for ShareIndex {
+    type Target = u64;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl std::fmt::Display for ShareIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(transparent)]
 pub struct IdentityId(pub H256);
@@ -76,19 +99,105 @@ pub struct Permit {
 #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ChainState {
     pub block: u64,
+    /// The first log in `block` that has not been processed.
+    #[serde(default)]
+    pub log_index: u64,
+    /// The hash that `block` had when it was processed, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_hash: Option<H256>,
+}
+
+impl ChainState {
+    pub fn resume_point(&self) -> EventIndex {
+        EventIndex {
+            block: self.block,
+            log_index: self.log_index,
+        }
+    }
 }
 
 #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct ChainStateUpdate {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub block: Option<u64>,
+    /// The hash of `block`, which is saved along with it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_hash: Option<H256>,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 #[cfg_attr(test, derive(Debug, PartialEq, Eq))]
 pub struct SecretShare {
-    pub index: u64,
+    pub index: ShareIndex,
+    #[serde(with = "crate::utils::bytes_serde")]
     pub share: zeroize::Zeroizing<Vec<u8>>,
+    /// Where the share came from. Kept for audits; never served or used by any crypto.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provenance: Option<ShareProvenance>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareProvenance {
+    /// The `SharesDealt` event that carried the share.
+    pub event: EventIndex,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tx: Option<H256>,
+    /// The dealer's public key: SEC1-encoded P-384, or raw X25519.
+    #[serde(with = "crate::utils::bytes_serde")]
+    pub dealer_pk: Vec<u8>,
+    /// Seconds since the Unix epoch at which the share was stored.
+    pub stored_at: u64,
+}
+
+/// A node's signed statement that it stored the share dealt by the event at `event`.
+/// A dealer holding valid receipts from a threshold of nodes knows that the secret can be
+/// reconstructed. Receipts contain no secret material.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ShareReceipt {
+    pub share: ShareId,
+    pub event: EventIndex,
+}
+
+impl ShareReceipt {
+    /// Returns the ABI encoding of the receipt under [`SHARE_RECEIPT_DOMAIN_SEP`], which is what a
+    /// node signs.
+    ///
+    /// [`SHARE_RECEIPT_DOMAIN_SEP`]: crate::identity::SHARE_RECEIPT_DOMAIN_SEP
+    pub fn signing_bytes(&self) -> Vec<u8> {
+        use ethers::abi::Token;
+        let Self {
+            share:
+                ShareId {
+                    secret_name,
+                    identity:
+                        IdentityLocator {
+                            chain,
+                            registry,
+                            id: IdentityId(identity),
+                        },
+                    version,
+                },
+            event: EventIndex { block, log_index },
+        } = self;
+        ethers::abi::encode(&[
+            Token::Bytes(crate::identity::SHARE_RECEIPT_DOMAIN_SEP.to_vec()),
+            Token::String(secret_name.clone()),
+            Token::Uint((*chain).into()),
+            Token::Address(*registry),
+            Token::FixedBytes(identity.as_bytes().to_vec()),
+            Token::Uint((*version).into()),
+            Token::Uint((*block).into()),
+            Token::Uint((*log_index).into()),
+        ])
+    }
+
+    /// Returns whether `signature` is the node identity `signer`'s signature of this receipt.
+    pub fn verify(&self, signer: &p384::PublicKey, signature: &p384::ecdsa::Signature) -> bool {
+        use p384::ecdsa::signature::Verifier as _;
+        p384::ecdsa::VerifyingKey::from(signer)
+            .verify(&self.signing_bytes(), signature)
+            .is_ok()
+    }
 }
 
 #[derive(Clone, Serialize, Deserialize, zeroize::Zeroize)]
@@ -113,7 +222,7 @@ impl AsRef<[u8]> for WrappedKey {
     }
 }
 
-#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct EventIndex {
     pub block: u64,
     pub log_index: u64,
@@ -133,8 +242,80 @@ pub struct SsssRequest {
     pub body: H256,
 }
 
+/// The verifier config recorded in place of a policy that could not be decoded.
+/// It never decodes as a [`PolicyPreamble`], so every verification against it fails.
+pub const BROKEN_POLICY: &[u8] = &[];
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct PolicyPreamble {
     pub verifier: String,
     pub policy: Vec<u8>,
 }
+
+impl PolicyPreamble {
+    /// Decodes the CBOR preamble of a decompressed policy config.
+    pub fn decode(config: &[u8]) -> Result<Self, ciborium::de::Error<std::io::Error>> {
+        ciborium::de::from_reader_with_recursion_limit(config, 10)
+    }
+}
+
+/// A JSON Merge Patch (RFC 7396) of a decompressed policy config. Configs are CBOR, so the patch
+/// is applied to the config's maps as it would be to the equivalent JSON objects.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct PolicyPatch(pub serde_json::Value);
+
+#[derive(Debug, thiserror::Error)]
+pub enum PolicyPatchError {
+    #[error("failed to decode policy config: {0}")]
+    Decode(#[from] ciborium::de::Error<std::io::Error>),
+    #[error("failed to convert patch to CBOR: {0}")]
+    Convert(#[from] ciborium::value::Error),
+    #[error("failed to encode patched policy config: {0}")]
+    Encode(#[from] ciborium::ser::Error<std::io::Error>),
+}
+
+impl PolicyPatch {
+    pub fn apply(&self, config: &[u8]) -> Result<Vec<u8>, PolicyPatchError> {
+        let mut patched: ciborium::Value =
+            ciborium::de::from_reader_with_recursion_limit(config, 10)?;
+        merge_patch(&mut patched, &self.0)?;
+        let mut config = Vec::with_capacity(config.len());
+        ciborium::ser::into_writer(&patched, &mut config)?;
+        Ok(config)
+    }
+}
+
+fn merge_patch(
+    target: &mut ciborium::Value,
+    patch: &serde_json::Value,
+) -> Result<(), ciborium::value::Error> {
+    let serde_json::Value::Object(patch) = patch else {
+        *target = ciborium::Value::serialized(patch)?;
+        return Ok(());
+    };
+    if !target.is_map() {
+        *target = ciborium::Value::Map(Vec::new());
+    }
+    let ciborium::Value::Map(entries) = target else {
+        unreachable!()
+    };
+    for (key, value) in patch {
+        let existing = entries
+            .iter()
+            .position(|(k, _)| k.as_text() == Some(key.as_str()));
+        match (existing, value) {
+            (Some(pos), serde_json::Value::Null) => {
+                entries.remove(pos);
+            }
+            (None, serde_json::Value::Null) => {}
+            (Some(pos), value) => merge_patch(&mut entries[pos].1, value)?,
+            (None, value) => {
+                let mut entry = ciborium::Value::Null;
+                merge_patch(&mut entry, value)?;
+                entries.push((ciborium::Value::Text(key.clone()), entry));
+            }
+        }
+    }
+    Ok(())
+}
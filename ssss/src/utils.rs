@@ -1,55 +1,645 @@
-use tokio::time::{sleep, Duration};
-use tracing::warn;
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Mutex,
+    task::{Context, Poll},
+};
 
-pub async fn retry<T, E, Fut>(f: impl Fn() -> Fut) -> T
+use futures_util::stream::{FuturesOrdered, Stream, StreamExt as _};
+use once_cell::sync::OnceCell;
+use pin_project_lite::pin_project;
+use prometheus::IntGauge;
+use rand::{rngs::SmallRng, Rng, SeedableRng as _};
+use tokio::time::{sleep, Duration, Instant};
+use tracing::{trace, warn};
+
+use crate::metrics;
+
+/// How long to wait between attempts and when to give up. Every helper here uses the default
+/// policy unless it is given one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The most that the first retry waits. The bound doubles with each failure.
+    pub base_delay: Duration,
+    /// The most that any retry waits, unless the error asks for a longer backoff.
+    pub max_delay: Duration,
+    /// Give up instead of retrying once this much time would have passed since the first attempt.
+    pub max_elapsed: Option<Duration>,
+    /// Give up after this many failed attempts.
+    pub max_failures: Option<u64>,
+    /// How long to wait before asking again after an attempt that succeeded but was not done.
+    /// Such attempts are polls rather than failures, so they neither back off nor count.
+    pub poll_interval: Duration,
+    /// Seeds the jitter of each sequence of retries, so that the sequence repeats. Without one,
+    /// each sequence draws its jitter from fresh entropy.
+    pub rng_seed: Option<u64>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            base_delay: Duration::from_millis(1500),
+            max_delay: Duration::from_secs(30),
+            max_elapsed: None,
+            max_failures: None,
+            poll_interval: Duration::from_millis(1500),
+            rng_seed: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn with_rng_seed(self, seed: u64) -> Self {
+        Self {
+            rng_seed: Some(seed),
+            ..self
+        }
+    }
+
+    /// Returns the source of jitter for one sequence of retries.
+    pub fn rng(&self) -> SmallRng {
+        match self.rng_seed {
+            Some(seed) => SmallRng::seed_from_u64(seed),
+            None => SmallRng::from_entropy(),
+        }
+    }
+
+    /// Returns a delay drawn uniformly from zero through the exponential bound for the retry
+    /// after `failures` failures, so that retries that failed together do not retry together.
+    pub fn delay(&self, failures: u64, rng: &mut impl Rng) -> Duration {
+        let doublings = failures.saturating_sub(1).min(31) as u32;
+        let bound = self
+            .base_delay
+            .saturating_mul(1 << doublings)
+            .min(self.max_delay);
+        rng.gen_range(Duration::ZERO..=bound)
+    }
+}
+
+pub async fn retry<T, E, Fut>(operation: &'static str, f: impl Fn() -> Fut) -> T
+where
+    E: std::fmt::Display,
+    Fut: std::future::Future<Output = Result<T, E>>,
+{
+    unlimited(do_retry(operation, &Default::default(), f, Some, |_| None, |_| true).await)
+}
+
+/// Like [`retry`], but returns the error instead of retrying once `should_retry` rejects it, such
+/// as for an error that no retry could fix.
+pub async fn retry_while<T, E, Fut>(
+    operation: &'static str,
+    f: impl Fn() -> Fut,
+    should_retry: impl Fn(&E) -> bool,
+) -> Result<T, E>
+where
+    E: std::fmt::Display,
+    Fut: std::future::Future<Output = Result<T, E>>,
+{
+    retry_while_with_policy(operation, &Default::default(), f, should_retry).await
+}
+
+/// Like [`retry_while`], but retries as `policy` says, returning the last error if it gives up.
+pub async fn retry_while_with_policy<T, E, Fut>(
+    operation: &'static str,
+    policy: &RetryPolicy,
+    f: impl Fn() -> Fut,
+    should_retry: impl Fn(&E) -> bool,
+) -> Result<T, E>
+where
+    E: std::fmt::Display,
+    Fut: std::future::Future<Output = Result<T, E>>,
+{
+    do_retry(operation, policy, f, Some, |_| None, should_retry)
+        .await
+        .map_err(|e| match e {
+            GaveUp::Refused(e) | GaveUp::Exceeded(Some(e)) => e,
+            GaveUp::Exceeded(None) => unreachable!("every attempt is done unless it fails"),
+        })
+}
+
+pub async fn retry_if<T, E, U, Fut>(
+    operation: &'static str,
+    f: impl Fn() -> Fut,
+    map_done: impl Fn(T) -> Option<U>,
+) -> U
 where
     E: std::fmt::Display,
     Fut: std::future::Future<Output = Result<T, E>>,
 {
-    do_retry(f, Some, None).await.unwrap()
+    unlimited(
+        do_retry(
+            operation,
+            &Default::default(),
+            f,
+            map_done,
+            |_| None,
+            |_| true,
+        )
+        .await,
+    )
 }
 
-pub async fn retry_if<T, E, U, Fut>(f: impl Fn() -> Fut, map_done: impl Fn(T) -> Option<U>) -> U
+/// Like [`retry_if`], but waits for `backoff(&err)` instead of the usual delay when it is `Some`.
+pub async fn retry_if_with_backoff<T, E, U, Fut>(
+    operation: &'static str,
+    f: impl Fn() -> Fut,
+    map_done: impl Fn(T) -> Option<U>,
+    backoff: impl Fn(&E) -> Option<Duration>,
+) -> U
 where
     E: std::fmt::Display,
     Fut: std::future::Future<Output = Result<T, E>>,
 {
-    do_retry(f, map_done, None).await.unwrap()
+    unlimited(
+        do_retry(operation, &Default::default(), f, map_done, backoff, |_| {
+            true
+        })
+        .await,
+    )
 }
 
-pub async fn retry_times<T, E, Fut>(f: impl Fn() -> Fut, limit: u64) -> Result<T, RetriesExceeded>
+pub async fn retry_times<T, E, Fut>(
+    operation: &'static str,
+    f: impl Fn() -> Fut,
+    limit: u64,
+) -> Result<T, RetriesExceeded>
 where
     E: std::fmt::Display,
     Fut: std::future::Future<Output = Result<T, E>>,
 {
-    do_retry(f, Some, Some(limit)).await
+    let policy = RetryPolicy {
+        max_failures: Some(limit),
+        ..Default::default()
+    };
+    do_retry(operation, &policy, f, Some, |_| None, |_| true)
+        .await
+        .map_err(|_| RetriesExceeded)
+}
+
+/// Why [`do_retry`] stopped retrying.
+enum GaveUp<E> {
+    /// The policy allowed no more retries. Holds the last error, unless the last attempt instead
+    /// returned a value that was not done.
+    Exceeded(Option<E>),
+    Refused(E),
 }
 
+fn unlimited<U, E>(res: Result<U, GaveUp<E>>) -> U {
+    match res {
+        Ok(val) => val,
+        Err(_) => unreachable!("retries are unlimited and every error is retried"),
+    }
+}
+
+/// Calls `f` until `map_done` accepts its result, waiting between failed attempts as `policy`
+/// says or, for errors that ask for one, as `backoff` says. Each retry is logged and counted under
+/// `operation`. Results that are not done are polled again at the policy's fixed interval.
 async fn do_retry<T, E, U, Fut>(
+    operation: &'static str,
+    policy: &RetryPolicy,
     f: impl Fn() -> Fut,
     map_done: impl Fn(T) -> Option<U>,
-    limit: Option<u64>,
-) -> Result<U, RetriesExceeded>
+    backoff: impl Fn(&E) -> Option<Duration>,
+    should_retry: impl Fn(&E) -> bool,
+) -> Result<U, GaveUp<E>>
 where
     E: std::fmt::Display,
     Fut: std::future::Future<Output = Result<T, E>>,
 {
+    let started = Instant::now();
     let mut failures = 0;
+    let mut rng = policy.rng();
     loop {
-        match limit {
-            Some(limit) if failures >= limit => return Err(RetriesExceeded),
-            _ => {}
-        }
-        match f().await.map(&map_done) {
+        let (e, backoff) = match f().await.map(&map_done) {
             Ok(Some(val)) => return Ok(val),
-            Err(e) => warn!("failed: {e}"),
-            _ => {}
-        }
+            Err(e) if !should_retry(&e) => return Err(GaveUp::Refused(e)),
+            Err(e) => {
+                let backoff = backoff(&e);
+                (e, backoff)
+            }
+            Ok(None) => {
+                let delay = policy.poll_interval;
+                if policy
+                    .max_elapsed
+                    .is_some_and(|max| started.elapsed() + delay > max)
+                {
+                    metrics::RETRIES_EXHAUSTED
+                        .with_label_values(&[operation])
+                        .inc();
+                    return Err(GaveUp::Exceeded(None));
+                }
+                trace!(operation, delay = ?delay, "not done; polling again");
+                sleep(delay).await;
+                continue;
+            }
+        };
+        let kind = match backoff {
+            Some(_) => "backoff",
+            None => "error",
+        };
         failures += 1;
-        sleep(Duration::from_millis(1500)).await;
+        let delay = backoff.unwrap_or_else(|| policy.delay(failures, &mut rng));
+        let exceeded = policy.max_failures.is_some_and(|max| failures >= max)
+            || policy
+                .max_elapsed
+                .is_some_and(|max| started.elapsed() + delay > max);
+        if exceeded {
+            warn!(
+                operation,
+                attempt = failures,
+                "giving up after failure: {e}"
+            );
+            metrics::RETRIES_EXHAUSTED
+                .with_label_values(&[operation])
+                .inc();
+            return Err(GaveUp::Exceeded(Some(e)));
+        }
+        warn!(
+            operation,
+            attempt = failures,
+            delay = ?delay,
+            "retrying after failure: {e}"
+        );
+        metrics::RETRIES.with_label_values(&[operation, kind]).inc();
+        metrics::RETRY_DELAY
+            .with_label_values(&[operation])
+            .observe(delay.as_secs_f64());
+        sleep(delay).await;
+        retry_budget().acquire().await;
     }
 }
 
+static RETRY_BUDGET: OnceCell<RetryBudget> = OnceCell::new();
+
+/// Sets the budget shared by all retries in the process.
+/// Fails if the budget was already set or a retry has already occurred.
+pub fn set_retry_budget(budget: RetryBudget) -> Result<(), RetryBudget> {
+    RETRY_BUDGET.set(budget)
+}
+
+fn retry_budget() -> &'static RetryBudget {
+    RETRY_BUDGET.get_or_init(Default::default)
+}
+
+/// A token bucket that caps the total rate of retries so that a recovering node
+/// is not swamped by the retries that accumulated during an outage.
+#[derive(Debug)]
+pub struct RetryBudget {
+    capacity: f64,
+    refill_per_sec: f64,
+    state: Mutex<BudgetState>,
+}
+
+#[derive(Debug)]
+struct BudgetState {
+    tokens: f64,
+    refilled_at: Instant,
+}
+
+impl RetryBudget {
+    /// Creates a budget that allows bursts of `capacity` retries and
+    /// `refill_per_sec` retries per second thereafter.
+    pub fn new(capacity: u32, refill_per_sec: f64) -> Result<Self, InvalidRefillRate> {
+        if !(refill_per_sec.is_finite() && refill_per_sec > 0.0) {
+            return Err(InvalidRefillRate(refill_per_sec));
+        }
+        let capacity = f64::from(capacity.max(1));
+        Ok(Self {
+            capacity,
+            refill_per_sec,
+            state: Mutex::new(BudgetState {
+                tokens: capacity,
+                refilled_at: Instant::now(),
+            }),
+        })
+    }
+
+    /// Takes one retry token, waiting for one to be refilled if the budget is exhausted.
+    pub async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let now = Instant::now();
+                let elapsed = now.duration_since(state.refilled_at).as_secs_f64();
+                state.tokens = (state.tokens + elapsed * self.refill_per_sec).min(self.capacity);
+                state.refilled_at = now;
+                if state.tokens >= 1.0 {
+                    state.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - state.tokens) / self.refill_per_sec)
+            };
+            sleep(wait).await;
+        }
+    }
+}
+
+impl Default for RetryBudget {
+    fn default() -> Self {
+        Self::new(100, 20.0).unwrap()
+    }
+}
+
+#[derive(Clone, Copy, Debug, thiserror::Error)]
+#[error("retry budget must refill at a finite, positive rate, not {0}")]
+pub struct InvalidRefillRate(pub f64);
+
 #[derive(Clone, Copy, Debug, Default, thiserror::Error)]
 #[error("retries exceeded")]
 pub struct RetriesExceeded;
+
+pin_project! {
+    /// Runs up to `limit` of a stream's futures at once and yields their outputs in order, like
+    /// `StreamExt::buffered`, while counting in `depth` the outputs that are not yet consumed.
+    /// Those are the buffered futures plus the last output, which is counted as consumed once the
+    /// next one is requested.
+    pub struct CountingStream<S>
+    where
+        S: Stream,
+        S::Item: Future,
+    {
+        #[pin]
+        inner: S,
+        inner_done: bool,
+        queue: FuturesOrdered<S::Item>,
+        limit: usize,
+        depth: IntGauge,
+        yielded: bool,
+    }
+
+    impl<S> PinnedDrop for CountingStream<S>
+    where
+        S: Stream,
+        S::Item: Future,
+    {
+        fn drop(this: Pin<&mut Self>) {
+            let this = this.project();
+            this.depth
+                .sub(this.queue.len() as i64 + i64::from(*this.yielded));
+        }
+    }
+}
+
+impl<S> CountingStream<S>
+where
+    S: Stream,
+    S::Item: Future,
+{
+    pub fn new(inner: S, limit: usize, depth: IntGauge) -> Self {
+        Self {
+            inner,
+            inner_done: false,
+            queue: FuturesOrdered::new(),
+            limit,
+            depth,
+            yielded: false,
+        }
+    }
+}
+
+impl<S> Stream for CountingStream<S>
+where
+    S: Stream,
+    S::Item: Future,
+{
+    type Item = <S::Item as Future>::Output;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+        if std::mem::take(this.yielded) {
+            this.depth.dec();
+        }
+        while !*this.inner_done && this.queue.len() < *this.limit {
+            match this.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(fut)) => {
+                    this.queue.push_back(fut);
+                    this.depth.inc();
+                }
+                Poll::Ready(None) => *this.inner_done = true,
+                Poll::Pending => break,
+            }
+        }
+        match this.queue.poll_next_unpin(cx) {
+            Poll::Ready(Some(output)) => {
+                *this.yielded = true;
+                Poll::Ready(Some(output))
+            }
+            Poll::Ready(None) if *this.inner_done => Poll::Ready(None),
+            _ => Poll::Pending,
+        }
+    }
+}
+
+/// Serializes bytes as hex in human-readable formats and as a byte string otherwise.
+pub mod bytes_serde {
+    use serde::{de, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&zeroize::Zeroizing::new(hex::encode(bytes)))
+        } else {
+            serializer.serialize_bytes(bytes)
+        }
+    }
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: From<Vec<u8>>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Vec<u8>;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                f.write_str("bytes or a hex string")
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                hex::decode(v).map_err(E::custom)
+            }
+
+            fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<Self::Value, E> {
+                Ok(v.to_vec())
+            }
+
+            fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<Self::Value, E> {
+                Ok(v)
+            }
+        }
+
+        let bytes = if deserializer.is_human_readable() {
+            deserializer.deserialize_str(Visitor)
+        } else {
+            deserializer.deserialize_byte_buf(Visitor)
+        };
+        bytes.map(Into::into)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_budget_defers_when_exhausted() {
+        let budget = RetryBudget::new(2, 1.0).unwrap();
+        let start = Instant::now();
+        budget.acquire().await;
+        budget.acquire().await;
+        assert_eq!(start.elapsed(), Duration::ZERO);
+        budget.acquire().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(1));
+        budget.acquire().await;
+        assert_eq!(start.elapsed(), Duration::from_secs(2));
+    }
+
+    #[test]
+    fn retry_budget_must_refill() {
+        for rate in [0.0, -1.0, f64::NAN, f64::INFINITY] {
+            assert!(RetryBudget::new(2, rate).is_err(), "{rate}");
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_while_stops_at_rejected_error() {
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let res: Result<(), &str> = retry_while(
+            "test",
+            || {
+                attempts.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                futures_util::future::ready(Err("permanent"))
+            },
+            |e| *e != "permanent",
+        )
+        .await;
+        assert_eq!(res, Err("permanent"));
+        assert_eq!(attempts.into_inner(), 1);
+    }
+
+    #[test]
+    fn retry_delays_are_jittered_and_capped() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(5),
+            ..Default::default()
+        };
+        for (failures, bound) in [(1, 1), (2, 2), (3, 4), (4, 5), (64, 5)] {
+            let mut rng = policy.rng();
+            let delays: Vec<_> = (0..100).map(|_| policy.delay(failures, &mut rng)).collect();
+            assert!(delays.iter().all(|d| *d <= Duration::from_secs(bound)));
+            assert!(
+                delays.iter().any(|d| *d != delays[0]),
+                "delays are not jittered"
+            );
+        }
+    }
+
+    #[test]
+    fn seeded_retry_delays_repeat() {
+        let policy = RetryPolicy::default().with_rng_seed(42);
+        let delays = |policy: &RetryPolicy| {
+            let mut rng = policy.rng();
+            (1..=8)
+                .map(|failures| policy.delay(failures, &mut rng).as_millis())
+                .collect::<Vec<_>>()
+        };
+        let first = delays(&policy);
+        assert_eq!(first, delays(&policy));
+        assert_ne!(first, delays(&policy.with_rng_seed(43)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn not_done_results_poll_at_a_fixed_interval() {
+        let policy = RetryPolicy {
+            poll_interval: Duration::from_secs(2),
+            max_failures: Some(1),
+            ..Default::default()
+        };
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let retries_before = metrics::RETRIES
+            .with_label_values(&["poll_test", "error"])
+            .get();
+        let start = Instant::now();
+        let res = do_retry(
+            "poll_test",
+            &policy,
+            || {
+                let attempt = attempts.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                futures_util::future::ready(Ok::<_, &str>(attempt))
+            },
+            |attempt| (attempt == 10).then_some(attempt),
+            |_| None,
+            |_| true,
+        )
+        .await;
+        // Ten polls are not failures, so they neither exhaust the policy nor back off.
+        assert!(matches!(res, Ok(10)));
+        assert_eq!(start.elapsed(), Duration::from_secs(20));
+        assert_eq!(
+            metrics::RETRIES
+                .with_label_values(&["poll_test", "error"])
+                .get(),
+            retries_before
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn retry_gives_up_after_max_elapsed() {
+        let policy = RetryPolicy {
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(1),
+            max_elapsed: Some(Duration::from_secs(10)),
+            ..Default::default()
+        };
+        let attempts = std::sync::atomic::AtomicU32::new(0);
+        let start = Instant::now();
+        let res: Result<(), u32> = retry_while_with_policy(
+            "test",
+            &policy,
+            || {
+                let attempt = attempts.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                futures_util::future::ready(Err(attempt))
+            },
+            |_| true,
+        )
+        .await;
+        let attempts = attempts.into_inner();
+        assert_eq!(res, Err(attempts - 1), "the last error is returned");
+        assert!(attempts > 10);
+        assert!(start.elapsed() <= Duration::from_secs(10));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn counting_stream_tracks_unconsumed_outputs() {
+        let depth = IntGauge::new("depth", "depth").unwrap();
+        let produced = futures_util::stream::iter(0..10).map(futures_util::future::ready);
+        let mut peak = 0;
+        let consumed: Vec<_> = CountingStream::new(produced, 3, depth.clone())
+            .then(|i| {
+                peak = peak.max(depth.get());
+                async move {
+                    // The consumer is slower than the producer, so the buffer stays full.
+                    sleep(Duration::from_millis(10)).await;
+                    i
+                }
+            })
+            .collect()
+            .await;
+        assert_eq!(consumed, (0..10).collect::<Vec<_>>());
+        assert_eq!(peak, 3);
+        assert_eq!(depth.get(), 0);
+    }
+
+    #[tokio::test]
+    async fn counting_stream_drains_on_drop() {
+        let depth = IntGauge::new("depth", "depth").unwrap();
+        let produced = futures_util::stream::iter(0..10).map(futures_util::future::ready);
+        let mut stream = CountingStream::new(produced, 3, depth.clone());
+        stream.next().await;
+        assert_eq!(depth.get(), 3);
+        drop(stream);
+        assert_eq!(depth.get(), 0);
+    }
+}
@@ -0,0 +1,321 @@
+use ethers::{
+    abi::AbiEncode as _,
+    types::{Bytes, Signature},
+};
+use serde::Deserialize;
+
+use super::*;
+
+/// Grants permits signed by one of the authorities that the identity's policy trusts, such as a
+/// service that decides off-chain who may act as the identity.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct AuthorityVerifier;
+
+impl Verifier for AuthorityVerifier {
+    async fn verify(
+        &self,
+        policy_bytes: &[u8],
+        req: RequestKind,
+        identity: IdentityLocator,
+        recipient: Address,
+        authorization: &[u8],
+        _context: &[u8],
+        _relayer: Option<Address>,
+    ) -> Result<Verification, Error> {
+        let policy: Policy = ciborium::de::from_reader_with_recursion_limit(policy_bytes, 10)
+            .map_err(|e| Error::PolicyDecode(anyhow::Error::from(e)))?;
+        if policy.version != 1 {
+            return Err(Error::PolicyDecode(anyhow::anyhow!(
+                "unsupported authority policy version {}",
+                policy.version
+            )));
+        }
+        let permit: SignedPermit = ciborium::de::from_reader_with_recursion_limit(authorization, 3)
+            .map_err(|e| Error::PermitDecode(anyhow::Error::from(e)))?;
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        policy.check(&permit, req, identity, recipient, now)?;
+        Ok(Verification {
+            nonce: permit.nonce,
+            public_key: vec![],
+            expiry: match req {
+                RequestKind::Grant { duration } => Some(duration.min(policy.max_duration)),
+                RequestKind::Revoke => None,
+            },
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[forbid(unused)]
+struct Policy {
+    version: u8,
+    authorities: Vec<Authority>,
+    #[serde(default)]
+    max_duration: u64,
+    /// How long after it is retired that an authority's signatures are still accepted (seconds),
+    /// so that permits issued just before a key rotation are not refused.
+    #[serde(default)]
+    rotation_grace: u64,
+    /// Signed permit max age (seconds)
+    #[serde(default = "default_max_age")]
+    max_age: u64,
+}
+
+fn default_max_age() -> u64 {
+    15 * 60 // 15 minutes
+}
+
+#[derive(Deserialize)]
+struct Authority {
+    signer: Address,
+    /// When the authority was replaced, in seconds since the Unix epoch. Only permits that it
+    /// issued before then are accepted, and only until the policy's `rotation_grace` has passed.
+    #[serde(default)]
+    retired_at: Option<u64>,
+}
+
+/// What an authority signs to grant or revoke a permit.
+#[derive(Deserialize)]
+struct SignedPermit {
+    #[serde(with = "crate::utils::bytes_serde")]
+    nonce: Vec<u8>,
+    /// When the authority signed the permit, in seconds since the Unix epoch.
+    issued_at: u64,
+    /// An EIP-191 signature of [`SignedPermit::digest`].
+    #[serde(with = "crate::utils::bytes_serde")]
+    signature: Vec<u8>,
+}
+
+/// Prefixes what authorities sign, so that their signatures of other messages are not permits.
+const DIGEST_DOMAIN: &[u8] = b"ssss-authority-permit-v1";
+
+impl SignedPermit {
+    fn digest(&self, req: RequestKind, identity: IdentityLocator, recipient: Address) -> [u8; 32] {
+        let (grant, duration) = match req {
+            RequestKind::Grant { duration } => (true, duration),
+            RequestKind::Revoke => (false, 0),
+        };
+        let fields = (
+            identity.chain,
+            identity.registry,
+            identity.id.0,
+            recipient,
+            grant,
+            duration,
+            Bytes::from(self.nonce.clone()),
+            self.issued_at,
+        )
+            .encode();
+        ethers::core::utils::keccak256([DIGEST_DOMAIN, &fields].concat())
+    }
+}
+
+impl Policy {
+    fn check(
+        &self,
+        permit: &SignedPermit,
+        req: RequestKind,
+        identity: IdentityLocator,
+        recipient: Address,
+        now: u64,
+    ) -> Result<(), Error> {
+        if permit.issued_at > now + 5 {
+            return Err(Error::Timing("signed permit not yet valid".into()));
+        }
+        if now.saturating_sub(permit.issued_at) >= self.max_age {
+            return Err(Error::Timing("signed permit expired".into()));
+        }
+        let signer = Signature::try_from(permit.signature.as_slice())
+            .and_then(|sig| sig.recover(&permit.digest(req, identity, recipient)[..]))
+            .map_err(|_| Error::Unauthorized("invalid permit signature".into()))?;
+        let authority = self
+            .authorities
+            .iter()
+            .find(|authority| authority.signer == signer)
+            .ok_or_else(|| Error::Unauthorized("permit not signed by an authority".into()))?;
+        match authority.retired_at {
+            Some(retired_at)
+                if permit.issued_at >= retired_at
+                    || now >= retired_at.saturating_add(self.rotation_grace) =>
+            {
+                Err(Error::Unauthorized(
+                    "permit signed by a retired authority".into(),
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ciborium::cbor;
+    use ethers::{
+        signers::{LocalWallet, Signer as _},
+        types::H256,
+    };
+
+    use super::*;
+    use crate::types::IdentityId;
+
+    const NOW: u64 = 1_700_000_000;
+
+    async fn sign(
+        wallet: &LocalWallet,
+        req: RequestKind,
+        identity: IdentityLocator,
+        recipient: Address,
+        issued_at: u64,
+    ) -> SignedPermit {
+        let mut permit = SignedPermit {
+            nonce: H256::random().0.to_vec(),
+            issued_at,
+            signature: vec![],
+        };
+        let digest = permit.digest(req, identity, recipient);
+        permit.signature = wallet.sign_message(digest).await.unwrap().to_vec();
+        permit
+    }
+
+    #[tokio::test]
+    async fn accept_only_current_authorities() {
+        let (current, retired, stranger) = (
+            LocalWallet::new(&mut rand::thread_rng()),
+            LocalWallet::new(&mut rand::thread_rng()),
+            LocalWallet::new(&mut rand::thread_rng()),
+        );
+        let policy = Policy {
+            version: 1,
+            authorities: vec![
+                Authority {
+                    signer: current.address(),
+                    retired_at: None,
+                },
+                Authority {
+                    signer: retired.address(),
+                    retired_at: Some(NOW - 60),
+                },
+            ],
+            max_duration: 3600,
+            rotation_grace: 120,
+            max_age: default_max_age(),
+        };
+        let identity = IdentityLocator {
+            chain: 31337,
+            registry: Address::repeat_byte(1),
+            id: IdentityId(H256::random()),
+        };
+        let recipient = Address::repeat_byte(2);
+        let grant = RequestKind::Grant { duration: 60 };
+        let check =
+            |permit: &SignedPermit, now| policy.check(permit, grant, identity, recipient, now);
+
+        let permit = sign(&current, grant, identity, recipient, NOW).await;
+        check(&permit, NOW).unwrap();
+        // The signature covers the request, so it cannot be used for another.
+        assert!(matches!(
+            policy.check(&permit, RequestKind::Revoke, identity, recipient, NOW),
+            Err(Error::Unauthorized(_))
+        ));
+        assert!(matches!(
+            check(&permit, NOW + default_max_age()),
+            Err(Error::Timing(_))
+        ));
+
+        // Permits issued before the rotation are honored through the grace period.
+        let permit = sign(&retired, grant, identity, recipient, NOW - 90).await;
+        check(&permit, NOW).unwrap();
+        assert!(matches!(
+            check(&permit, NOW + 60),
+            Err(Error::Unauthorized(_))
+        ));
+        // Those issued after it are not, in case the key was retired because it leaked.
+        let permit = sign(&retired, grant, identity, recipient, NOW).await;
+        assert!(matches!(check(&permit, NOW), Err(Error::Unauthorized(_))));
+
+        let permit = sign(&stranger, grant, identity, recipient, NOW).await;
+        assert!(matches!(check(&permit, NOW), Err(Error::Unauthorized(_))));
+    }
+
+    #[tokio::test]
+    async fn verify_cbor_policy_and_permit() {
+        let authority = LocalWallet::new(&mut rand::thread_rng());
+        let mut policy = vec![];
+        ciborium::ser::into_writer(
+            &cbor!({
+                "version" => 1,
+                "authorities" => [{ "signer" => format!("{:?}", authority.address()) }],
+                "max_duration" => 3600,
+            })
+            .unwrap(),
+            &mut policy,
+        )
+        .unwrap();
+        let mut config = vec![];
+        ciborium::ser::into_writer(
+            &crate::types::PolicyPreamble {
+                verifier: "authority".into(),
+                policy,
+            },
+            &mut config,
+        )
+        .unwrap();
+        let identity = IdentityLocator {
+            chain: 31337,
+            registry: Address::repeat_byte(1),
+            id: IdentityId(H256::random()),
+        };
+        let recipient = Address::repeat_byte(2);
+        let grant = RequestKind::Grant { duration: 7200 };
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs();
+        let permit = sign(&authority, grant, identity, recipient, now).await;
+        let encode = |signature: &[u8]| {
+            let mut authorization = vec![];
+            ciborium::ser::into_writer(
+                &cbor!({
+                    "nonce" => ciborium::value::Value::Bytes(permit.nonce.clone()),
+                    "issued_at" => permit.issued_at,
+                    "signature" => ciborium::value::Value::Bytes(signature.to_vec()),
+                })
+                .unwrap(),
+                &mut authorization,
+            )
+            .unwrap();
+            authorization
+        };
+
+        let verification = super::super::verify(
+            &config,
+            grant,
+            identity,
+            recipient,
+            &encode(&permit.signature),
+            &[],
+            None,
+        )
+        .await
+        .unwrap();
+        assert_eq!(verification.nonce, permit.nonce);
+        assert_eq!(verification.expiry, Some(3600));
+
+        let mut forged = permit.signature.clone();
+        forged[0] ^= 1;
+        let res = super::super::verify(
+            &config,
+            grant,
+            identity,
+            recipient,
+            &encode(&forged),
+            &[],
+            None,
+        )
+        .await;
+        assert!(matches!(res, Err(Error::Unauthorized(_))), "{res:?}");
+    }
+}
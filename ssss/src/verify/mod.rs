@@ -1,3 +1,4 @@
+mod authority;
 mod nitro;
 
 use ethers::types::Address;
@@ -32,6 +33,8 @@ pub enum Error {
     PolicyDecode(#[source] anyhow::Error),
     #[error("failed to decode attesation document: {0}")]
     AttestationDecode(#[source] anyhow::Error),
+    #[error("failed to decode signed permit: {0}")]
+    PermitDecode(#[source] anyhow::Error),
     #[error("invalid binding provided")]
     InvalidBinding,
     #[error("binding mismatch. expected {}", hex::encode(_0))]
@@ -72,6 +75,11 @@ pub async fn verify(
                 .verify(&policy_bytes, req, identity, recipient, auth, ctx, relayer)
                 .await
         }
+        "authority" => {
+            authority::AuthorityVerifier
+                .verify(&policy_bytes, req, identity, recipient, auth, ctx, relayer)
+                .await
+        }
         #[cfg(debug_assertions)]
         "mock" => Ok(Verification {
             nonce: {